@@ -0,0 +1,79 @@
+//! Audio outputs other than a sound device.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{AudioThreadState, DeviceSettings};
+
+/// Output the [`AudioEngine`](crate::AudioEngine) plays to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    /// An output device picked according to the settings.
+    ///
+    /// If the device is lost, the engine switches to the default device.
+    Device(DeviceSettings),
+    /// No sound: the audio is produced in real time by a thread and thrown away.
+    ///
+    /// Useful on machines without sound, the playback position advances as usual.
+    Null { channels: u16, sample_rate: u32 },
+    /// No sound: the audio is produced only when rendered with
+    /// [`AudioEngine::render()`](crate::AudioEngine::render).
+    ///
+    /// The playback position follows the rendered audio rather than the real time, so the
+    /// playback is fully deterministic.
+    Offline { channels: u16, sample_rate: u32 },
+}
+
+/// Length of the audio produced at once by [`Backend::Null`].
+const NULL_PERIOD: Duration = Duration::from_millis(10);
+
+/// Thread producing the audio for [`Backend::Null`].
+///
+/// Stops the thread when dropped.
+pub(crate) struct NullOutput {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NullOutput {
+    pub fn new(mut state: AudioThreadState) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let sample_rate = state.config.sample_rate.0;
+        let frames = ((f64::from(sample_rate) * NULL_PERIOD.as_secs_f64()) as usize).max(1);
+        let mut data = vec![0f32; frames * usize::from(state.config.channels)];
+        let period = Duration::from_secs_f64(frames as f64 / f64::from(sample_rate));
+
+        let thread = thread::Builder::new()
+            .name("plitki null audio".to_owned())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    let mut deadline = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        state.data_callback(&mut data, Instant::now(), Duration::ZERO);
+
+                        deadline += period;
+                        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    }
+                }
+            })
+            .expect("could not spawn the null audio thread");
+
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for NullOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Output devices.
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::sync::{Arc, Mutex, PoisonError, TryLockError};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+
+use crossbeam_channel::{Receiver, Sender};
+use rodio::Sample;
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{
+    self, BufferSize, BuildStreamError, DefaultStreamConfigError, Device, DevicesError, HostId,
+    HostUnavailable, OutputCallbackInfo, PlayStreamError, SampleFormat, Stream, StreamConfig,
+    StreamError,
+};
+
+use crate::AudioThreadState;
+
+/// How often to try rebuilding the stream after the device was lost.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of frames the audio thread state renders at once when resampling.
+const RESAMPLER_FRAMES: usize = 256;
+
+/// Settings for picking the output device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceSettings {
+    /// Audio host, or `None` for the default host.
+    pub host: Option<HostId>,
+    /// Name of the output device, or `None` for the default device of the host.
+    pub device: Option<String>,
+    /// Buffer size in frames, or `None` for the default buffer size of the device.
+    pub buffer_size: Option<u32>,
+}
+
+/// Error with the audio output.
+#[derive(Debug)]
+pub enum AudioError {
+    /// The audio host is not available.
+    HostUnavailable(HostUnavailable),
+    /// Listing the devices failed.
+    Devices(DevicesError),
+    /// The host has no output devices.
+    NoDevice,
+    /// There's no output device with this name.
+    DeviceNotFound(String),
+    /// Picking the output config failed.
+    Config(DefaultStreamConfigError),
+    /// Building the output stream failed.
+    BuildStream(BuildStreamError),
+    /// Starting the output stream failed.
+    PlayStream(PlayStreamError),
+    /// The output stream failed while playing.
+    Stream(StreamError),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::HostUnavailable(err) => write!(f, "audio host is unavailable: {err}"),
+            AudioError::Devices(err) => write!(f, "error listing output devices: {err}"),
+            AudioError::NoDevice => write!(f, "no output device available"),
+            AudioError::DeviceNotFound(name) => write!(f, "output device {name:?} not found"),
+            AudioError::Config(err) => write!(f, "could not pick output config: {err}"),
+            AudioError::BuildStream(err) => write!(f, "could not build output stream: {err}"),
+            AudioError::PlayStream(err) => write!(f, "could not play output stream: {err}"),
+            AudioError::Stream(err) => write!(f, "output stream error: {err}"),
+        }
+    }
+}
+
+impl error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AudioError::HostUnavailable(err) => Some(err),
+            AudioError::Devices(err) => Some(err),
+            AudioError::NoDevice | AudioError::DeviceNotFound(_) => None,
+            AudioError::Config(err) => Some(err),
+            AudioError::BuildStream(err) => Some(err),
+            AudioError::PlayStream(err) => Some(err),
+            AudioError::Stream(err) => Some(err),
+        }
+    }
+}
+
+impl From<HostUnavailable> for AudioError {
+    #[inline]
+    fn from(err: HostUnavailable) -> Self {
+        Self::HostUnavailable(err)
+    }
+}
+
+impl From<DevicesError> for AudioError {
+    #[inline]
+    fn from(err: DevicesError) -> Self {
+        Self::Devices(err)
+    }
+}
+
+impl From<DefaultStreamConfigError> for AudioError {
+    #[inline]
+    fn from(err: DefaultStreamConfigError) -> Self {
+        Self::Config(err)
+    }
+}
+
+impl From<BuildStreamError> for AudioError {
+    #[inline]
+    fn from(err: BuildStreamError) -> Self {
+        Self::BuildStream(err)
+    }
+}
+
+impl From<PlayStreamError> for AudioError {
+    #[inline]
+    fn from(err: PlayStreamError) -> Self {
+        Self::PlayStream(err)
+    }
+}
+
+/// Returns the audio hosts available on this system.
+pub fn available_hosts() -> Vec<HostId> {
+    cpal::available_hosts()
+}
+
+/// Returns the names of the output devices of `host`, or of the default host if `None`.
+pub fn output_devices(host: Option<HostId>) -> Result<Vec<String>, AudioError> {
+    let host = match host {
+        Some(id) => cpal::host_from_id(id)?,
+        None => cpal::default_host(),
+    };
+
+    // Skip the devices which fail to report a name, as they can't be picked anyway.
+    Ok(host
+        .output_devices()?
+        .filter_map(|device| device.name().ok())
+        .collect())
+}
+
+/// Finds the output device according to `settings`.
+fn find_device(settings: &DeviceSettings) -> Result<Device, AudioError> {
+    let host = match settings.host {
+        Some(id) => cpal::host_from_id(id)?,
+        None => cpal::default_host(),
+    };
+
+    match &settings.device {
+        Some(name) => host
+            .output_devices()?
+            .find(|device| device.name().is_ok_and(|x| x == *name))
+            .ok_or_else(|| AudioError::DeviceNotFound(name.clone())),
+        None => host.default_output_device().ok_or(AudioError::NoDevice),
+    }
+}
+
+/// Output to a sound device.
+///
+/// Rebuilds the stream when the device is lost, keeping the audio thread state and therefore the
+/// playback position.
+pub(crate) struct DeviceOutput {
+    settings: DeviceSettings,
+    /// Config of the audio thread state.
+    ///
+    /// The stream can use a different config after it was rebuilt on another device.
+    config: StreamConfig,
+    state: Arc<Mutex<AudioThreadState>>,
+    /// Current stream, `None` after the device was lost and until it is rebuilt.
+    stream: RefCell<Option<Stream>>,
+    /// Errors reported by the stream.
+    error_sender: Sender<StreamError>,
+    error_receiver: Receiver<StreamError>,
+    /// Instant of the next attempt to rebuild the stream.
+    next_retry: Cell<Instant>,
+    /// Last error which wasn't taken with [`DeviceOutput::take_error()`].
+    error: RefCell<Option<AudioError>>,
+}
+
+impl DeviceOutput {
+    /// Builds and starts a stream on the device picked by `settings`.
+    ///
+    /// `new_state` creates the audio thread state for the stream config.
+    pub fn new(
+        settings: DeviceSettings,
+        new_state: impl FnOnce(StreamConfig) -> AudioThreadState,
+    ) -> Result<Self, AudioError> {
+        let device = find_device(&settings)?;
+        let supported = device.default_output_config()?;
+        debug!(
+            "using device {:?} with config {:?}",
+            device.name(),
+            supported
+        );
+
+        let mut config = supported.config();
+        if let Some(buffer_size) = settings.buffer_size {
+            config.buffer_size = BufferSize::Fixed(buffer_size);
+        }
+
+        let state = Arc::new(Mutex::new(new_state(config.clone())));
+        let (error_sender, error_receiver) = crossbeam_channel::unbounded();
+
+        let stream = build_stream(
+            &device,
+            &config,
+            supported.sample_format(),
+            &config,
+            &state,
+            &error_sender,
+        )?;
+
+        Ok(Self {
+            settings,
+            config,
+            state,
+            stream: RefCell::new(Some(stream)),
+            error_sender,
+            error_receiver,
+            next_retry: Cell::new(Instant::now()),
+            error: RefCell::new(None),
+        })
+    }
+
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+
+    /// Handles the stream errors, rebuilding the stream if the device was lost.
+    pub fn recover(&self) {
+        for err in self.error_receiver.try_iter() {
+            error!("audio error: {err:?}");
+
+            if matches!(err, StreamError::DeviceNotAvailable)
+                && self.stream.borrow_mut().take().is_some()
+            {
+                warn!("output device lost, rebuilding the stream");
+                self.state
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .output_lost();
+                self.next_retry.set(Instant::now());
+            }
+
+            *self.error.borrow_mut() = Some(AudioError::Stream(err));
+        }
+
+        if self.stream.borrow().is_some() || Instant::now() < self.next_retry.get() {
+            return;
+        }
+
+        match self.rebuild() {
+            Ok(stream) => *self.stream.borrow_mut() = Some(stream),
+            Err(err) => {
+                error!("error rebuilding the output stream: {err}");
+                *self.error.borrow_mut() = Some(err);
+                self.next_retry.set(Instant::now() + RETRY_INTERVAL);
+            }
+        }
+    }
+
+    /// Builds a stream on the picked device, or on the default device if the picked one is gone.
+    ///
+    /// The stream uses the default config of the device, resampling the audio if its sample rate
+    /// or channel count differs from the one of the audio thread state.
+    fn rebuild(&self) -> Result<Stream, AudioError> {
+        let device = match find_device(&self.settings) {
+            Err(AudioError::DeviceNotFound(_)) => find_device(&DeviceSettings {
+                device: None,
+                ..self.settings.clone()
+            })?,
+            device => device?,
+        };
+        let supported = device.default_output_config()?;
+        debug!(
+            "rebuilding the stream on device {:?} with config {:?}",
+            device.name(),
+            supported
+        );
+
+        let mut config = supported.config();
+        if let Some(buffer_size) = self.settings.buffer_size {
+            config.buffer_size = BufferSize::Fixed(buffer_size);
+        }
+
+        build_stream(
+            &device,
+            &config,
+            supported.sample_format(),
+            &self.config,
+            &self.state,
+            &self.error_sender,
+        )
+    }
+
+    /// Returns the last error with the output, if any.
+    pub fn take_error(&self) -> Option<AudioError> {
+        self.error.borrow_mut().take()
+    }
+}
+
+/// Builds and starts a stream on `device`.
+///
+/// `state_config` is the config of `state`. If it differs from `config` in sample rate or channel
+/// count, the audio is resampled.
+fn build_stream(
+    device: &Device,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    state_config: &StreamConfig,
+    state: &Arc<Mutex<AudioThreadState>>,
+    error_sender: &Sender<StreamError>,
+) -> Result<Stream, AudioError> {
+    let on_error = {
+        let error_sender = error_sender.clone();
+        move |err| {
+            let _ = error_sender.send(err);
+        }
+    };
+
+    let resampler = (config.sample_rate != state_config.sample_rate
+        || config.channels != state_config.channels)
+        .then(|| {
+            debug!("resampling from {state_config:?} to {config:?}");
+            Resampler::new(state_config, config)
+        });
+
+    let stream = match sample_format {
+        SampleFormat::I16 => {
+            device.build_output_stream(config, callback::<i16>(state.clone(), resampler), on_error)
+        }
+        SampleFormat::U16 => {
+            device.build_output_stream(config, callback::<u16>(state.clone(), resampler), on_error)
+        }
+        SampleFormat::F32 => {
+            device.build_output_stream(config, callback::<f32>(state.clone(), resampler), on_error)
+        }
+    }?;
+    stream.play()?;
+
+    Ok(stream)
+}
+
+/// Returns the stream data callback producing the audio from `state`.
+///
+/// The audio goes through `resampler` if the stream config differs from the one of `state`.
+fn callback<S: Sample>(
+    state: Arc<Mutex<AudioThreadState>>,
+    mut resampler: Option<Resampler>,
+) -> impl FnMut(&mut [S], &OutputCallbackInfo) {
+    move |data, info| {
+        // Get current instant as soon as possible because it corresponds to the callback
+        // timestamp.
+        let now = Instant::now();
+
+        let timestamp = info.timestamp();
+        let time_until_played = timestamp
+            .playback
+            .duration_since(&timestamp.callback)
+            .unwrap_or_else(|| {
+                error!("cpal playback timestamp < callback timestamp, how is this possible?");
+                Duration::ZERO
+            });
+
+        // The state is only locked elsewhere while the stream is being rebuilt, never block the
+        // audio thread on it.
+        let mut state = match state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                data.fill(S::from(&0f32));
+                return;
+            }
+        };
+
+        match &mut resampler {
+            Some(resampler) => resampler.process(data, |buffer, delay| {
+                state.data_callback(buffer, now, time_until_played + delay)
+            }),
+            None => state.data_callback(data, now, time_until_played),
+        }
+    }
+}
+
+/// Converts the audio to a different sample rate and channel count.
+///
+/// Used when the stream is rebuilt on a device which doesn't support the config the audio thread
+/// state was created for. Interpolates linearly, which is good enough for a fallback.
+struct Resampler {
+    /// Channel count of the input.
+    channels: usize,
+    /// Channel count of the output.
+    output_channels: usize,
+    /// Output sample rate.
+    output_rate: f64,
+    /// Input frames per output frame.
+    step: f64,
+    /// Rendered input, interleaved.
+    buffer: Vec<f32>,
+    /// Index of the next frame in `buffer`.
+    next: usize,
+    /// Input frames the output is interpolated between.
+    previous: Vec<f32>,
+    current: Vec<f32>,
+    /// Position between `previous` and `current`.
+    fraction: f64,
+}
+
+impl Resampler {
+    fn new(from: &StreamConfig, to: &StreamConfig) -> Self {
+        let channels = usize::from(from.channels);
+
+        Self {
+            channels,
+            output_channels: usize::from(to.channels),
+            output_rate: f64::from(to.sample_rate.0),
+            step: f64::from(from.sample_rate.0) / f64::from(to.sample_rate.0),
+            buffer: vec![0.; RESAMPLER_FRAMES * channels],
+            next: RESAMPLER_FRAMES,
+            previous: vec![0.; channels],
+            current: vec![0.; channels],
+            fraction: 1.,
+        }
+    }
+
+    /// Fills `data` with the resampled audio.
+    ///
+    /// `render` fills the buffer with the input, the duration is the time from the start of
+    /// `data` until the start of the buffer.
+    fn process<S: Sample>(&mut self, data: &mut [S], mut render: impl FnMut(&mut [f32], Duration)) {
+        for (i, frame) in data.chunks_mut(self.output_channels).enumerate() {
+            while self.fraction >= 1. {
+                self.fraction -= 1.;
+
+                if self.next == RESAMPLER_FRAMES {
+                    render(
+                        &mut self.buffer,
+                        Duration::from_secs_f64(i as f64 / self.output_rate),
+                    );
+                    self.next = 0;
+                }
+
+                mem::swap(&mut self.previous, &mut self.current);
+                let start = self.next * self.channels;
+                self.current
+                    .copy_from_slice(&self.buffer[start..start + self.channels]);
+                self.next += 1;
+            }
+
+            for (channel, out) in frame.iter_mut().enumerate() {
+                *out = S::from(&self.sample(channel));
+            }
+            self.fraction += self.step;
+        }
+    }
+
+    /// Returns the interpolated sample for the output channel.
+    fn sample(&self, channel: usize) -> f32 {
+        let fraction = self.fraction as f32;
+        let at = |c: usize| self.previous[c] + (self.current[c] - self.previous[c]) * fraction;
+
+        if self.output_channels >= self.channels {
+            at(channel % self.channels)
+        } else {
+            // Mix the extra input channels down.
+            let mixed = (channel..self.channels).step_by(self.output_channels);
+            let count = mixed.len();
+            mixed.map(at).sum::<f32>() / count as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(channels: u16, sample_rate: u32) -> StreamConfig {
+        StreamConfig {
+            channels,
+            sample_rate: cpal::SampleRate(sample_rate),
+            buffer_size: BufferSize::Default,
+        }
+    }
+
+    #[test]
+    fn resample_downmix() {
+        let mut resampler = Resampler::new(&config(2, 10), &config(1, 20));
+
+        let mut data = [0f32; 8];
+        resampler.process(&mut data, |buffer, _| {
+            for (i, frame) in buffer.chunks_mut(2).enumerate() {
+                frame[0] = i as f32;
+                frame[1] = i as f32 + 2.;
+            }
+        });
+
+        assert_eq!(data, [0., 0.5, 1., 1.5, 2., 2.5, 3., 3.5]);
+    }
+
+    #[test]
+    fn resample_upmix() {
+        let mut resampler = Resampler::new(&config(1, 10), &config(2, 10));
+
+        let mut data = [0f32; 8];
+        resampler.process(&mut data, |buffer, _| {
+            for (i, sample) in buffer.iter_mut().enumerate() {
+                *sample = i as f32;
+            }
+        });
+
+        assert_eq!(data, [0., 0., 0., 0., 1., 1., 2., 2.]);
+    }
+}
@@ -4,37 +4,203 @@
 extern crate tracing;
 
 use std::cell::{Cell, RefCell};
+use std::mem;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{OutputCallbackInfo, SampleFormat, Stream};
 use crossbeam_channel::{Receiver, Sender};
 pub use rodio;
-use rodio::cpal::StreamConfig;
+use rodio::cpal::{BufferSize, SampleRate, StreamConfig};
 use rodio::source::UniformSourceIterator;
-use rodio::{Sample, Source, cpal};
+use rodio::{Sample, Source};
 use triple_buffer::TripleBuffer;
 
+mod backend;
+pub use backend::Backend;
+use backend::NullOutput;
+
+mod device;
+use device::DeviceOutput;
+pub use device::{AudioError, DeviceSettings, available_hosts, output_devices};
+
+mod mixer;
+pub use mixer::{DEFAULT_POLYPHONY, MAX_VOICES, SampleBank, SampleId};
+use mixer::{MAX_PENDING, Mixer, Trigger};
+
+mod stretch;
+use stretch::Stretcher;
+pub use stretch::{MAX_RATE, MIN_RATE, Pitch};
+
+mod track;
+use track::{ConvertedTrack, TrackData};
+pub use track::{Track, TrackError};
+
 /// Message sent to the audio thread.
 enum ToAudioMessage {
     /// Play the next track.
     Play {
         /// Track to play.
-        track: Box<dyn Source<Item = f32> + Send>,
+        track: PlayingTrack,
         /// Identifier of the track.
         id: usize,
     },
     /// Change volume.
     SetVolume(f32),
+    /// Change playback rate.
+    SetRate { rate: f64, pitch: Pitch },
+    /// Pause the playback.
+    Pause,
+    /// Resume the playback.
+    Resume,
+    /// Replace the sample bank.
+    SetSampleBank(Box<SampleBank>),
+    /// Seek the current track.
+    Seek {
+        /// Current track decoded again and seeked on the calling thread, or `None` if the current
+        /// track seeks cheaply by itself.
+        track: Option<Box<ConvertedTrack>>,
+        /// Track timestamp to seek to.
+        position: Duration,
+        /// Identifier of the track after seeking.
+        id: usize,
+    },
+}
+
+/// Value replaced on the audio thread, sent back to be dropped on the calling thread.
+///
+/// Freeing memory can block, so the audio thread never drops tracks and sample banks itself.
+// The values are never read, only dropped.
+#[allow(dead_code)]
+enum Garbage {
+    Track(PlayingTrack),
+    SampleBank(Box<SampleBank>),
+}
+
+/// Maximum number of [`Garbage`] values waiting to be dropped.
+///
+/// The channel is bounded so that sending doesn't allocate. If it's full, the audio thread drops
+/// the value itself.
+const MAX_GARBAGE: usize = 16;
+
+/// Track sent to the audio thread.
+///
+/// Should have the same sample rate and channel count as the output stream.
+enum PlayingTrack {
+    /// Track which can't seek.
+    Source(Box<dyn Source<Item = f32> + Send>),
+    /// Decoded track which seeks using the demuxer.
+    Decoded(Box<ConvertedTrack>),
+    /// Section of a track playing in a loop.
+    Looped(LoopedSamples),
+}
+
+impl PlayingTrack {
+    /// Seeks the track to the track timestamp `position`.
+    ///
+    /// Only [`PlayingTrack::Looped`] can seek on the audio thread, the other tracks would need to
+    /// decode and allocate.
+    fn seek(&mut self, position: Duration) {
+        match self {
+            PlayingTrack::Looped(samples) => samples.seek(position),
+            _ => error!("tried to seek a track which can't seek on the audio thread"),
+        }
+    }
+}
+
+/// How the current track seeks.
+enum Seeking {
+    /// The track can't seek.
+    Unsupported,
+    /// The track seeks on the audio thread, which is cheap since it's fully in memory.
+    InPlace,
+    /// The track is decoded again and seeked on the calling thread.
+    Reopen(TrackData),
+}
+
+impl Iterator for PlayingTrack {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            PlayingTrack::Source(source) => source.next(),
+            PlayingTrack::Decoded(track) => track.next(),
+            PlayingTrack::Looped(samples) => samples.next(),
+        }
+    }
 }
 
 /// The main struct managing the audio playback.
 pub struct AudioEngine {
-    _stream: Stream,
+    output: Output,
     config: StreamConfig,
     timestamp_consumer: RefCell<triple_buffer::Output<Option<AudioTimestamp>>>,
     sender: Sender<ToAudioMessage>,
+    trigger_sender: Sender<Trigger>,
+    garbage_receiver: Receiver<Garbage>,
     current_track_id: Cell<usize>,
+    /// Section of the current track that is playing.
+    current_section: Cell<TrackSection>,
+    /// How the current track seeks.
+    current_track_seeking: RefCell<Seeking>,
+    /// Whether the playback is paused.
+    paused: Cell<bool>,
+}
+
+/// Output the audio is played to, see [`Backend`].
+enum Output {
+    Device(Box<DeviceOutput>),
+    Null {
+        _output: NullOutput,
+    },
+    Offline {
+        state: Box<RefCell<AudioThreadState>>,
+        /// Instant corresponding to the start of the rendered audio.
+        start: Instant,
+        /// Number of frames rendered so far.
+        rendered: Cell<u64>,
+    },
+}
+
+/// Section of a track that is playing.
+#[derive(Debug, Clone, Copy)]
+struct TrackSection {
+    /// Track timestamp where the playback started.
+    start: Duration,
+    /// Start and length of the loop if the section is playing in a loop.
+    looped: Option<(Duration, Duration)>,
+}
+
+impl TrackSection {
+    const WHOLE_TRACK: Self = Self {
+        start: Duration::ZERO,
+        looped: None,
+    };
+
+    /// Converts the track time played since the playback started into a track timestamp.
+    fn track_time(self, time_played: Duration) -> Duration {
+        let time = self.start + time_played;
+        match self.looped {
+            Some((start, length)) => start + wrap(time.saturating_sub(start), length),
+            None => time,
+        }
+    }
+
+    /// Returns the section which starts playing from the track timestamp `position`.
+    ///
+    /// Positions outside of the loop are moved into it.
+    fn seeked(self, position: Duration) -> Self {
+        let start = match self.looped {
+            Some((start, length)) => start + wrap(position.saturating_sub(start), length),
+            None => position,
+        };
+        Self { start, ..self }
+    }
+}
+
+/// Wraps `time` around to be less than `length`.
+fn wrap(time: Duration, length: Duration) -> Duration {
+    Duration::from_nanos((time.as_nanos() % length.as_nanos()) as u64)
 }
 
 impl std::fmt::Debug for AudioEngine {
@@ -44,61 +210,236 @@ impl std::fmt::Debug for AudioEngine {
 }
 
 impl AudioEngine {
-    /// Creates a new [`AudioEngine`].
+    /// Creates a new [`AudioEngine`] playing to the default output device.
+    ///
+    /// If the output device can't be used, falls back to [`Backend::Null`].
     pub fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .expect("no output device available");
-        let config = device
-            .default_output_config()
-            .expect("could not pick output config");
-        debug!("using device {:?} with config {:?}", device.name(), config);
-
-        let stream_config = config.config();
-        let on_error = move |err| {
-            error!("audio error: {err:?}");
-        };
+        Self::with_backend(Backend::Device(DeviceSettings::default())).unwrap_or_else(|err| {
+            warn!("error opening the output device, the audio will not be heard: {err}");
+            Self::with_backend(Backend::Null {
+                channels: 2,
+                sample_rate: 44100,
+            })
+            .expect("the null backend can't fail")
+        })
+    }
 
+    /// Creates a new [`AudioEngine`] playing to `backend`.
+    ///
+    /// Only [`Backend::Device`] can fail.
+    pub fn with_backend(backend: Backend) -> Result<Self, AudioError> {
         let (timestamp_producer, timestamp_consumer) = TripleBuffer::new(&None).split();
         let (sender, receiver) = crossbeam_channel::unbounded();
+        let (trigger_sender, trigger_receiver) = crossbeam_channel::bounded(MAX_PENDING);
+        let (garbage_sender, garbage_receiver) = crossbeam_channel::bounded(MAX_GARBAGE);
 
-        let state = AudioThreadState::new(stream_config.clone(), timestamp_producer, receiver);
+        let new_state = |config: StreamConfig| {
+            AudioThreadState::new(
+                config,
+                timestamp_producer,
+                receiver,
+                trigger_receiver,
+                garbage_sender,
+            )
+        };
 
-        let stream = match config.sample_format() {
-            SampleFormat::I16 => {
-                device.build_output_stream(&stream_config, state.into_callback::<i16>(), on_error)
+        let (output, config) = match backend {
+            Backend::Device(settings) => {
+                let output = DeviceOutput::new(settings, new_state)?;
+                let config = output.config().clone();
+                (Output::Device(Box::new(output)), config)
             }
-            SampleFormat::U16 => {
-                device.build_output_stream(&stream_config, state.into_callback::<u16>(), on_error)
+            Backend::Null {
+                channels,
+                sample_rate,
+            } => {
+                let config = stream_config(channels, sample_rate);
+                let output = NullOutput::new(new_state(config.clone()));
+                (Output::Null { _output: output }, config)
             }
-            SampleFormat::F32 => {
-                device.build_output_stream(&stream_config, state.into_callback::<f32>(), on_error)
+            Backend::Offline {
+                channels,
+                sample_rate,
+            } => {
+                let config = stream_config(channels, sample_rate);
+                let output = Output::Offline {
+                    state: Box::new(RefCell::new(new_state(config.clone()))),
+                    start: Instant::now(),
+                    rendered: Cell::new(0),
+                };
+                (output, config)
             }
-        }
-        .expect("could not build output stream");
-        stream.play().expect("could not play stream");
+        };
 
-        Self {
-            _stream: stream,
-            config: stream_config,
+        Ok(Self {
+            output,
+            config,
             timestamp_consumer: RefCell::new(timestamp_consumer),
             sender,
+            trigger_sender,
+            garbage_receiver,
             current_track_id: Cell::new(0),
-        }
+            current_section: Cell::new(TrackSection::WHOLE_TRACK),
+            current_track_seeking: RefCell::new(Seeking::Unsupported),
+            paused: Cell::new(false),
+        })
     }
 
     /// Starts playing the `track`.
     ///
+    /// Starting a track resumes the playback if it was paused. The track can't seek, use
+    /// [`AudioEngine::play_decoded_track()`] for that.
+    ///
     /// After calling this method, [`AudioEngine::track_time()`] may return [`Duration::ZERO`] for a
     /// little bit, until the track actually starts playing.
     pub fn play_track(&self, track: impl Source<Item = impl Sample + Send> + Send + 'static) {
+        self.play_track_from(track, Duration::ZERO);
+    }
+
+    /// Starts playing the `track` from `start`.
+    ///
+    /// The part of the track before `start` is decoded and thrown away on the calling thread.
+    ///
+    /// After calling this method, [`AudioEngine::track_time()`] may return `start` for a little
+    /// bit, until the track actually starts playing.
+    pub fn play_track_from(
+        &self,
+        track: impl Source<Item = impl Sample + Send> + Send + 'static,
+        start: Duration,
+    ) {
         // Do all these allocations here, rather than on the audio thread.
+        let mut track =
+            UniformSourceIterator::new(track, self.config.channels, self.config.sample_rate.0);
+        for _ in 0..self.sample_count(start) {
+            if track.next().is_none() {
+                break;
+            }
+        }
+
+        let section = TrackSection {
+            start,
+            looped: None,
+        };
+        self.send_track(
+            PlayingTrack::Source(Box::new(track)),
+            section,
+            Seeking::Unsupported,
+        );
+    }
+
+    /// Starts playing the decoded `track` from `start`.
+    ///
+    /// Unlike [`AudioEngine::play_track_from()`], the track seeks to `start` with the demuxer seek
+    /// index instead of decoding everything before it, and it supports [`AudioEngine::seek()`].
+    ///
+    /// After calling this method, [`AudioEngine::track_time()`] may return `start` for a little
+    /// bit, until the track actually starts playing.
+    pub fn play_decoded_track(&self, track: Track, start: Duration) -> Result<(), TrackError> {
+        let data = track.data();
+        let mut track = ConvertedTrack::new(track, self.config.channels, self.config.sample_rate.0);
+        track.seek(start)?;
+
+        let section = TrackSection {
+            start,
+            looped: None,
+        };
+        self.send_track(
+            PlayingTrack::Decoded(Box::new(track)),
+            section,
+            Seeking::Reopen(data),
+        );
+        Ok(())
+    }
+
+    /// Starts playing the section of the `track` between `start` and `end` in a loop.
+    ///
+    /// The section is decoded up front on the calling thread. [`AudioEngine::track_time()`]
+    /// returns the playback position within the track, which jumps back to `start` every time the
+    /// playback reaches `end`. [`AudioEngine::seek()`] wraps positions around into the loop.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `end` is not after `start`.
+    pub fn play_track_loop(
+        &self,
+        track: impl Source<Item = impl Sample + Send> + Send + 'static,
+        start: Duration,
+        end: Duration,
+    ) {
+        assert!(start < end, "end must be after start");
+
         let track =
             UniformSourceIterator::new(track, self.config.channels, self.config.sample_rate.0);
-        let track = Box::new(track);
+        self.play_samples_loop(track.skip(self.sample_count(start)), start, end);
+    }
 
+    /// Starts playing the section of the decoded `track` between `start` and `end` in a loop.
+    ///
+    /// Works like [`AudioEngine::play_track_loop()`], except that the track seeks to `start` with
+    /// the demuxer seek index instead of decoding everything before it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `end` is not after `start`.
+    pub fn play_decoded_track_loop(
+        &self,
+        track: Track,
+        start: Duration,
+        end: Duration,
+    ) -> Result<(), TrackError> {
+        assert!(start < end, "end must be after start");
+
+        let mut track = ConvertedTrack::new(track, self.config.channels, self.config.sample_rate.0);
+        track.seek(start)?;
+        self.play_samples_loop(track, start, end);
+        Ok(())
+    }
+
+    /// Starts playing `samples`, which begin at the track timestamp `start`, in a loop until `end`.
+    fn play_samples_loop(
+        &self,
+        samples: impl Iterator<Item = f32>,
+        start: Duration,
+        end: Duration,
+    ) {
+        let channels = self.config.channels;
+        let sample_rate = self.config.sample_rate.0;
+
+        let len = self
+            .sample_count(end)
+            .saturating_sub(self.sample_count(start))
+            .max(usize::from(channels));
+        let mut samples: Vec<f32> = samples.take(len).collect();
+        // Pad the section with silence if the track ends early so the loop length is exact.
+        samples.resize(len, 0.);
+
+        let frames = len / usize::from(channels);
+        let length = Duration::from_secs_f64(frames as f64 / f64::from(sample_rate));
+        let section = TrackSection {
+            start,
+            looped: Some((start, length)),
+        };
+        let track = LoopedSamples {
+            samples,
+            position: 0,
+            start,
+            channels,
+            sample_rate,
+        };
+        self.send_track(PlayingTrack::Looped(track), section, Seeking::InPlace);
+    }
+
+    /// Returns the number of samples in the given `duration`, rounded to whole frames.
+    fn sample_count(&self, duration: Duration) -> usize {
+        let frames = (duration.as_secs_f64() * f64::from(self.config.sample_rate.0)).round();
+        frames as usize * usize::from(self.config.channels)
+    }
+
+    fn send_track(&self, track: PlayingTrack, section: TrackSection, seeking: Seeking) {
         self.current_track_id.set(self.current_track_id.get() + 1);
+        self.current_section.set(section);
+        *self.current_track_seeking.borrow_mut() = seeking;
+        self.paused.set(false);
 
         let message = ToAudioMessage::Play {
             track,
@@ -107,19 +448,41 @@ impl AudioEngine {
         if let Err(err) = self.sender.send(message) {
             error!("error sending track to audio thread: {err:?}");
         }
+
+        self.drop_garbage();
     }
 
     /// Returns current playback position of the track.
     ///
+    /// The playback position is in track time, so at a rate of 1.5× it advances by 1.5 seconds
+    /// every second.
+    ///
     /// The playback position will keep increasing past the end of the track (until another track is
-    /// started with [`AudioEngine::play_track()`]).
+    /// started with [`AudioEngine::play_track()`]). It stops increasing while the playback is paused.
+    ///
+    /// If the output device was lost, this method also tries to rebuild the output stream, picking
+    /// up from the same playback position.
     pub fn track_time(&self) -> Duration {
+        if let Output::Device(output) = &self.output {
+            output.recover();
+        }
+
+        self.drop_garbage();
+
+        self.current_section.get().track_time(self.time_played())
+    }
+
+    /// Returns the track time played since the current track started playing.
+    fn time_played(&self) -> Duration {
         let mut timestamp_consumer = self.timestamp_consumer.borrow_mut();
 
         let AudioTimestamp {
             track_id,
             track_timestamp,
             will_play_at,
+            rate,
+            playing_before,
+            playing_after,
         } = match *timestamp_consumer.read() {
             Some(x) => x,
             None => return Duration::ZERO,
@@ -130,23 +493,232 @@ impl AudioEngine {
             return Duration::ZERO;
         }
 
-        let now = Instant::now();
+        let now = self.now();
         if let Some(time_until_played) = will_play_at.checked_duration_since(now) {
-            track_timestamp.saturating_sub(time_until_played)
+            if !playing_before {
+                return track_timestamp;
+            }
+            track_timestamp.saturating_sub(time_until_played.mul_f64(rate))
         } else {
+            if !playing_after {
+                return track_timestamp;
+            }
             let time_since_played = now.duration_since(will_play_at);
-            track_timestamp + time_since_played
+            track_timestamp + time_since_played.mul_f64(rate)
         }
     }
 
+    /// Pauses the playback.
+    ///
+    /// [`AudioEngine::track_time()`] stops increasing at the moment the audio actually stops.
+    pub fn pause(&self) {
+        self.paused.set(true);
+        self.send_message(ToAudioMessage::Pause);
+    }
+
+    /// Resumes the playback after [`AudioEngine::pause()`].
+    pub fn resume(&self) {
+        self.paused.set(false);
+        self.send_message(ToAudioMessage::Resume);
+    }
+
+    /// Returns whether the playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.get()
+    }
+
+    /// Seeks the current track to the track timestamp `position`.
+    ///
+    /// Only tracks started with [`AudioEngine::play_decoded_track()`] and
+    /// [`AudioEngine::play_track_loop()`] can seek, for other tracks this does nothing.
+    ///
+    /// A decoded track is decoded again and seeked on the calling thread, like in
+    /// [`AudioEngine::play_decoded_track()`]. If that fails, the track keeps playing as before.
+    ///
+    /// After calling this method, [`AudioEngine::track_time()`] returns `position` until the track
+    /// actually starts playing from there. Seeking keeps the playback paused if it was paused.
+    pub fn seek(&self, position: Duration) -> Result<(), TrackError> {
+        let section = self.current_section.get().seeked(position);
+
+        let track = match &*self.current_track_seeking.borrow() {
+            Seeking::Unsupported => {
+                warn!("tried to seek a track which can't seek");
+                return Ok(());
+            }
+            Seeking::InPlace => None,
+            Seeking::Reopen(data) => {
+                let mut track = ConvertedTrack::new(
+                    data.open()?,
+                    self.config.channels,
+                    self.config.sample_rate.0,
+                );
+                track.seek(section.start)?;
+                Some(Box::new(track))
+            }
+        };
+
+        self.current_track_id.set(self.current_track_id.get() + 1);
+        self.current_section.set(section);
+
+        self.send_message(ToAudioMessage::Seek {
+            track,
+            position: section.start,
+            id: self.current_track_id.get(),
+        });
+        Ok(())
+    }
+
     /// Sets the audio volume.
     ///
     /// Volume is a multiplier for all samples, so 1. is 100% and 0. is 0%.
     pub fn set_volume(&self, volume: f32) {
-        let message = ToAudioMessage::SetVolume(volume);
+        self.send_message(ToAudioMessage::SetVolume(volume));
+    }
+
+    /// Sets the playback rate.
+    ///
+    /// The rate is clamped to [`MIN_RATE`]..=[`MAX_RATE`] and applies to the current and all
+    /// further tracks. With [`Pitch::Keep`] the audio is stretched in time to keep its pitch,
+    /// otherwise the pitch changes together with the rate.
+    pub fn set_rate(&self, rate: f64, pitch: Pitch) {
+        self.send_message(ToAudioMessage::SetRate { rate, pitch });
+    }
+
+    /// Returns the last error with the output device which happened while playing, if any.
+    ///
+    /// Errors are collected in [`AudioEngine::track_time()`].
+    pub fn take_error(&self) -> Option<AudioError> {
+        match &self.output {
+            Output::Device(output) => output.take_error(),
+            _ => None,
+        }
+    }
+
+    /// Returns the current instant on the clock of the output.
+    fn now(&self) -> Instant {
+        match &self.output {
+            Output::Offline {
+                start, rendered, ..
+            } => *start + self.frames_duration(rendered.get()),
+            _ => Instant::now(),
+        }
+    }
+
+    /// Returns the duration of `frames` frames of audio.
+    fn frames_duration(&self, frames: u64) -> Duration {
+        let nanos = u128::from(frames) * 1_000_000_000 / u128::from(self.config.sample_rate.0);
+        Duration::from_nanos(nanos as u64)
+    }
+
+    /// Renders the next `frames` frames of audio with [`Backend::Offline`].
+    ///
+    /// Returns the interleaved samples. The clock of [`AudioEngine::track_time()`] advances by the
+    /// duration of the rendered audio.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the engine doesn't use [`Backend::Offline`].
+    pub fn render(&self, frames: usize) -> Vec<f32> {
+        let Output::Offline {
+            state, rendered, ..
+        } = &self.output
+        else {
+            panic!("only the offline backend can render");
+        };
+
+        let mut data = vec![0.; frames * usize::from(self.config.channels)];
+        state
+            .borrow_mut()
+            .data_callback(&mut data, self.now(), Duration::ZERO);
+        rendered.set(rendered.get() + frames as u64);
+        data
+    }
+
+    /// Renders the next `duration` of audio with [`Backend::Offline`] into a WAV file at `path`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the engine doesn't use [`Backend::Offline`].
+    pub fn render_to_wav(&self, path: impl AsRef<Path>, duration: Duration) -> hound::Result<()> {
+        let frames = (duration.as_secs_f64() * f64::from(self.config.sample_rate.0)).round();
+        let samples = self.render(frames as usize);
+
+        let spec = hound::WavSpec {
+            channels: self.config.channels,
+            sample_rate: self.config.sample_rate.0,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for sample in samples {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()
+    }
+
+    /// Returns a new empty [`SampleBank`] for the output stream of this engine.
+    pub fn new_sample_bank(&self) -> SampleBank {
+        SampleBank::new(self.config.channels, self.config.sample_rate.0)
+    }
+
+    /// Replaces the sample bank, stopping all playing samples.
+    ///
+    /// The `bank` must come from [`AudioEngine::new_sample_bank()`] of this engine.
+    pub fn set_sample_bank(&self, bank: SampleBank) {
+        self.send_message(ToAudioMessage::SetSampleBank(Box::new(bank)));
+    }
+
+    /// Plays the sample from the sample bank as soon as possible, for example for a hitsound.
+    pub fn play_sample(&self, id: SampleId) {
+        self.send_trigger(Trigger {
+            sample: id,
+            at: None,
+            track_id: self.current_track_id.get(),
+        });
+    }
+
+    /// Plays the sample from the sample bank when the track reaches the track timestamp
+    /// `timestamp`, for example for a keysound.
+    ///
+    /// Samples triggered ahead of time start exactly at `timestamp`, and samples triggered late
+    /// start as soon as possible. Starting another track or seeking cancels the samples that
+    /// haven't started yet. In a looped section, the sample only plays on the first pass.
+    pub fn play_sample_at(&self, id: SampleId, timestamp: Duration) {
+        let section = self.current_section.get();
+        self.send_trigger(Trigger {
+            sample: id,
+            at: Some(timestamp.saturating_sub(section.start)),
+            track_id: self.current_track_id.get(),
+        });
+    }
+
+    fn send_trigger(&self, trigger: Trigger) {
+        // Never block: the queue is only full if the audio thread is stuck.
+        if let Err(err) = self.trigger_sender.try_send(trigger) {
+            error!("error sending sample to audio thread: {err:?}");
+        }
+    }
+
+    fn send_message(&self, message: ToAudioMessage) {
         if let Err(err) = self.sender.send(message) {
             error!("error sending message to audio thread: {err:?}");
         }
+
+        self.drop_garbage();
+    }
+
+    /// Drops the values replaced on the audio thread.
+    fn drop_garbage(&self) {
+        self.garbage_receiver.try_iter().for_each(drop);
+    }
+}
+
+/// Returns the stream config for the given channel count and sample rate.
+fn stream_config(channels: u16, sample_rate: u32) -> StreamConfig {
+    StreamConfig {
+        channels,
+        sample_rate: SampleRate(sample_rate),
+        buffer_size: BufferSize::Default,
     }
 }
 
@@ -156,16 +728,76 @@ impl Default for AudioEngine {
     }
 }
 
+/// Source which plays samples in a loop.
+struct LoopedSamples {
+    /// Samples to play, should not be empty.
+    samples: Vec<f32>,
+    /// Index of the next sample to play.
+    position: usize,
+    /// Track timestamp of the first sample.
+    start: Duration,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl LoopedSamples {
+    /// Seeks to the track timestamp `position`, wrapping around into the loop.
+    fn seek(&mut self, position: Duration) {
+        let offset = position.saturating_sub(self.start).as_secs_f64();
+        let frame = (offset * f64::from(self.sample_rate)).round() as usize;
+        self.position = (frame * usize::from(self.channels)) % self.samples.len();
+    }
+}
+
+impl Iterator for LoopedSamples {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = *self.samples.get(self.position)?;
+        self.position = (self.position + 1) % self.samples.len();
+        Some(sample)
+    }
+}
+
+impl Source for LoopedSamples {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
 /// A timestamp from the audio thread.
 ///
 /// Indicates that the track timestamp [`AudioTimestamp::track_timestamp`] will play at
-/// [`AudioTimestamp::will_play_at`] time.
+/// [`AudioTimestamp::will_play_at`] time, and the track time advances [`AudioTimestamp::rate`]
+/// times faster than real time around that, unless the playback is paused.
 #[derive(Debug, Clone, Copy)]
 struct AudioTimestamp {
     /// Identifier of the track this timestamp is for.
     track_id: usize,
     track_timestamp: Duration,
     will_play_at: Instant,
+    /// Playback rate.
+    rate: f64,
+    /// Whether the track is playing before [`AudioTimestamp::will_play_at`].
+    ///
+    /// If `false`, the track time stays at [`AudioTimestamp::track_timestamp`] until then.
+    playing_before: bool,
+    /// Whether the track is playing after [`AudioTimestamp::will_play_at`].
+    ///
+    /// If `false`, the track time stays at [`AudioTimestamp::track_timestamp`] after that.
+    playing_after: bool,
 }
 
 struct AudioThreadState {
@@ -177,20 +809,34 @@ struct AudioThreadState {
     /// Audio that's currently playing.
     ///
     /// Should have the same sample rate and channel count as [`AudioThreadState::config`].
-    track: Box<dyn Source<Item = f32> + Send>,
+    track: PlayingTrack,
 
     /// Identifier of the track that's currently playing.
     track_id: usize,
 
-    /// Total number of samples taken from [`AudioThreadState::track`].
-    samples_taken: usize,
+    /// Playback rate changer, which tracks the position in [`AudioThreadState::track`].
+    stretcher: Stretcher,
+
+    /// Mixer of the hitsounds and keysounds.
+    mixer: Mixer,
 
     /// Audio volume.
     volume: f32,
 
+    /// Whether the playback is paused.
+    paused: bool,
+
+    /// Whether the track played until the current callback.
+    was_playing: bool,
+
+    /// Whether the playback is paused and the last written timestamp already accounts for that.
+    frozen: bool,
+
     timestamp_producer: triple_buffer::Input<Option<AudioTimestamp>>,
 
     receiver: Receiver<ToAudioMessage>,
+
+    garbage_sender: Sender<Garbage>,
 }
 
 impl AudioThreadState {
@@ -198,73 +844,375 @@ impl AudioThreadState {
         config: StreamConfig,
         timestamp_producer: triple_buffer::Input<Option<AudioTimestamp>>,
         receiver: Receiver<ToAudioMessage>,
+        trigger_receiver: Receiver<Trigger>,
+        garbage_sender: Sender<Garbage>,
     ) -> Self {
         let silence = rodio::source::Zero::new(config.channels, config.sample_rate.0);
 
         Self {
+            stretcher: Stretcher::new(config.channels, config.sample_rate.0),
+            mixer: Mixer::new(config.channels, config.sample_rate.0, trigger_receiver),
             config,
             silence: silence.clone(),
-            track: Box::new(silence),
-            samples_taken: 0,
+            track: PlayingTrack::Source(Box::new(silence)),
             volume: 1.,
+            paused: false,
+            was_playing: false,
+            frozen: false,
             timestamp_producer,
             receiver,
+            garbage_sender,
             track_id: usize::MAX,
         }
     }
 
-    fn into_callback<S: Sample>(mut self) -> impl FnMut(&mut [S], &OutputCallbackInfo) {
-        move |data, info| self.data_callback(data, info)
+    /// Fills `data` with the next audio samples.
+    ///
+    /// `callback_called_at` is the instant of the callback, and `time_until_played` is the latency
+    /// until `data` is heard.
+    fn data_callback<S: Sample>(
+        &mut self,
+        data: &mut [S],
+        callback_called_at: Instant,
+        time_until_played: Duration,
+    ) {
+        self.receive_messages();
+        self.mixer.receive_triggers(self.track_id);
+
+        self.update_timestamp(callback_called_at + time_until_played);
+
+        if self.paused {
+            data.fill(S::from(&0f32));
+            return;
+        }
+
+        let mut source = (&mut self.track).chain(self.silence.clone());
+        for frame in data.chunks_mut(usize::from(self.config.channels)) {
+            let position = self.stretcher.position();
+            let samples = self.stretcher.next_frame(&mut source);
+            let sounds = self.mixer.next_frame(position, self.track_id);
+            for (out, (sample, sound)) in frame.iter_mut().zip(samples.iter().zip(sounds)) {
+                let sample = (sample + sound) * self.volume;
+                *out = S::from(&sample);
+            }
+        }
     }
 
-    fn data_callback<S: Sample>(&mut self, data: &mut [S], info: &OutputCallbackInfo) {
-        // Get current instant as soon as possible because it corresponds to the callback timestamp.
-        let now = Instant::now();
+    /// Writes the timestamp for the audio which will play at `will_play_at`.
+    fn update_timestamp(&mut self, will_play_at: Instant) {
+        // Keep the timestamp where the playback paused, as the later callbacks would move its
+        // will_play_at past the moment the audio actually stopped.
+        if self.frozen {
+            return;
+        }
 
-        self.receive_messages();
+        let track_timestamp =
+            Duration::from_secs_f64(self.stretcher.position() / self.config.sample_rate.0 as f64);
 
-        self.update_timestamp(info, now);
+        self.timestamp_producer.write(Some(AudioTimestamp {
+            track_id: self.track_id,
+            track_timestamp,
+            will_play_at,
+            rate: self.stretcher.rate(),
+            playing_before: self.was_playing,
+            playing_after: !self.paused,
+        }));
 
-        let source = self.track.as_mut().chain(self.silence.clone());
-        for (out, sample) in data.iter_mut().zip(source) {
-            let sample = sample * self.volume;
-            *out = S::from(&sample);
+        self.was_playing = !self.paused;
+        self.frozen = self.paused;
+    }
+
+    /// Freezes the track time at the current position after the output stopped unexpectedly.
+    fn output_lost(&mut self) {
+        if self.frozen {
+            return;
         }
-        self.samples_taken += data.len();
-    }
-
-    fn update_timestamp(&mut self, info: &OutputCallbackInfo, callback_called_at: Instant) {
-        let timestamp = info.timestamp();
-        let time_until_played = timestamp
-            .playback
-            .duration_since(&timestamp.callback)
-            .unwrap_or_else(|| {
-                error!("cpal playback timestamp < callback timestamp, how is this possible?");
-                Duration::ZERO
-            });
-        let track_timestamp = Duration::from_secs_f64(
-            self.samples_taken as f64
-                / self.config.sample_rate.0 as f64
-                / self.config.channels as f64,
-        );
+
+        let track_timestamp =
+            Duration::from_secs_f64(self.stretcher.position() / self.config.sample_rate.0 as f64);
 
         self.timestamp_producer.write(Some(AudioTimestamp {
             track_id: self.track_id,
             track_timestamp,
-            will_play_at: callback_called_at + time_until_played,
+            will_play_at: Instant::now(),
+            rate: self.stretcher.rate(),
+            playing_before: false,
+            playing_after: false,
         }));
+
+        self.was_playing = false;
     }
 
     fn receive_messages(&mut self) {
         for message in self.receiver.try_iter() {
             match message {
                 ToAudioMessage::Play { track, id } => {
-                    self.track = track;
+                    let old = mem::replace(&mut self.track, track);
+                    self.send_garbage(Garbage::Track(old));
                     self.track_id = id;
-                    self.samples_taken = 0;
+                    self.stretcher.reset();
+                    self.paused = false;
+                    self.was_playing = false;
+                    self.frozen = false;
                 }
                 ToAudioMessage::SetVolume(volume) => self.volume = volume,
+                ToAudioMessage::SetRate { rate, pitch } => self.stretcher.set_rate(rate, pitch),
+                ToAudioMessage::SetSampleBank(bank) => {
+                    let old = self.mixer.set_bank(bank);
+                    self.send_garbage(Garbage::SampleBank(old));
+                }
+                ToAudioMessage::Pause => self.paused = true,
+                ToAudioMessage::Resume => {
+                    self.paused = false;
+                    self.frozen = false;
+                }
+                ToAudioMessage::Seek {
+                    track,
+                    position,
+                    id,
+                } => {
+                    match track {
+                        Some(track) => {
+                            let old = mem::replace(&mut self.track, PlayingTrack::Decoded(track));
+                            self.send_garbage(Garbage::Track(old));
+                        }
+                        None => self.track.seek(position),
+                    }
+                    self.track_id = id;
+                    self.stretcher.reset();
+                    self.was_playing = false;
+                    self.frozen = false;
+                }
             }
         }
     }
+
+    /// Sends `garbage` to be dropped outside of the audio thread.
+    fn send_garbage(&self, garbage: Garbage) {
+        if let Err(err) = self.garbage_sender.try_send(garbage) {
+            warn!("too many values to drop, dropping on the audio thread");
+            drop(err.into_inner());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    /// Returns an engine rendering mono audio at 100 Hz.
+    fn offline_engine() -> AudioEngine {
+        AudioEngine::with_backend(Backend::Offline {
+            channels: 1,
+            sample_rate: 100,
+        })
+        .unwrap()
+    }
+
+    /// Returns two seconds of mono audio at 100 Hz where sample `i` is `i / 200`.
+    fn ramp() -> SamplesBuffer<f32> {
+        SamplesBuffer::new(
+            1,
+            100,
+            (0..200).map(|i| i as f32 / 200.).collect::<Vec<_>>(),
+        )
+    }
+
+    #[track_caller]
+    fn assert_track_time(engine: &AudioEngine, millis: u64) {
+        let time = engine.track_time();
+        let expected = Duration::from_millis(millis);
+        assert!(
+            time.abs_diff(expected) < Duration::from_micros(10),
+            "{time:?} != {expected:?}"
+        );
+    }
+
+    #[track_caller]
+    fn assert_samples(samples: &[f32], first: usize) {
+        for (i, sample) in samples.iter().enumerate() {
+            let expected = (first + i) as f32 / 200.;
+            assert!((sample - expected).abs() < 1e-5, "{sample} != {expected}");
+        }
+    }
+
+    #[test]
+    fn offline_render() {
+        let engine = offline_engine();
+        assert_track_time(&engine, 0);
+
+        engine.play_track(ramp());
+        assert_samples(&engine.render(50), 0);
+        assert_track_time(&engine, 500);
+        assert_samples(&engine.render(50), 50);
+        assert_track_time(&engine, 1000);
+    }
+
+    #[test]
+    fn pause_freezes_track_time() {
+        let engine = offline_engine();
+        engine.play_track(ramp());
+        engine.render(50);
+
+        engine.pause();
+        assert!(engine.is_paused());
+        assert_eq!(engine.render(10), [0.; 10]);
+        assert_track_time(&engine, 500);
+        engine.render(10);
+        assert_track_time(&engine, 500);
+
+        engine.resume();
+        assert_samples(&engine.render(10), 50);
+        assert_track_time(&engine, 600);
+    }
+
+    #[test]
+    fn seek_loop() {
+        let engine = offline_engine();
+        engine.play_track_loop(
+            ramp(),
+            Duration::from_millis(500),
+            Duration::from_millis(1500),
+        );
+        assert_samples(&engine.render(30), 50);
+        assert_track_time(&engine, 800);
+
+        engine.seek(Duration::from_millis(1200)).unwrap();
+        assert_track_time(&engine, 1200);
+        assert_samples(&engine.render(10), 120);
+        assert_track_time(&engine, 1300);
+
+        engine.render(30);
+        assert_track_time(&engine, 600);
+    }
+
+    #[test]
+    fn seek_decoded_track() {
+        let mut data = std::io::Cursor::new(Vec::new());
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 100,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+        for sample in ramp() {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let engine = offline_engine();
+        let track = Track::new(data.into_inner()).unwrap();
+        engine
+            .play_decoded_track(track, Duration::from_millis(300))
+            .unwrap();
+        assert_samples(&engine.render(20), 30);
+        assert_track_time(&engine, 500);
+
+        engine.seek(Duration::from_millis(1200)).unwrap();
+        assert_track_time(&engine, 1200);
+        assert_samples(&engine.render(10), 120);
+        assert_track_time(&engine, 1300);
+    }
+
+    #[test]
+    fn play_sample_at_timestamp() {
+        let engine = offline_engine();
+        let mut bank = engine.new_sample_bank();
+        let id = bank.add(SamplesBuffer::new(1, 100, vec![1f32; 5]));
+        engine.set_sample_bank(bank);
+
+        engine.play_track(rodio::source::Zero::<f32>::new(1, 100));
+        engine.play_sample_at(id, Duration::from_millis(200));
+
+        let samples = engine.render(30);
+        let expected: Vec<f32> = (0..30)
+            .map(|i| if (20..25).contains(&i) { 1. } else { 0. })
+            .collect();
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn replaced_values_are_dropped_outside_audio_thread() {
+        let engine = offline_engine();
+        engine.play_track(ramp());
+        engine.set_sample_bank(engine.new_sample_bank());
+        engine.render(1);
+
+        // The initial silence and the initial sample bank were replaced.
+        assert_eq!(engine.garbage_receiver.len(), 2);
+        engine.track_time();
+        assert!(engine.garbage_receiver.is_empty());
+    }
+
+    #[test]
+    fn render_to_wav() {
+        let engine = offline_engine();
+        engine.play_track(ramp());
+
+        let path = std::env::temp_dir().join("plitki-audio-render-to-wav.wav");
+        engine
+            .render_to_wav(&path, Duration::from_millis(500))
+            .unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 1);
+        assert_eq!(reader.spec().sample_rate, 100);
+        let samples: Vec<f32> = reader.samples().map(Result::unwrap).collect();
+        assert_samples(&samples, 0);
+        assert_eq!(samples.len(), 50);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn seeked_section() {
+        let section = TrackSection::WHOLE_TRACK.seeked(Duration::from_secs(3));
+        assert_eq!(section.track_time(Duration::ZERO), Duration::from_secs(3));
+        assert_eq!(
+            section.track_time(Duration::from_secs(2)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn seeked_loop_wraps_around() {
+        let section = TrackSection {
+            start: Duration::from_secs(10),
+            looped: Some((Duration::from_secs(10), Duration::from_secs(4))),
+        };
+        assert_eq!(
+            section.track_time(Duration::from_secs(5)),
+            Duration::from_secs(11)
+        );
+
+        let section = section.seeked(Duration::from_secs(16));
+        assert_eq!(section.start, Duration::from_secs(12));
+        assert_eq!(
+            section.track_time(Duration::from_secs(3)),
+            Duration::from_secs(11)
+        );
+
+        let section = section.seeked(Duration::from_secs(1));
+        assert_eq!(section.start, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn looped_samples_seek() {
+        let mut samples = LoopedSamples {
+            samples: (0..8).map(|i| i as f32).collect(),
+            position: 0,
+            start: Duration::from_secs(1),
+            channels: 2,
+            sample_rate: 2,
+        };
+
+        samples.seek(Duration::from_millis(2500));
+        assert_eq!(samples.next(), Some(6.));
+
+        samples.seek(Duration::from_millis(3500));
+        assert_eq!(samples.next(), Some(2.));
+    }
 }
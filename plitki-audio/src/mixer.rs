@@ -0,0 +1,353 @@
+//! Hitsound and keysound mixing.
+
+use std::mem;
+use std::time::Duration;
+
+use crossbeam_channel::Receiver;
+use rodio::source::UniformSourceIterator;
+use rodio::{Sample, Source};
+
+/// Maximum number of samples playing at once.
+///
+/// When all voices are busy, a new sample replaces the one that started the earliest.
+pub const MAX_VOICES: usize = 64;
+
+/// Default maximum number of instances of one sample playing at once.
+pub const DEFAULT_POLYPHONY: usize = 4;
+
+/// Maximum number of triggered samples waiting for their play timestamp.
+///
+/// Triggers beyond this are dropped.
+pub(crate) const MAX_PENDING: usize = 256;
+
+/// Identifier of a sample in a [`SampleBank`].
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct SampleId(usize);
+
+/// Set of short samples, such as hitsounds and keysounds, ready to be played.
+///
+/// Samples are decoded and converted to the output sample rate and channel count when they are
+/// added, so that playing them costs nothing but mixing. Create a bank with
+/// [`AudioEngine::new_sample_bank()`](crate::AudioEngine::new_sample_bank) and hand it over with
+/// [`AudioEngine::set_sample_bank()`](crate::AudioEngine::set_sample_bank).
+pub struct SampleBank {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<BankSample>,
+}
+
+/// A sample in a [`SampleBank`].
+struct BankSample {
+    /// Samples, interleaved.
+    samples: Vec<f32>,
+    /// Volume multiplier.
+    volume: f32,
+    /// Maximum number of instances playing at once.
+    polyphony: usize,
+}
+
+impl std::fmt::Debug for SampleBank {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SampleBank")
+            .field("len", &self.samples.len())
+            .finish()
+    }
+}
+
+impl SampleBank {
+    pub(crate) fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels,
+            sample_rate,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Decodes `source` and adds it to the bank.
+    ///
+    /// The sample plays at full volume with up to [`DEFAULT_POLYPHONY`] instances at once.
+    pub fn add(&mut self, source: impl Source<Item = impl Sample + Send> + Send) -> SampleId {
+        let samples = UniformSourceIterator::new(source, self.channels, self.sample_rate).collect();
+
+        self.samples.push(BankSample {
+            samples,
+            volume: 1.,
+            polyphony: DEFAULT_POLYPHONY,
+        });
+        SampleId(self.samples.len() - 1)
+    }
+
+    /// Sets the volume of the sample.
+    ///
+    /// Volume is a multiplier for all samples, so 1. is 100% and 0. is 0%.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not from this bank.
+    pub fn set_volume(&mut self, id: SampleId, volume: f32) {
+        self.samples[id.0].volume = volume;
+    }
+
+    /// Sets the maximum number of instances of the sample playing at once.
+    ///
+    /// Playing the sample once more stops its instance that started the earliest. The polyphony is
+    /// at least 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not from this bank.
+    pub fn set_polyphony(&mut self, id: SampleId, polyphony: usize) {
+        self.samples[id.0].polyphony = polyphony.max(1);
+    }
+
+    /// Returns the number of samples in the bank.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if the bank has no samples.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+/// Request to play a sample, sent to the audio thread.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Trigger {
+    pub sample: SampleId,
+    /// Time since the start of the track section where the sample should start playing.
+    ///
+    /// `None` means as soon as possible.
+    pub at: Option<Duration>,
+    /// Identifier of the track `at` refers to.
+    pub track_id: usize,
+}
+
+/// A playing sample.
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    sample: usize,
+    /// Index of the next sample to play.
+    position: usize,
+}
+
+/// Mixer of the samples from a [`SampleBank`].
+///
+/// All buffers are allocated up front, so the mixer can run on the audio thread.
+pub(crate) struct Mixer {
+    sample_rate: u32,
+    bank: Box<SampleBank>,
+    /// Triggers waiting for their play timestamp.
+    pending: Vec<Trigger>,
+    /// Playing samples, in the order they started.
+    voices: Vec<Voice>,
+    /// Mixed output frame.
+    frame: Vec<f32>,
+    receiver: Receiver<Trigger>,
+}
+
+impl Mixer {
+    pub fn new(channels: u16, sample_rate: u32, receiver: Receiver<Trigger>) -> Self {
+        Self {
+            sample_rate,
+            bank: Box::new(SampleBank::new(channels, sample_rate)),
+            pending: Vec::with_capacity(MAX_PENDING),
+            voices: Vec::with_capacity(MAX_VOICES),
+            frame: vec![0.; usize::from(channels)],
+            receiver,
+        }
+    }
+
+    /// Replaces the sample bank, stopping all samples.
+    ///
+    /// Returns the old bank, which shouldn't be dropped on the audio thread.
+    pub fn set_bank(&mut self, bank: Box<SampleBank>) -> Box<SampleBank> {
+        self.voices.clear();
+        self.pending.clear();
+        mem::replace(&mut self.bank, bank)
+    }
+
+    /// Receives the triggers, dropping the ones for tracks before `track_id`.
+    pub fn receive_triggers(&mut self, track_id: usize) {
+        self.pending.retain(|trigger| trigger.track_id >= track_id);
+
+        for trigger in self.receiver.try_iter() {
+            if trigger.at.is_some() && trigger.track_id < track_id {
+                continue;
+            }
+
+            if self.pending.len() == MAX_PENDING {
+                warn!("too many pending samples, dropping");
+                continue;
+            }
+            self.pending.push(trigger);
+        }
+    }
+
+    /// Returns the next mixed frame.
+    ///
+    /// `position` is the time since the start of the track section of the frame, in frames, and
+    /// `track_id` is the identifier of the track.
+    pub fn next_frame(&mut self, position: f64, track_id: usize) -> &[f32] {
+        if !self.pending.is_empty() {
+            self.start_voices(position, track_id);
+        }
+
+        self.frame.fill(0.);
+
+        let channels = self.frame.len();
+        let bank = &self.bank.samples;
+        self.voices.retain_mut(|voice| {
+            let sample = &bank[voice.sample];
+            let end = (voice.position + channels).min(sample.samples.len());
+            let samples = &sample.samples[voice.position..end];
+            for (out, value) in self.frame.iter_mut().zip(samples) {
+                *out += value * sample.volume;
+            }
+            voice.position = end;
+            end < sample.samples.len()
+        });
+
+        &self.frame
+    }
+
+    /// Starts the pending samples whose time has come.
+    fn start_voices(&mut self, position: f64, track_id: usize) {
+        let sample_rate = f64::from(self.sample_rate);
+        let mut i = 0;
+        while i < self.pending.len() {
+            let trigger = self.pending[i];
+            let due = match trigger.at {
+                None => true,
+                Some(at) => {
+                    trigger.track_id == track_id && at.as_secs_f64() * sample_rate <= position
+                }
+            };
+            if !due {
+                i += 1;
+                continue;
+            }
+
+            self.pending.swap_remove(i);
+            self.start_voice(trigger.sample);
+        }
+    }
+
+    /// Starts playing the sample, stopping older voices to stay within the polyphony limits.
+    fn start_voice(&mut self, SampleId(sample): SampleId) {
+        let Some(bank_sample) = self.bank.samples.get(sample) else {
+            warn!("tried to play a sample which is not in the bank");
+            return;
+        };
+        if bank_sample.samples.is_empty() {
+            return;
+        }
+
+        let playing = self.voices.iter().filter(|voice| voice.sample == sample);
+        let stopped = if playing.count() >= bank_sample.polyphony {
+            self.voices.iter().position(|voice| voice.sample == sample)
+        } else if self.voices.len() == MAX_VOICES {
+            Some(0)
+        } else {
+            None
+        };
+        if let Some(index) = stopped {
+            self.voices.remove(index);
+        }
+
+        self.voices.push(Voice {
+            sample,
+            position: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixer() -> (Mixer, crossbeam_channel::Sender<Trigger>) {
+        let (sender, receiver) = crossbeam_channel::bounded(MAX_PENDING);
+        let mut mixer = Mixer::new(1, 10, receiver);
+
+        let mut bank = SampleBank::new(1, 10);
+        bank.add(rodio::buffer::SamplesBuffer::new(1, 10, vec![1f32, 2., 3.]));
+        let id = bank.add(rodio::buffer::SamplesBuffer::new(1, 10, vec![10f32; 10]));
+        bank.set_volume(id, 0.5);
+        bank.set_polyphony(id, 2);
+        mixer.set_bank(Box::new(bank));
+
+        (mixer, sender)
+    }
+
+    fn play(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| mixer.next_frame(i as f64, 0)[0])
+            .collect()
+    }
+
+    #[test]
+    fn plays_at_timestamp() {
+        let (mut mixer, sender) = mixer();
+        sender
+            .send(Trigger {
+                sample: SampleId(0),
+                at: Some(Duration::from_millis(200)),
+                track_id: 0,
+            })
+            .unwrap();
+        mixer.receive_triggers(0);
+
+        assert_eq!(play(&mut mixer, 6), [0., 0., 1., 2., 3., 0.]);
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn mixes_with_volume() {
+        let (mut mixer, sender) = mixer();
+        for sample in [SampleId(0), SampleId(1)] {
+            let trigger = Trigger {
+                sample,
+                at: None,
+                track_id: 0,
+            };
+            sender.send(trigger).unwrap();
+        }
+        mixer.receive_triggers(0);
+
+        assert_eq!(play(&mut mixer, 4), [6., 7., 8., 5.]);
+    }
+
+    #[test]
+    fn polyphony_limit() {
+        let (mut mixer, sender) = mixer();
+        let trigger = Trigger {
+            sample: SampleId(1),
+            at: None,
+            track_id: 0,
+        };
+        for _ in 0..3 {
+            sender.send(trigger).unwrap();
+            mixer.receive_triggers(0);
+            mixer.next_frame(0., 0);
+        }
+
+        assert_eq!(mixer.voices.len(), 2);
+        assert_eq!(mixer.voices[0].position, 2);
+    }
+
+    #[test]
+    fn drops_stale_triggers() {
+        let (mut mixer, sender) = mixer();
+        sender
+            .send(Trigger {
+                sample: SampleId(0),
+                at: Some(Duration::ZERO),
+                track_id: 0,
+            })
+            .unwrap();
+        mixer.receive_triggers(1);
+
+        assert_eq!(play(&mut mixer, 3), [0., 0., 0.]);
+    }
+}
@@ -0,0 +1,404 @@
+//! Playback rate change.
+
+use std::f32::consts::PI;
+
+/// Lowest supported playback rate.
+pub const MIN_RATE: f64 = 0.5;
+
+/// Highest supported playback rate.
+pub const MAX_RATE: f64 = 2.0;
+
+/// How changing the playback rate affects the pitch.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Default)]
+pub enum Pitch {
+    /// Keep the pitch by stretching the audio in time.
+    #[default]
+    Keep,
+    /// Change the pitch together with the rate, like speeding up a record.
+    Change,
+}
+
+/// Length of a block of output, in seconds.
+///
+/// With [`Pitch::Keep`], output is produced one block at a time by crossfading the continuation of
+/// the previous input segment into the next one over the whole block.
+const BLOCK_LENGTH: f64 = 0.015;
+
+/// Distance between the coarse search candidates, in frames.
+const COARSE_STEP: usize = 4;
+
+/// Playback rate changer.
+///
+/// Keeps the pitch with WSOLA (waveform similarity overlap-add): every block the next input segment
+/// is picked close to the nominal track position, where it best matches the natural continuation of
+/// the previous segment, and the two are crossfaded. Changes the pitch by resampling with linear
+/// interpolation.
+///
+/// All buffers are allocated up front, so the stretcher can run on the audio thread.
+pub(crate) struct Stretcher {
+    channels: usize,
+    /// Number of frames in a block.
+    block: usize,
+    /// Maximum distance from the nominal position to the next segment, in frames.
+    search: usize,
+    rate: f64,
+    pitch: Pitch,
+    /// Buffered input samples, interleaved.
+    input: Vec<f32>,
+    /// Index of the first frame of `input` in the track.
+    input_start: usize,
+    /// Track position of the next output frame, in frames.
+    position: f64,
+    /// Output samples of the current block, interleaved.
+    ///
+    /// With [`Pitch::Change`], holds just the last output frame.
+    output: Vec<f32>,
+    /// Index of the next frame to output from the current block.
+    output_index: usize,
+    /// Rate the current block was produced at.
+    block_rate: f64,
+    /// Index of the track frame continuing the previous segment, where the next block starts.
+    continuation: usize,
+    /// Fade-in gain for every frame of a block.
+    fade: Vec<f32>,
+}
+
+impl Stretcher {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = usize::from(channels);
+        let block = ((f64::from(sample_rate) * BLOCK_LENGTH) as usize).max(COARSE_STEP);
+        let search = block / 2;
+
+        let fade = (0..block)
+            .map(|i| 0.5 - 0.5 * (PI * i as f32 / block as f32).cos())
+            .collect();
+
+        Self {
+            channels,
+            block,
+            search,
+            rate: 1.,
+            pitch: Pitch::Keep,
+            // The buffered input spans at most 4 blocks, see next_block().
+            input: Vec::with_capacity(block * 8 * channels),
+            input_start: 0,
+            position: 0.,
+            output: vec![0.; block * channels],
+            output_index: block,
+            block_rate: 1.,
+            continuation: 0,
+            fade,
+        }
+    }
+
+    /// Resets the stretcher to the start of a new track.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.input_start = 0;
+        self.position = 0.;
+        self.output_index = self.block;
+        self.continuation = 0;
+    }
+
+    /// Returns the track position of the next output frame, in frames.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Sets the playback rate, clamped to [`MIN_RATE`]..=[`MAX_RATE`].
+    pub fn set_rate(&mut self, rate: f64, pitch: Pitch) {
+        self.rate = rate.clamp(MIN_RATE, MAX_RATE);
+
+        if pitch != self.pitch {
+            self.pitch = pitch;
+
+            // Start a new block from the current position.
+            self.output_index = self.block;
+            self.continuation = self.position.round() as usize;
+        }
+    }
+
+    /// Returns the next output frame, pulling the input from `source`.
+    ///
+    /// `source` is treated as silence once it runs out.
+    pub fn next_frame(&mut self, source: &mut impl Iterator<Item = f32>) -> &[f32] {
+        let channels = self.channels;
+
+        match self.pitch {
+            Pitch::Keep => {
+                if self.output_index == self.block {
+                    self.next_block(source);
+                }
+
+                let index = self.output_index;
+                self.output_index += 1;
+                self.position += self.block_rate;
+                &self.output[index * channels..(index + 1) * channels]
+            }
+            Pitch::Change => {
+                let index = self.position as usize;
+                let t = (self.position - index as f64) as f32;
+
+                self.discard(index);
+                self.fill(source, index + 2);
+
+                let from = (index - self.input_start) * channels;
+                for c in 0..channels {
+                    let a = self.input[from + c];
+                    let b = self.input[from + channels + c];
+                    self.output[c] = a + (b - a) * t;
+                }
+
+                self.position += self.rate;
+                &self.output[..channels]
+            }
+        }
+    }
+
+    /// Produces the next block of output with WSOLA.
+    fn next_block(&mut self, source: &mut impl Iterator<Item = f32>) {
+        let channels = self.channels;
+        let block = self.block;
+
+        let nominal = self.position.round() as usize;
+        let lowest = nominal.saturating_sub(self.search).max(self.input_start);
+        let highest = nominal + self.search;
+
+        // The continuation is at most a search distance plus a block away from the nominal
+        // position, so the buffered input spans at most 4 blocks.
+        self.discard(self.continuation.min(lowest));
+        self.fill(source, (self.continuation + block).max(highest + block));
+
+        let start = if self.rate == 1. && nominal == self.continuation {
+            nominal
+        } else {
+            self.best_segment(nominal, lowest, highest)
+        };
+
+        let from = (self.continuation - self.input_start) * channels;
+        let to = (start - self.input_start) * channels;
+        for (i, &fade) in self.fade.iter().enumerate() {
+            for c in 0..channels {
+                let a = self.input[from + i * channels + c];
+                let b = self.input[to + i * channels + c];
+                self.output[i * channels + c] = a + (b - a) * fade;
+            }
+        }
+
+        self.continuation = start + block;
+        self.output_index = 0;
+        self.block_rate = self.rate;
+    }
+
+    /// Returns the start of the segment between `lowest` and `highest` which best continues the
+    /// previous segment, preferring the ones closer to `nominal`.
+    fn best_segment(&self, nominal: usize, lowest: usize, highest: usize) -> usize {
+        let around = |center: usize, step: usize, distance: usize| {
+            (step..=distance)
+                .step_by(step)
+                .flat_map(move |d| [center.checked_sub(d), Some(center + d)])
+                .flatten()
+                .filter(move |&candidate| (lowest..=highest).contains(&candidate))
+        };
+
+        let coarse = around(nominal, COARSE_STEP, self.search);
+        let best = self.most_similar(nominal, coarse, COARSE_STEP);
+
+        // Refine around the best coarse candidate.
+        let fine = around(best, 1, COARSE_STEP - 1);
+        self.most_similar(best, fine, 1)
+    }
+
+    /// Returns the candidate most similar to the continuation of the previous segment, starting
+    /// with `first` and preferring the earlier ones.
+    fn most_similar(
+        &self,
+        first: usize,
+        candidates: impl Iterator<Item = usize>,
+        stride: usize,
+    ) -> usize {
+        let mut best = first;
+        let mut best_similarity = self.similarity(first, stride);
+
+        for candidate in candidates {
+            let similarity = self.similarity(candidate, stride);
+            if similarity > best_similarity {
+                best = candidate;
+                best_similarity = similarity;
+            }
+        }
+
+        best
+    }
+
+    /// Returns the normalized cross-correlation of the segment starting at `candidate` with the
+    /// continuation of the previous segment, computed over every `stride`th frame.
+    fn similarity(&self, candidate: usize, stride: usize) -> f32 {
+        let channels = self.channels;
+        let target = (self.continuation - self.input_start) * channels;
+        let candidate = (candidate - self.input_start) * channels;
+
+        let mut product = 0.;
+        let mut energy = 0.;
+        for i in (0..self.block).step_by(stride) {
+            let x: f32 = self.input[target + i * channels..][..channels].iter().sum();
+            let y: f32 = self.input[candidate + i * channels..][..channels]
+                .iter()
+                .sum();
+            product += x * y;
+            energy += y * y;
+        }
+
+        if energy > 0. {
+            product / energy.sqrt()
+        } else {
+            0.
+        }
+    }
+
+    /// Buffers the input up to the frame at `end`.
+    fn fill(&mut self, source: &mut impl Iterator<Item = f32>, end: usize) {
+        while self.input_start + self.input.len() / self.channels < end {
+            for _ in 0..self.channels {
+                self.input.push(source.next().unwrap_or(0.));
+            }
+        }
+    }
+
+    /// Drops the buffered input before the frame at `start`.
+    fn discard(&mut self, start: usize) {
+        let buffered = self.input.len() / self.channels;
+        let count = start.saturating_sub(self.input_start).min(buffered);
+        self.input.drain(..count * self.channels);
+        self.input_start += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Returns one second of a 441 Hz sine wave in stereo.
+    fn sine() -> impl Iterator<Item = f32> + Clone {
+        (0..SAMPLE_RATE as usize * 2).map(|i| {
+            let t = (i / 2) as f32 / SAMPLE_RATE as f32;
+            (t * 441. * 2. * PI).sin()
+        })
+    }
+
+    /// Plays `source` at `rate` and returns the number of zero crossings in the left channel,
+    /// along with the final position.
+    fn play(
+        source: impl Iterator<Item = f32>,
+        rate: f64,
+        pitch: Pitch,
+        frames: usize,
+    ) -> (usize, f64) {
+        let mut source = source;
+        let mut stretcher = Stretcher::new(2, SAMPLE_RATE);
+        stretcher.set_rate(rate, pitch);
+
+        let mut crossings = 0;
+        let mut last = 0.;
+        for _ in 0..frames {
+            let sample = stretcher.next_frame(&mut source)[0];
+            if (last < 0.) != (sample < 0.) {
+                crossings += 1;
+            }
+            last = sample;
+        }
+
+        (crossings, stretcher.position())
+    }
+
+    #[test]
+    fn unchanged_rate_keeps_samples() {
+        for pitch in [Pitch::Keep, Pitch::Change] {
+            let mut source = sine();
+            let mut stretcher = Stretcher::new(2, SAMPLE_RATE);
+            stretcher.set_rate(1., pitch);
+
+            for expected in sine().step_by(2) {
+                let frame = stretcher.next_frame(&mut source);
+                assert!((frame[0] - expected).abs() < 1e-6);
+                assert_eq!(frame[0], frame[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn change_pitch_resamples() {
+        let ramp = (0..100).flat_map(|i| [i as f32, -i as f32]);
+        let mut source = ramp;
+        let mut stretcher = Stretcher::new(2, SAMPLE_RATE);
+        stretcher.set_rate(1.5, Pitch::Change);
+
+        assert_eq!(stretcher.next_frame(&mut source), [0., 0.]);
+        assert_eq!(stretcher.next_frame(&mut source), [1.5, -1.5]);
+        assert_eq!(stretcher.next_frame(&mut source), [3., -3.]);
+        assert_eq!(stretcher.position(), 4.5);
+    }
+
+    #[test]
+    fn rate_changes_position() {
+        let frames = SAMPLE_RATE as usize / 2;
+        for pitch in [Pitch::Keep, Pitch::Change] {
+            let (_, position) = play(sine(), 1.3, pitch, frames);
+            assert!((position - frames as f64 * 1.3).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn keep_pitch() {
+        let frames = SAMPLE_RATE as usize / 2;
+
+        // Half a second of 441 Hz has 441 zero crossings.
+        let (crossings, _) = play(sine(), 1.5, Pitch::Keep, frames);
+        assert!((430..=450).contains(&crossings), "{crossings}");
+
+        // Nightcore.
+        let (crossings, _) = play(sine(), 1.5, Pitch::Change, frames);
+        assert!((650..=670).contains(&crossings), "{crossings}");
+    }
+
+    #[test]
+    fn rate_is_clamped() {
+        let mut stretcher = Stretcher::new(2, SAMPLE_RATE);
+        stretcher.set_rate(10., Pitch::Keep);
+        assert_eq!(stretcher.rate(), MAX_RATE);
+        stretcher.set_rate(0., Pitch::Keep);
+        assert_eq!(stretcher.rate(), MIN_RATE);
+    }
+
+    #[test]
+    fn input_buffer_doesnt_grow() {
+        let mut source = sine().cycle();
+        let mut stretcher = Stretcher::new(2, SAMPLE_RATE);
+        let capacity = stretcher.input.capacity();
+
+        for (i, rate) in [0.5, 2., 0.7, 1.9, 1.]
+            .into_iter()
+            .cycle()
+            .take(20)
+            .enumerate()
+        {
+            let pitch = if i % 3 == 0 {
+                Pitch::Change
+            } else {
+                Pitch::Keep
+            };
+            stretcher.set_rate(rate, pitch);
+            for _ in 0..SAMPLE_RATE / 10 {
+                stretcher.next_frame(&mut source);
+            }
+        }
+
+        assert_eq!(stretcher.input.capacity(), capacity);
+    }
+}
@@ -0,0 +1,399 @@
+//! Seekable tracks.
+
+use std::io::{self, Cursor};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{error, fmt, mem};
+
+use rodio::Source;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::TimeBase;
+
+/// Number of consecutive packets which may fail to decode before giving up.
+const MAX_DECODE_ERRORS: usize = 3;
+
+/// Error returned when a [`Track`] can't be decoded or can't seek.
+#[derive(Debug)]
+pub struct TrackError(SymphoniaError);
+
+impl fmt::Display for TrackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error decoding the track: {}", self.0)
+    }
+}
+
+impl error::Error for TrackError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<SymphoniaError> for TrackError {
+    #[inline]
+    fn from(err: SymphoniaError) -> Self {
+        Self(err)
+    }
+}
+
+/// An audio track which can seek.
+///
+/// Seeking uses the demuxer's seek index, and then decodes from the closest packet, rather than
+/// decoding the whole track from the start.
+pub struct Track {
+    /// Contents of the audio file.
+    data: Arc<[u8]>,
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    /// Identifier of the decoded track in `format`.
+    track_id: u32,
+    /// Time base of the track timestamps.
+    time_base: Option<TimeBase>,
+    channels: u16,
+    sample_rate: u32,
+    /// Decoded samples of the current packet, interleaved.
+    buffer: Option<SampleBuffer<f32>>,
+    /// Index of the next sample in `buffer`.
+    index: usize,
+    /// Number of decoded samples to drop to land exactly on the seek position.
+    skip: usize,
+    /// Number of silent samples left to play before the decoded samples.
+    silence: usize,
+    /// Length of the silence before the track.
+    leading_silence: Duration,
+}
+
+impl Track {
+    /// Creates a new `Track` from the contents of an audio file.
+    pub fn new(data: Vec<u8>) -> Result<Self, TrackError> {
+        Self::open(Arc::from(data))
+    }
+
+    fn open(data: Arc<[u8]>) -> Result<Self, TrackError> {
+        let source =
+            MediaSourceStream::new(Box::new(Cursor::new(data.clone())), Default::default());
+        let probed = symphonia::default::get_probe().format(
+            &Hint::new(),
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+
+        let format = probed.format;
+        let track = format
+            .default_track()
+            .ok_or(SymphoniaError::Unsupported("no audio track"))?;
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut track = Self {
+            data,
+            format,
+            decoder,
+            track_id,
+            time_base,
+            channels: 0,
+            sample_rate: 0,
+            buffer: None,
+            index: 0,
+            skip: 0,
+            silence: 0,
+            leading_silence: Duration::ZERO,
+        };
+
+        // Decode the first packet to find out the channel count and sample rate.
+        if !track.decode_packet()? {
+            return Err(SymphoniaError::Unsupported("empty audio track").into());
+        }
+
+        Ok(track)
+    }
+
+    /// Returns the track with `duration` of silence added before it.
+    ///
+    /// The silence is a part of the track as far as [`Track::seek()`] is concerned.
+    pub fn with_leading_silence(mut self, duration: Duration) -> Self {
+        self.silence = self.sample_count(duration);
+        self.leading_silence = duration;
+        self
+    }
+
+    /// Returns the data to decode this track again.
+    pub(crate) fn data(&self) -> TrackData {
+        TrackData {
+            data: self.data.clone(),
+            leading_silence: self.leading_silence,
+        }
+    }
+
+    /// Seeks to `position` from the start of the track.
+    pub fn seek(&mut self, position: Duration) -> Result<(), TrackError> {
+        let silence = self.leading_silence.saturating_sub(position);
+        let position = position.saturating_sub(self.leading_silence);
+
+        let seeked_to = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: position.into(),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+
+        // The accurate seek lands on a packet before the position, skip the rest.
+        let skip = seeked_to.required_ts.saturating_sub(seeked_to.actual_ts);
+        self.skip = match self.time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(skip);
+                self.sample_count(Duration::from_secs_f64(time.seconds as f64 + time.frac))
+            }
+            None => skip as usize * usize::from(self.channels),
+        };
+        self.silence = self.sample_count(silence);
+        self.index = self.samples().len();
+
+        Ok(())
+    }
+
+    /// Returns the number of samples in the given `duration`, rounded to whole frames.
+    fn sample_count(&self, duration: Duration) -> usize {
+        let frames = (duration.as_secs_f64() * f64::from(self.sample_rate)).round();
+        frames as usize * usize::from(self.channels)
+    }
+
+    /// Returns the decoded samples of the current packet.
+    fn samples(&self) -> &[f32] {
+        self.buffer.as_ref().map_or(&[], |buffer| buffer.samples())
+    }
+
+    /// Decodes the next packet into `buffer`.
+    ///
+    /// Returns `false` at the end of the track.
+    fn decode_packet(&mut self) -> Result<bool, SymphoniaError> {
+        let mut errors = 0;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(false);
+                }
+                Err(err) => return Err(err),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(err @ SymphoniaError::DecodeError(_)) => {
+                    errors += 1;
+                    if errors > MAX_DECODE_ERRORS {
+                        return Err(err);
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            let spec = *decoded.spec();
+            if self.channels == 0 {
+                self.channels = spec.channels.count() as u16;
+                self.sample_rate = spec.rate;
+            }
+
+            let capacity = decoded.capacity() * spec.channels.count();
+            let buffer = match &mut self.buffer {
+                Some(buffer) if buffer.capacity() >= capacity => buffer,
+                buffer => buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+            };
+            buffer.copy_interleaved_ref(decoded);
+
+            self.index = self.skip.min(buffer.len());
+            self.skip -= self.index;
+            return Ok(true);
+        }
+    }
+}
+
+/// Data to decode a [`Track`] again, sharing the contents of the audio file.
+#[derive(Clone)]
+pub(crate) struct TrackData {
+    data: Arc<[u8]>,
+    leading_silence: Duration,
+}
+
+impl TrackData {
+    /// Decodes the track from the start.
+    pub fn open(&self) -> Result<Track, TrackError> {
+        Ok(Track::open(self.data.clone())?.with_leading_silence(self.leading_silence))
+    }
+}
+
+impl Iterator for Track {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0.);
+        }
+
+        while self.index == self.samples().len() {
+            match self.decode_packet() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(err) => {
+                    error!("error decoding track: {err}");
+                    return None;
+                }
+            }
+        }
+
+        let sample = self.samples()[self.index];
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+impl Source for Track {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Converts a [`Track`] to the channel count and sample rate of the output stream.
+///
+/// Unlike rodio's `UniformSourceIterator`, keeps the track accessible, so it can seek.
+pub(crate) struct ConvertedTrack {
+    track: Track,
+    /// Number of track frames per output frame.
+    step: f64,
+    /// Position between the `from` and the `to` frames, from 0 to 1.
+    t: f64,
+    /// Track frame before the position.
+    from: Vec<f32>,
+    /// Track frame after the position.
+    to: Vec<f32>,
+    /// Whether the track ran out of frames for `to`.
+    ended: bool,
+    /// Output frame, interleaved.
+    frame: Vec<f32>,
+    /// Index of the next sample in `frame`.
+    index: usize,
+}
+
+impl ConvertedTrack {
+    pub fn new(track: Track, channels: u16, sample_rate: u32) -> Self {
+        let track_channels = usize::from(track.channels);
+        let mut converted = Self {
+            step: f64::from(track.sample_rate) / f64::from(sample_rate),
+            track,
+            t: 0.,
+            from: vec![0.; track_channels],
+            to: vec![0.; track_channels],
+            ended: false,
+            frame: vec![0.; usize::from(channels)],
+            index: 0,
+        };
+        converted.reset();
+        converted
+    }
+
+    /// Seeks the track to `position`.
+    pub fn seek(&mut self, position: Duration) -> Result<(), TrackError> {
+        self.track.seek(position)?;
+        self.reset();
+        Ok(())
+    }
+
+    /// Starts the conversion over from the current track position.
+    fn reset(&mut self) {
+        self.t = 0.;
+        self.index = self.frame.len();
+
+        if read_frame(&mut self.track, &mut self.from) {
+            self.ended = !read_frame(&mut self.track, &mut self.to);
+        } else {
+            // Nothing left to play.
+            self.t = 1.;
+            self.ended = true;
+        }
+    }
+
+    /// Computes the next output frame, returns `false` at the end of the track.
+    fn next_frame(&mut self) -> bool {
+        while self.t >= 1. {
+            if self.ended {
+                return false;
+            }
+
+            self.t -= 1.;
+            mem::swap(&mut self.from, &mut self.to);
+            self.ended = !read_frame(&mut self.track, &mut self.to);
+        }
+
+        // Extra output channels repeat the last track channel, missing ones are dropped.
+        let last = self.from.len() - 1;
+        let t = self.t as f32;
+        for (c, sample) in self.frame.iter_mut().enumerate() {
+            let (a, b) = (self.from[c.min(last)], self.to[c.min(last)]);
+            *sample = a + (b - a) * t;
+        }
+
+        self.t += self.step;
+        true
+    }
+}
+
+/// Reads a frame from `track` into `frame`.
+///
+/// Returns `false` and fills `frame` with silence if the track ended.
+fn read_frame(track: &mut Track, frame: &mut [f32]) -> bool {
+    let mut read = 0;
+    for (sample, value) in frame.iter_mut().zip(&mut *track) {
+        *sample = value;
+        read += 1;
+    }
+
+    if read < frame.len() {
+        frame.fill(0.);
+        return false;
+    }
+    true
+}
+
+impl Iterator for ConvertedTrack {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index == self.frame.len() {
+            if !self.next_frame() {
+                return None;
+            }
+            self.index = 0;
+        }
+
+        let sample = self.frame[self.index];
+        self.index += 1;
+        Some(sample)
+    }
+}
@@ -0,0 +1,354 @@
+//! Automatic input generation.
+
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+
+use crate::{
+    object::Object,
+    replay::ReplayInput,
+    rng::SplitMix64,
+    state::GameState,
+    timing::{GameTimestamp, GameTimestampDifference, MapTimestamp},
+};
+
+/// How long regular objects are held, in <sup>1</sup>⁄<sub>100</sub>ths of a millisecond.
+///
+/// Objects closer together than twice this are held for half of the distance instead.
+const REGULAR_HOLD: i64 = 40_00;
+
+/// Input source which plays a map by itself.
+///
+/// Without jitter, every object is pressed and released exactly on time.
+///
+/// # Examples
+///
+/// ```
+/// # use plitki_core::map::{Lane, Map};
+/// # use plitki_core::scroll::ScrollSpeedMultiplier;
+/// # use plitki_core::state::GameState;
+/// # use plitki_core::judgement::HitWindows;
+/// # use plitki_core::timing::GameTimestamp;
+/// use plitki_core::autoplay::Autoplay;
+///
+/// # let map = Map {
+/// #     song_artist: None,
+/// #     song_title: None,
+/// #     difficulty_name: None,
+/// #     background_file: None,
+/// #     mapper: None,
+/// #     audio_file: None,
+/// #     timing_points: vec![],
+/// #     scroll_speed_changes: vec![],
+/// #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+/// #     scroll_groups: vec![],
+/// #     lanes: vec![Lane { objects: vec![] }],
+/// # };
+/// # let mut state = GameState::new(map, HitWindows::default()).unwrap();
+/// # let timestamp = GameTimestamp::from_millis(0);
+/// let mut autoplay = Autoplay::new(&state);
+///
+/// while let Some(input) = autoplay.next_input(timestamp) {
+///     while let Some(event) = state.update(input.timestamp) {
+///         // Handle event.
+///     }
+///
+///     if let Some(event) = input.apply(&mut state) {
+///         // Handle event.
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Autoplay {
+    /// Generated input, sorted by timestamp.
+    inputs: Vec<ReplayInput>,
+    /// Index of the next input to return.
+    next: usize,
+}
+
+impl Autoplay {
+    /// Creates a new `Autoplay` which hits every object in `state` perfectly.
+    ///
+    /// The input is generated for the current timestamp converter of `state`. Objects skipped with
+    /// [`GameState::seek()`] are left out.
+    #[inline]
+    pub fn new(state: &GameState) -> Self {
+        Self::generate(state, || 0)
+    }
+
+    /// Creates a new `Autoplay` which deviates every press and long note release by up to
+    /// `max_deviation` in either direction.
+    ///
+    /// The deviations come from a pseudorandom number generator initialized with `seed`, so the
+    /// same seed always produces the same input.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_deviation` is negative.
+    pub fn with_jitter(
+        state: &GameState,
+        max_deviation: GameTimestampDifference,
+        seed: u64,
+    ) -> Self {
+        let max_deviation = max_deviation.into_milli_hundredths();
+        assert!(max_deviation >= 0, "max_deviation must be non-negative");
+
+        let mut rng = SplitMix64(seed);
+        Self::generate(state, || rng.next_deviation(i64::from(max_deviation)))
+    }
+
+    fn generate(state: &GameState, mut deviation: impl FnMut() -> i64) -> Self {
+        let converter = &state.timestamp_converter;
+        let to_game = |timestamp: MapTimestamp| {
+            i64::from(timestamp.to_game(converter).into_milli_hundredths())
+        };
+        let to_timestamp = |value: i64| {
+            let value = value.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32;
+            GameTimestamp::saturating_from_milli_hundredths(value)
+        };
+
+        let mut inputs = Vec::new();
+
+        for (lane, (objects, lane_state)) in state
+            .immutable
+            .chart
+            .lanes
+            .iter()
+            .map(|lane| &lane.objects)
+            .zip(&state.lane_states)
+            .enumerate()
+        {
+            // Inputs in a lane must alternate between presses and releases.
+            let mut released_at = i64::MIN;
+
+            for (index, object) in objects.iter().enumerate() {
+                if lane_state.object_states[index].is_skipped() {
+                    continue;
+                }
+
+                let start = to_game(object.start_timestamp());
+                let press = max(start + deviation(), released_at);
+
+                let release = match *object {
+                    Object::Regular { .. } => {
+                        let hold = objects.get(index + 1).map_or(REGULAR_HOLD, |next| {
+                            min(REGULAR_HOLD, (to_game(next.start_timestamp()) - start) / 2)
+                        });
+                        press + max(hold, 0)
+                    }
+                    Object::LongNote { end, .. } => max(to_game(end) + deviation(), press),
+                };
+                released_at = release;
+
+                inputs.push(ReplayInput::press(lane, to_timestamp(press)));
+                inputs.push(ReplayInput::release(lane, to_timestamp(release)));
+            }
+        }
+
+        // The sort is stable, so presses stay before their releases.
+        inputs.sort_by_key(|input| input.timestamp);
+
+        Self { inputs, next: 0 }
+    }
+
+    /// Returns the next input if its timestamp is at or before `timestamp`.
+    ///
+    /// Call this in a loop until it returns `None`.
+    #[inline]
+    pub fn next_input(&mut self, timestamp: GameTimestamp) -> Option<ReplayInput> {
+        let input = *self.inputs.get(self.next)?;
+        if input.timestamp > timestamp {
+            return None;
+        }
+
+        self.next += 1;
+        Some(input)
+    }
+
+    /// Returns all generated input, sorted by timestamp.
+    #[inline]
+    pub fn inputs(&self) -> &[ReplayInput] {
+        &self.inputs
+    }
+
+    /// Returns `true` if all input has been returned.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.next == self.inputs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        judgement::{HitWindows, Judgement},
+        map::{ArbitraryMapType, Lane, Map},
+        replay::InputKind,
+        scroll::ScrollSpeedMultiplier,
+        state::{Event, EventKind},
+    };
+    use alloc::vec;
+    use proptest::prelude::*;
+
+    fn play(state: &mut GameState, mut autoplay: Autoplay) -> Vec<Event> {
+        let mut events = Vec::new();
+        while let Some(input) =
+            autoplay.next_input(GameTimestamp::from_milli_hundredths(2i32.pow(30) - 1))
+        {
+            while let Some(event) = state.update(input.timestamp) {
+                events.push(event);
+            }
+            events.extend(input.apply(state));
+        }
+        events
+    }
+
+    fn map() -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![
+                Lane {
+                    objects: vec![
+                        Object::Regular {
+                            timestamp: MapTimestamp::from_millis(1_000),
+                        },
+                        Object::Regular {
+                            timestamp: MapTimestamp::from_millis(1_010),
+                        },
+                        Object::LongNote {
+                            start: MapTimestamp::from_millis(1_500),
+                            end: MapTimestamp::from_millis(2_000),
+                        },
+                    ],
+                },
+                Lane {
+                    objects: vec![Object::Regular {
+                        timestamp: MapTimestamp::from_millis(1_000),
+                    }],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn autoplay_inputs() {
+        let state = GameState::new(map(), HitWindows::default()).unwrap();
+        let autoplay = Autoplay::new(&state);
+
+        let inputs: Vec<_> = autoplay
+            .inputs()
+            .iter()
+            .map(|input| (input.lane, input.kind, input.timestamp.as_millis()))
+            .collect();
+        assert_eq!(
+            inputs,
+            vec![
+                (0, InputKind::Press, 1_000),
+                (1, InputKind::Press, 1_000),
+                (0, InputKind::Release, 1_005),
+                (0, InputKind::Press, 1_010),
+                (1, InputKind::Release, 1_040),
+                (0, InputKind::Release, 1_050),
+                (0, InputKind::Press, 1_500),
+                (0, InputKind::Release, 2_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn autoplay_leaves_out_skipped_objects() {
+        let mut state = GameState::new(map(), HitWindows::default()).unwrap();
+        state.seek(GameTimestamp::from_millis(1_005));
+        let autoplay = Autoplay::new(&state);
+
+        let inputs: Vec<_> = autoplay
+            .inputs()
+            .iter()
+            .map(|input| (input.lane, input.kind, input.timestamp.as_millis()))
+            .collect();
+        assert_eq!(
+            inputs,
+            vec![
+                (0, InputKind::Press, 1_010),
+                (0, InputKind::Release, 1_050),
+                (0, InputKind::Press, 1_500),
+                (0, InputKind::Release, 2_000),
+            ]
+        );
+
+        for event in play(&mut state, autoplay) {
+            assert!(matches!(event.kind, EventKind::Hit(_)));
+        }
+    }
+
+    #[test]
+    fn autoplay_with_jitter_is_deterministic() {
+        let state = GameState::new(map(), HitWindows::default()).unwrap();
+        let max_deviation = GameTimestampDifference::from_millis(10);
+
+        let a = Autoplay::with_jitter(&state, max_deviation, 42);
+        let b = Autoplay::with_jitter(&state, max_deviation, 42);
+        assert_eq!(a, b);
+        assert_ne!(a, Autoplay::new(&state));
+
+        let mut state = state;
+        for event in play(&mut state, a) {
+            if let EventKind::Hit(hit) = event.kind {
+                assert!(hit.difference.into_milli_hundredths().abs() <= 10_00);
+            } else {
+                panic!("unexpected miss");
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn autoplay_hits_everything_perfectly(
+            map in any_with::<Map>(ArbitraryMapType::ValidWithObjects),
+        ) {
+            let mut state = GameState::new(map, HitWindows::quaver_standard()).unwrap();
+            let autoplay = Autoplay::new(&state);
+
+            for event in play(&mut state, autoplay) {
+                match event.kind {
+                    EventKind::Hit(hit) => prop_assert_eq!(hit.judgement, Judgement::Marvelous),
+                    EventKind::Miss | EventKind::Failed => {
+                        prop_assert!(false, "object was missed")
+                    }
+                }
+            }
+
+            for (lane, lane_state) in state.lane_states.iter().enumerate() {
+                prop_assert!(!state.has_active_objects(lane));
+                prop_assert!(lane_state.object_states.iter().all(|s| s.is_hit()));
+            }
+        }
+
+        #[test]
+        fn autoplay_with_jitter_doesnt_panic(
+            map in any_with::<Map>(ArbitraryMapType::ValidWithLanes),
+            max_deviation in 0..2i32.pow(30),
+            seed: u64,
+        ) {
+            let mut state = GameState::new(map, HitWindows::quaver_standard()).unwrap();
+            let max_deviation = GameTimestampDifference::from_milli_hundredths(max_deviation);
+            let autoplay = Autoplay::with_jitter(&state, max_deviation, seed);
+
+            prop_assert!(autoplay
+                .inputs()
+                .windows(2)
+                .all(|ab| ab[0].timestamp <= ab[1].timestamp));
+
+            play(&mut state, autoplay);
+        }
+    }
+}
@@ -0,0 +1,1030 @@
+//! Incremental chart editing with undo and redo.
+//!
+//! Edits are applied directly to a [`GameState`] and update only the caches they affect, which is
+//! much faster than creating a new [`GameState`] after every change:
+//!
+//! - object edits compute the caches and the snaps of the edited objects only,
+//! - scroll speed change edits recompute the positions of the objects and timing lines in the same
+//!   scroll group from the edited timestamp onwards,
+//! - timing point edits recompute only the timing lines and the snaps.
+//!
+//! The result is always the same as calling [`GameState::new()`] with the edited chart, which can
+//! be obtained with [`ImmutableGameState::to_chart()`](crate::state::ImmutableGameState::to_chart).
+//!
+//! # Examples
+//!
+//! ```
+//! # use plitki_core::map::{Lane, Map};
+//! # use plitki_core::scroll::ScrollSpeedMultiplier;
+//! # use plitki_core::state::GameState;
+//! # use plitki_core::judgement::HitWindows;
+//! use plitki_core::editor::{Edit, History};
+//! use plitki_core::object::Object;
+//! use plitki_core::timing::MapTimestamp;
+//!
+//! # let map = Map {
+//! #     song_artist: None,
+//! #     song_title: None,
+//! #     difficulty_name: None,
+//! #     background_file: None,
+//! #     mapper: None,
+//! #     audio_file: None,
+//! #     timing_points: vec![],
+//! #     scroll_speed_changes: vec![],
+//! #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+//! #     scroll_groups: vec![],
+//! #     lanes: vec![Lane { objects: vec![] }],
+//! # };
+//! let mut state = GameState::new(map, HitWindows::default()).unwrap();
+//! let mut history = History::new();
+//!
+//! let object = Object::Regular {
+//!     timestamp: MapTimestamp::from_millis(1_000),
+//! };
+//! history.apply(
+//!     &mut state,
+//!     Edit::InsertObject {
+//!         lane: 0,
+//!         object,
+//!         scroll_group: None,
+//!     },
+//! )?;
+//! assert_eq!(state.immutable.chart.lanes[0].objects, [object]);
+//!
+//! history.undo(&mut state);
+//! assert!(state.immutable.chart.lanes[0].objects.is_empty());
+//!
+//! history.redo(&mut state);
+//! assert_eq!(state.immutable.chart.lanes[0].objects, [object]);
+//! # Ok::<(), plitki_core::editor::EditError>(())
+//! ```
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::{
+    map::{
+        sort_and_dedup_scroll_speed_changes, sort_and_dedup_timing_points, Chart,
+        ScrollSpeedChange, TimingPoint,
+    },
+    object::Object,
+    scroll::Position,
+    snap::{ObjectSnap, Snap},
+    state::{GameState, ObjectCache, RegularObjectCache},
+    timing::MapTimestamp,
+};
+
+/// An edit of the chart.
+///
+/// Objects are referred to by their lane and index in
+/// [`ImmutableGameState::chart`](crate::state::ImmutableGameState::chart), where the objects of the
+/// scroll groups are in the same lanes as the rest of the objects. Scroll speed changes and timing
+/// points are referred to by their index.
+///
+/// Objects, scroll speed changes and timing points are kept sorted, so inserting or moving them can
+/// change the indices of the others. Scroll speed changes and timing points on the same timestamp
+/// as an inserted one are replaced, and scroll speed changes which don't change the multiplier are
+/// dropped, same as in [`GameState::new()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// Inserts an object.
+    InsertObject {
+        /// Lane to insert the object into.
+        lane: usize,
+        /// The object to insert.
+        object: Object,
+        /// Scroll group of the object, `None` means the chart's own scroll speed changes.
+        scroll_group: Option<usize>,
+    },
+    /// Removes an object.
+    RemoveObject {
+        /// Lane of the object.
+        lane: usize,
+        /// Index of the object in the lane.
+        index: usize,
+    },
+    /// Replaces an object with a different one, possibly in a different lane.
+    ///
+    /// The object stays in its scroll group.
+    MoveObject {
+        /// Lane of the object.
+        lane: usize,
+        /// Index of the object in the lane.
+        index: usize,
+        /// Lane to move the object to.
+        new_lane: usize,
+        /// The object with the new timestamps.
+        new_object: Object,
+    },
+    /// Inserts a scroll speed change.
+    InsertScrollSpeedChange {
+        /// Scroll group to insert into, `None` means the chart's own scroll speed changes.
+        scroll_group: Option<usize>,
+        /// The scroll speed change to insert.
+        change: ScrollSpeedChange,
+    },
+    /// Removes a scroll speed change.
+    RemoveScrollSpeedChange {
+        /// Scroll group of the scroll speed change, `None` means the chart's own scroll speed
+        /// changes.
+        scroll_group: Option<usize>,
+        /// Index of the scroll speed change.
+        index: usize,
+    },
+    /// Replaces a scroll speed change with a different one.
+    ModifyScrollSpeedChange {
+        /// Scroll group of the scroll speed change, `None` means the chart's own scroll speed
+        /// changes.
+        scroll_group: Option<usize>,
+        /// Index of the scroll speed change.
+        index: usize,
+        /// The new scroll speed change.
+        change: ScrollSpeedChange,
+    },
+    /// Inserts a timing point.
+    InsertTimingPoint {
+        /// The timing point to insert.
+        timing_point: TimingPoint,
+    },
+    /// Removes a timing point.
+    RemoveTimingPoint {
+        /// Index of the timing point.
+        index: usize,
+    },
+    /// Replaces a timing point with a different one.
+    ModifyTimingPoint {
+        /// Index of the timing point.
+        index: usize,
+        /// The new timing point.
+        timing_point: TimingPoint,
+    },
+}
+
+/// An error returned when an [`Edit`] can't be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    /// The edit would make two objects overlap.
+    ///
+    /// The tuple contains the two overlapping objects, one of which is the edited one.
+    OverlappingObjects(Object, Object),
+}
+
+/// A log of applied edits which can be undone and redone.
+///
+/// The history must always be used with the same [`GameState`], and the chart must not be changed
+/// other than through the history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct History {
+    /// Changes which undo the applied edits, the last edit is at the end.
+    undo_stack: Vec<Vec<Change>>,
+    /// Changes which redo the undone edits, the last undone edit is at the end.
+    redo_stack: Vec<Vec<Change>>,
+}
+
+/// Parts of the chart changed by an edit, used to update the caches.
+#[derive(Debug, Default)]
+pub(crate) struct ChartChanges {
+    /// Lanes and indices of the inserted objects, which have placeholder caches.
+    pub(crate) inserted_objects: Vec<(usize, usize)>,
+    /// Whether any objects were removed.
+    pub(crate) removed_objects: bool,
+    /// Scroll group and the earliest timestamp of the changed scroll speed changes.
+    ///
+    /// An edit changes the scroll speed changes of at most one scroll group.
+    pub(crate) scroll_speed_changes: Option<(Option<usize>, MapTimestamp)>,
+    /// Whether the timing points were changed.
+    pub(crate) timing_points: bool,
+    /// End timestamp of the last object before the edit.
+    pub(crate) last_timestamp: Option<MapTimestamp>,
+}
+
+/// A primitive change of the chart.
+///
+/// Applying a change returns the change which reverts it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    InsertObject {
+        lane: usize,
+        index: usize,
+        object: Object,
+        scroll_group: Option<usize>,
+    },
+    RemoveObject {
+        lane: usize,
+        index: usize,
+    },
+    /// Replaces `removed` scroll speed changes starting at `index` with `inserted`.
+    SpliceScrollSpeedChanges {
+        scroll_group: Option<usize>,
+        index: usize,
+        removed: usize,
+        inserted: Vec<ScrollSpeedChange>,
+    },
+    /// Replaces `removed` timing points starting at `index` with `inserted`.
+    SpliceTimingPoints {
+        index: usize,
+        removed: usize,
+        inserted: Vec<TimingPoint>,
+    },
+}
+
+impl GameState {
+    /// Applies an edit to the chart.
+    ///
+    /// Only the caches affected by the edit are updated. The states of the existing objects are
+    /// kept, and objects inserted before the [first active object](Self::first_active_object)
+    /// are marked as [skipped](crate::state::ObjectState::is_skipped).
+    ///
+    /// Use a [`History`] to be able to undo the edit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the lane, scroll group or the index of the edited object, scroll speed change or
+    /// timing point is out of bounds.
+    pub fn edit(&mut self, edit: Edit) -> Result<(), EditError> {
+        let changes = changes(self, edit)?;
+        if !changes.is_empty() {
+            apply(self, changes);
+        }
+        Ok(())
+    }
+}
+
+impl History {
+    /// Creates an empty `History`.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies an edit to `state` and records it.
+    ///
+    /// The undone edits can no longer be redone afterwards. Edits which don't change anything
+    /// aren't recorded.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`GameState::edit()`].
+    pub fn apply(&mut self, state: &mut GameState, edit: Edit) -> Result<(), EditError> {
+        let changes = changes(state, edit)?;
+        if !changes.is_empty() {
+            self.undo_stack.push(apply(state, changes));
+            self.redo_stack.clear();
+        }
+        Ok(())
+    }
+
+    /// Undoes the last edit.
+    ///
+    /// Returns `false` if there was nothing to undo.
+    pub fn undo(&mut self, state: &mut GameState) -> bool {
+        match self.undo_stack.pop() {
+            Some(changes) => {
+                self.redo_stack.push(apply(state, changes));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone edit.
+    ///
+    /// Returns `false` if there was nothing to redo.
+    pub fn redo(&mut self, state: &mut GameState) -> bool {
+        match self.redo_stack.pop() {
+            Some(changes) => {
+                self.undo_stack.push(apply(state, changes));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if there is an edit to undo.
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Returns `true` if there is an undone edit to redo.
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Forgets all edits.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
+
+/// Validates an edit and converts it into primitive changes.
+fn changes(state: &GameState, edit: Edit) -> Result<Vec<Change>, EditError> {
+    let chart = &state.immutable.chart;
+
+    let changes = match edit {
+        Edit::InsertObject {
+            lane,
+            object,
+            scroll_group,
+        } => {
+            if let Some(index) = scroll_group {
+                assert!(
+                    index < chart.scroll_groups.len(),
+                    "scroll group index out of bounds"
+                );
+            }
+
+            let index = insertion_index(&chart.lanes[lane].objects, None, object)?;
+            vec![Change::InsertObject {
+                lane,
+                index,
+                object,
+                scroll_group,
+            }]
+        }
+        Edit::RemoveObject { lane, index } => {
+            assert!(
+                index < chart.lanes[lane].objects.len(),
+                "object index out of bounds"
+            );
+            vec![Change::RemoveObject { lane, index }]
+        }
+        Edit::MoveObject {
+            lane,
+            index,
+            new_lane,
+            new_object,
+        } => {
+            let object = chart.lanes[lane].objects[index];
+            if new_lane == lane && new_object == object {
+                return Ok(Vec::new());
+            }
+
+            let scroll_group =
+                state.immutable.lane_caches[lane].object_caches[index].scroll_group();
+            let skip = if new_lane == lane { Some(index) } else { None };
+            let new_index = insertion_index(&chart.lanes[new_lane].objects, skip, new_object)?;
+            vec![
+                Change::RemoveObject { lane, index },
+                Change::InsertObject {
+                    lane: new_lane,
+                    index: new_index,
+                    object: new_object,
+                    scroll_group,
+                },
+            ]
+        }
+        Edit::InsertScrollSpeedChange {
+            scroll_group,
+            change,
+        } => splice_scroll_speed_changes(chart, scroll_group, |changes| {
+            insert_scroll_speed_change(changes, change)
+        }),
+        Edit::RemoveScrollSpeedChange {
+            scroll_group,
+            index,
+        } => splice_scroll_speed_changes(chart, scroll_group, |changes| {
+            changes.remove(index);
+        }),
+        Edit::ModifyScrollSpeedChange {
+            scroll_group,
+            index,
+            change,
+        } => splice_scroll_speed_changes(chart, scroll_group, |changes| {
+            changes.remove(index);
+            insert_scroll_speed_change(changes, change);
+        }),
+        Edit::InsertTimingPoint { timing_point } => splice_timing_points(chart, |timing_points| {
+            insert_timing_point(timing_points, timing_point)
+        }),
+        Edit::RemoveTimingPoint { index } => splice_timing_points(chart, |timing_points| {
+            timing_points.remove(index);
+        }),
+        Edit::ModifyTimingPoint {
+            index,
+            timing_point,
+        } => splice_timing_points(chart, |timing_points| {
+            timing_points.remove(index);
+            insert_timing_point(timing_points, timing_point);
+        }),
+    };
+
+    Ok(changes)
+}
+
+/// Returns the index at which `object` should be inserted into `objects`.
+///
+/// `skip` is the index of an object which is going to be removed first. The returned index takes
+/// its removal into account.
+fn insertion_index(
+    objects: &[Object],
+    skip: Option<usize>,
+    object: Object,
+) -> Result<usize, EditError> {
+    let index = objects.partition_point(|x| x.start_timestamp() < object.start_timestamp());
+    let is_kept = |i: &usize| Some(*i) != skip;
+
+    if let Some(previous) = (0..index).rev().find(is_kept) {
+        let previous = objects[previous];
+        if previous.end_timestamp() >= object.start_timestamp() {
+            return Err(EditError::OverlappingObjects(previous, object));
+        }
+    }
+
+    if let Some(next) = (index..objects.len()).find(is_kept) {
+        let next = objects[next];
+        if object.end_timestamp() >= next.start_timestamp() {
+            return Err(EditError::OverlappingObjects(object, next));
+        }
+    }
+
+    Ok(match skip {
+        Some(skip) if skip < index => index - 1,
+        _ => index,
+    })
+}
+
+/// Inserts a scroll speed change after the others with the same timestamp, so it takes priority.
+fn insert_scroll_speed_change(changes: &mut Vec<ScrollSpeedChange>, change: ScrollSpeedChange) {
+    let index = changes.partition_point(|x| x.timestamp <= change.timestamp);
+    changes.insert(index, change);
+}
+
+/// Inserts a timing point after the others with the same timestamp, so it takes priority.
+fn insert_timing_point(timing_points: &mut Vec<TimingPoint>, timing_point: TimingPoint) {
+    let index = timing_points.partition_point(|x| x.timestamp <= timing_point.timestamp);
+    timing_points.insert(index, timing_point);
+}
+
+/// Returns the change which applies `edit` to the scroll speed changes of a scroll group.
+fn splice_scroll_speed_changes(
+    chart: &Chart,
+    scroll_group: Option<usize>,
+    edit: impl FnOnce(&mut Vec<ScrollSpeedChange>),
+) -> Vec<Change> {
+    let (changes, initial_scroll_speed_multiplier) = match scroll_group {
+        None => (
+            &chart.scroll_speed_changes,
+            chart.initial_scroll_speed_multiplier,
+        ),
+        Some(index) => {
+            let group = &chart.scroll_groups[index];
+            (
+                &group.scroll_speed_changes,
+                group.initial_scroll_speed_multiplier,
+            )
+        }
+    };
+
+    let mut new_changes = changes.clone();
+    edit(&mut new_changes);
+    sort_and_dedup_scroll_speed_changes(&mut new_changes, initial_scroll_speed_multiplier);
+
+    splice(changes, &new_changes)
+        .map(
+            |(index, removed, inserted)| Change::SpliceScrollSpeedChanges {
+                scroll_group,
+                index,
+                removed,
+                inserted,
+            },
+        )
+        .into_iter()
+        .collect()
+}
+
+/// Returns the change which applies `edit` to the timing points.
+fn splice_timing_points(chart: &Chart, edit: impl FnOnce(&mut Vec<TimingPoint>)) -> Vec<Change> {
+    let mut new_timing_points = chart.timing_points.clone();
+    edit(&mut new_timing_points);
+    sort_and_dedup_timing_points(&mut new_timing_points);
+
+    splice(&chart.timing_points, &new_timing_points)
+        .map(|(index, removed, inserted)| Change::SpliceTimingPoints {
+            index,
+            removed,
+            inserted,
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Returns the smallest splice turning `old` into `new`.
+///
+/// The splice replaces `removed` elements starting at `index` with `inserted`. Returns `None` if
+/// `old` and `new` are equal.
+fn splice<T: Copy + PartialEq>(old: &[T], new: &[T]) -> Option<(usize, usize, Vec<T>)> {
+    if old == new {
+        return None;
+    }
+
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    Some((
+        prefix,
+        old.len() - prefix - suffix,
+        new[prefix..new.len() - suffix].to_vec(),
+    ))
+}
+
+/// Applies the changes to `state` and updates the caches.
+///
+/// Returns the changes which revert the applied ones.
+fn apply(state: &mut GameState, changes: Vec<Change>) -> Vec<Change> {
+    let mut chart_changes = ChartChanges {
+        last_timestamp: state.last_timestamp(),
+        ..ChartChanges::default()
+    };
+
+    let immutable = Arc::make_mut(&mut state.immutable);
+    let mut reverse = Vec::with_capacity(changes.len());
+
+    for change in changes {
+        let reverse_change = match change {
+            Change::InsertObject {
+                lane,
+                index,
+                object,
+                scroll_group,
+            } => {
+                immutable.chart.lanes[lane].objects.insert(index, object);
+                // A placeholder, the actual cache is computed below.
+                immutable.lane_caches[lane].object_caches.insert(
+                    index,
+                    ObjectCache::Regular(RegularObjectCache {
+                        position: Position::zero(),
+                        scroll_group,
+                    }),
+                );
+                // A placeholder as well.
+                immutable.lane_caches[lane].snaps.insert(
+                    index,
+                    ObjectSnap {
+                        start: Snap::Other,
+                        end: Snap::Other,
+                    },
+                );
+                state.lane_states[lane].insert_object(index, &object);
+
+                for inserted in &mut chart_changes.inserted_objects {
+                    if inserted.0 == lane && inserted.1 >= index {
+                        inserted.1 += 1;
+                    }
+                }
+                chart_changes.inserted_objects.push((lane, index));
+
+                Change::RemoveObject { lane, index }
+            }
+            Change::RemoveObject { lane, index } => {
+                let object = immutable.chart.lanes[lane].objects.remove(index);
+                let cache = immutable.lane_caches[lane].object_caches.remove(index);
+                immutable.lane_caches[lane].snaps.remove(index);
+                state.lane_states[lane].remove_object(index);
+
+                chart_changes
+                    .inserted_objects
+                    .retain(|&inserted| inserted != (lane, index));
+                for inserted in &mut chart_changes.inserted_objects {
+                    if inserted.0 == lane && inserted.1 > index {
+                        inserted.1 -= 1;
+                    }
+                }
+                chart_changes.removed_objects = true;
+
+                Change::InsertObject {
+                    lane,
+                    index,
+                    object,
+                    scroll_group: cache.scroll_group(),
+                }
+            }
+            Change::SpliceScrollSpeedChanges {
+                scroll_group,
+                index,
+                removed,
+                inserted,
+            } => {
+                let changes = match scroll_group {
+                    None => &mut immutable.chart.scroll_speed_changes,
+                    Some(group) => &mut immutable.chart.scroll_groups[group].scroll_speed_changes,
+                };
+
+                let range = index..index + removed;
+                let from = changes[range.clone()]
+                    .iter()
+                    .chain(&inserted)
+                    .map(|change| change.timestamp)
+                    .min();
+                if let Some(from) = from {
+                    let from = chart_changes
+                        .scroll_speed_changes
+                        .map_or(from, |(_, earliest)| earliest.min(from));
+                    chart_changes.scroll_speed_changes = Some((scroll_group, from));
+                }
+
+                let inserted_count = inserted.len();
+                let removed = changes.splice(range, inserted).collect();
+
+                Change::SpliceScrollSpeedChanges {
+                    scroll_group,
+                    index,
+                    removed: inserted_count,
+                    inserted: removed,
+                }
+            }
+            Change::SpliceTimingPoints {
+                index,
+                removed,
+                inserted,
+            } => {
+                chart_changes.timing_points = true;
+
+                let inserted_count = inserted.len();
+                let removed = immutable
+                    .chart
+                    .timing_points
+                    .splice(index..index + removed, inserted)
+                    .collect();
+
+                Change::SpliceTimingPoints {
+                    index,
+                    removed: inserted_count,
+                    inserted: removed,
+                }
+            }
+        };
+
+        reverse.push(reverse_change);
+    }
+
+    immutable.update_caches(&chart_changes);
+    state.seek_count = state.seek_count.wrapping_add(1);
+
+    reverse.reverse();
+    reverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        judgement::HitWindows,
+        map::{ArbitraryMapType, Lane, Map, ScrollGroup, TimeSignature},
+        scroll::ScrollSpeedMultiplier,
+        timing::MapTimestampDifference,
+    };
+    use alloc::string::String;
+    use proptest::prelude::*;
+
+    fn map() -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![
+                Lane {
+                    objects: vec![
+                        Object::Regular {
+                            timestamp: MapTimestamp::from_millis(1_000),
+                        },
+                        Object::LongNote {
+                            start: MapTimestamp::from_millis(2_000),
+                            end: MapTimestamp::from_millis(3_000),
+                        },
+                    ],
+                },
+                Lane {
+                    objects: Vec::new(),
+                },
+            ],
+        }
+    }
+
+    fn assert_matches_new(state: &GameState) {
+        let expected = GameState::new(state.immutable.to_chart(), HitWindows::default()).unwrap();
+        assert_eq!(state.immutable, expected.immutable);
+    }
+
+    #[test]
+    fn overlapping_objects_are_rejected() {
+        let mut state = GameState::new(map(), HitWindows::default()).unwrap();
+        let original = state.clone();
+
+        let object = Object::Regular {
+            timestamp: MapTimestamp::from_millis(2_500),
+        };
+        assert_eq!(
+            state.edit(Edit::InsertObject {
+                lane: 0,
+                object,
+                scroll_group: None,
+            }),
+            Err(EditError::OverlappingObjects(
+                state.immutable.chart.lanes[0].objects[1],
+                object
+            ))
+        );
+
+        // Moving an object in place doesn't overlap with itself.
+        let new_object = Object::LongNote {
+            start: MapTimestamp::from_millis(1_500),
+            end: MapTimestamp::from_millis(3_500),
+        };
+        assert_eq!(
+            state.edit(Edit::MoveObject {
+                lane: 0,
+                index: 1,
+                new_lane: 0,
+                new_object: Object::LongNote {
+                    start: MapTimestamp::from_millis(1_000),
+                    end: MapTimestamp::from_millis(3_500),
+                },
+            }),
+            Err(EditError::OverlappingObjects(
+                Object::LongNote {
+                    start: MapTimestamp::from_millis(1_000),
+                    end: MapTimestamp::from_millis(3_500),
+                },
+                state.immutable.chart.lanes[0].objects[0],
+            ))
+        );
+        assert_eq!(state, original);
+
+        state
+            .edit(Edit::MoveObject {
+                lane: 0,
+                index: 1,
+                new_lane: 0,
+                new_object,
+            })
+            .unwrap();
+        assert_eq!(state.immutable.chart.lanes[0].objects[1], new_object);
+        assert_matches_new(&state);
+    }
+
+    #[test]
+    fn scroll_speed_change_moves_objects() {
+        let mut state = GameState::new(map(), HitWindows::default()).unwrap();
+
+        let change = ScrollSpeedChange {
+            timestamp: MapTimestamp::from_millis(1_500),
+            multiplier: ScrollSpeedMultiplier::new(2000),
+        };
+        state
+            .edit(Edit::InsertScrollSpeedChange {
+                scroll_group: None,
+                change,
+            })
+            .unwrap();
+        assert_matches_new(&state);
+
+        let caches = &state.immutable.lane_caches[0].object_caches;
+        assert_eq!(
+            caches[0].start_position(),
+            state.position_at_time(MapTimestamp::from_millis(1_000))
+        );
+        assert_eq!(
+            caches[1].start_position(),
+            Position::zero()
+                + MapTimestampDifference::from_millis(1_500) * ScrollSpeedMultiplier::default()
+                + MapTimestampDifference::from_millis(500) * ScrollSpeedMultiplier::new(2000)
+        );
+
+        // A scroll speed change which doesn't change the multiplier is dropped.
+        let before = state.clone();
+        state
+            .edit(Edit::InsertScrollSpeedChange {
+                scroll_group: None,
+                change: ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(2_500),
+                    ..change
+                },
+            })
+            .unwrap();
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut state = GameState::new(map(), HitWindows::default()).unwrap();
+        let original = state.immutable.clone();
+        let mut history = History::new();
+        assert!(!history.can_undo());
+        assert!(!history.undo(&mut state));
+
+        history
+            .apply(
+                &mut state,
+                Edit::MoveObject {
+                    lane: 0,
+                    index: 0,
+                    new_lane: 1,
+                    new_object: Object::Regular {
+                        timestamp: MapTimestamp::from_millis(500),
+                    },
+                },
+            )
+            .unwrap();
+        history
+            .apply(&mut state, Edit::RemoveObject { lane: 0, index: 0 })
+            .unwrap();
+        let edited = state.immutable.clone();
+        assert!(state.immutable.chart.lanes[0].objects.is_empty());
+        assert_eq!(state.immutable.chart.lanes[1].objects.len(), 1);
+
+        assert!(history.undo(&mut state));
+        assert!(history.undo(&mut state));
+        assert!(!history.can_undo());
+        assert_eq!(state.immutable, original);
+
+        assert!(history.redo(&mut state));
+        assert!(history.redo(&mut state));
+        assert!(!history.can_redo());
+        assert_eq!(state.immutable, edited);
+
+        // A new edit clears the undone edits.
+        history.undo(&mut state);
+        history
+            .apply(
+                &mut state,
+                Edit::InsertTimingPoint {
+                    timing_point: TimingPoint {
+                        timestamp: MapTimestamp::from_millis(0),
+                        beat_duration: MapTimestampDifference::from_millis(500),
+                        signature: TimeSignature {
+                            beat_count: 4,
+                            beat_unit: 4,
+                        },
+                    },
+                },
+            )
+            .unwrap();
+        assert!(!history.can_redo());
+        assert_matches_new(&state);
+    }
+
+    #[test]
+    fn edits_keep_object_states() {
+        let mut state = GameState::new(map(), HitWindows::default()).unwrap();
+        state.seek(crate::timing::GameTimestamp::from_millis(1_500));
+        assert_eq!(state.first_active_object(0), Some(1));
+
+        state
+            .edit(Edit::InsertObject {
+                lane: 0,
+                object: Object::Regular {
+                    timestamp: MapTimestamp::from_millis(500),
+                },
+                scroll_group: None,
+            })
+            .unwrap();
+        assert!(state.lane_states[0].object_states[0].is_skipped());
+        assert_eq!(state.first_active_object(0), Some(2));
+
+        state
+            .edit(Edit::RemoveObject { lane: 0, index: 2 })
+            .unwrap();
+        assert_eq!(state.first_active_object(0), None);
+    }
+
+    /// Raw edit parameters, turned into an [`Edit`] for a particular state.
+    type RawEdit = (
+        u8,
+        usize,
+        usize,
+        MapTimestamp,
+        MapTimestamp,
+        bool,
+        ScrollSpeedChange,
+        TimingPoint,
+    );
+
+    fn to_edit(state: &GameState, raw: RawEdit) -> Option<Edit> {
+        let (kind, a, b, start, end, is_ln, change, timing_point) = raw;
+        let chart = &state.immutable.chart;
+
+        let object = if is_ln && start < end {
+            Object::LongNote { start, end }
+        } else {
+            Object::Regular { timestamp: start }
+        };
+        let scroll_group = match a % (chart.scroll_groups.len() + 1) {
+            0 => None,
+            index => Some(index - 1),
+        };
+        let scroll_speed_change_count = match scroll_group {
+            None => chart.scroll_speed_changes.len(),
+            Some(index) => chart.scroll_groups[index].scroll_speed_changes.len(),
+        };
+        let lane = a % chart.lane_count();
+        let object_count = chart.lanes[lane].objects.len();
+
+        let edit = match kind % 9 {
+            0 => Edit::InsertObject {
+                lane,
+                object,
+                scroll_group,
+            },
+            1 if object_count > 0 => Edit::RemoveObject {
+                lane,
+                index: b % object_count,
+            },
+            2 if object_count > 0 => Edit::MoveObject {
+                lane,
+                index: b % object_count,
+                new_lane: b % chart.lane_count(),
+                new_object: object,
+            },
+            3 => Edit::InsertScrollSpeedChange {
+                scroll_group,
+                change,
+            },
+            4 if scroll_speed_change_count > 0 => Edit::RemoveScrollSpeedChange {
+                scroll_group,
+                index: b % scroll_speed_change_count,
+            },
+            5 if scroll_speed_change_count > 0 => Edit::ModifyScrollSpeedChange {
+                scroll_group,
+                index: b % scroll_speed_change_count,
+                change,
+            },
+            6 => Edit::InsertTimingPoint { timing_point },
+            7 if !chart.timing_points.is_empty() => Edit::RemoveTimingPoint {
+                index: b % chart.timing_points.len(),
+            },
+            8 if !chart.timing_points.is_empty() => Edit::ModifyTimingPoint {
+                index: b % chart.timing_points.len(),
+                timing_point,
+            },
+            _ => return None,
+        };
+        Some(edit)
+    }
+
+    proptest! {
+        #[test]
+        fn edits_match_new_game_state(
+            mut map in any_with::<Map>(ArbitraryMapType::ValidWithLanes),
+            groups in prop::collection::vec(
+                (any::<Vec<ScrollSpeedChange>>(), any::<ScrollSpeedMultiplier>()),
+                0..3,
+            ),
+            raw_edits in prop::collection::vec(any::<RawEdit>(), 0..20),
+        ) {
+            map.scroll_groups = groups
+                .into_iter()
+                .map(|(scroll_speed_changes, initial_scroll_speed_multiplier)| ScrollGroup {
+                    name: String::new(),
+                    scroll_speed_changes,
+                    initial_scroll_speed_multiplier,
+                    lanes: Vec::new(),
+                })
+                .collect();
+
+            let mut state = GameState::new(map, HitWindows::default()).unwrap();
+            let original = state.immutable.clone();
+            let mut history = History::new();
+
+            for raw in raw_edits {
+                let edit = match to_edit(&state, raw) {
+                    Some(edit) => edit,
+                    None => continue,
+                };
+
+                let before = state.clone();
+                match history.apply(&mut state, edit) {
+                    Ok(()) => {
+                        let expected =
+                            GameState::new(state.immutable.to_chart(), HitWindows::default())
+                                .unwrap();
+                        prop_assert_eq!(&state.immutable, &expected.immutable);
+                        prop_assert_eq!(&state.lane_states, &expected.lane_states);
+                    }
+                    Err(_) => prop_assert_eq!(&state, &before),
+                }
+            }
+
+            let edited = state.immutable.clone();
+
+            while history.undo(&mut state) {}
+            prop_assert_eq!(&state.immutable, &original);
+
+            while history.redo(&mut state) {}
+            prop_assert_eq!(&state.immutable, &edited);
+        }
+    }
+}
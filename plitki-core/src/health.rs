@@ -0,0 +1,389 @@
+//! Health and failing.
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use crate::{judgement::Judgement, timing::GameTimestamp};
+
+/// Full health, in <sup>1</sup>⁄<sub>100</sub>ths of a percent.
+pub const MAX_HEALTH: u32 = 100_00;
+
+/// Additional conditions which make the player fail regardless of the health.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum FailMode {
+    /// Fail only when the health runs out.
+    Normal,
+    /// Fail on the first miss.
+    SuddenDeath,
+    /// Fail on the first judgement worse than [`Judgement::Perfect`].
+    PerfectOnly,
+}
+
+/// Health drain over time.
+///
+/// The drain rate depends on the current health: it changes linearly from `empty_rate` at zero
+/// health to `full_rate` at full health. Rates are in <sup>1</sup>⁄<sub>100</sub>ths of a percent
+/// per second of game time.
+///
+/// The drain is applied on every judgement for the time since the previous judgement, so it can't
+/// make the player fail in the middle of a break.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Drain {
+    /// Drain rate at full health.
+    pub full_rate: u32,
+    /// Drain rate at zero health.
+    pub empty_rate: u32,
+}
+
+/// A health model, which determines how the health changes and when the player fails.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct HealthModel {
+    /// Health change for every judgement in the [`Judgement::ALL`] order, in
+    /// <sup>1</sup>⁄<sub>100</sub>ths of a percent.
+    pub judgements: [i32; 6],
+    /// Health change for a missed object or a long note released too early, in
+    /// <sup>1</sup>⁄<sub>100</sub>ths of a percent.
+    pub miss: i32,
+    /// Health drain over time.
+    pub drain: Drain,
+    /// Additional fail conditions.
+    pub fail_mode: FailMode,
+}
+
+/// Health tracker.
+///
+/// Set [`GameState::health`](crate::state::GameState::health) to enable failing. The game state
+/// updates the health on every event and produces a [`Failed`](crate::state::EventKind::Failed)
+/// event once the player fails.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct Health {
+    /// The health model.
+    model: HealthModel,
+    /// Current health, from 0 to [`MAX_HEALTH`].
+    value: u32,
+    /// Timestamp of the last judgement, the drain is applied from this point.
+    last_timestamp: Option<GameTimestamp>,
+    /// Drain that didn't add up to a whole unit of health yet, in
+    /// <sup>1</sup>⁄<sub>100000</sub>ths of a unit.
+    drain_remainder: u64,
+    /// Whether the player has failed.
+    failed: bool,
+}
+
+/// Number of <sup>1</sup>⁄<sub>100</sub>ths of a millisecond in a second.
+const SECOND: u64 = 1000_00;
+
+/// Longest time span the drain is applied over at once, in <sup>1</sup>⁄<sub>100</sub>ths of a
+/// millisecond.
+const DRAIN_STEP: u64 = 100_00;
+
+impl Drain {
+    /// No drain.
+    pub const NONE: Self = Self::constant(0);
+
+    /// Returns a drain with the same `rate` at any health.
+    #[inline]
+    pub const fn constant(rate: u32) -> Self {
+        Self {
+            full_rate: rate,
+            empty_rate: rate,
+        }
+    }
+
+    /// Returns the drain rate at the given `health`.
+    fn rate(self, health: u32) -> u64 {
+        let full = i64::from(self.full_rate);
+        let empty = i64::from(self.empty_rate);
+        let rate = empty + (full - empty) * i64::from(health) / i64::from(MAX_HEALTH);
+        rate as u64
+    }
+}
+
+impl HealthModel {
+    /// Quaver's health weights, without drain.
+    #[inline]
+    pub const fn quaver() -> Self {
+        Self {
+            judgements: [50, 40, 20, -300, -450, -600],
+            miss: -600,
+            drain: Drain::NONE,
+            fail_mode: FailMode::Normal,
+        }
+    }
+
+    /// Returns the health model with the given fail mode.
+    #[inline]
+    pub const fn with_fail_mode(mut self, fail_mode: FailMode) -> Self {
+        self.fail_mode = fail_mode;
+        self
+    }
+}
+
+impl Default for HealthModel {
+    #[inline]
+    fn default() -> Self {
+        Self::quaver()
+    }
+}
+
+impl Health {
+    /// Creates a new `Health` at full health.
+    #[inline]
+    pub fn new(model: HealthModel) -> Self {
+        Self {
+            model,
+            value: MAX_HEALTH,
+            last_timestamp: None,
+            drain_remainder: 0,
+            failed: false,
+        }
+    }
+
+    /// Returns the health model.
+    #[inline]
+    pub fn model(&self) -> HealthModel {
+        self.model
+    }
+
+    /// Returns the current health, from 0 to [`MAX_HEALTH`].
+    #[inline]
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// Returns `true` if the player has failed.
+    #[inline]
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Resets the health back to full.
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Self::new(self.model);
+    }
+
+    /// Applies a judgement that happened at `timestamp`.
+    ///
+    /// Returns `true` if this made the player fail.
+    pub fn judge(&mut self, judgement: Judgement, timestamp: GameTimestamp) -> bool {
+        let fails = match self.model.fail_mode {
+            FailMode::Normal => false,
+            FailMode::SuddenDeath => judgement == Judgement::Miss,
+            FailMode::PerfectOnly => judgement > Judgement::Perfect,
+        };
+
+        self.change(self.model.judgements[judgement.index()], fails, timestamp)
+    }
+
+    /// Applies a missed object or a long note released too early at `timestamp`.
+    ///
+    /// Returns `true` if this made the player fail.
+    pub fn miss(&mut self, timestamp: GameTimestamp) -> bool {
+        let fails = self.model.fail_mode != FailMode::Normal;
+        self.change(self.model.miss, fails, timestamp)
+    }
+
+    fn change(&mut self, change: i32, fails: bool, timestamp: GameTimestamp) -> bool {
+        if self.failed {
+            return false;
+        }
+
+        self.drain(timestamp);
+
+        // Failing from the drain takes priority over the health gain.
+        if fails || self.value == 0 {
+            self.failed = true;
+            return true;
+        }
+
+        let value = i64::from(self.value) + i64::from(change);
+        self.value = value.clamp(0, i64::from(MAX_HEALTH)) as u32;
+
+        self.failed = self.value == 0;
+        self.failed
+    }
+
+    fn drain(&mut self, timestamp: GameTimestamp) {
+        let elapsed = match self.last_timestamp {
+            Some(last) if last < timestamp => (timestamp - last).into_milli_hundredths() as u64,
+            Some(_) => return,
+            None => 0,
+        };
+        self.last_timestamp = Some(timestamp);
+
+        // The rate depends on the health, so apply the drain in small steps.
+        let mut elapsed = elapsed;
+        while elapsed > 0 && self.value > 0 {
+            let step = elapsed.min(DRAIN_STEP);
+            elapsed -= step;
+
+            let rate = self.model.drain.rate(self.value);
+            if rate == 0 {
+                break;
+            }
+
+            let drained = u128::from(rate) * u128::from(step) + u128::from(self.drain_remainder);
+            self.drain_remainder = (drained % u128::from(SECOND)) as u64;
+            let drained = (drained / u128::from(SECOND)).min(u128::from(self.value)) as u32;
+            self.value -= drained;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn at(millis: i32) -> GameTimestamp {
+        GameTimestamp::from_millis(millis)
+    }
+
+    #[test]
+    fn judgements_change_health() {
+        let mut health = Health::new(HealthModel::quaver());
+        assert_eq!(health.value(), MAX_HEALTH);
+
+        assert!(!health.judge(Judgement::Okay, at(0)));
+        assert_eq!(health.value(), MAX_HEALTH - 450);
+
+        assert!(!health.judge(Judgement::Marvelous, at(100)));
+        assert_eq!(health.value(), MAX_HEALTH - 400);
+
+        assert!(!health.miss(at(200)));
+        assert_eq!(health.value(), MAX_HEALTH - 1000);
+
+        // The health doesn't go over the maximum.
+        for i in 0..100 {
+            health.judge(Judgement::Perfect, at(300 + i));
+        }
+        assert_eq!(health.value(), MAX_HEALTH);
+    }
+
+    #[test]
+    fn running_out_of_health_fails() {
+        let mut health = Health::new(HealthModel::quaver());
+
+        for i in 0..16 {
+            assert!(!health.miss(at(i)));
+        }
+        assert!(health.miss(at(16)));
+        assert!(health.is_failed());
+        assert_eq!(health.value(), 0);
+
+        // Failing happens only once.
+        assert!(!health.judge(Judgement::Marvelous, at(17)));
+        assert!(!health.miss(at(18)));
+        assert_eq!(health.value(), 0);
+
+        health.reset();
+        assert!(!health.is_failed());
+        assert_eq!(health.value(), MAX_HEALTH);
+    }
+
+    #[test]
+    fn sudden_death() {
+        let model = HealthModel::quaver().with_fail_mode(FailMode::SuddenDeath);
+
+        let mut health = Health::new(model);
+        assert!(!health.judge(Judgement::Okay, at(0)));
+        assert!(health.judge(Judgement::Miss, at(1)));
+
+        let mut health = Health::new(model);
+        assert!(health.miss(at(0)));
+    }
+
+    #[test]
+    fn perfect_only() {
+        let model = HealthModel::quaver().with_fail_mode(FailMode::PerfectOnly);
+
+        let mut health = Health::new(model);
+        assert!(!health.judge(Judgement::Marvelous, at(0)));
+        assert!(!health.judge(Judgement::Perfect, at(1)));
+        assert!(health.judge(Judgement::Great, at(2)));
+
+        let mut health = Health::new(model);
+        assert!(health.miss(at(0)));
+    }
+
+    #[test]
+    fn constant_drain() {
+        let model = HealthModel {
+            judgements: [0; 6],
+            miss: 0,
+            drain: Drain::constant(1_00),
+            fail_mode: FailMode::Normal,
+        };
+
+        let mut health = Health::new(model);
+        // The drain starts at the first judgement.
+        health.judge(Judgement::Marvelous, at(10_000));
+        assert_eq!(health.value(), MAX_HEALTH);
+
+        health.judge(Judgement::Marvelous, at(20_000));
+        assert_eq!(health.value(), MAX_HEALTH - 10_00);
+
+        // Fractional drain adds up.
+        for i in 1..=100 {
+            health.judge(Judgement::Marvelous, at(20_000 + i * 5));
+        }
+        assert_eq!(health.value(), MAX_HEALTH - 10_50);
+
+        // Running out of health from the drain fails.
+        assert!(health.judge(Judgement::Marvelous, at(200_000)));
+    }
+
+    #[test]
+    fn health_dependent_drain() {
+        let model = HealthModel {
+            judgements: [0; 6],
+            miss: 0,
+            drain: Drain {
+                full_rate: 10_00,
+                empty_rate: 0,
+            },
+            fail_mode: FailMode::Normal,
+        };
+
+        let mut health = Health::new(model);
+        health.judge(Judgement::Marvelous, at(0));
+        health.judge(Judgement::Marvelous, at(1_000));
+        // A little less than 10% because the drain slows down along the way.
+        assert!(health.value() > MAX_HEALTH - 10_00);
+        assert!(health.value() < MAX_HEALTH - 9_00);
+
+        // The drain slows down as the health goes down, so it never fails by itself.
+        assert!(!health.judge(Judgement::Marvelous, at(1_000_000)));
+        assert!(health.value() > 0);
+    }
+
+    proptest! {
+        #[test]
+        fn health_stays_in_range(
+            judgements: [i32; 6],
+            miss: i32,
+            full_rate: u32,
+            empty_rate: u32,
+            events in prop::collection::vec((any::<Option<Judgement>>(), 0..2i32.pow(30)), 0..100),
+        ) {
+            let model = HealthModel {
+                judgements,
+                miss,
+                drain: Drain { full_rate, empty_rate },
+                fail_mode: FailMode::Normal,
+            };
+            let mut health = Health::new(model);
+
+            for (judgement, timestamp) in events {
+                let timestamp = GameTimestamp::from_milli_hundredths(timestamp);
+                let was_failed = health.is_failed();
+                let failed = match judgement {
+                    Some(judgement) => health.judge(judgement, timestamp),
+                    None => health.miss(timestamp),
+                };
+
+                prop_assert!(health.value() <= MAX_HEALTH);
+                prop_assert_eq!(failed, !was_failed && health.is_failed());
+            }
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Judgements and hit windows.
+#![allow(clippy::inconsistent_digit_grouping)]
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+use crate::timing::GameTimestampDifference;
+
+/// A judgement given to a hit.
+///
+/// Judgements are ordered from the best to the worst.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum Judgement {
+    /// The best judgement.
+    Marvelous,
+    /// The second best judgement.
+    Perfect,
+    /// The third best judgement.
+    Great,
+    /// The fourth best judgement.
+    Good,
+    /// The worst judgement that is still considered a hit.
+    Okay,
+    /// A miss.
+    ///
+    /// This is given both to hits outside of the [`Judgement::Okay`] window and to objects that
+    /// have not been hit at all.
+    Miss,
+}
+
+/// Largest timestamp differences for every [`Judgement`].
+///
+/// A hit receives the best judgement whose window contains the absolute hit difference. The
+/// [`Judgement::Miss`] window is the largest difference at which an object can be interacted with
+/// at all.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct JudgementWindows {
+    /// Windows in the [`Judgement::ALL`] order.
+    ///
+    /// Invariant: the windows are non-negative and non-decreasing.
+    #[cfg_attr(test, proptest(strategy = "arbitrary_windows()"))]
+    windows: [GameTimestampDifference; 6],
+}
+
+/// Judgement windows for long note presses and releases.
+///
+/// Regular objects and long note starts are judged with [`HitWindows::press`], and long note ends
+/// are judged with [`HitWindows::release`].
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct HitWindows {
+    /// Windows for key presses.
+    pub press: JudgementWindows,
+    /// Windows for long note releases.
+    pub release: JudgementWindows,
+}
+
+#[cfg(test)]
+fn arbitrary_windows() -> impl Strategy<Value = [GameTimestampDifference; 6]> {
+    prop::array::uniform6(0..2i32.pow(30)).prop_map(|mut windows| {
+        windows.sort_unstable();
+        windows.map(GameTimestampDifference::from_milli_hundredths)
+    })
+}
+
+impl Judgement {
+    /// All judgements, from the best to the worst.
+    pub const ALL: [Judgement; 6] = [
+        Judgement::Marvelous,
+        Judgement::Perfect,
+        Judgement::Great,
+        Judgement::Good,
+        Judgement::Okay,
+        Judgement::Miss,
+    ];
+
+    /// Returns the index of the judgement in [`Judgement::ALL`].
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl JudgementWindows {
+    /// Creates new `JudgementWindows`.
+    ///
+    /// `windows` are the largest differences for every judgement in the [`Judgement::ALL`] order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any window is negative or smaller than the window of a better judgement.
+    #[inline]
+    pub fn new(windows: [GameTimestampDifference; 6]) -> Self {
+        Self::checked_new(windows).expect("windows must be non-negative and non-decreasing")
+    }
+
+    /// Creates new `JudgementWindows`, returning `None` if any window is negative or smaller than
+    /// the window of a better judgement.
+    #[inline]
+    pub fn checked_new(windows: [GameTimestampDifference; 6]) -> Option<Self> {
+        if windows[0] < GameTimestampDifference::from_milli_hundredths(0) {
+            return None;
+        }
+        if windows.windows(2).any(|ab| ab[0] > ab[1]) {
+            return None;
+        }
+
+        Some(Self { windows })
+    }
+
+    /// Creates new `JudgementWindows` from millisecond values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any window is negative or smaller than the window of a better judgement.
+    #[inline]
+    pub fn from_millis(windows: [i32; 6]) -> Self {
+        Self::new(windows.map(GameTimestampDifference::from_millis))
+    }
+
+    /// Creates new `JudgementWindows` where every judgement has the same `window`.
+    ///
+    /// With these windows every hit is judged as [`Judgement::Marvelous`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is negative.
+    #[inline]
+    pub fn uniform(window: GameTimestampDifference) -> Self {
+        Self::new([window; 6])
+    }
+
+    /// Returns the window of a judgement.
+    #[inline]
+    pub fn window(&self, judgement: Judgement) -> GameTimestampDifference {
+        self.windows[judgement.index()]
+    }
+
+    /// Returns the largest window.
+    ///
+    /// Objects further away than this can no longer be interacted with.
+    #[inline]
+    pub fn largest(&self) -> GameTimestampDifference {
+        self.window(Judgement::Miss)
+    }
+
+    /// Returns the judgement for a hit `difference`.
+    ///
+    /// Differences outside of all windows are judged as [`Judgement::Miss`].
+    #[inline]
+    pub fn judge(&self, difference: GameTimestampDifference) -> Judgement {
+        let difference = difference.into_milli_hundredths().unsigned_abs();
+
+        for judgement in Judgement::ALL {
+            let window = self.window(judgement).into_milli_hundredths() as u32;
+            if difference <= window {
+                return judgement;
+            }
+        }
+
+        Judgement::Miss
+    }
+
+    /// Returns windows multiplied by `numerator / denominator`, saturating at the numeric bounds.
+    fn scaled(&self, numerator: i64, denominator: i64) -> Self {
+        Self::new(self.windows.map(|window| {
+            let window = i64::from(window.into_milli_hundredths()) * numerator / denominator;
+            GameTimestampDifference::from_milli_hundredths(window.min(i64::from(i32::MAX)) as i32)
+        }))
+    }
+}
+
+impl HitWindows {
+    /// Creates new `HitWindows`.
+    #[inline]
+    pub fn new(press: JudgementWindows, release: JudgementWindows) -> Self {
+        Self { press, release }
+    }
+
+    /// Creates new `HitWindows` where every judgement for both presses and releases has the same
+    /// `window`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is negative.
+    #[inline]
+    pub fn uniform(window: GameTimestampDifference) -> Self {
+        Self::new(
+            JudgementWindows::uniform(window),
+            JudgementWindows::uniform(window),
+        )
+    }
+
+    /// Creates new `HitWindows` where the release windows are the press windows multiplied by 1.5.
+    ///
+    /// This is how both Quaver and osu!mania judge long note releases.
+    #[inline]
+    pub fn with_lenient_release(press: JudgementWindows) -> Self {
+        Self::new(press, press.scaled(3, 2))
+    }
+
+    /// Returns the Quaver Standard judgement windows.
+    #[inline]
+    pub fn quaver_standard() -> Self {
+        Self::with_lenient_release(JudgementWindows::from_millis([18, 43, 76, 106, 127, 164]))
+    }
+
+    /// Returns the Quaver Strict judgement windows.
+    #[inline]
+    pub fn quaver_strict() -> Self {
+        Self::with_lenient_release(JudgementWindows::from_millis([16, 39, 69, 96, 115, 149]))
+    }
+
+    /// Returns the Quaver Lenient judgement windows.
+    #[inline]
+    pub fn quaver_lenient() -> Self {
+        Self::with_lenient_release(JudgementWindows::from_millis([21, 52, 91, 127, 152, 196]))
+    }
+
+    /// Returns the osu!mania judgement windows for the given overall difficulty (OD).
+    ///
+    /// The judgements map onto the osu!mania ones as follows: Marvelous is MAX (rainbow 300),
+    /// Perfect is 300, Great is 200, Good is 100 and Okay is 50.
+    ///
+    /// `overall_difficulty` is clamped to the `0..=10` range.
+    #[inline]
+    pub fn osu_mania(overall_difficulty: f32) -> Self {
+        let od = overall_difficulty.clamp(0., 10.);
+        // 3 ms for every point of OD.
+        let od = (od * 300.) as i32;
+
+        let press = JudgementWindows::new(
+            [
+                16_00,
+                64_00 - od,
+                97_00 - od,
+                127_00 - od,
+                151_00 - od,
+                188_00 - od,
+            ]
+            .map(GameTimestampDifference::from_milli_hundredths),
+        );
+        Self::with_lenient_release(press)
+    }
+}
+
+impl Default for HitWindows {
+    #[inline]
+    fn default() -> Self {
+        Self::quaver_standard()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn judge_quaver_standard() {
+        let windows = HitWindows::quaver_standard().press;
+
+        for (millis, judgement) in [
+            (0, Judgement::Marvelous),
+            (18, Judgement::Marvelous),
+            (-18, Judgement::Marvelous),
+            (19, Judgement::Perfect),
+            (-43, Judgement::Perfect),
+            (76, Judgement::Great),
+            (-106, Judgement::Good),
+            (127, Judgement::Okay),
+            (-128, Judgement::Miss),
+            (164, Judgement::Miss),
+            (1000, Judgement::Miss),
+        ] {
+            assert_eq!(
+                windows.judge(GameTimestampDifference::from_millis(millis)),
+                judgement
+            );
+        }
+    }
+
+    #[test]
+    fn release_windows_are_lenient() {
+        let windows = HitWindows::quaver_standard();
+        assert_eq!(
+            windows.release.window(Judgement::Perfect),
+            GameTimestampDifference::from_milli_hundredths(64_50)
+        );
+        assert_eq!(
+            windows.release.largest(),
+            GameTimestampDifference::from_millis(246)
+        );
+    }
+
+    #[test]
+    fn osu_mania() {
+        let windows = HitWindows::osu_mania(8.);
+        assert_eq!(
+            windows.press,
+            JudgementWindows::from_millis([16, 40, 73, 103, 127, 164])
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn decreasing_windows_panic() {
+        JudgementWindows::from_millis([18, 43, 76, 106, 127, 126]);
+    }
+
+    proptest! {
+        #[test]
+        fn judge_is_monotonic(
+            windows: JudgementWindows,
+            a: GameTimestampDifference,
+            b: GameTimestampDifference,
+        ) {
+            let (a, b) = (a.into_milli_hundredths(), b.into_milli_hundredths());
+            let (a, b) = if a.unsigned_abs() <= b.unsigned_abs() { (a, b) } else { (b, a) };
+
+            let a = windows.judge(GameTimestampDifference::from_milli_hundredths(a));
+            let b = windows.judge(GameTimestampDifference::from_milli_hundredths(b));
+            prop_assert!(a <= b);
+        }
+
+        #[test]
+        fn osu_mania_doesnt_panic(overall_difficulty: f32) {
+            let _ = HitWindows::osu_mania(overall_difficulty);
+        }
+    }
+}
@@ -9,9 +9,19 @@ extern crate alloc;
 
 mod macros;
 
+pub mod autoplay;
+pub mod editor;
+pub mod health;
+pub mod judgement;
 pub mod map;
+pub mod mods;
 pub mod object;
+pub mod rating;
+pub mod replay;
+pub mod rng;
+pub mod score;
 pub mod scroll;
+pub mod snap;
 pub mod state;
 pub mod timing;
 pub mod visibility_cache;
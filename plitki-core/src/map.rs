@@ -1,5 +1,5 @@
 //! Functionality related to mapsets and maps.
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, vec, vec::Vec};
 
 #[cfg(test)]
 use proptest::prelude::*;
@@ -87,14 +87,58 @@ pub struct Lane {
     pub objects: Vec<Object>,
 }
 
+/// A mapset: a song with one or more difficulties.
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub struct Mapset {
+    /// Artist of the song.
+    pub song_artist: Option<String>,
+    /// Title of the song.
+    pub song_title: Option<String>,
+    /// Filename of the background.
+    pub background_file: Option<String>,
+    /// Filename of the audio track.
+    pub audio_file: Option<String>,
+    /// Difficulties of the mapset.
+    pub difficulties: Vec<Difficulty>,
+}
+
+/// One difficulty in a mapset.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Difficulty {
+    /// Difficulty name.
+    pub name: Option<String>,
+    /// Mapper's name.
+    pub mapper: Option<String>,
+    /// Objects, timing and scroll speed changes of the difficulty.
+    pub chart: Chart,
+}
+
+/// The playable part of a difficulty: objects, timing and scroll speed changes.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Chart {
+    /// BPM and time signature changes.
+    pub timing_points: Vec<TimingPoint>,
+    /// Scroll speed changes (SVs).
+    pub scroll_speed_changes: Vec<ScrollSpeedChange>,
+    /// The scroll speed multiplier in effect at the map start, before any scroll speed changes.
+    pub initial_scroll_speed_multiplier: ScrollSpeedMultiplier,
+    /// Scroll groups with their own scroll speed changes.
+    ///
+    /// Objects in [`lanes`](Self::lanes) scroll with the chart's own scroll speed changes, while
+    /// objects in the lanes of a scroll group scroll with the scroll speed changes of that group.
+    pub scroll_groups: Vec<ScrollGroup>,
+    /// Lanes constituting the chart.
+    pub lanes: Vec<Lane>,
+}
+
 /// A map (beatmap, chart, file).
+///
+/// This is a single difficulty together with the song metadata, as stored in a single map file.
+/// It converts to and from a [`Mapset`] with one [`Difficulty`], and to a [`Chart`].
 #[derive(Debug, Eq, PartialEq, Clone)]
 #[cfg_attr(test, derive(Arbitrary))]
 #[cfg_attr(test, proptest(params(ArbitraryMapType)))]
 pub struct Map {
-    // TODO: separate these out? Leave only the actual object info here?
-    // Idea for separation: Mapset contains Difficulties, which have this info plus a Map which has
-    // just lanes, timing, etc.
     /// Artist of the song.
     pub song_artist: Option<String>,
     /// Title of the song.
@@ -114,6 +158,17 @@ pub struct Map {
     pub scroll_speed_changes: Vec<ScrollSpeedChange>,
     /// The scroll speed multiplier in effect at the map start, before any scroll speed changes.
     pub initial_scroll_speed_multiplier: ScrollSpeedMultiplier,
+    /// Scroll groups with their own scroll speed changes.
+    ///
+    /// Objects in [`lanes`](Self::lanes) scroll with the map's own scroll speed changes, while
+    /// objects in the lanes of a scroll group scroll with the scroll speed changes of that group.
+    #[cfg_attr(
+        test,
+        proptest(
+            strategy = "if params == ArbitraryMapType::Any { proptest::collection::vec(any::<ScrollGroup>(), 0..3).boxed() } else { Just(Vec::new()).boxed() }"
+        )
+    )]
+    pub scroll_groups: Vec<ScrollGroup>,
     /// Lanes constituting the map.
     #[cfg_attr(
         test,
@@ -124,6 +179,26 @@ pub struct Map {
     pub lanes: Vec<Lane>,
 }
 
+/// A set of objects which scroll with their own scroll speed changes (a timing group).
+///
+/// Scroll groups let different lanes or different sets of objects move at different speeds.
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct ScrollGroup {
+    /// Name of the scroll group.
+    pub name: String,
+    /// Scroll speed changes of the scroll group.
+    pub scroll_speed_changes: Vec<ScrollSpeedChange>,
+    /// The scroll speed multiplier of the scroll group in effect at the map start, before any
+    /// scroll speed changes.
+    pub initial_scroll_speed_multiplier: ScrollSpeedMultiplier,
+    /// Objects of the scroll group, in the same lanes as in the map.
+    ///
+    /// There may be fewer lanes than in the map, in which case the remaining lanes have no objects
+    /// in this scroll group.
+    pub lanes: Vec<Lane>,
+}
+
 /// A scroll speed change (an SV).
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 #[cfg_attr(test, derive(Arbitrary))]
@@ -168,99 +243,574 @@ impl Lane {
     }
 }
 
-impl Map {
+impl Mapset {
+    /// Splits the mapset into maps, one for every difficulty.
+    pub fn into_maps(self) -> Vec<Map> {
+        let Mapset {
+            song_artist,
+            song_title,
+            background_file,
+            audio_file,
+            difficulties,
+        } = self;
+
+        difficulties
+            .into_iter()
+            .map(|difficulty| Map {
+                song_artist: song_artist.clone(),
+                song_title: song_title.clone(),
+                difficulty_name: difficulty.name,
+                mapper: difficulty.mapper,
+                background_file: background_file.clone(),
+                audio_file: audio_file.clone(),
+                timing_points: difficulty.chart.timing_points,
+                scroll_speed_changes: difficulty.chart.scroll_speed_changes,
+                initial_scroll_speed_multiplier: difficulty.chart.initial_scroll_speed_multiplier,
+                scroll_groups: difficulty.chart.scroll_groups,
+                lanes: difficulty.chart.lanes,
+            })
+            .collect()
+    }
+}
+
+impl From<Map> for Mapset {
+    #[inline]
+    fn from(map: Map) -> Self {
+        Self {
+            song_artist: map.song_artist,
+            song_title: map.song_title,
+            background_file: map.background_file,
+            audio_file: map.audio_file,
+            difficulties: vec![Difficulty {
+                name: map.difficulty_name,
+                mapper: map.mapper,
+                chart: Chart {
+                    timing_points: map.timing_points,
+                    scroll_speed_changes: map.scroll_speed_changes,
+                    initial_scroll_speed_multiplier: map.initial_scroll_speed_multiplier,
+                    scroll_groups: map.scroll_groups,
+                    lanes: map.lanes,
+                },
+            }],
+        }
+    }
+}
+
+impl From<Map> for Chart {
+    #[inline]
+    fn from(map: Map) -> Self {
+        Self {
+            timing_points: map.timing_points,
+            scroll_speed_changes: map.scroll_speed_changes,
+            initial_scroll_speed_multiplier: map.initial_scroll_speed_multiplier,
+            scroll_groups: map.scroll_groups,
+            lanes: map.lanes,
+        }
+    }
+}
+
+impl ScrollGroup {
     /// Sorts and de-duplicates scroll speed changes.
     ///
     /// This method removes all but the last scroll speed changes on every given timestamp.
+    #[inline]
     pub fn sort_and_dedup_scroll_speed_changes(&mut self) {
-        let changes = &mut self.scroll_speed_changes;
-        changes.sort_by_key(|a| a.timestamp);
+        sort_and_dedup_scroll_speed_changes(
+            &mut self.scroll_speed_changes,
+            self.initial_scroll_speed_multiplier,
+        );
+    }
+}
 
-        let initial_multiplier = self.initial_scroll_speed_multiplier;
-        let first_meaningful_change_index = if let Some((mut i, _)) = changes
-            .iter()
-            .enumerate()
-            .find(|(_, x)| x.multiplier != initial_multiplier)
-        {
-            // Skip to the last among the duplicates so the loop below works correctly.
-            while i + 1 < changes.len() && changes[i + 1].timestamp == changes[i].timestamp {
-                i += 1;
-            }
-            i
-        } else {
-            changes.clear();
-            return;
-        };
+impl Chart {
+    /// Sorts and de-duplicates scroll speed changes, including those of the scroll groups.
+    ///
+    /// This method removes all but the last scroll speed changes on every given timestamp.
+    #[inline]
+    pub fn sort_and_dedup_scroll_speed_changes(&mut self) {
+        sort_and_dedup_scroll_speed_changes(
+            &mut self.scroll_speed_changes,
+            self.initial_scroll_speed_multiplier,
+        );
 
-        // Vec::dedup_by_key would have been useful, but it removes all but the first occurrence
-        // of a value, while want to retain the last occurrence.
-        if changes.len() <= 1 {
-            return;
+        for group in &mut self.scroll_groups {
+            group.sort_and_dedup_scroll_speed_changes();
         }
+    }
 
-        // Might be possible to do in-place, but certainly non-trivial.
-        //
-        // The new_changes.last().unwrap().multiplier in the loop causes a failure in type
-        // inference for some reason.
-        let mut new_changes: Vec<ScrollSpeedChange> = Vec::with_capacity(changes.len());
-        for i in first_meaningful_change_index..changes.len() {
-            // Skip changes which don't change the multiplier from the previous one.
-            if i > first_meaningful_change_index
-                && changes[i].multiplier == new_changes.last().unwrap().multiplier
-            {
-                continue;
-            }
+    /// Sorts and de-duplicates timing points.
+    ///
+    /// This method removes all but the last timing point on every given timestamp.
+    #[inline]
+    pub fn sort_and_dedup_timing_points(&mut self) {
+        sort_and_dedup_timing_points(&mut self.timing_points);
+    }
 
-            // Skip to the last change with this timestamp.
-            if i + 1 < changes.len() && changes[i + 1].timestamp == changes[i].timestamp {
-                continue;
-            }
+    /// Returns the number of lanes in the chart.
+    #[inline]
+    pub fn lane_count(&self) -> usize {
+        self.lanes.len()
+    }
 
-            new_changes.push(changes[i]);
-        }
+    /// Returns all objects in a lane, including the objects of the scroll groups.
+    ///
+    /// The objects are not sorted.
+    #[inline]
+    pub fn lane_objects(&self, lane: usize) -> impl Iterator<Item = &Object> {
+        lane_objects(&self.lanes, &self.scroll_groups, lane)
+    }
+
+    /// Moves the objects of all scroll groups into [`lanes`](Self::lanes).
+    ///
+    /// Afterwards, all objects scroll with the chart's own scroll speed changes. This is useful for
+    /// formats which don't support scroll groups. The scroll groups themselves are kept, but have
+    /// no objects left.
+    ///
+    /// Objects in scroll group lanes past the lane count of the chart are dropped.
+    pub fn flatten_scroll_groups(&mut self) {
+        flatten_scroll_groups(&mut self.lanes, &mut self.scroll_groups);
+    }
+}
+
+impl Map {
+    /// Sorts and de-duplicates scroll speed changes, including those of the scroll groups.
+    ///
+    /// This method removes all but the last scroll speed changes on every given timestamp.
+    #[inline]
+    pub fn sort_and_dedup_scroll_speed_changes(&mut self) {
+        sort_and_dedup_scroll_speed_changes(
+            &mut self.scroll_speed_changes,
+            self.initial_scroll_speed_multiplier,
+        );
 
-        *changes = new_changes;
+        for group in &mut self.scroll_groups {
+            group.sort_and_dedup_scroll_speed_changes();
+        }
     }
 
     /// Sorts and de-duplicates timing points.
     ///
     /// This method removes all but the last timing point on every given timestamp.
+    #[inline]
     pub fn sort_and_dedup_timing_points(&mut self) {
-        let timing_points = &mut self.timing_points;
-        timing_points.sort_by_key(|a| a.timestamp);
+        sort_and_dedup_timing_points(&mut self.timing_points);
+    }
+
+    /// Returns the number of lanes in the map.
+    #[inline]
+    pub fn lane_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Returns all objects in a lane, including the objects of the scroll groups.
+    ///
+    /// The objects are not sorted.
+    #[inline]
+    pub fn lane_objects(&self, lane: usize) -> impl Iterator<Item = &Object> {
+        lane_objects(&self.lanes, &self.scroll_groups, lane)
+    }
+
+    /// Moves the objects of all scroll groups into [`lanes`](Self::lanes).
+    ///
+    /// Afterwards, all objects scroll with the map's own scroll speed changes. This is useful for
+    /// formats which don't support scroll groups. The scroll groups themselves are kept, but have
+    /// no objects left.
+    ///
+    /// Objects in scroll group lanes past the lane count of the map are dropped.
+    pub fn flatten_scroll_groups(&mut self) {
+        flatten_scroll_groups(&mut self.lanes, &mut self.scroll_groups);
+    }
+}
+
+pub(crate) fn lane_objects<'a>(
+    lanes: &'a [Lane],
+    scroll_groups: &'a [ScrollGroup],
+    lane: usize,
+) -> impl Iterator<Item = &'a Object> {
+    lanes[lane].objects.iter().chain(
+        scroll_groups
+            .iter()
+            .filter_map(move |group| group.lanes.get(lane))
+            .flat_map(|lane| &lane.objects),
+    )
+}
+
+fn flatten_scroll_groups(lanes: &mut [Lane], scroll_groups: &mut [ScrollGroup]) {
+    for group in scroll_groups {
+        for (lane, group_lane) in lanes.iter_mut().zip(&mut group.lanes) {
+            lane.objects.append(&mut group_lane.objects);
+        }
+        group.lanes.clear();
+    }
+}
+
+pub(crate) fn sort_and_dedup_scroll_speed_changes(
+    changes: &mut Vec<ScrollSpeedChange>,
+    initial_multiplier: ScrollSpeedMultiplier,
+) {
+    changes.sort_by_key(|a| a.timestamp);
+
+    let first_meaningful_change_index = if let Some((mut i, _)) = changes
+        .iter()
+        .enumerate()
+        .find(|(_, x)| x.multiplier != initial_multiplier)
+    {
+        // Skip to the last among the duplicates so the loop below works correctly.
+        while i + 1 < changes.len() && changes[i + 1].timestamp == changes[i].timestamp {
+            i += 1;
+        }
+        i
+    } else {
+        changes.clear();
+        return;
+    };
+
+    // Vec::dedup_by_key would have been useful, but it removes all but the first occurrence
+    // of a value, while want to retain the last occurrence.
+    if changes.len() <= 1 {
+        return;
+    }
+
+    // Might be possible to do in-place, but certainly non-trivial.
+    //
+    // The new_changes.last().unwrap().multiplier in the loop causes a failure in type
+    // inference for some reason.
+    let mut new_changes: Vec<ScrollSpeedChange> = Vec::with_capacity(changes.len());
+    for i in first_meaningful_change_index..changes.len() {
+        // Skip changes which don't change the multiplier from the previous one.
+        if i > first_meaningful_change_index
+            && changes[i].multiplier == new_changes.last().unwrap().multiplier
+        {
+            continue;
+        }
 
-        // Vec::dedup_by_key would have been useful, but it removes all but the first occurrence
-        // of a value, while want to retain the last occurrence.
-        if timing_points.len() <= 1 {
-            return;
+        // Skip to the last change with this timestamp.
+        if i + 1 < changes.len() && changes[i + 1].timestamp == changes[i].timestamp {
+            continue;
         }
 
-        let mut new_timing_points: Vec<TimingPoint> = Vec::with_capacity(timing_points.len());
-        for i in 0..timing_points.len() {
-            // Skip to the last change with this timestamp.
-            if i + 1 < timing_points.len()
-                && timing_points[i + 1].timestamp == timing_points[i].timestamp
-            {
-                continue;
+        new_changes.push(changes[i]);
+    }
+
+    *changes = new_changes;
+}
+
+pub(crate) fn sort_and_dedup_timing_points(timing_points: &mut Vec<TimingPoint>) {
+    timing_points.sort_by_key(|a| a.timestamp);
+
+    // Vec::dedup_by_key would have been useful, but it removes all but the first occurrence
+    // of a value, while want to retain the last occurrence.
+    if timing_points.len() <= 1 {
+        return;
+    }
+
+    let mut new_timing_points: Vec<TimingPoint> = Vec::with_capacity(timing_points.len());
+    for i in 0..timing_points.len() {
+        // Skip to the last change with this timestamp.
+        if i + 1 < timing_points.len()
+            && timing_points[i + 1].timestamp == timing_points[i].timestamp
+        {
+            continue;
+        }
+
+        new_timing_points.push(timing_points[i]);
+    }
+
+    *timing_points = new_timing_points;
+}
+
+/// Returns the beat duration of the BPM which lasts the longest until `end`.
+///
+/// This is the BPM at which BPM-relative SVs keep their multiplier as is. `end` is the end of the
+/// last object; if it's `None`, the first BPM is returned. Returns `None` if there are no timing
+/// points.
+///
+/// The timing points must be sorted by timestamp.
+pub fn base_beat_duration(
+    timing_points: &[TimingPoint],
+    end: Option<MapTimestamp>,
+) -> Option<MapTimestampDifference> {
+    let first = timing_points.first()?.beat_duration;
+    let end = match end {
+        Some(end) => end,
+        None => return Some(first),
+    };
+
+    let mut durations: Vec<(MapTimestampDifference, i64)> = Vec::new();
+    let mut next_timestamp = end;
+    for timing_point in timing_points.iter().rev().filter(|x| x.timestamp <= end) {
+        let duration = i64::from(next_timestamp.into_milli_hundredths())
+            - i64::from(timing_point.timestamp.into_milli_hundredths());
+
+        match durations
+            .iter_mut()
+            .find(|(beat_duration, _)| *beat_duration == timing_point.beat_duration)
+        {
+            Some((_, total)) => *total += duration,
+            None => durations.push((timing_point.beat_duration, duration)),
+        }
+
+        next_timestamp = timing_point.timestamp;
+    }
+
+    durations
+        .into_iter()
+        // Prefer the higher BPM on ties so that the result doesn't depend on the order.
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        .map(|(beat_duration, _)| beat_duration)
+        .or(Some(first))
+}
+
+/// Returns `multiplier` scaled by `numerator / denominator`, rounded and saturated.
+fn scale_multiplier(
+    multiplier: ScrollSpeedMultiplier,
+    numerator: MapTimestampDifference,
+    denominator: MapTimestampDifference,
+) -> ScrollSpeedMultiplier {
+    let value =
+        i64::from(multiplier.into_thousandths()) * i64::from(numerator.into_milli_hundredths());
+    let denominator = i64::from(denominator.into_milli_hundredths());
+
+    let scaled = if denominator == 0 {
+        value.signum() * i64::MAX
+    } else {
+        let (quotient, remainder) = (value / denominator, value % denominator);
+        if 2 * remainder.abs() >= denominator.abs() {
+            quotient + value.signum() * denominator.signum()
+        } else {
+            quotient
+        }
+    };
+
+    ScrollSpeedMultiplier::saturating_from_thousandths(scaled)
+}
+
+/// Converts BPM-relative SVs into scroll speed changes.
+///
+/// In osu! and in older `.qua` maps, the BPM affects the scroll speed: the SV multipliers are
+/// relative to the [base BPM](base_beat_duration), and every timing point resets the SV multiplier
+/// back to 1. plitki scroll speed changes are independent of the BPM.
+///
+/// `end` is the end of the last object. Returns the initial scroll speed multiplier and the scroll
+/// speed changes. Without timing points, the SVs are returned as is.
+pub fn normalize_scroll_speed_changes(
+    timing_points: &[TimingPoint],
+    svs: &[ScrollSpeedChange],
+    end: Option<MapTimestamp>,
+) -> (ScrollSpeedMultiplier, Vec<ScrollSpeedChange>) {
+    let mut timing_points = timing_points.to_vec();
+    timing_points.sort_by_key(|x| x.timestamp);
+    let mut svs = svs.to_vec();
+    svs.sort_by_key(|x| x.timestamp);
+
+    let base = match base_beat_duration(&timing_points, end) {
+        Some(x) => x,
+        // Without timing points BPM can't affect the SVs in the first place.
+        None => return (ScrollSpeedMultiplier::default(), svs),
+    };
+
+    let mut scroll_speed_changes = Vec::new();
+    let mut current_beat_duration = timing_points[0].beat_duration;
+    let mut current_sv_index = 0;
+    let mut current_sv_timestamp = None;
+    let mut current_sv_multiplier = ScrollSpeedMultiplier::default();
+    let mut current_adjusted_multiplier = None;
+    let mut initial_multiplier = None;
+
+    for (i, timing_point) in timing_points.iter().enumerate() {
+        let next_timing_point_has_same_timestamp = timing_points
+            .get(i + 1)
+            .is_some_and(|next| next.timestamp == timing_point.timestamp);
+
+        while let Some(&sv) = svs.get(current_sv_index) {
+            if sv.timestamp > timing_point.timestamp {
+                break;
+            }
+
+            // If there are more timing points on this timestamp, the SV only applies on the very
+            // last one, so skip it for now.
+            if next_timing_point_has_same_timestamp && sv.timestamp == timing_point.timestamp {
+                break;
             }
 
-            new_timing_points.push(timing_points[i]);
+            if sv.timestamp < timing_point.timestamp {
+                let multiplier = scale_multiplier(sv.multiplier, base, current_beat_duration);
+
+                if current_adjusted_multiplier.is_none() {
+                    current_adjusted_multiplier = Some(multiplier);
+                    initial_multiplier = Some(multiplier);
+                }
+
+                if current_adjusted_multiplier != Some(multiplier) {
+                    scroll_speed_changes.push(ScrollSpeedChange {
+                        timestamp: sv.timestamp,
+                        multiplier,
+                    });
+                    current_adjusted_multiplier = Some(multiplier);
+                }
+            }
+
+            current_sv_timestamp = Some(sv.timestamp);
+            current_sv_multiplier = sv.multiplier;
+            current_sv_index += 1;
+        }
+
+        // Timing points reset the previous SV multiplier.
+        if current_sv_timestamp.is_none_or(|x| x < timing_point.timestamp) {
+            current_sv_multiplier = ScrollSpeedMultiplier::default();
         }
 
-        *timing_points = new_timing_points;
+        current_beat_duration = timing_point.beat_duration;
+
+        let multiplier = scale_multiplier(current_sv_multiplier, base, current_beat_duration);
+
+        if current_adjusted_multiplier.is_none() {
+            current_adjusted_multiplier = Some(multiplier);
+            initial_multiplier = Some(multiplier);
+        }
+
+        if current_adjusted_multiplier != Some(multiplier) {
+            scroll_speed_changes.push(ScrollSpeedChange {
+                timestamp: timing_point.timestamp,
+                multiplier,
+            });
+            current_adjusted_multiplier = Some(multiplier);
+        }
     }
 
-    /// Returns the number of lanes in the map.
-    #[inline]
-    pub fn lane_count(&self) -> usize {
-        self.lanes.len()
+    for sv in &svs[current_sv_index..] {
+        let multiplier = scale_multiplier(sv.multiplier, base, current_beat_duration);
+        if current_adjusted_multiplier != Some(multiplier) {
+            scroll_speed_changes.push(ScrollSpeedChange {
+                timestamp: sv.timestamp,
+                multiplier,
+            });
+            current_adjusted_multiplier = Some(multiplier);
+        }
+    }
+
+    (initial_multiplier.unwrap_or_default(), scroll_speed_changes)
+}
+
+/// Converts scroll speed changes into BPM-relative SVs.
+///
+/// This is the reverse of [`normalize_scroll_speed_changes()`]. BPM-relative SVs have no initial
+/// multiplier, so it is simulated with an SV 1 ms before the first change. Without timing points,
+/// the scroll speed changes are returned as is, and the initial multiplier is lost.
+pub fn denormalize_scroll_speed_changes(
+    timing_points: &[TimingPoint],
+    scroll_speed_changes: &[ScrollSpeedChange],
+    initial_multiplier: ScrollSpeedMultiplier,
+    end: Option<MapTimestamp>,
+) -> Vec<ScrollSpeedChange> {
+    let mut timing_points = timing_points.to_vec();
+    timing_points.sort_by_key(|x| x.timestamp);
+    let mut scroll_speed_changes = scroll_speed_changes.to_vec();
+    scroll_speed_changes.sort_by_key(|x| x.timestamp);
+
+    let base = match base_beat_duration(&timing_points, end) {
+        Some(x) => x,
+        // Without timing points BPM can't affect the SVs in the first place.
+        None => return scroll_speed_changes,
+    };
+
+    let one_ms_before = |timestamp: MapTimestamp| {
+        MapTimestamp::saturating_from_milli_hundredths(
+            timestamp.into_milli_hundredths().saturating_sub(100),
+        )
+    };
+
+    let mut svs = Vec::new();
+    let mut current_beat_duration = timing_points[0].beat_duration;
+    let mut current_index = 0;
+    let mut current_multiplier = initial_multiplier;
+    let mut current_adjusted_multiplier = None;
+
+    for (i, timing_point) in timing_points.iter().enumerate() {
+        while let Some(&change) = scroll_speed_changes.get(current_index) {
+            if change.timestamp > timing_point.timestamp {
+                break;
+            }
+
+            if change.timestamp < timing_point.timestamp {
+                let multiplier = scale_multiplier(change.multiplier, current_beat_duration, base);
+
+                if current_adjusted_multiplier != Some(multiplier) {
+                    if current_adjusted_multiplier.is_none()
+                        && change.multiplier != initial_multiplier
+                    {
+                        // Insert an SV 1 ms earlier to simulate the initial scroll speed
+                        // multiplier.
+                        svs.push(ScrollSpeedChange {
+                            timestamp: one_ms_before(change.timestamp),
+                            multiplier: scale_multiplier(
+                                initial_multiplier,
+                                current_beat_duration,
+                                base,
+                            ),
+                        });
+                    }
+
+                    svs.push(ScrollSpeedChange {
+                        timestamp: change.timestamp,
+                        multiplier,
+                    });
+                    current_adjusted_multiplier = Some(multiplier);
+                }
+            }
+
+            current_multiplier = change.multiplier;
+            current_index += 1;
+        }
+
+        current_beat_duration = timing_point.beat_duration;
+
+        if current_adjusted_multiplier.is_none() && current_multiplier != initial_multiplier {
+            // Insert an SV 1 ms earlier to simulate the initial scroll speed multiplier.
+            svs.push(ScrollSpeedChange {
+                timestamp: one_ms_before(timing_point.timestamp),
+                multiplier: scale_multiplier(initial_multiplier, current_beat_duration, base),
+            });
+        }
+
+        // Timing points reset the SV multiplier.
+        current_adjusted_multiplier = Some(ScrollSpeedMultiplier::default());
+
+        // Skip over multiple timing points at the same timestamp.
+        if timing_points
+            .get(i + 1)
+            .is_some_and(|next| next.timestamp == timing_point.timestamp)
+        {
+            continue;
+        }
+
+        let multiplier = scale_multiplier(current_multiplier, current_beat_duration, base);
+        if current_adjusted_multiplier != Some(multiplier) {
+            svs.push(ScrollSpeedChange {
+                timestamp: timing_point.timestamp,
+                multiplier,
+            });
+            current_adjusted_multiplier = Some(multiplier);
+        }
+    }
+
+    for change in &scroll_speed_changes[current_index..] {
+        let multiplier = scale_multiplier(change.multiplier, current_beat_duration, base);
+        if current_adjusted_multiplier != Some(multiplier) {
+            svs.push(ScrollSpeedChange {
+                timestamp: change.timestamp,
+                multiplier,
+            });
+            current_adjusted_multiplier = Some(multiplier);
+        }
     }
+
+    svs
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use alloc::vec;
 
     #[test]
     fn sort_and_dedup_scroll_speed_changes() {
@@ -319,6 +869,7 @@ mod tests {
                 },
             ],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+            scroll_groups: vec![],
             lanes: Vec::new(),
         };
 
@@ -366,6 +917,7 @@ mod tests {
                 multiplier: ScrollSpeedMultiplier::new(0),
             }],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+            scroll_groups: vec![],
             lanes: Vec::new(),
         };
 
@@ -389,6 +941,7 @@ mod tests {
                 multiplier: ScrollSpeedMultiplier::new(1),
             }],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+            scroll_groups: vec![],
             lanes: Vec::new(),
         };
 
@@ -428,12 +981,121 @@ mod tests {
                 },
             ],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(-1),
+            scroll_groups: vec![],
             lanes: vec![],
         };
 
         map.sort_and_dedup_scroll_speed_changes();
     }
 
+    fn bpm_relative_svs() -> (Vec<TimingPoint>, Vec<ScrollSpeedChange>) {
+        let timing_point = |millis, beat_duration| TimingPoint {
+            timestamp: MapTimestamp::from_millis(millis),
+            beat_duration: MapTimestampDifference::from_millis(beat_duration),
+            signature: TimeSignature {
+                beat_count: 4,
+                beat_unit: 4,
+            },
+        };
+        let sv = |millis, multiplier| ScrollSpeedChange {
+            timestamp: MapTimestamp::from_millis(millis),
+            multiplier: ScrollSpeedMultiplier::new(multiplier),
+        };
+
+        (
+            vec![timing_point(0, 500), timing_point(1000, 250)],
+            vec![sv(500, 2000), sv(1200, 500)],
+        )
+    }
+
+    #[test]
+    fn base_beat_duration_is_the_longest() {
+        let (timing_points, _) = bpm_relative_svs();
+
+        assert_eq!(base_beat_duration(&[], None), None);
+        assert_eq!(
+            base_beat_duration(&timing_points, None),
+            Some(MapTimestampDifference::from_millis(500))
+        );
+        assert_eq!(
+            base_beat_duration(&timing_points, Some(MapTimestamp::from_millis(1500))),
+            Some(MapTimestampDifference::from_millis(500))
+        );
+        assert_eq!(
+            base_beat_duration(&timing_points, Some(MapTimestamp::from_millis(3000))),
+            Some(MapTimestampDifference::from_millis(250))
+        );
+        // Timing points after the last object don't count.
+        assert_eq!(
+            base_beat_duration(&timing_points, Some(MapTimestamp::from_millis(-1000))),
+            Some(MapTimestampDifference::from_millis(500))
+        );
+        // On ties, the higher BPM wins.
+        assert_eq!(
+            base_beat_duration(&timing_points, Some(MapTimestamp::from_millis(2000))),
+            Some(MapTimestampDifference::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn normalize_and_denormalize_scroll_speed_changes() {
+        let (timing_points, svs) = bpm_relative_svs();
+        let end = Some(MapTimestamp::from_millis(1500));
+
+        let (initial, changes) = normalize_scroll_speed_changes(&timing_points, &svs, end);
+        assert_eq!(initial, ScrollSpeedMultiplier::new(1000));
+        assert_eq!(
+            changes,
+            vec![
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(500),
+                    multiplier: ScrollSpeedMultiplier::new(2000),
+                },
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(1200),
+                    multiplier: ScrollSpeedMultiplier::new(1000),
+                },
+            ]
+        );
+
+        assert_eq!(
+            denormalize_scroll_speed_changes(&timing_points, &changes, initial, end),
+            svs
+        );
+    }
+
+    #[test]
+    fn denormalize_scroll_speed_changes_initial_multiplier() {
+        let (timing_points, _) = bpm_relative_svs();
+        let changes = [ScrollSpeedChange {
+            timestamp: MapTimestamp::from_millis(-500),
+            multiplier: ScrollSpeedMultiplier::new(1000),
+        }];
+
+        assert_eq!(
+            denormalize_scroll_speed_changes(
+                &timing_points,
+                &changes,
+                ScrollSpeedMultiplier::new(3000),
+                None
+            ),
+            vec![
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(-501),
+                    multiplier: ScrollSpeedMultiplier::new(3000),
+                },
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(-500),
+                    multiplier: ScrollSpeedMultiplier::new(1000),
+                },
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(1000),
+                    multiplier: ScrollSpeedMultiplier::new(500),
+                },
+            ]
+        );
+    }
+
     proptest! {
         #[test]
         fn sort_and_dedup_scroll_speed_changes_doesnt_panic(mut map: Map) {
@@ -462,6 +1124,24 @@ mod tests {
             }
         }
 
+        #[test]
+        fn map_mapset_roundtrip(map: Map) {
+            let mapset = Mapset::from(map.clone());
+            prop_assert_eq!(mapset.difficulties.len(), 1);
+            prop_assert_eq!(&mapset.difficulties[0].chart, &Chart::from(map.clone()));
+            prop_assert_eq!(mapset.into_maps(), vec![map]);
+        }
+
+        #[test]
+        fn normalize_and_denormalize_scroll_speed_changes_dont_panic(map: Map) {
+            let (initial, changes) = normalize_scroll_speed_changes(
+                &map.timing_points,
+                &map.scroll_speed_changes,
+                None,
+            );
+            denormalize_scroll_speed_changes(&map.timing_points, &changes, initial, None);
+        }
+
         #[test]
         fn sort_and_dedup_timing_points_doesnt_panic(mut map: Map) {
             map.sort_and_dedup_timing_points();
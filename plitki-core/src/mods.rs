@@ -0,0 +1,532 @@
+//! Gameplay modifiers which transform the objects of a map.
+//!
+//! Modifiers are applied to a [`Map`] or a [`Chart`] before creating a
+//! [`GameState`](crate::state::GameState). Given a map with objects that don't overlap, every
+//! modifier produces a map with objects that don't overlap either, so it is still accepted by
+//! [`GameState::new`](crate::state::GameState::new).
+//!
+//! Objects of scroll groups are transformed together with the other objects in their lane, and
+//! stay in their scroll group.
+use alloc::{vec, vec::Vec};
+use core::{cmp::max, convert::TryFrom, mem};
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+use crate::{
+    map::{Chart, Lane, Map, ScrollGroup},
+    object::Object,
+    rng::SplitMix64,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+
+/// A gameplay modifier.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum Mod {
+    /// Flips the lanes horizontally.
+    Mirror,
+    /// Shuffles the lanes.
+    ///
+    /// The same seed always produces the same lane order.
+    Random {
+        /// Seed of the pseudorandom number generator.
+        seed: u64,
+    },
+    /// Moves every object into a random lane which is free at the time.
+    ///
+    /// The same seed always produces the same object placement.
+    PerNoteRandom {
+        /// Seed of the pseudorandom number generator.
+        seed: u64,
+    },
+    /// Turns long notes into regular objects at their start timestamp.
+    NoLongNotes,
+    /// Turns objects into long notes which last until shortly before the next object in the lane.
+    ///
+    /// Objects are extended to end `gap` before the next object. Objects which are already longer
+    /// than that, and the last object in every lane, are left as is.
+    FullLongNote {
+        /// Time between the end of a long note and the next object in the lane.
+        #[cfg_attr(test, proptest(strategy = "arbitrary_gap()"))]
+        gap: MapTimestampDifference,
+    },
+    /// Swaps the space between objects with the objects themselves.
+    ///
+    /// Every object turns into a long note which starts where the object ended and ends `gap`
+    /// before the next object in the lane. The last object in every lane turns into a regular
+    /// object.
+    Inverse {
+        /// Time between the end of a long note and the next object in the lane.
+        #[cfg_attr(test, proptest(strategy = "arbitrary_gap()"))]
+        gap: MapTimestampDifference,
+    },
+}
+
+#[cfg(test)]
+fn arbitrary_gap() -> impl proptest::strategy::Strategy<Value = MapTimestampDifference> {
+    (0..2i32.pow(30)).prop_map(MapTimestampDifference::from_milli_hundredths)
+}
+
+impl Mod {
+    /// Applies the modifier to a map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `gap` of [`Mod::FullLongNote`] or [`Mod::Inverse`] is negative.
+    #[inline]
+    pub fn apply(self, map: &mut Map) {
+        self.apply_to_lanes(&mut map.lanes, &mut map.scroll_groups);
+    }
+
+    /// Applies the modifier to a chart.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `gap` of [`Mod::FullLongNote`] or [`Mod::Inverse`] is negative.
+    #[inline]
+    pub fn apply_to_chart(self, chart: &mut Chart) {
+        self.apply_to_lanes(&mut chart.lanes, &mut chart.scroll_groups);
+    }
+
+    fn apply_to_lanes(self, lanes: &mut [Lane], scroll_groups: &mut [ScrollGroup]) {
+        let mut tagged_lanes = merge_scroll_groups(lanes, scroll_groups);
+        self.apply_to_tagged_lanes(&mut tagged_lanes);
+        split_scroll_groups(tagged_lanes, lanes, scroll_groups);
+    }
+
+    fn apply_to_tagged_lanes(self, lanes: &mut [Vec<TaggedObject>]) {
+        match self {
+            Mod::Mirror => lanes.reverse(),
+            Mod::Random { seed } => {
+                let mut rng = SplitMix64(seed);
+
+                // Fisher-Yates shuffle.
+                for i in (1..lanes.len()).rev() {
+                    lanes.swap(i, rng.next_below(i + 1));
+                }
+            }
+            Mod::PerNoteRandom { seed } => per_note_random(lanes, seed),
+            Mod::NoLongNotes => {
+                for (object, _) in lanes.iter_mut().flatten() {
+                    *object = Object::Regular {
+                        timestamp: object.start_timestamp(),
+                    };
+                }
+            }
+            Mod::FullLongNote { gap } => {
+                let gap = gap.into_milli_hundredths();
+                assert!(gap >= 0, "gap must be non-negative");
+
+                for objects in lanes {
+                    for i in 1..objects.len() {
+                        let next = objects[i].0.start_timestamp();
+                        let object = &mut objects[i - 1].0;
+
+                        if let Some(end) = release_before(next, gap) {
+                            if end > object.end_timestamp() {
+                                *object = Object::LongNote {
+                                    start: object.start_timestamp(),
+                                    end,
+                                };
+                            }
+                        }
+                    }
+                }
+            }
+            Mod::Inverse { gap } => {
+                let gap = gap.into_milli_hundredths();
+                assert!(gap >= 0, "gap must be non-negative");
+
+                for objects in lanes {
+                    for i in 0..objects.len() {
+                        let start = objects[i].0.end_timestamp();
+                        objects[i].0 =
+                            match objects.get(i + 1).map(|(next, _)| next.start_timestamp()) {
+                                Some(next) => match release_before(next, gap) {
+                                    Some(end) if end > start => Object::LongNote { start, end },
+                                    // Not enough space for a long note.
+                                    _ => Object::Regular { timestamp: start },
+                                },
+                                None => Object::Regular {
+                                    timestamp: objects[i].0.start_timestamp(),
+                                },
+                            };
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An object along with the index of its scroll group, or `None` if it's not in a scroll group.
+type TaggedObject = (Object, Option<usize>);
+
+/// Takes the objects out of the lanes and the scroll groups, merging them into sorted lanes.
+///
+/// Scroll group lanes past the lane count are left as is.
+fn merge_scroll_groups(
+    lanes: &mut [Lane],
+    scroll_groups: &mut [ScrollGroup],
+) -> Vec<Vec<TaggedObject>> {
+    lanes
+        .iter_mut()
+        .enumerate()
+        .map(|(index, lane)| {
+            let mut objects: Vec<TaggedObject> = mem::take(&mut lane.objects)
+                .into_iter()
+                .map(|object| (object, None))
+                .collect();
+
+            for (group_index, group) in scroll_groups.iter_mut().enumerate() {
+                if let Some(group_lane) = group.lanes.get_mut(index) {
+                    objects.extend(
+                        mem::take(&mut group_lane.objects)
+                            .into_iter()
+                            .map(|object| (object, Some(group_index))),
+                    );
+                }
+            }
+
+            // The transforms rely on the objects being sorted. Stable sort to keep the result
+            // deterministic for objects with equal start timestamps.
+            objects.sort_by_key(|(object, _)| object.start_timestamp());
+            objects
+        })
+        .collect()
+}
+
+/// Puts the objects back into the lanes and the scroll groups they came from.
+fn split_scroll_groups(
+    tagged_lanes: Vec<Vec<TaggedObject>>,
+    lanes: &mut [Lane],
+    scroll_groups: &mut [ScrollGroup],
+) {
+    for (index, objects) in tagged_lanes.into_iter().enumerate() {
+        for (object, group) in objects {
+            let lane = match group {
+                None => &mut lanes[index],
+                Some(group) => {
+                    let group_lanes = &mut scroll_groups[group].lanes;
+                    if group_lanes.len() <= index {
+                        group_lanes.resize(index + 1, Lane::new());
+                    }
+                    &mut group_lanes[index]
+                }
+            };
+            lane.objects.push(object);
+        }
+    }
+}
+
+/// Returns the timestamp `gap` before `next`, and at least slightly before `next`, so that a long
+/// note ending there doesn't overlap an object at `next`.
+fn release_before(next: MapTimestamp, gap: i32) -> Option<MapTimestamp> {
+    let end = i64::from(next.into_milli_hundredths()) - i64::from(max(gap, 1));
+    i32::try_from(end)
+        .ok()
+        .and_then(MapTimestamp::checked_from_milli_hundredths)
+}
+
+fn per_note_random(lanes: &mut [Vec<TaggedObject>], seed: u64) {
+    let mut objects: Vec<TaggedObject> = lanes.iter_mut().flat_map(mem::take).collect();
+    // Stable sort to keep the result deterministic for objects with equal start timestamps.
+    objects.sort_by_key(|(object, _)| object.start_timestamp());
+
+    let mut rng = SplitMix64(seed);
+    let mut free_lanes = Vec::with_capacity(lanes.len());
+    let mut last_ends: Vec<Option<MapTimestamp>> = vec![None; lanes.len()];
+
+    for (object, group) in objects {
+        let start = object.start_timestamp();
+
+        // The objects which are still active at this timestamp were in different lanes in the
+        // original map, along with this object, so there's always a free lane.
+        free_lanes.clear();
+        free_lanes.extend(
+            last_ends
+                .iter()
+                .enumerate()
+                .filter(|(_, end)| end.is_none_or(|end| end < start))
+                .map(|(lane, _)| lane),
+        );
+
+        let lane = if free_lanes.is_empty() {
+            // Only possible if the original objects overlap. Pick the lane which frees up first.
+            (0..lanes.len())
+                .min_by_key(|&lane| last_ends[lane])
+                .unwrap()
+        } else {
+            free_lanes[rng.next_below(free_lanes.len())]
+        };
+
+        lanes[lane].push((object, group));
+        last_ends[lane] = Some(object.end_timestamp());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        judgement::HitWindows, map::ArbitraryMapType, scroll::ScrollSpeedMultiplier,
+        state::GameState,
+    };
+
+    fn regular(millis: i32) -> Object {
+        Object::Regular {
+            timestamp: MapTimestamp::from_millis(millis),
+        }
+    }
+
+    fn long_note(start: i32, end: i32) -> Object {
+        Object::LongNote {
+            start: MapTimestamp::from_millis(start),
+            end: MapTimestamp::from_millis(end),
+        }
+    }
+
+    fn map(lanes: Vec<Vec<Object>>) -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            mapper: None,
+            background_file: None,
+            audio_file: None,
+            timing_points: vec![],
+            scroll_speed_changes: vec![],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
+            lanes: lanes.into_iter().map(|objects| Lane { objects }).collect(),
+        }
+    }
+
+    fn objects(map: &Map) -> Vec<Vec<Object>> {
+        map.lanes.iter().map(|lane| lane.objects.clone()).collect()
+    }
+
+    fn object_count(map: &Map) -> usize {
+        map.lanes.iter().map(|lane| lane.objects.len()).sum()
+    }
+
+    #[test]
+    fn mirror() {
+        let mut map = map(vec![vec![regular(0)], vec![], vec![long_note(10, 20)]]);
+        Mod::Mirror.apply(&mut map);
+        assert_eq!(
+            objects(&map),
+            vec![vec![long_note(10, 20)], vec![], vec![regular(0)]]
+        );
+    }
+
+    #[test]
+    fn mirror_keeps_scroll_groups() {
+        let mut map = map(vec![vec![regular(0)], vec![]]);
+        map.scroll_groups.push(ScrollGroup {
+            name: "group".into(),
+            scroll_speed_changes: vec![],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(2000),
+            lanes: vec![Lane {
+                objects: vec![regular(10)],
+            }],
+        });
+
+        Mod::Mirror.apply(&mut map);
+        assert_eq!(objects(&map), vec![vec![], vec![regular(0)]]);
+        assert_eq!(
+            map.scroll_groups[0].lanes,
+            vec![
+                Lane { objects: vec![] },
+                Lane {
+                    objects: vec![regular(10)]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn full_long_note_across_scroll_groups() {
+        let mut map = map(vec![vec![regular(0)]]);
+        map.scroll_groups.push(ScrollGroup {
+            name: "group".into(),
+            scroll_speed_changes: vec![],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(2000),
+            lanes: vec![Lane {
+                objects: vec![regular(100)],
+            }],
+        });
+
+        Mod::FullLongNote {
+            gap: MapTimestampDifference::from_millis(10),
+        }
+        .apply(&mut map);
+        assert_eq!(objects(&map), vec![vec![long_note(0, 90)]]);
+        assert_eq!(map.scroll_groups[0].lanes[0].objects, vec![regular(100)]);
+    }
+
+    #[test]
+    fn random_is_deterministic() {
+        let original = map((0..7).map(|i| vec![regular(i)]).collect());
+
+        let mut a = original.clone();
+        let mut b = original.clone();
+        Mod::Random { seed: 42 }.apply(&mut a);
+        Mod::Random { seed: 42 }.apply(&mut b);
+        assert_eq!(a, b);
+
+        // Lanes are moved around as a whole.
+        let mut lanes = objects(&a);
+        lanes.sort_by_key(|objects| objects[0].start_timestamp());
+        assert_eq!(lanes, objects(&original));
+    }
+
+    #[test]
+    fn per_note_random_is_deterministic() {
+        let original = map(vec![
+            vec![regular(0), regular(20), long_note(40, 60)],
+            vec![regular(10), long_note(30, 50)],
+        ]);
+
+        let mut a = original.clone();
+        let mut b = original.clone();
+        Mod::PerNoteRandom { seed: 42 }.apply(&mut a);
+        Mod::PerNoteRandom { seed: 42 }.apply(&mut b);
+        assert_eq!(a, b);
+        assert_eq!(object_count(&a), object_count(&original));
+    }
+
+    #[test]
+    fn per_note_random_full_chord() {
+        // With every lane occupied there's only one way to place the long notes.
+        let mut map = map(vec![
+            vec![long_note(0, 100), regular(200)],
+            vec![long_note(0, 100)],
+        ]);
+        Mod::PerNoteRandom { seed: 0 }.apply(&mut map);
+        assert!(map
+            .lanes
+            .iter()
+            .all(|lane| lane.objects[0] == long_note(0, 100)));
+    }
+
+    #[test]
+    fn no_long_notes() {
+        let mut map = map(vec![vec![regular(0), long_note(10, 20)]]);
+        Mod::NoLongNotes.apply(&mut map);
+        assert_eq!(objects(&map), vec![vec![regular(0), regular(10)]]);
+    }
+
+    #[test]
+    fn full_long_note() {
+        let mut map = map(vec![vec![
+            regular(0),
+            long_note(100, 150),
+            long_note(200, 390),
+            regular(400),
+            regular(405),
+            regular(500),
+        ]]);
+        Mod::FullLongNote {
+            gap: MapTimestampDifference::from_millis(10),
+        }
+        .apply(&mut map);
+        assert_eq!(
+            objects(&map),
+            vec![vec![
+                long_note(0, 90),
+                long_note(100, 190),
+                long_note(200, 390),
+                regular(400),
+                long_note(405, 490),
+                regular(500),
+            ]]
+        );
+    }
+
+    #[test]
+    fn inverse() {
+        let mut map = map(vec![vec![
+            regular(0),
+            long_note(100, 150),
+            regular(200),
+            regular(205),
+            long_note(300, 400),
+        ]]);
+        Mod::Inverse {
+            gap: MapTimestampDifference::from_millis(10),
+        }
+        .apply(&mut map);
+        assert_eq!(
+            objects(&map),
+            vec![vec![
+                long_note(0, 90),
+                long_note(150, 190),
+                regular(200),
+                long_note(205, 290),
+                regular(300),
+            ]]
+        );
+    }
+
+    #[test]
+    fn zero_gap_doesnt_overlap() {
+        for mod_ in [
+            Mod::FullLongNote {
+                gap: MapTimestampDifference::from_millis(0),
+            },
+            Mod::Inverse {
+                gap: MapTimestampDifference::from_millis(0),
+            },
+        ] {
+            let mut map = map(vec![vec![regular(0), regular(1)]]);
+            mod_.apply(&mut map);
+            assert!(GameState::new(map, HitWindows::default()).is_ok());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_gap_panics() {
+        let mut map = map(vec![vec![regular(0), regular(100)]]);
+        Mod::Inverse {
+            gap: MapTimestampDifference::from_millis(-10),
+        }
+        .apply(&mut map);
+    }
+
+    proptest! {
+        #[test]
+        fn mods_dont_create_overlaps(
+            mut map in any_with::<Map>(ArbitraryMapType::Valid),
+            mods: Vec<Mod>,
+        ) {
+            for mod_ in mods {
+                mod_.apply(&mut map);
+            }
+
+            prop_assert!(GameState::new(map, HitWindows::default()).is_ok());
+        }
+
+        #[test]
+        fn mods_keep_object_count(
+            mut map in any_with::<Map>(ArbitraryMapType::Valid),
+            mod_: Mod,
+        ) {
+            let lane_count = map.lane_count();
+            let count = object_count(&map);
+
+            mod_.apply(&mut map);
+
+            prop_assert_eq!(map.lane_count(), lane_count);
+            prop_assert_eq!(object_count(&map), count);
+        }
+
+        #[test]
+        fn mods_dont_panic(mut map in any_with::<Map>(ArbitraryMapType::Any), mod_: Mod) {
+            mod_.apply(&mut map);
+        }
+    }
+}
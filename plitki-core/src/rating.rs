@@ -0,0 +1,439 @@
+//! Difficulty rating calculation.
+//!
+//! The rating is strain-based: every object adds to the strain of its lane and to the overall
+//! strain, both of which decay over time. The map is split into sections, the peak strain of every
+//! section is recorded, and the star rating is a weighted sum of the section peaks, with the
+//! hardest sections weighing the most.
+//!
+//! All computations use integer math, so the rating is the same on every platform.
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use alloc::{vec, vec::Vec};
+use core::cmp::{max, min};
+
+use crate::{
+    map::{lane_objects, Chart, Lane, Map, ScrollGroup},
+    object::Object,
+    timing::{GameTimestampDifference, MapTimestamp, Rate},
+};
+
+/// Length of one section, in <sup>1</sup>⁄<sub>100</sub>ths of a millisecond of real time.
+const SECTION_LENGTH: i64 = 400_00;
+
+/// Half-life of the lane strain, in <sup>1</sup>⁄<sub>100</sub>ths of a millisecond.
+const LANE_HALF_LIFE: i64 = 333_33;
+/// Half-life of the overall strain, in <sup>1</sup>⁄<sub>100</sub>ths of a millisecond.
+const OVERALL_HALF_LIFE: i64 = 575_71;
+
+/// Strain added to the lane of every object, in thousandths.
+const LANE_STRAIN: u64 = 2_000;
+/// Overall strain added by every object, in thousandths.
+const OVERALL_STRAIN: u64 = 1_000;
+/// Overall strain added by an object which is released while another long note is still held.
+const RELEASE_STRAIN: u64 = 1_000;
+/// Overall strain added by an object on the same hand as the previous object, but another finger.
+const SAME_HAND_STRAIN: u64 = 250;
+/// Strain multiplier for objects pressed while another long note is held, in thousandths.
+const HOLD_MULTIPLIER: u64 = 1_250;
+
+/// Long note ends closer than this are considered released together, in
+/// <sup>1</sup>⁄<sub>100</sub>ths of a millisecond.
+const RELEASE_LENIENCY: i64 = 1_00;
+
+/// Weight multiplier of every next hardest section, in thousandths.
+const SECTION_WEIGHT_DECAY: u64 = 900;
+/// Multiplier from the weighted strain sum to the star rating, in thousandths.
+const STAR_MULTIPLIER: u64 = 18;
+
+/// Difficulty rating of a map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DifficultyRating {
+    /// The star rating, in <sup>1</sup>⁄<sub>100</sub>ths of a star.
+    pub stars: u32,
+    /// Timestamp where the first section starts.
+    ///
+    /// This is the start timestamp of the first object.
+    pub start: MapTimestamp,
+    /// Length of every section in real time, that is, with the rate applied.
+    pub section_length: GameTimestampDifference,
+    /// Peak strain of every section, in thousandths.
+    ///
+    /// Useful for drawing a difficulty graph.
+    pub section_strains: Vec<u32>,
+}
+
+/// Which hand presses a lane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hand {
+    Left,
+    Right,
+    /// The middle lane of an odd lane count, which can be pressed with either hand.
+    Either,
+}
+
+/// An object with timestamps in real time.
+#[derive(Debug, Clone, Copy)]
+struct Note {
+    lane: usize,
+    start: i64,
+    end: i64,
+}
+
+impl DifficultyRating {
+    /// Computes the difficulty rating of a map played at the given rate.
+    #[inline]
+    pub fn for_map(map: &Map, rate: Rate) -> Self {
+        Self::for_lanes(&map.lanes, &map.scroll_groups, rate)
+    }
+
+    /// Computes the difficulty rating of a chart played at the given rate.
+    #[inline]
+    pub fn for_chart(chart: &Chart, rate: Rate) -> Self {
+        Self::for_lanes(&chart.lanes, &chart.scroll_groups, rate)
+    }
+
+    /// Returns the star rating as an `f32`.
+    #[inline]
+    pub fn stars_f32(&self) -> f32 {
+        self.stars as f32 / 100.
+    }
+
+    fn for_lanes(lanes: &[Lane], scroll_groups: &[ScrollGroup], rate: Rate) -> Self {
+        let rate = i64::from(rate.into_thousandths());
+        let to_real =
+            |timestamp: MapTimestamp| i64::from(timestamp.into_milli_hundredths()) * 1000 / rate;
+
+        // Scroll groups only affect the visuals, so their objects are rated like any other.
+        let mut notes: Vec<Note> = (0..lanes.len())
+            .flat_map(|lane| {
+                lane_objects(lanes, scroll_groups, lane).map(move |object| Note {
+                    lane,
+                    start: to_real(object.start_timestamp()),
+                    end: to_real(max(object.start_timestamp(), object.end_timestamp())),
+                })
+            })
+            .collect();
+        notes.sort_by_key(|note| (note.start, note.lane));
+
+        let start = (0..lanes.len())
+            .flat_map(|lane| lane_objects(lanes, scroll_groups, lane))
+            .map(Object::start_timestamp)
+            .min()
+            .unwrap_or_else(MapTimestamp::zero);
+        let section_length = GameTimestampDifference::from_milli_hundredths(SECTION_LENGTH as i32);
+
+        let first_start = match notes.first() {
+            Some(note) => note.start,
+            None => {
+                return Self {
+                    stars: 0,
+                    start,
+                    section_length,
+                    section_strains: Vec::new(),
+                }
+            }
+        };
+
+        let lane_count = lanes.len();
+        let hand = |lane: usize| {
+            if lane_count % 2 == 1 && lane == lane_count / 2 {
+                Hand::Either
+            } else if lane < lane_count / 2 {
+                Hand::Left
+            } else {
+                Hand::Right
+            }
+        };
+
+        let mut lane_strains = vec![0u64; lane_count];
+        let mut lane_last_starts = vec![first_start; lane_count];
+        let mut hold_ends: Vec<Option<i64>> = vec![None; lane_count];
+        let mut overall_strain = 0u64;
+
+        // The last object at an earlier timestamp, used to detect same-hand patterns.
+        let mut previous: Option<Note> = None;
+        let mut last = notes[0];
+
+        let mut section_strains = Vec::new();
+        let mut section_peak = 0u64;
+        let mut section_end = first_start + SECTION_LENGTH;
+
+        for (i, &note) in notes.iter().enumerate() {
+            // Finish the sections before this note.
+            while note.start >= section_end {
+                section_strains.push(section_peak);
+
+                // The next section starts with the strain left over from the last note.
+                section_peak =
+                    decay(
+                        lane_strains[last.lane],
+                        section_end - last.start,
+                        LANE_HALF_LIFE,
+                    ) + decay(overall_strain, section_end - last.start, OVERALL_HALF_LIFE);
+                section_end += SECTION_LENGTH;
+            }
+
+            if i > 0 && notes[i - 1].start < note.start {
+                previous = Some(notes[i - 1]);
+            }
+
+            // Check the long notes held in other lanes.
+            let mut hold_multiplier = 1000;
+            let mut release_strain = 0;
+            for (lane, hold_end) in hold_ends.iter().enumerate() {
+                let hold_end = match *hold_end {
+                    Some(end) if lane != note.lane && end > note.start => end,
+                    _ => continue,
+                };
+
+                if note.end > hold_end + RELEASE_LENIENCY {
+                    // The other long note must be released while this object is held.
+                    release_strain = RELEASE_STRAIN;
+                }
+                if hold_end > note.end + RELEASE_LENIENCY {
+                    // This object is pressed while the other long note is held.
+                    hold_multiplier = HOLD_MULTIPLIER;
+                }
+            }
+
+            let mut added_overall = OVERALL_STRAIN + release_strain;
+            if let Some(previous) = previous {
+                let (a, b) = (hand(previous.lane), hand(note.lane));
+                if previous.lane != note.lane && a == b && a != Hand::Either {
+                    added_overall += SAME_HAND_STRAIN;
+                }
+            }
+
+            let lane_strain = &mut lane_strains[note.lane];
+            *lane_strain = decay(
+                *lane_strain,
+                note.start - lane_last_starts[note.lane],
+                LANE_HALF_LIFE,
+            ) + LANE_STRAIN * hold_multiplier / 1000;
+            overall_strain = decay(overall_strain, note.start - last.start, OVERALL_HALF_LIFE)
+                + added_overall * hold_multiplier / 1000;
+
+            section_peak = max(section_peak, *lane_strain + overall_strain);
+
+            lane_last_starts[note.lane] = note.start;
+            hold_ends[note.lane] = Some(note.end);
+            last = note;
+        }
+        section_strains.push(section_peak);
+
+        // Sum the section peaks, hardest first, with decreasing weights.
+        let mut sorted = section_strains.clone();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut weighted_sum = 0u64;
+        let mut weight = 1_000_000u64;
+        for strain in sorted {
+            if weight == 0 {
+                break;
+            }
+
+            weighted_sum += strain * weight / 1_000_000;
+            weight = weight * SECTION_WEIGHT_DECAY / 1000;
+        }
+
+        // Thousandths of strain times thousandths of a multiplier into hundredths of a star.
+        let stars = weighted_sum * STAR_MULTIPLIER / 10_000;
+
+        Self {
+            stars: min(stars, u64::from(u32::MAX)) as u32,
+            start,
+            section_length,
+            section_strains: section_strains
+                .into_iter()
+                .map(|x| min(x, u64::from(u32::MAX)) as u32)
+                .collect(),
+        }
+    }
+}
+
+/// Decays `strain` over `time`, halving it every `half_life`.
+fn decay(strain: u64, time: i64, half_life: i64) -> u64 {
+    let time = max(time, 0);
+
+    let halvings = time / half_life;
+    if halvings >= 64 {
+        return 0;
+    }
+    let strain = strain >> halvings;
+
+    // 2^-x approximated as 1 - ln(2) x + (ln(2) - 1/2) x^2 on 0..1, in millionths.
+    let x = (time % half_life) * 1000 / half_life;
+    let multiplier = 1_000_000 - 693 * x + 193 * x * x / 1000;
+    (u128::from(strain) * multiplier as u128 / 1_000_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{map::ArbitraryMapType, scroll::ScrollSpeedMultiplier};
+    use proptest::prelude::*;
+
+    fn regular(millis: i32) -> Object {
+        Object::Regular {
+            timestamp: MapTimestamp::from_millis(millis),
+        }
+    }
+
+    fn long_note(start: i32, end: i32) -> Object {
+        Object::LongNote {
+            start: MapTimestamp::from_millis(start),
+            end: MapTimestamp::from_millis(end),
+        }
+    }
+
+    fn map(lanes: Vec<Vec<Object>>) -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            mapper: None,
+            background_file: None,
+            audio_file: None,
+            timing_points: vec![],
+            scroll_speed_changes: vec![],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
+            lanes: lanes.into_iter().map(|objects| Lane { objects }).collect(),
+        }
+    }
+
+    fn stars(map: &Map) -> u32 {
+        DifficultyRating::for_map(map, Rate::default()).stars
+    }
+
+    /// A stream of `count` objects cycling through `lanes` out of 4, `interval` ms apart.
+    fn stream(lanes: &[usize], count: i32, interval: i32) -> Map {
+        let mut objects = vec![vec![]; 4];
+        for i in 0..count {
+            objects[lanes[i as usize % lanes.len()]].push(regular(i * interval));
+        }
+        map(objects)
+    }
+
+    #[test]
+    fn empty_map() {
+        let rating = DifficultyRating::for_map(&map(vec![vec![]; 4]), Rate::default());
+        assert_eq!(rating.stars, 0);
+        assert!(rating.section_strains.is_empty());
+    }
+
+    #[test]
+    fn sections() {
+        let rating = DifficultyRating::for_map(
+            &map(vec![vec![regular(1000), regular(1100), regular(2500)]]),
+            Rate::default(),
+        );
+        assert_eq!(rating.start, MapTimestamp::from_millis(1000));
+        assert_eq!(
+            rating.section_length,
+            GameTimestampDifference::from_millis(400)
+        );
+        assert_eq!(rating.section_strains.len(), 4);
+        assert!(rating.section_strains[0] > rating.section_strains[1]);
+        assert!(rating.section_strains[1] > rating.section_strains[2]);
+        assert!(rating.section_strains[3] > rating.section_strains[2]);
+    }
+
+    #[test]
+    fn denser_is_harder() {
+        let slow = stars(&stream(&[0, 1, 2, 3], 200, 200));
+        let fast = stars(&stream(&[0, 1, 2, 3], 400, 100));
+        assert!(slow > 0);
+        assert!(fast > slow);
+    }
+
+    #[test]
+    fn rate_makes_harder() {
+        let map = stream(&[0, 1, 2, 3], 200, 150);
+        let normal = DifficultyRating::for_map(&map, Rate::default());
+        let fast = DifficultyRating::for_map(&map, Rate::new(1500));
+        assert!(fast.stars > normal.stars);
+        assert!(fast.section_strains.len() < normal.section_strains.len());
+    }
+
+    #[test]
+    fn jacks_are_harder_than_trills() {
+        let jack = stars(&stream(&[0], 200, 100));
+        let trill = stars(&stream(&[0, 3], 200, 100));
+        assert!(jack > trill);
+    }
+
+    #[test]
+    fn one_hand_is_harder_than_alternating_hands() {
+        let one_hand = stars(&stream(&[0, 1], 200, 100));
+        let alternating = stars(&stream(&[0, 3], 200, 100));
+        assert!(one_hand > alternating);
+    }
+
+    #[test]
+    fn chords_are_harder() {
+        let mut chords = vec![vec![]; 4];
+        for i in 0..100 {
+            chords[0].push(regular(i * 200));
+            chords[3].push(regular(i * 200));
+        }
+        let chords = stars(&map(chords));
+        let single = stars(&stream(&[0, 3], 100, 200));
+        assert!(chords > single);
+    }
+
+    #[test]
+    fn unaligned_releases_are_harder() {
+        let mut aligned = vec![vec![]; 4];
+        let mut unaligned = vec![vec![]; 4];
+        for i in 0..50 {
+            let start = i * 500;
+            aligned[0].push(long_note(start, start + 400));
+            aligned[1].push(long_note(start + 100, start + 400));
+            unaligned[0].push(long_note(start, start + 300));
+            unaligned[1].push(long_note(start + 100, start + 400));
+        }
+        assert!(stars(&map(unaligned)) > stars(&map(aligned)));
+    }
+
+    #[test]
+    fn decay_halves() {
+        assert_eq!(decay(1000, 0, 100), 1000);
+        assert_eq!(decay(1000, 100, 100), 500);
+        assert_eq!(decay(1000, 200, 100), 250);
+        assert_eq!(decay(1000, -5, 100), 1000);
+        assert_eq!(decay(u64::MAX, i64::MAX, 100), 0);
+        // 2^-0.5 is about 0.707.
+        assert!((700..=714).contains(&decay(1000, 50, 100)));
+    }
+
+    proptest! {
+        #[test]
+        fn rating_doesnt_panic(
+            map in any_with::<Map>(ArbitraryMapType::Any),
+            rate in 500..=2000u16,
+        ) {
+            DifficultyRating::for_map(&map, Rate::new(rate));
+        }
+
+        #[test]
+        fn rating_is_deterministic(map in any_with::<Map>(ArbitraryMapType::Valid)) {
+            let rating = DifficultyRating::for_map(&map, Rate::default());
+            prop_assert_eq!(
+                &rating,
+                &DifficultyRating::for_chart(&map.clone().into(), Rate::default())
+            );
+
+            let mut shuffled = map.clone();
+            for lane in &mut shuffled.lanes {
+                lane.objects.reverse();
+            }
+            prop_assert_eq!(rating, DifficultyRating::for_map(&shuffled, Rate::default()));
+        }
+
+        #[test]
+        fn decay_doesnt_increase(strain: u64, time: i64, half_life in 1..2i64.pow(40)) {
+            prop_assert!(decay(strain, time, half_life) <= strain);
+        }
+    }
+}
@@ -0,0 +1,756 @@
+//! Recording and playback of player input.
+//!
+//! Since all of the game logic uses integer math, feeding the recorded input into a fresh
+//! [`GameState`] for the same map reproduces exactly the same [`Event`]s.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+#[cfg(test)]
+use proptest_derive::Arbitrary;
+
+use crate::{
+    health::{Drain, FailMode, Health, HealthModel},
+    judgement::{HitWindows, Judgement, JudgementWindows},
+    map::Map,
+    state::{Event, GameState, GameStateCreationError},
+    timing::{
+        GameTimestamp, GameTimestampDifference, MapTimestampDifference, Rate, TimestampConverter,
+    },
+};
+
+/// Magic bytes at the start of a serialized replay.
+const MAGIC: &[u8; 4] = b"PLRP";
+
+/// Current version of the serialized replay format.
+///
+/// Version 1 layout, all integers little-endian:
+/// - magic, 4 bytes;
+/// - version, `u8`;
+/// - global offset, `i32`;
+/// - local offset, `i32`;
+/// - rate, `u16`;
+/// - press windows, 6 × `i32`;
+/// - release windows, 6 × `i32`;
+/// - health model presence, `u8`, 0 or 1;
+/// - if present, the health model: judgement changes, 6 × `i32`; miss change, `i32`; full and
+///   empty drain rates, 2 × `u32`; fail mode, `u8`, 0 for normal, 1 for sudden death, 2 for perfect
+///   only;
+/// - input count, varint;
+/// - inputs, each as a varint lane followed by a varint containing the zigzag-encoded difference
+///   from the previous input timestamp shifted left by one, with the lowest bit set for releases.
+const VERSION: u8 = 1;
+
+/// Kind of a recorded input.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum InputKind {
+    /// A key press.
+    Press,
+    /// A key release.
+    Release,
+}
+
+/// A recorded input.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct ReplayInput {
+    /// Timestamp of the input.
+    pub timestamp: GameTimestamp,
+    /// Lane of the input.
+    pub lane: usize,
+    /// Kind of the input.
+    pub kind: InputKind,
+}
+
+/// A replay: all input of a play session along with the settings that affect gameplay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// Timestamp converter used during the play session.
+    pub timestamp_converter: TimestampConverter,
+    /// Hit windows used during the play session.
+    pub hit_windows: HitWindows,
+    /// Health model used during the play session, if any.
+    pub health_model: Option<HealthModel>,
+    /// Recorded input in order.
+    pub inputs: Vec<ReplayInput>,
+}
+
+/// Plays back the input of a [`Replay`].
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer<'a> {
+    /// Input left to play back.
+    inputs: &'a [ReplayInput],
+}
+
+/// An error returned from [`Replay::from_bytes()`].
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum ReplayDecodeError {
+    /// The data does not start with the replay magic bytes.
+    InvalidMagic,
+    /// The replay format version is not supported.
+    UnsupportedVersion(u8),
+    /// The data ended unexpectedly.
+    UnexpectedEnd,
+    /// The data contains an invalid value, such as an out-of-range timestamp or zero rate.
+    InvalidValue,
+    /// There is extra data after the end of the replay.
+    TrailingData,
+}
+
+impl ReplayInput {
+    /// Creates a new key press input.
+    #[inline]
+    pub fn press(lane: usize, timestamp: GameTimestamp) -> Self {
+        Self {
+            timestamp,
+            lane,
+            kind: InputKind::Press,
+        }
+    }
+
+    /// Creates a new key release input.
+    #[inline]
+    pub fn release(lane: usize, timestamp: GameTimestamp) -> Self {
+        Self {
+            timestamp,
+            lane,
+            kind: InputKind::Release,
+        }
+    }
+
+    /// Applies the input to `state`.
+    ///
+    /// This calls [`GameState::key_press()`] or [`GameState::key_release()`], so you should call
+    /// [`GameState::update()`] with the input timestamp beforehand to avoid missing events.
+    #[inline]
+    pub fn apply(self, state: &mut GameState) -> Option<Event> {
+        match self.kind {
+            InputKind::Press => state.key_press(self.lane, self.timestamp),
+            InputKind::Release => state.key_release(self.lane, self.timestamp),
+        }
+    }
+}
+
+impl Replay {
+    /// Creates a new empty `Replay` with the settings of `state`.
+    #[inline]
+    pub fn new(state: &GameState) -> Self {
+        Self {
+            timestamp_converter: state.timestamp_converter,
+            hit_windows: state.hit_windows,
+            health_model: state.health.as_ref().map(Health::model),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Records an input.
+    #[inline]
+    pub fn record(&mut self, input: ReplayInput) {
+        self.inputs.push(input);
+    }
+
+    /// Creates a fresh `GameState` for `map` with the settings of this replay.
+    #[allow(clippy::result_large_err)]
+    pub fn game_state(&self, map: Map) -> Result<GameState, GameStateCreationError> {
+        let mut state = GameState::new(map, self.hit_windows)?;
+        state.timestamp_converter = self.timestamp_converter;
+        state.health = self.health_model.map(Health::new);
+        Ok(state)
+    }
+
+    /// Returns a player for the recorded input.
+    #[inline]
+    pub fn player(&self) -> ReplayPlayer<'_> {
+        ReplayPlayer {
+            inputs: &self.inputs,
+        }
+    }
+
+    /// Serializes the replay into the compact binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out =
+            Vec::with_capacity(5 + 4 + 4 + 2 + 4 * 12 + 1 + 4 * 9 + 1 + 10 + self.inputs.len() * 3);
+
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+
+        let converter = &self.timestamp_converter;
+        write_i32(&mut out, converter.global_offset.into_milli_hundredths());
+        write_i32(&mut out, converter.local_offset.into_milli_hundredths());
+        out.extend_from_slice(&converter.rate.into_thousandths().to_le_bytes());
+
+        for windows in [self.hit_windows.press, self.hit_windows.release] {
+            for judgement in Judgement::ALL {
+                write_i32(&mut out, windows.window(judgement).into_milli_hundredths());
+            }
+        }
+
+        match self.health_model {
+            None => out.push(0),
+            Some(model) => {
+                out.push(1);
+                for change in model.judgements {
+                    write_i32(&mut out, change);
+                }
+                write_i32(&mut out, model.miss);
+                out.extend_from_slice(&model.drain.full_rate.to_le_bytes());
+                out.extend_from_slice(&model.drain.empty_rate.to_le_bytes());
+                out.push(match model.fail_mode {
+                    FailMode::Normal => 0,
+                    FailMode::SuddenDeath => 1,
+                    FailMode::PerfectOnly => 2,
+                });
+            }
+        }
+
+        write_varint(&mut out, self.inputs.len() as u64);
+
+        let mut previous = 0i64;
+        for input in &self.inputs {
+            write_varint(&mut out, input.lane as u64);
+
+            let timestamp = i64::from(input.timestamp.into_milli_hundredths());
+            let delta = zigzag_encode(timestamp - previous);
+            let is_release = matches!(input.kind, InputKind::Release);
+            write_varint(&mut out, delta << 1 | u64::from(is_release));
+            previous = timestamp;
+        }
+
+        out
+    }
+
+    /// Deserializes a replay from the binary format produced by [`Replay::to_bytes()`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayDecodeError> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ReplayDecodeError::InvalidMagic);
+        }
+
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(ReplayDecodeError::UnsupportedVersion(version));
+        }
+
+        let global_offset = GameTimestampDifference::from_milli_hundredths(reader.i32()?);
+        let local_offset = MapTimestampDifference::from_milli_hundredths(reader.i32()?);
+        let rate = match reader.u16()? {
+            0 => return Err(ReplayDecodeError::InvalidValue),
+            rate => Rate::new(rate),
+        };
+        let timestamp_converter = TimestampConverter {
+            global_offset,
+            local_offset,
+            rate,
+        };
+
+        let mut windows = || {
+            let mut windows = [GameTimestampDifference::from_milli_hundredths(0); 6];
+            for window in &mut windows {
+                *window = GameTimestampDifference::from_milli_hundredths(reader.i32()?);
+            }
+            JudgementWindows::checked_new(windows).ok_or(ReplayDecodeError::InvalidValue)
+        };
+        let press = windows()?;
+        let release = windows()?;
+        let hit_windows = HitWindows::new(press, release);
+
+        let health_model = match reader.u8()? {
+            0 => None,
+            1 => {
+                let mut judgements = [0; 6];
+                for change in &mut judgements {
+                    *change = reader.i32()?;
+                }
+                let miss = reader.i32()?;
+                let drain = Drain {
+                    full_rate: reader.u32()?,
+                    empty_rate: reader.u32()?,
+                };
+                let fail_mode = match reader.u8()? {
+                    0 => FailMode::Normal,
+                    1 => FailMode::SuddenDeath,
+                    2 => FailMode::PerfectOnly,
+                    _ => return Err(ReplayDecodeError::InvalidValue),
+                };
+                Some(HealthModel {
+                    judgements,
+                    miss,
+                    drain,
+                    fail_mode,
+                })
+            }
+            _ => return Err(ReplayDecodeError::InvalidValue),
+        };
+
+        let count = reader.varint()?;
+        // Every input takes at least two bytes, don't let a corrupted count allocate too much.
+        if count > reader.bytes.len() as u64 / 2 {
+            return Err(ReplayDecodeError::UnexpectedEnd);
+        }
+
+        let mut inputs = Vec::with_capacity(count as usize);
+        let mut previous = 0i64;
+        for _ in 0..count {
+            let lane =
+                usize::try_from(reader.varint()?).map_err(|_| ReplayDecodeError::InvalidValue)?;
+
+            let value = reader.varint()?;
+            let kind = if value & 1 == 0 {
+                InputKind::Press
+            } else {
+                InputKind::Release
+            };
+
+            let timestamp = previous + zigzag_decode(value >> 1);
+            let timestamp = i32::try_from(timestamp)
+                .ok()
+                .and_then(GameTimestamp::checked_from_milli_hundredths)
+                .ok_or(ReplayDecodeError::InvalidValue)?;
+            previous = i64::from(timestamp.into_milli_hundredths());
+
+            inputs.push(ReplayInput {
+                timestamp,
+                lane,
+                kind,
+            });
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(ReplayDecodeError::TrailingData);
+        }
+
+        Ok(Self {
+            timestamp_converter,
+            hit_windows,
+            health_model,
+            inputs,
+        })
+    }
+}
+
+impl<'a> ReplayPlayer<'a> {
+    /// Returns the next input if its timestamp is at or before `timestamp`.
+    ///
+    /// Call this in a loop until it returns `None`. Inputs are returned in the recorded order.
+    #[inline]
+    pub fn next_input(&mut self, timestamp: GameTimestamp) -> Option<ReplayInput> {
+        let (&input, rest) = self.inputs.split_first()?;
+        if input.timestamp > timestamp {
+            return None;
+        }
+
+        self.inputs = rest;
+        Some(input)
+    }
+
+    /// Returns the timestamp of the next input, or `None` if all input has been played back.
+    #[inline]
+    pub fn next_timestamp(&self) -> Option<GameTimestamp> {
+        self.inputs.first().map(|input| input.timestamp)
+    }
+
+    /// Returns `true` if all input has been played back.
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+fn write_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn zigzag_decode(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplayDecodeError> {
+        if self.bytes.len() < count {
+            return Err(ReplayDecodeError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ReplayDecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ReplayDecodeError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, ReplayDecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, ReplayDecodeError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayDecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            let bits = u64::from(byte & 0x7f);
+            if shift == 63 && bits > 1 {
+                return Err(ReplayDecodeError::InvalidValue);
+            }
+
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(ReplayDecodeError::InvalidValue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::ArbitraryMapType;
+    use alloc::vec;
+    use proptest::prelude::*;
+
+    fn empty_replay() -> Replay {
+        Replay {
+            timestamp_converter: TimestampConverter {
+                global_offset: GameTimestampDifference::from_millis(0),
+                local_offset: MapTimestampDifference::from_millis(0),
+                rate: Rate::default(),
+            },
+            hit_windows: HitWindows::quaver_standard(),
+            health_model: None,
+            inputs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn serialize_empty() {
+        let replay = empty_replay();
+        let bytes = replay.to_bytes();
+        assert_eq!(&bytes[..5], b"PLRP\x01");
+        assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+    }
+
+    #[test]
+    fn inputs_are_compact() {
+        let mut replay = empty_replay();
+        let empty_len = replay.to_bytes().len();
+
+        replay.record(ReplayInput::press(0, GameTimestamp::from_millis(10)));
+        replay.record(ReplayInput::release(3, GameTimestamp::from_millis(200)));
+
+        let bytes = replay.to_bytes();
+        // Lanes take 1 byte each, timestamp differences take 2 and 3 bytes.
+        assert_eq!(bytes.len(), empty_len + 1 + 2 + 1 + 3);
+        assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+    }
+
+    #[test]
+    fn serialize_health_model() {
+        let mut replay = empty_replay();
+        replay.health_model = Some(HealthModel::quaver().with_fail_mode(FailMode::PerfectOnly));
+        let bytes = replay.to_bytes();
+        assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+
+        let mut invalid_fail_mode = bytes;
+        let fail_mode_index = invalid_fail_mode.len() - 2;
+        invalid_fail_mode[fail_mode_index] = 3;
+        assert_eq!(
+            Replay::from_bytes(&invalid_fail_mode),
+            Err(ReplayDecodeError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        let mut replay = empty_replay();
+        replay.record(ReplayInput::press(0, GameTimestamp::from_millis(10)));
+        let bytes = replay.to_bytes();
+
+        assert_eq!(
+            Replay::from_bytes(b"PLRQ"),
+            Err(ReplayDecodeError::InvalidMagic)
+        );
+        assert_eq!(
+            Replay::from_bytes(b"PLRP\x02"),
+            Err(ReplayDecodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Replay::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayDecodeError::UnexpectedEnd)
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Replay::from_bytes(&trailing),
+            Err(ReplayDecodeError::TrailingData)
+        );
+
+        let mut zero_rate = bytes;
+        zero_rate[13] = 0;
+        zero_rate[14] = 0;
+        assert_eq!(
+            Replay::from_bytes(&zero_rate),
+            Err(ReplayDecodeError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn player_respects_timestamps() {
+        let mut replay = empty_replay();
+        replay.record(ReplayInput::press(0, GameTimestamp::from_millis(10)));
+        replay.record(ReplayInput::release(0, GameTimestamp::from_millis(20)));
+
+        let mut player = replay.player();
+        assert_eq!(player.next_input(GameTimestamp::from_millis(5)), None);
+        assert_eq!(
+            player.next_input(GameTimestamp::from_millis(15)),
+            Some(replay.inputs[0])
+        );
+        assert_eq!(player.next_input(GameTimestamp::from_millis(15)), None);
+        assert_eq!(
+            player.next_timestamp(),
+            Some(GameTimestamp::from_millis(20))
+        );
+        assert_eq!(
+            player.next_input(GameTimestamp::from_millis(20)),
+            Some(replay.inputs[1])
+        );
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn replay_reproduces_events() {
+        use crate::{
+            map::Lane, object::Object, scroll::ScrollSpeedMultiplier, timing::MapTimestamp,
+        };
+
+        let map = Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane {
+                objects: vec![Object::Regular {
+                    timestamp: MapTimestamp::from_millis(100),
+                }],
+            }],
+        };
+
+        let mut state = GameState::new(map.clone(), HitWindows::default()).unwrap();
+        let mut replay = Replay::new(&state);
+        let input = ReplayInput::press(0, GameTimestamp::from_millis(110));
+        replay.record(input);
+        let event = input.apply(&mut state).unwrap();
+
+        let mut replayed_state = replay.game_state(map).unwrap();
+        let input = replay
+            .player()
+            .next_input(GameTimestamp::from_millis(110))
+            .unwrap();
+        assert_eq!(input.apply(&mut replayed_state), Some(event));
+        assert_eq!(replayed_state.lane_states, state.lane_states);
+    }
+
+    #[test]
+    fn replay_reproduces_fail() {
+        use crate::{
+            map::Lane, object::Object, scroll::ScrollSpeedMultiplier, timing::MapTimestamp,
+        };
+
+        let map = Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(100),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(1_000),
+                    },
+                ],
+            }],
+        };
+
+        let mut state = GameState::new(map.clone(), HitWindows::quaver_standard()).unwrap();
+        state.health = Some(Health::new(
+            HealthModel::quaver().with_fail_mode(FailMode::SuddenDeath),
+        ));
+        let mut replay = Replay::new(&state);
+        let input = ReplayInput::press(0, GameTimestamp::from_millis(1_000));
+        replay.record(input);
+        let events = apply(&mut state, input);
+        assert!(state.is_failed());
+
+        let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+        let mut replayed_state = replay.game_state(map).unwrap();
+        assert_eq!(apply(&mut replayed_state, input), events);
+        assert!(replayed_state.is_failed());
+        assert_eq!(replayed_state.lane_states, state.lane_states);
+    }
+
+    /// Applies `input` to `state` along with the preceding update, returning all events.
+    fn apply(state: &mut GameState, input: ReplayInput) -> Vec<(usize, Event)> {
+        let mut events = Vec::new();
+        for lane in 0..state.lane_count() {
+            while let Some(event) = state.update_lane(lane, input.timestamp) {
+                events.push((lane, event));
+            }
+        }
+        if let Some(event) = input.apply(state) {
+            events.push((input.lane, event));
+        }
+        events
+    }
+
+    proptest! {
+        #[test]
+        fn serialization_roundtrip(
+            timestamp_converter: TimestampConverter,
+            hit_windows: HitWindows,
+            health_model in prop::option::of(arbitrary_health_model()),
+            inputs in prop::collection::vec(
+                (any::<GameTimestamp>(), any::<usize>(), any::<InputKind>()),
+                0..100,
+            ),
+        ) {
+            let inputs = inputs
+                .into_iter()
+                .map(|(timestamp, lane, kind)| ReplayInput { timestamp, lane, kind })
+                .collect();
+            let replay = Replay { timestamp_converter, hit_windows, health_model, inputs };
+
+            prop_assert_eq!(Replay::from_bytes(&replay.to_bytes()), Ok(replay));
+        }
+
+        #[test]
+        fn from_bytes_doesnt_panic(bytes: Vec<u8>) {
+            let _ = Replay::from_bytes(&bytes);
+        }
+
+        #[test]
+        fn record_then_replay_is_identical(
+            (map, inputs) in valid_map_with_inputs(),
+            hit_windows: HitWindows,
+            timestamp_converter: TimestampConverter,
+            health_model in prop::option::of(arbitrary_health_model()),
+        ) {
+            let mut state = GameState::new(map.clone(), hit_windows).unwrap();
+            state.timestamp_converter = timestamp_converter;
+            state.health = health_model.map(Health::new);
+
+            let mut replay = Replay::new(&state);
+            let mut events = Vec::new();
+            for input in inputs {
+                replay.record(input);
+                events.extend(apply(&mut state, input));
+            }
+
+            let replay = Replay::from_bytes(&replay.to_bytes()).unwrap();
+            let mut replayed_state = replay.game_state(map).unwrap();
+            let mut replayed_events = Vec::new();
+            let mut player = replay.player();
+            while let Some(timestamp) = player.next_timestamp() {
+                while let Some(input) = player.next_input(timestamp) {
+                    replayed_events.extend(apply(&mut replayed_state, input));
+                }
+            }
+
+            prop_assert_eq!(replayed_events, events);
+            prop_assert_eq!(replayed_state.lane_states, state.lane_states);
+        }
+    }
+
+    fn arbitrary_health_model() -> impl Strategy<Value = HealthModel> {
+        (
+            any::<[i32; 6]>(),
+            any::<i32>(),
+            any::<u32>(),
+            any::<u32>(),
+            prop_oneof![
+                Just(FailMode::Normal),
+                Just(FailMode::SuddenDeath),
+                Just(FailMode::PerfectOnly),
+            ],
+        )
+            .prop_map(
+                |(judgements, miss, full_rate, empty_rate, fail_mode)| HealthModel {
+                    judgements,
+                    miss,
+                    drain: Drain {
+                        full_rate,
+                        empty_rate,
+                    },
+                    fail_mode,
+                },
+            )
+    }
+
+    fn valid_map_with_inputs() -> impl Strategy<Value = (Map, Vec<ReplayInput>)> {
+        any_with::<Map>(ArbitraryMapType::ValidWithLanes).prop_flat_map(|map| {
+            let inputs = prop::collection::vec(
+                (
+                    any::<GameTimestamp>(),
+                    0..map.lane_count(),
+                    any::<InputKind>(),
+                ),
+                0..100,
+            )
+            .prop_map(|mut inputs| {
+                inputs.sort_by_key(|(timestamp, _, _)| *timestamp);
+                inputs
+                    .into_iter()
+                    .map(|(timestamp, lane, kind)| ReplayInput {
+                        timestamp,
+                        lane,
+                        kind,
+                    })
+                    .collect()
+            });
+            (Just(map), inputs)
+        })
+    }
+}
@@ -0,0 +1,39 @@
+//! Pseudorandom number generation.
+
+/// A SplitMix64 pseudorandom number generator.
+///
+/// Used instead of a dependency so that the output is the same for a given seed everywhere.
+#[derive(Debug, Clone)]
+pub struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    /// Creates a new generator from `seed`.
+    #[inline]
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Returns the next random value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random value from `-max..=max`.
+    pub fn next_deviation(&mut self, max: i64) -> i64 {
+        let range = max as u64 * 2 + 1;
+        (self.next_u64() % range) as i64 - max
+    }
+
+    /// Returns a random value from `0..n`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn next_below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
@@ -0,0 +1,646 @@
+//! Score and accuracy computation.
+
+use crate::{
+    judgement::Judgement,
+    object::Object,
+    state::{Event, EventKind, GameState, LongNoteState, ObjectState},
+    timing::GameTimestampDifference,
+};
+
+/// A scoring system, which determines how the accuracy and the score are computed.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum ScoringSystem {
+    /// Quaver's weighted accuracy.
+    ///
+    /// Every judgement has a weight (Marvelous 100, Perfect 98.25, Great 65, Good 25, Okay -100,
+    /// Miss -50), and the accuracy is the average weight. There is no score.
+    Quaver,
+    /// osu!mania ScoreV1.
+    ///
+    /// The accuracy treats Marvelous the same as Perfect. The score goes up to 1,000,000 and is
+    /// split evenly between the base score and the bonus score, which depends on consistently
+    /// hitting Marvelous judgements.
+    OsuManiaScoreV1,
+    /// osu!mania ScoreV2.
+    ///
+    /// The accuracy weighs Marvelous slightly higher than Perfect (305 vs. 300). The score goes up
+    /// to 1,000,000, 30% of which comes from the max combo and 70% from the accuracy raised to the
+    /// 10th power.
+    OsuManiaScoreV2,
+    /// Etterna's Wife3 millisecond-based accuracy at judge 4.
+    ///
+    /// Every press is worth from 2 points (within 5 ms) down to -5.5 points (at 180 ms or a miss)
+    /// following an error function curve. Releases are not timed, but releasing a long note early
+    /// costs 4.5 points. There is no score.
+    Wife,
+}
+
+/// A grade, given based on the accuracy.
+///
+/// Grades are ordered from the best to the worst.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Grade {
+    /// The best grade.
+    ///
+    /// This is 100% accuracy for Quaver, and AAAA for Wife. osu!mania has no such grade.
+    X,
+    /// 99% for Quaver, 100% for osu!mania and AAA for Wife.
+    SS,
+    /// 95% for Quaver and osu!mania, AA for Wife.
+    S,
+    /// 90% for Quaver and osu!mania, A for Wife.
+    A,
+    /// 80% for Quaver and osu!mania, B for Wife.
+    B,
+    /// 70% for Quaver and osu!mania, C for Wife.
+    C,
+    /// The worst grade.
+    D,
+}
+
+/// Score and accuracy tracker.
+///
+/// Feed every [`Event`] produced by the [`GameState`] into [`Score::process_event()`] to keep the
+/// numbers up to date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    /// The scoring system.
+    system: ScoringSystem,
+    /// Total number of judgements in the map.
+    ///
+    /// Regular objects produce one judgement and long notes produce two: for the press and for the
+    /// release.
+    total_judgements: u32,
+    /// Number of judgements of every kind, in the [`Judgement::ALL`] order.
+    counts: [u32; 6],
+    /// Current combo.
+    combo: u32,
+    /// Max combo.
+    max_combo: u32,
+    /// osu!mania ScoreV1 score accumulated so far.
+    score_v1: f64,
+    /// osu!mania ScoreV1 bonus, ranging from 0 to 100.
+    bonus_v1: i32,
+    /// Wife points accumulated so far.
+    wife_points: f64,
+    /// Number of presses that Wife points were given for.
+    wife_presses: u32,
+}
+
+/// Max score in the osu!mania scoring systems.
+const OSU_MAX_SCORE: f64 = 1_000_000.;
+
+impl ScoringSystem {
+    /// Returns the grade for the given accuracy in percent.
+    #[inline]
+    pub fn grade(self, accuracy: f64) -> Grade {
+        let thresholds = match self {
+            ScoringSystem::Quaver => [100., 99., 95., 90., 80., 70.],
+            ScoringSystem::OsuManiaScoreV1 | ScoringSystem::OsuManiaScoreV2 => {
+                [f64::INFINITY, 100., 95., 90., 80., 70.]
+            }
+            ScoringSystem::Wife => [99.955, 99.7, 93., 80., 70., 60.],
+        };
+
+        let grades = [Grade::X, Grade::SS, Grade::S, Grade::A, Grade::B, Grade::C];
+        for (&grade, &threshold) in grades.iter().zip(thresholds.iter()) {
+            if accuracy >= threshold {
+                return grade;
+            }
+        }
+
+        Grade::D
+    }
+}
+
+impl Score {
+    /// Creates a new `Score` for the map of the given `state`.
+    ///
+    /// Objects skipped with [`GameState::seek()`] are not counted.
+    pub fn new(system: ScoringSystem, state: &GameState) -> Self {
+        let total_judgements = state
+            .immutable
+            .chart
+            .lanes
+            .iter()
+            .zip(&state.lane_states)
+            .flat_map(|(lane, lane_state)| lane.objects.iter().zip(&lane_state.object_states))
+            .filter(|(_, object_state)| !object_state.is_skipped())
+            .map(|(object, _)| match object {
+                Object::Regular { .. } => 1,
+                Object::LongNote { .. } => 2,
+            })
+            .sum();
+
+        Self {
+            system,
+            total_judgements,
+            counts: [0; 6],
+            combo: 0,
+            max_combo: 0,
+            score_v1: 0.,
+            bonus_v1: 100,
+            wife_points: 0.,
+            wife_presses: 0,
+        }
+    }
+
+    /// Processes an event.
+    ///
+    /// `state` must be the state that produced the `event` (after producing it), and `lane` must
+    /// be the lane of the `event`.
+    ///
+    /// [`EventKind::Failed`] events don't change the score.
+    pub fn process_event(&mut self, state: &GameState, lane: usize, event: Event) {
+        let object_state = state.lane_states[lane].object_states[event.object_index];
+
+        match event.kind {
+            EventKind::Hit(hit) => {
+                self.add_judgement(hit.judgement);
+
+                let is_press = !matches!(
+                    object_state,
+                    ObjectState::LongNote(LongNoteState::Hit { .. })
+                );
+                if is_press {
+                    self.add_wife_press(wife3(hit.difference));
+                }
+            }
+            EventKind::Miss => match object_state {
+                // Released too early, the press was already judged.
+                ObjectState::LongNote(LongNoteState::Missed {
+                    press_difference: Some(_),
+                    ..
+                }) => {
+                    self.add_judgement(Judgement::Miss);
+                    self.wife_points += WIFE_HOLD_DROP_WEIGHT;
+                }
+                // Missed entirely, this counts for both the press and the release.
+                ObjectState::LongNote(_) => {
+                    self.add_judgement(Judgement::Miss);
+                    self.add_judgement(Judgement::Miss);
+                    self.add_wife_press(WIFE_MISS_WEIGHT);
+                }
+                ObjectState::Regular(_) => {
+                    self.add_judgement(Judgement::Miss);
+                    self.add_wife_press(WIFE_MISS_WEIGHT);
+                }
+            },
+            EventKind::Failed => {}
+        }
+    }
+
+    fn add_judgement(&mut self, judgement: Judgement) {
+        self.counts[judgement.index()] += 1;
+
+        if judgement == Judgement::Miss {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+
+        // osu!mania ScoreV1.
+        let (value, bonus_value, bonus) = match judgement {
+            Judgement::Marvelous => (320., 32., 2),
+            Judgement::Perfect => (300., 32., 1),
+            Judgement::Great => (200., 16., -8),
+            Judgement::Good => (100., 8., -24),
+            Judgement::Okay => (50., 4., -44),
+            Judgement::Miss => (0., 0., -100),
+        };
+        self.bonus_v1 = (self.bonus_v1 + bonus).clamp(0, 100);
+
+        let per_judgement = OSU_MAX_SCORE * 0.5 / f64::from(self.total_judgements.max(1));
+        let base_score = per_judgement * value / 320.;
+        let bonus_score = per_judgement * bonus_value * sqrt(f64::from(self.bonus_v1)) / 320.;
+        self.score_v1 += base_score + bonus_score;
+    }
+
+    fn add_wife_press(&mut self, points: f64) {
+        self.wife_points += points;
+        self.wife_presses += 1;
+    }
+
+    /// Returns the scoring system.
+    #[inline]
+    pub fn system(&self) -> ScoringSystem {
+        self.system
+    }
+
+    /// Returns the number of judgements of the given kind.
+    #[inline]
+    pub fn count(&self, judgement: Judgement) -> u32 {
+        self.counts[judgement.index()]
+    }
+
+    /// Returns the number of judgements so far.
+    #[inline]
+    pub fn judgement_count(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the total number of judgements in the map.
+    #[inline]
+    pub fn total_judgements(&self) -> u32 {
+        self.total_judgements
+    }
+
+    /// Returns the current combo.
+    #[inline]
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    /// Returns the max combo.
+    #[inline]
+    pub fn max_combo(&self) -> u32 {
+        self.max_combo
+    }
+
+    /// Returns the accuracy in percent, from 0 to 100.
+    ///
+    /// Before the first judgement the accuracy is 100.
+    pub fn accuracy(&self) -> f64 {
+        let count = self.judgement_count();
+        if count == 0 {
+            return 100.;
+        }
+
+        let (weights, max_weight): ([i64; 6], i64) = match self.system {
+            // Quaver weights multiplied by 100.
+            ScoringSystem::Quaver => ([10000, 9825, 6500, 2500, -10000, -5000], 10000),
+            ScoringSystem::OsuManiaScoreV1 => ([300, 300, 200, 100, 50, 0], 300),
+            ScoringSystem::OsuManiaScoreV2 => ([305, 300, 200, 100, 50, 0], 305),
+            ScoringSystem::Wife => {
+                if self.wife_presses == 0 {
+                    return 100.;
+                }
+
+                let max_points = f64::from(self.wife_presses) * WIFE_MAX_POINTS;
+                return (self.wife_points / max_points * 100.).clamp(0., 100.);
+            }
+        };
+
+        let points: i64 = self
+            .counts
+            .iter()
+            .zip(weights)
+            .map(|(&count, weight)| i64::from(count) * weight)
+            .sum();
+        let max_points = i64::from(count) * max_weight;
+
+        (points.max(0) as f64 / max_points as f64) * 100.
+    }
+
+    /// Returns the score, or `None` if the scoring system has no score.
+    ///
+    /// The score ranges from 0 to 1,000,000.
+    pub fn score(&self) -> Option<u32> {
+        let score = match self.system {
+            ScoringSystem::Quaver | ScoringSystem::Wife => return None,
+            ScoringSystem::OsuManiaScoreV1 => self.score_v1,
+            ScoringSystem::OsuManiaScoreV2 => {
+                let total = f64::from(self.total_judgements.max(1));
+                let combo_portion = f64::from(self.max_combo) / total;
+
+                let accuracy = self.accuracy() / 100.;
+                let mut accuracy_10 = 1.;
+                for _ in 0..10 {
+                    accuracy_10 *= accuracy;
+                }
+                let progress = f64::from(self.judgement_count()) / total;
+
+                OSU_MAX_SCORE * (0.3 * combo_portion + 0.7 * accuracy_10 * progress)
+            }
+        };
+
+        Some(round(score.clamp(0., OSU_MAX_SCORE)) as u32)
+    }
+
+    /// Returns the grade for the current accuracy.
+    #[inline]
+    pub fn grade(&self) -> Grade {
+        self.system.grade(self.accuracy())
+    }
+}
+
+/// Wife points for a perfect press.
+const WIFE_MAX_POINTS: f64 = 2.;
+/// Wife points for a miss.
+const WIFE_MISS_WEIGHT: f64 = -5.5;
+/// Wife points for releasing a long note early.
+const WIFE_HOLD_DROP_WEIGHT: f64 = -4.5;
+
+/// Returns the Wife3 points for a press with the given `difference` at judge 4.
+fn wife3(difference: GameTimestampDifference) -> f64 {
+    // Everything is in milliseconds.
+    const RIDICULOUS: f64 = 5.;
+    const ZERO: f64 = 65.;
+    const DEVIATION: f64 = 22.7;
+    const MAX_BOO_WEIGHT: f64 = 180.;
+
+    let difference = f64::from(difference.into_milli_hundredths().unsigned_abs()) / 100.;
+
+    if difference <= RIDICULOUS {
+        WIFE_MAX_POINTS
+    } else if difference <= ZERO {
+        WIFE_MAX_POINTS * erf((ZERO - difference) / DEVIATION)
+    } else if difference <= MAX_BOO_WEIGHT {
+        (difference - ZERO) * WIFE_MISS_WEIGHT / (MAX_BOO_WEIGHT - ZERO)
+    } else {
+        WIFE_MISS_WEIGHT
+    }
+}
+
+/// Error function approximation for non-negative `x`.
+///
+/// Uses the Abramowitz and Stegun formula 7.1.28 which has the maximum error of 3·10<sup>-7</sup>
+/// and needs nothing but basic arithmetic, so the result is the same on every platform.
+fn erf(x: f64) -> f64 {
+    const A: [f64; 6] = [
+        0.0705230784,
+        0.0422820123,
+        0.0092705272,
+        0.0001520143,
+        0.0002765672,
+        0.0000430638,
+    ];
+
+    let mut sum = 1.;
+    let mut power = 1.;
+    for a in A {
+        power *= x;
+        sum += a * power;
+    }
+
+    // sum^16.
+    for _ in 0..4 {
+        sum *= sum;
+    }
+
+    1. - 1. / sum
+}
+
+/// Square root for non-negative `x` using Newton's method.
+fn sqrt(x: f64) -> f64 {
+    if x == 0. {
+        return 0.;
+    }
+
+    let mut guess = if x > 1. { x } else { 1. };
+    for _ in 0..64 {
+        let next = (guess + x / guess) / 2.;
+        if next >= guess {
+            break;
+        }
+        guess = next;
+    }
+    guess
+}
+
+/// Rounds non-negative `x` to the nearest integer.
+fn round(x: f64) -> u64 {
+    (x + 0.5) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        judgement::HitWindows,
+        map::{ArbitraryMapType, Lane, Map},
+        scroll::ScrollSpeedMultiplier,
+        timing::{GameTimestamp, MapTimestamp},
+    };
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use proptest::prelude::*;
+
+    fn map() -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(1_000),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(2_000),
+                        end: MapTimestamp::from_millis(3_000),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(4_000),
+                    },
+                ],
+            }],
+        }
+    }
+
+    fn play(system: ScoringSystem, inputs: &[(bool, i32)]) -> Score {
+        let mut state = GameState::new(map(), HitWindows::quaver_standard()).unwrap();
+        let mut score = Score::new(system, &state);
+
+        for &(press, millis) in inputs {
+            let timestamp = GameTimestamp::from_millis(millis);
+            while let Some(event) = state.update(timestamp) {
+                score.process_event(&state, 0, event);
+            }
+
+            let event = if press {
+                state.key_press(0, timestamp)
+            } else {
+                state.key_release(0, timestamp)
+            };
+            if let Some(event) = event {
+                score.process_event(&state, 0, event);
+            }
+        }
+
+        while let Some(event) = state.update(GameTimestamp::from_millis(10_000)) {
+            score.process_event(&state, 0, event);
+        }
+
+        score
+    }
+
+    const PERFECT_PLAY: [(bool, i32); 4] =
+        [(true, 1_000), (true, 2_000), (false, 3_000), (true, 4_000)];
+
+    #[test]
+    fn perfect_play() {
+        for system in [
+            ScoringSystem::Quaver,
+            ScoringSystem::OsuManiaScoreV1,
+            ScoringSystem::OsuManiaScoreV2,
+            ScoringSystem::Wife,
+        ] {
+            let score = play(system, &PERFECT_PLAY);
+            assert_eq!(score.total_judgements(), 4);
+            assert_eq!(score.count(Judgement::Marvelous), 4);
+            assert_eq!(score.combo(), 4);
+            assert_eq!(score.max_combo(), 4);
+            assert_eq!(score.accuracy(), 100.);
+
+            if system != ScoringSystem::Quaver && system != ScoringSystem::Wife {
+                assert_eq!(score.score(), Some(1_000_000));
+            }
+        }
+
+        assert_eq!(play(ScoringSystem::Quaver, &PERFECT_PLAY).grade(), Grade::X);
+        assert_eq!(
+            play(ScoringSystem::OsuManiaScoreV1, &PERFECT_PLAY).grade(),
+            Grade::SS
+        );
+    }
+
+    #[test]
+    fn missed_long_note_counts_twice() {
+        let score = play(ScoringSystem::Quaver, &[(true, 1_000), (true, 4_000)]);
+        assert_eq!(score.count(Judgement::Marvelous), 2);
+        assert_eq!(score.count(Judgement::Miss), 2);
+        assert_eq!(score.combo(), 1);
+        assert_eq!(score.max_combo(), 1);
+        // (2 * 100 - 2 * 50) / 4.
+        assert_eq!(score.accuracy(), 25.);
+        assert_eq!(score.grade(), Grade::D);
+    }
+
+    #[test]
+    fn quaver_accuracy() {
+        // Perfect, Marvelous, Great release, Marvelous.
+        let score = play(
+            ScoringSystem::Quaver,
+            &[(true, 1_030), (true, 2_000), (false, 3_100), (true, 4_000)],
+        );
+        assert_eq!(score.count(Judgement::Perfect), 1);
+        assert_eq!(score.count(Judgement::Great), 1);
+        assert_eq!(score.accuracy(), (98.25 + 100. + 65. + 100.) / 4.);
+    }
+
+    #[test]
+    fn skipped_objects_dont_count() {
+        let mut state = GameState::new(map(), HitWindows::quaver_standard()).unwrap();
+        state.seek(GameTimestamp::from_millis(2_500));
+
+        let mut score = Score::new(ScoringSystem::OsuManiaScoreV1, &state);
+        assert_eq!(score.total_judgements(), 1);
+
+        let timestamp = GameTimestamp::from_millis(4_000);
+        assert_eq!(state.update(timestamp), None);
+        let event = state.key_press(0, timestamp).unwrap();
+        score.process_event(&state, 0, event);
+
+        assert_eq!(score.accuracy(), 100.);
+        assert_eq!(score.score(), Some(1_000_000));
+    }
+
+    #[test]
+    fn wife_curve() {
+        let wife3 = |millis| wife3(GameTimestampDifference::from_millis(millis));
+        assert_eq!(wife3(0), 2.);
+        assert_eq!(wife3(-5), 2.);
+        assert!((wife3(65)).abs() < 1e-6);
+        assert!(wife3(30) > 0. && wife3(30) < 2.);
+        assert_eq!(wife3(180), WIFE_MISS_WEIGHT);
+        assert_eq!(wife3(1000), WIFE_MISS_WEIGHT);
+    }
+
+    #[test]
+    fn wife_ignores_release_timing() {
+        let score = play(
+            ScoringSystem::Wife,
+            &[(true, 1_000), (true, 2_000), (false, 3_200), (true, 4_000)],
+        );
+        assert_eq!(score.accuracy(), 100.);
+
+        let score = play(
+            ScoringSystem::Wife,
+            &[(true, 1_000), (true, 2_000), (false, 2_500), (true, 4_000)],
+        );
+        assert_eq!(score.accuracy(), (6. + WIFE_HOLD_DROP_WEIGHT) / 6. * 100.);
+    }
+
+    #[test]
+    fn sqrt_matches() {
+        for x in 0..=100 {
+            let x = f64::from(x);
+            assert!((sqrt(x) * sqrt(x) - x).abs() < 1e-9);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn score_stays_in_range(
+            (map, events) in valid_map_with_events(),
+            hit_windows: HitWindows,
+        ) {
+            let mut state = GameState::new(map, hit_windows).unwrap();
+            let mut scores = [
+                ScoringSystem::Quaver,
+                ScoringSystem::OsuManiaScoreV1,
+                ScoringSystem::OsuManiaScoreV2,
+                ScoringSystem::Wife,
+            ].map(|system| Score::new(system, &state));
+
+            // Pressing an already held long note presses it again, so only generate valid
+            // sequences of presses and releases.
+            let mut is_pressed = vec![false; state.lane_count()];
+
+            for (press, lane, timestamp) in events {
+                if is_pressed[lane] == press {
+                    continue;
+                }
+                is_pressed[lane] = press;
+
+                // Key presses and releases update all lanes, so process the events beforehand.
+                for lane in 0..state.lane_count() {
+                    while let Some(event) = state.update_lane(lane, timestamp) {
+                        for score in &mut scores {
+                            score.process_event(&state, lane, event);
+                        }
+                    }
+                }
+
+                let event = if press {
+                    state.key_press(lane, timestamp)
+                } else {
+                    state.key_release(lane, timestamp)
+                };
+                if let Some(event) = event {
+                    for score in &mut scores {
+                        score.process_event(&state, lane, event);
+                    }
+                }
+            }
+
+            for score in &scores {
+                prop_assert!(score.judgement_count() <= score.total_judgements());
+                prop_assert!((0. ..=100.).contains(&score.accuracy()));
+                if let Some(value) = score.score() {
+                    prop_assert!(value <= 1_000_000);
+                }
+            }
+        }
+    }
+
+    fn valid_map_with_events(
+    ) -> impl proptest::strategy::Strategy<Value = (Map, Vec<(bool, usize, GameTimestamp)>)> {
+        any_with::<Map>(ArbitraryMapType::ValidWithLanes).prop_flat_map(|map| {
+            let events = prop::collection::vec(
+                (any::<bool>(), 0..map.lane_count(), any::<GameTimestamp>()),
+                0..100,
+            );
+            (Just(map), events)
+        })
+    }
+}
@@ -205,6 +205,18 @@ impl ScrollSpeedMultiplier {
     pub fn as_f32(self) -> f32 {
         (self.0 as f32) / 1000.
     }
+
+    /// Performs a saturating conversion from thousandths of the multiplier.
+    #[inline]
+    pub(crate) fn saturating_from_thousandths(value: i64) -> Self {
+        Self(value.clamp(-(2i64.pow(24)), 2i64.pow(24) - 1) as i32)
+    }
+
+    /// Returns the multiplier in thousandths.
+    #[inline]
+    pub(crate) fn into_thousandths(self) -> i32 {
+        self.0
+    }
 }
 
 impl Default for ScrollSpeedMultiplier {
@@ -0,0 +1,266 @@
+//! Rhythmic snaps of objects.
+use alloc::vec::Vec;
+
+use crate::{
+    map::TimingPoint,
+    object::Object,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+
+/// Rhythmic snap of a timestamp: the largest fraction of a beat the timestamp falls on.
+///
+/// Beats here are quarter notes, so in time signatures like 6/8, where a beat is an eighth note,
+/// every other beat falls on a [`Snap::Half`].
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Snap {
+    /// On a beat.
+    Whole,
+    /// On a <sup>1</sup>⁄<sub>2</sub> of a beat.
+    Half,
+    /// On a <sup>1</sup>⁄<sub>3</sub> of a beat.
+    Third,
+    /// On a <sup>1</sup>⁄<sub>4</sub> of a beat.
+    Quarter,
+    /// On a <sup>1</sup>⁄<sub>6</sub> of a beat.
+    Sixth,
+    /// On a <sup>1</sup>⁄<sub>8</sub> of a beat.
+    Eighth,
+    /// On a <sup>1</sup>⁄<sub>12</sub> of a beat.
+    Twelfth,
+    /// On a <sup>1</sup>⁄<sub>16</sub> of a beat.
+    Sixteenth,
+    /// None of the above, or there are no timing points to tell.
+    Other,
+}
+
+/// Snaps of the start and the end of an object.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct ObjectSnap {
+    /// Snap of the object start.
+    pub start: Snap,
+    /// Snap of the object end, which is the same as `start` for regular objects.
+    pub end: Snap,
+}
+
+impl Snap {
+    /// All snaps, from the coarsest to the finest.
+    pub const ALL: [Snap; 9] = [
+        Snap::Whole,
+        Snap::Half,
+        Snap::Third,
+        Snap::Quarter,
+        Snap::Sixth,
+        Snap::Eighth,
+        Snap::Twelfth,
+        Snap::Sixteenth,
+        Snap::Other,
+    ];
+
+    /// Returns the index of the snap in [`Snap::ALL`].
+    #[inline]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Returns the number of parts the snap divides a beat into, or `None` for [`Snap::Other`].
+    #[inline]
+    pub fn divisor(self) -> Option<u8> {
+        match self {
+            Snap::Whole => Some(1),
+            Snap::Half => Some(2),
+            Snap::Third => Some(3),
+            Snap::Quarter => Some(4),
+            Snap::Sixth => Some(6),
+            Snap::Eighth => Some(8),
+            Snap::Twelfth => Some(12),
+            Snap::Sixteenth => Some(16),
+            Snap::Other => None,
+        }
+    }
+
+    /// Classifies `timestamp` into a snap.
+    ///
+    /// The beats are counted from the timing point in effect at `timestamp`, or from the first
+    /// timing point for timestamps before it. `timing_points` must be sorted by timestamp.
+    ///
+    /// A timestamp gets the coarsest snap with a beat fraction within `tolerance` of it. This
+    /// accounts for rounding in map formats which store timestamps in whole milliseconds.
+    pub fn classify(
+        timing_points: &[TimingPoint],
+        timestamp: MapTimestamp,
+        tolerance: MapTimestampDifference,
+    ) -> Self {
+        let index = timing_points
+            .partition_point(|timing_point| timing_point.timestamp <= timestamp)
+            .saturating_sub(1);
+        let timing_point = match timing_points.get(index) {
+            Some(timing_point) => timing_point,
+            None => return Snap::Other,
+        };
+
+        let beat_unit = match timing_point.signature.beat_unit {
+            0 => 4,
+            beat_unit => i64::from(beat_unit),
+        };
+        // Duration of a quarter note.
+        let beat = i64::from(timing_point.beat_duration.into_milli_hundredths()) * beat_unit / 4;
+        if beat <= 0 {
+            return Snap::Other;
+        }
+
+        let offset = i64::from((timestamp - timing_point.timestamp).into_milli_hundredths());
+        let tolerance = i64::from(tolerance.into_milli_hundredths());
+
+        for &snap in &Snap::ALL[..Snap::ALL.len() - 1] {
+            let divisor = i64::from(snap.divisor().unwrap());
+
+            // Distance to the closest beat fraction, multiplied by the divisor.
+            let remainder = (offset * divisor).rem_euclid(beat);
+            let distance = remainder.min(beat - remainder);
+            if distance <= tolerance * divisor {
+                return snap;
+            }
+        }
+
+        Snap::Other
+    }
+
+    /// Returns the tolerance used for the snaps in [`LaneCache`](crate::state::LaneCache).
+    #[inline]
+    pub fn default_tolerance() -> MapTimestampDifference {
+        MapTimestampDifference::from_millis(2)
+    }
+}
+
+impl ObjectSnap {
+    /// Classifies the start and the end of `object` into snaps.
+    ///
+    /// See [`Snap::classify()`].
+    pub fn classify(
+        timing_points: &[TimingPoint],
+        object: Object,
+        tolerance: MapTimestampDifference,
+    ) -> Self {
+        let start = Snap::classify(timing_points, object.start_timestamp(), tolerance);
+        let end = match object {
+            Object::Regular { .. } => start,
+            Object::LongNote { end, .. } => Snap::classify(timing_points, end, tolerance),
+        };
+
+        Self { start, end }
+    }
+
+    /// Classifies every object in `objects` with [`Snap::default_tolerance()`].
+    pub(crate) fn classify_all(timing_points: &[TimingPoint], objects: &[Object]) -> Vec<Self> {
+        objects
+            .iter()
+            .map(|&object| Self::classify(timing_points, object, Snap::default_tolerance()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TimeSignature;
+    use alloc::vec;
+    use proptest::prelude::*;
+
+    fn timing_points() -> Vec<TimingPoint> {
+        vec![
+            // 120 BPM.
+            TimingPoint {
+                timestamp: MapTimestamp::from_millis(1_000),
+                beat_duration: MapTimestampDifference::from_millis(500),
+                signature: TimeSignature {
+                    beat_count: 4,
+                    beat_unit: 4,
+                },
+            },
+            // 180 BPM, with the beats in eighth notes.
+            TimingPoint {
+                timestamp: MapTimestamp::from_millis(10_000),
+                beat_duration: MapTimestampDifference::from_milli_hundredths(16_667),
+                signature: TimeSignature {
+                    beat_count: 6,
+                    beat_unit: 8,
+                },
+            },
+        ]
+    }
+
+    fn classify(millis: i32) -> Snap {
+        Snap::classify(
+            &timing_points(),
+            MapTimestamp::from_millis(millis),
+            Snap::default_tolerance(),
+        )
+    }
+
+    #[test]
+    fn snaps() {
+        assert_eq!(classify(1_000), Snap::Whole);
+        assert_eq!(classify(1_500), Snap::Whole);
+        assert_eq!(classify(1_250), Snap::Half);
+        assert_eq!(classify(1_167), Snap::Third);
+        assert_eq!(classify(1_125), Snap::Quarter);
+        assert_eq!(classify(1_083), Snap::Sixth);
+        assert_eq!(classify(1_063), Snap::Eighth);
+        assert_eq!(classify(1_042), Snap::Twelfth);
+        assert_eq!(classify(1_031), Snap::Sixteenth);
+        assert_eq!(classify(1_010), Snap::Other);
+
+        // Before the first timing point.
+        assert_eq!(classify(0), Snap::Whole);
+        assert_eq!(classify(750), Snap::Half);
+    }
+
+    #[test]
+    fn snaps_with_eighth_note_beats() {
+        // A quarter note is two beats of the second timing point.
+        assert_eq!(classify(10_000), Snap::Whole);
+        assert_eq!(classify(10_333), Snap::Whole);
+        assert_eq!(classify(10_167), Snap::Half);
+        assert_eq!(classify(10_083), Snap::Quarter);
+    }
+
+    #[test]
+    fn no_timing_points() {
+        assert_eq!(
+            Snap::classify(&[], MapTimestamp::from_millis(0), Snap::default_tolerance()),
+            Snap::Other
+        );
+    }
+
+    #[test]
+    fn object_snaps() {
+        let snap = ObjectSnap::classify(
+            &timing_points(),
+            Object::LongNote {
+                start: MapTimestamp::from_millis(1_250),
+                end: MapTimestamp::from_millis(2_000),
+            },
+            Snap::default_tolerance(),
+        );
+        assert_eq!(
+            snap,
+            ObjectSnap {
+                start: Snap::Half,
+                end: Snap::Whole,
+            }
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn classify_doesnt_panic(
+            timing_points: Vec<TimingPoint>,
+            timestamp: MapTimestamp,
+            tolerance: MapTimestampDifference,
+        ) {
+            let mut timing_points = timing_points;
+            timing_points.sort_by_key(|timing_point| timing_point.timestamp);
+            Snap::classify(&timing_points, timestamp, tolerance);
+        }
+    }
+}
@@ -1,15 +1,19 @@
 //! Functionality related to managing the game state.
 use alloc::{sync::Arc, vec::Vec};
-use core::cmp::Ord;
+use core::cmp::{Ord, Ordering};
 
 use circular_queue::CircularQueue;
 
 use crate::{
-    map::Map,
+    editor::ChartChanges,
+    health::Health,
+    judgement::{HitWindows, Judgement},
+    map::{Chart, Lane, ScrollSpeedChange, TimingPoint},
     object::Object,
-    scroll::Position,
+    scroll::{Position, ScrollSpeedMultiplier},
+    snap::{ObjectSnap, Snap},
     timing::{
-        GameTimestamp, GameTimestampDifference, MapTimestamp, MapTimestampDifference,
+        GameTimestamp, GameTimestampDifference, MapTimestamp, MapTimestampDifference, Rate,
         TimestampConverter,
     },
 };
@@ -21,10 +25,10 @@ pub struct GameState {
     ///
     /// Stored in an [`Arc`] so it doesn't have to be cloned.
     pub immutable: Arc<ImmutableGameState>,
-    /// Largest timestamp difference that will be considered a hit.
+    /// Judgement windows for presses and releases.
     ///
-    /// Notes past the hit window will be considered missed.
-    pub hit_window: GameTimestampDifference,
+    /// Notes past the largest window will be considered missed.
+    pub hit_windows: HitWindows,
     /// Converter between game timestamps and map timestamps.
     pub timestamp_converter: TimestampConverter,
     /// Contains states of the objects in lanes.
@@ -33,37 +37,67 @@ pub struct GameState {
     ///
     /// Useful for implementing an error bar.
     pub last_hits: CircularQueue<Hit>,
+    /// Health of the player.
+    ///
+    /// `None` means the player can't fail.
+    pub health: Option<Health>,
+    /// Lane and index of the object that made the player fail, until the [`EventKind::Failed`]
+    /// event is returned.
+    pending_fail: Option<(usize, usize)>,
+    /// Number of times [`GameState::seek()`] was called or the chart was edited.
+    ///
+    /// Used by [`GameState::update_to_latest()`] to find out if object states could have changed
+    /// in a non-incremental way.
+    pub(crate) seek_count: u32,
 }
 
 /// Immutable part of the game state.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ImmutableGameState {
-    /// The map.
+    /// The chart.
     ///
     /// Invariant: objects in each lane must be sorted by start timestamp and not overlap (which
     /// means they are sorted by both start and end timestamp).
-    pub map: Map,
+    ///
+    /// The objects of the scroll groups are moved into [`Chart::lanes`], so the scroll groups have
+    /// no lanes. The scroll group of every object is stored in its [`ObjectCache`].
+    pub chart: Chart,
     /// Contains immutable pre-computed information about objects.
     pub lane_caches: Vec<LaneCache>,
     /// A cache of positions for each scroll speed change timestamp.
     ///
-    /// Indices into the cache are equal to indices into [`Map::scroll_speed_changes`].
+    /// Indices into the cache are equal to indices into [`Chart::scroll_speed_changes`].
     pub position_cache: Vec<CachedPosition>,
+    /// Pre-computed information about scroll groups.
+    ///
+    /// Indices into this vector are equal to indices into [`Chart::scroll_groups`].
+    pub scroll_group_caches: Vec<ScrollGroupCache>,
+    /// Direction changes of all long notes.
+    ///
+    /// Each [`LongNoteCache`] refers to its direction changes as a range in this vector. Use
+    /// [`GameState::long_note_direction_changes()`] to get them.
+    pub direction_changes: Vec<DirectionChange>,
     /// Pre-computed timing lines.
     pub timing_lines: Vec<TimingLine>,
 
     /// Regular object which has the minimum position.
     pub min_regular: Option<RegularObjectCache>,
-    /// Long note, one of the ends of which has the minimum position.
+    /// Long note which has the minimum position.
     pub min_long_note: Option<LongNoteCache>,
     /// Regular object which has the minimum position.
     pub max_regular: Option<RegularObjectCache>,
-    /// Long note, one of the ends of which has the maximum position.
+    /// Long note which has the maximum position.
     pub max_long_note: Option<LongNoteCache>,
 
     /// Minimum position across all objects.
+    ///
+    /// Positions of objects in scroll groups are computed with the scroll speed changes of their
+    /// scroll group.
     pub min_position: Option<Position>,
     /// Maximum position across all objects.
+    ///
+    /// Positions of objects in scroll groups are computed with the scroll speed changes of their
+    /// scroll group.
     pub max_position: Option<Position>,
 
     /// Timing line with the maximum position.
@@ -82,6 +116,8 @@ pub enum RegularObjectState {
     },
     /// The object has been missed, that is, has not been hit on time.
     Missed,
+    /// The object has been skipped over with [`GameState::seek()`].
+    Skipped,
 }
 
 /// States of a long note object.
@@ -113,6 +149,8 @@ pub enum LongNoteState {
         /// been held.
         press_difference: Option<GameTimestampDifference>,
     },
+    /// The long note has been skipped over with [`GameState::seek()`].
+    Skipped,
 }
 
 /// State of an individual object.
@@ -152,6 +190,10 @@ pub struct RegularObjectCache {
     /// Zero position corresponds to timestamp zero. The position takes scroll speed changes into
     /// account.
     pub position: Position,
+    /// Index of the object's scroll group in [`Chart::scroll_groups`].
+    ///
+    /// `None` means the object scrolls with the chart's own scroll speed changes.
+    pub scroll_group: Option<usize>,
 }
 
 /// Cached information of a long note.
@@ -167,9 +209,52 @@ pub struct LongNoteCache {
     /// Zero position corresponds to timestamp zero. The position takes scroll speed changes into
     /// account.
     pub end_position: Position,
-    // TODO: this needs special fields to account for SV direction changes mid-LN. At the very
-    // least there should be fields for lowest and highest positions, and possibly fields for
-    // positions at every SV direction change timestamp to render them properly.
+    /// Lowest position of the long note.
+    ///
+    /// This is below both the start and the end position if the long note spans a scroll speed
+    /// change which makes it turn around.
+    pub lowest_position: Position,
+    /// Highest position of the long note.
+    ///
+    /// This is above both the start and the end position if the long note spans a scroll speed
+    /// change which makes it turn around.
+    pub highest_position: Position,
+    /// Index of the first direction change of this long note in
+    /// [`ImmutableGameState::direction_changes`].
+    pub first_direction_change: usize,
+    /// Number of direction changes of this long note.
+    pub direction_change_count: usize,
+    /// Index of the long note's scroll group in [`Chart::scroll_groups`].
+    ///
+    /// `None` means the long note scrolls with the chart's own scroll speed changes.
+    pub scroll_group: Option<usize>,
+}
+
+/// Point where a long note changes its scrolling direction.
+///
+/// This happens when a long note spans a scroll speed change to a multiplier of the opposite sign,
+/// which makes the long note body fold onto itself.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct DirectionChange {
+    /// Timestamp of the direction change.
+    pub timestamp: MapTimestamp,
+    /// Position at the timestamp, taking scroll speed changes into account.
+    pub position: Position,
+}
+
+/// Visual geometry of a long note at a particular moment.
+///
+/// Returned by [`GameState::long_note_geometry()`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct LongNoteGeometry {
+    /// Position of the long note head.
+    pub head_position: Position,
+    /// Position of the long note tail.
+    pub tail_position: Position,
+    /// Lowest position of the long note body.
+    pub lowest_position: Position,
+    /// Highest position of the long note body.
+    pub highest_position: Position,
 }
 
 /// Cached information of the objects in a lane.
@@ -177,6 +262,22 @@ pub struct LongNoteCache {
 pub struct LaneCache {
     /// Cached information of the objects in this lane.
     pub object_caches: Vec<ObjectCache>,
+    /// Rhythmic snaps of the objects in this lane, useful for coloring notes by snap.
+    ///
+    /// The snaps are classified with [`Snap::default_tolerance()`].
+    pub snaps: Vec<ObjectSnap>,
+}
+
+/// Cached information of a scroll group.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScrollGroupCache {
+    /// A cache of positions for each scroll speed change timestamp of the scroll group.
+    ///
+    /// Indices into the cache are equal to indices into
+    /// [`ScrollGroup::scroll_speed_changes`](crate::map::ScrollGroup::scroll_speed_changes).
+    pub position_cache: Vec<CachedPosition>,
+    /// Pre-computed timing lines, positioned with the scroll speed changes of the scroll group.
+    pub timing_lines: Vec<TimingLine>,
 }
 
 /// Cached position at a given timestamp.
@@ -206,6 +307,8 @@ pub struct Hit {
     pub timestamp: GameTimestamp,
     /// Difference between the actual press or release and the perfect timing.
     pub difference: GameTimestampDifference,
+    /// Judgement of the hit.
+    pub judgement: Judgement,
 }
 
 /// Type of an event that can occur as the result of an gameplay update.
@@ -220,6 +323,14 @@ pub enum EventKind {
     ///
     /// Long notes usually produce more than one hit (for press and for release).
     Hit(Hit),
+
+    /// The player has failed.
+    ///
+    /// This event follows the event that made the player fail, and has the same object index and
+    /// lane. Afterwards the state no longer changes and no more events are produced.
+    ///
+    /// Only produced when [`GameState::health`] is set.
+    Failed,
 }
 
 /// An event that can occur as the result of an gameplay update.
@@ -237,110 +348,113 @@ pub struct Event {
 pub enum GameStateCreationError {
     /// The map has overlapping objects.
     ///
-    /// The tuple contains the map, as well as two overlapping objects.
-    MapHasOverlappingObjects(Map, Object, Object),
+    /// The tuple contains the chart, as well as two overlapping objects.
+    MapHasOverlappingObjects(Chart, Object, Object),
+    /// A scroll group has more lanes than the map.
+    ///
+    /// The tuple contains the chart, as well as the index of the scroll group.
+    ScrollGroupHasTooManyLanes(Chart, usize),
 }
 
-// Manual implementation to avoid printing the whole `Map`.
+// Manual implementation to avoid printing the whole `Chart`.
 impl core::fmt::Debug for GameStateCreationError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            Self::MapHasOverlappingObjects(_map, a, b) => f
+            Self::MapHasOverlappingObjects(_chart, a, b) => f
                 .debug_tuple("MapHasOverlappingObjects")
                 .field(a)
                 .field(b)
                 .finish(),
+            Self::ScrollGroupHasTooManyLanes(_chart, index) => f
+                .debug_tuple("ScrollGroupHasTooManyLanes")
+                .field(index)
+                .finish(),
         }
     }
 }
 
 impl GameState {
-    /// Creates a new `GameState` given a map and a hit window.
+    /// Creates a new `GameState` given a chart and hit windows.
+    ///
+    /// A [`Map`](crate::map::Map) can be passed too, in which case its metadata is dropped.
     #[allow(clippy::result_large_err)]
     pub fn new(
-        mut map: Map,
-        hit_window: GameTimestampDifference,
+        chart: impl Into<Chart>,
+        hit_windows: HitWindows,
     ) -> Result<Self, GameStateCreationError> {
-        map.sort_and_dedup_scroll_speed_changes();
-        map.sort_and_dedup_timing_points();
+        let mut chart = chart.into();
+        chart.sort_and_dedup_scroll_speed_changes();
+        chart.sort_and_dedup_timing_points();
 
-        // Compute the position cache.
-        let zero_timestamp_scroll_speed_change_index = match map
-            .scroll_speed_changes
-            .binary_search_by_key(&MapTimestamp::from_milli_hundredths(0), |a| a.timestamp)
+        if let Some(index) = chart
+            .scroll_groups
+            .iter()
+            .position(|group| group.lanes.len() > chart.lanes.len())
         {
-            Ok(index) => Some(index),
-            Err(index) => {
-                if index == 0 {
-                    None
-                } else {
-                    Some(index - 1)
-                }
-            }
-        };
+            return Err(GameStateCreationError::ScrollGroupHasTooManyLanes(
+                chart, index,
+            ));
+        }
 
-        let mut position_cache = Vec::with_capacity(map.scroll_speed_changes.len());
-
-        // Compute positions for scroll speed changes before zero timestamp.
-        if let Some(index) = zero_timestamp_scroll_speed_change_index {
-            let mut last_timestamp = MapTimestamp::from_milli_hundredths(0);
-            let mut last_position = Position::zero();
-            for i in (0..=index).rev() {
-                let change = &map.scroll_speed_changes[i];
-                let position =
-                    last_position + (change.timestamp - last_timestamp) * change.multiplier;
-
-                // Zero timestamp always corresponds to zero position. But we cache it anyway to
-                // ensure the indices correspond to scroll speed change indices.
-                if change.timestamp == MapTimestamp::from_milli_hundredths(0) {
-                    assert_eq!(position, Position::zero());
+        // Move the objects of the scroll groups into the lanes, remembering their scroll groups.
+        let mut lane_scroll_groups: Vec<Vec<Option<usize>>> =
+            Vec::with_capacity(chart.lane_count());
+        for (index, lane) in chart.lanes.iter_mut().enumerate() {
+            let mut objects: Vec<(Object, Option<usize>)> = lane
+                .objects
+                .drain(..)
+                .map(|object| (object, None))
+                .collect();
+            for (group_index, group) in chart.scroll_groups.iter_mut().enumerate() {
+                if let Some(group_lane) = group.lanes.get_mut(index) {
+                    objects.extend(
+                        group_lane
+                            .objects
+                            .drain(..)
+                            .map(|object| (object, Some(group_index))),
+                    );
                 }
-
-                position_cache.push(CachedPosition {
-                    timestamp: change.timestamp,
-                    position,
-                });
-
-                last_timestamp = change.timestamp;
-                last_position = position;
             }
-        }
-        position_cache.reverse();
 
-        // Compute positions for scroll speed changes past zero timestamp.
-        let mut last_timestamp = MapTimestamp::from_milli_hundredths(0);
-        let mut last_position = Position::zero();
-        let mut last_multiplier = zero_timestamp_scroll_speed_change_index
-            .map(|index| map.scroll_speed_changes[index].multiplier)
-            .unwrap_or(map.initial_scroll_speed_multiplier);
-        for i in zero_timestamp_scroll_speed_change_index
-            .map(|x| x + 1)
-            .unwrap_or(0)..map.scroll_speed_changes.len()
-        {
-            let change = &map.scroll_speed_changes[i];
-            let position = last_position + (change.timestamp - last_timestamp) * last_multiplier;
-            position_cache.push(CachedPosition {
-                timestamp: change.timestamp,
-                position,
-            });
+            // Ensure the objects are sorted by their start timestamp (GameState invariant).
+            objects.sort_unstable_by_key(|(object, _)| object.start_timestamp());
 
-            last_timestamp = change.timestamp;
-            last_position = position;
-            last_multiplier = change.multiplier;
+            let (objects, scroll_groups) = objects.into_iter().unzip();
+            lane.objects = objects;
+            lane_scroll_groups.push(scroll_groups);
         }
+        for group in &mut chart.scroll_groups {
+            group.lanes.clear();
+        }
+
+        let position_cache = compute_position_cache(
+            &chart.scroll_speed_changes,
+            chart.initial_scroll_speed_multiplier,
+        );
+        let scroll_group_caches = chart
+            .scroll_groups
+            .iter()
+            .map(|group| ScrollGroupCache {
+                position_cache: compute_position_cache(
+                    &group.scroll_speed_changes,
+                    group.initial_scroll_speed_multiplier,
+                ),
+                timing_lines: Vec::new(),
+            })
+            .collect();
 
         // Compute per-lane and per-object data.
-        let mut lane_states = Vec::with_capacity(map.lane_count());
-        for lane in &mut map.lanes {
-            // Ensure the objects are sorted by their start timestamp (GameState invariant).
-            lane.objects.sort_unstable_by_key(Object::start_timestamp);
+        let mut lane_states = Vec::with_capacity(chart.lane_count());
+        for lane in &chart.lanes {
             // Ensure the objects don't overlap.
             for window in lane.objects.windows(2) {
                 let (a, b) = (window[0], window[1]);
                 // This does not permit an object at an LN end timestamp... Which is probably a
                 // good thing, especially considering the traditional LN skins with an LN end.
                 if a.end_timestamp() >= b.start_timestamp() {
-                    return Err(GameStateCreationError::MapHasOverlappingObjects(map, a, b));
+                    return Err(GameStateCreationError::MapHasOverlappingObjects(
+                        chart, a, b,
+                    ));
                 }
             }
 
@@ -362,11 +476,14 @@ impl GameState {
         let timestamp_converter = TimestampConverter {
             global_offset: GameTimestampDifference::from_millis(0),
             local_offset: MapTimestampDifference::from_millis(0),
+            rate: Rate::default(),
         };
 
         let mut immutable = ImmutableGameState {
-            map,
+            chart,
             position_cache,
+            scroll_group_caches,
+            direction_changes: Vec::new(),
             lane_caches: Vec::new(),
             timing_lines: Vec::new(),
             min_regular: None,
@@ -378,151 +495,47 @@ impl GameState {
             max_timing_line: None,
         };
 
-        let mut min_regular_position = None;
-        let mut min_long_note_position = None;
-        let mut max_regular_position = None;
-        let mut max_long_note_position = None;
-
         // Now that we can use position_at_time(), fill in the lane caches.
+        let mut direction_changes = Vec::new();
         let mut lane_caches = Vec::with_capacity(immutable.lane_count());
-        for lane in &immutable.map.lanes {
-            let mut object_caches = Vec::with_capacity(lane.objects.len());
-
-            for object in &lane.objects {
-                // TODO: this can be optimized to not do a binary search for every single
-                // timestamp, based on the fact that we're iterating in ascending timestamp order.
-                let cache = match *object {
-                    Object::Regular { timestamp } => ObjectCache::Regular(RegularObjectCache {
-                        position: immutable.position_at_time(timestamp),
-                    }),
-                    Object::LongNote { start, end } => ObjectCache::LongNote(LongNoteCache {
-                        start_position: immutable.position_at_time(start),
-                        end_position: immutable.position_at_time(end),
-                    }),
-                };
-
-                // Update minimum and maximum position.
-                let min = cache.start_position().min(cache.end_position());
-                match cache {
-                    ObjectCache::Regular(cache) => {
-                        if let Some(min_position) = min_regular_position {
-                            if min < min_position {
-                                min_regular_position = Some(min);
-                                immutable.min_regular = Some(cache);
-                            }
-                        } else {
-                            min_regular_position = Some(min);
-                            immutable.min_regular = Some(cache);
-                        }
-                    }
-                    ObjectCache::LongNote(cache) => {
-                        if let Some(min_position) = min_long_note_position {
-                            if min < min_position {
-                                min_long_note_position = Some(min);
-                                immutable.min_long_note = Some(cache);
-                            }
-                        } else {
-                            min_long_note_position = Some(min);
-                            immutable.min_long_note = Some(cache);
-                        }
-                    }
-                }
-                if let Some(ref mut min_position) = immutable.min_position {
-                    *min_position = (*min_position).min(min);
-                } else {
-                    immutable.min_position = Some(min);
-                }
-
-                let max = cache.start_position().max(cache.end_position());
-                match cache {
-                    ObjectCache::Regular(cache) => {
-                        if let Some(max_position) = max_regular_position {
-                            if max > max_position {
-                                max_regular_position = Some(max);
-                                immutable.max_regular = Some(cache);
-                            }
-                        } else {
-                            max_regular_position = Some(max);
-                            immutable.max_regular = Some(cache);
-                        }
-                    }
-                    ObjectCache::LongNote(cache) => {
-                        if let Some(max_position) = max_long_note_position {
-                            if max > max_position {
-                                max_long_note_position = Some(max);
-                                immutable.max_long_note = Some(cache);
-                            }
-                        } else {
-                            max_long_note_position = Some(max);
-                            immutable.max_long_note = Some(cache);
-                        }
-                    }
-                }
-                if let Some(ref mut max_position) = immutable.max_position {
-                    *max_position = (*max_position).max(max);
-                } else {
-                    immutable.max_position = Some(max);
-                }
+        for (lane, scroll_groups) in immutable.chart.lanes.iter().zip(&lane_scroll_groups) {
+            // TODO: this can be optimized to not do a binary search for every single timestamp,
+            // based on the fact that we're iterating in ascending timestamp order.
+            let object_caches = lane
+                .objects
+                .iter()
+                .zip(scroll_groups)
+                .map(|(&object, &scroll_group)| {
+                    immutable.scroll_speeds(scroll_group).object_cache(
+                        object,
+                        scroll_group,
+                        &mut direction_changes,
+                    )
+                })
+                .collect();
 
-                object_caches.push(cache);
-            }
+            let snaps = ObjectSnap::classify_all(&immutable.chart.timing_points, &lane.objects);
 
-            lane_caches.push(LaneCache { object_caches });
+            lane_caches.push(LaneCache {
+                object_caches,
+                snaps,
+            });
         }
         immutable.lane_caches = lane_caches;
+        immutable.direction_changes = direction_changes;
 
-        let mut timing_lines = Vec::new();
-        for (i, timing_point) in immutable.map.timing_points.iter().enumerate() {
-            // +1 and -1 ms like osu! does it. TODO: do we want this here?
-            let end = if let Some(next_timing_point) = immutable.map.timing_points.get(i + 1) {
-                next_timing_point.timestamp - MapTimestampDifference::from_millis(1)
-            } else {
-                immutable.last_timestamp().unwrap_or_else(|| {
-                    timing_point
-                        .timestamp
-                        .saturating_add(MapTimestampDifference::from_millis(1))
-                })
-            }
-            .into_milli_hundredths();
-
-            let step = (i64::from(timing_point.signature.beat_count)
-                * i64::from(timing_point.beat_duration.into_milli_hundredths()))
-            .clamp(0, i64::from(i32::MAX)) as i32;
-
-            let mut timestamp = timing_point.timestamp.into_milli_hundredths();
-            while timestamp < end {
-                {
-                    let timestamp = MapTimestamp::from_milli_hundredths(timestamp);
-                    let line = TimingLine {
-                        timestamp,
-                        position: immutable.position_at_time(timestamp),
-                    };
-                    timing_lines.push(line);
-
-                    if let Some(ref mut max_timing_line) = immutable.max_timing_line {
-                        if max_timing_line.position < line.position {
-                            *max_timing_line = line;
-                        }
-                    } else {
-                        immutable.max_timing_line = Some(line);
-                    }
-                }
-
-                if step == 0 {
-                    break;
-                }
-
-                timestamp = timestamp.saturating_add(step);
-            }
-        }
-        immutable.timing_lines = timing_lines;
+        immutable.update_extremes();
+        immutable.update_timing_lines();
 
         Ok(Self {
             immutable: Arc::new(immutable),
-            hit_window,
+            hit_windows,
             timestamp_converter,
             lane_states,
             last_hits: CircularQueue::with_capacity(32),
+            health: None,
+            pending_fail: None,
+            seek_count: 0,
         })
     }
 
@@ -531,7 +544,27 @@ impl GameState {
     /// The position takes scroll speed changes into account.
     #[inline]
     pub fn position_at_time(&self, timestamp: MapTimestamp) -> Position {
-        self.immutable.position_at_time(timestamp)
+        self.immutable
+            .scroll_group_position_at_time(None, timestamp)
+    }
+
+    /// Returns the position at the given map timestamp in a scroll group.
+    ///
+    /// `scroll_group` is an index into [`Chart::scroll_groups`], or `None` for the chart's own
+    /// scroll speed changes, in which case this is equivalent to
+    /// [`position_at_time()`](Self::position_at_time).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scroll_group` is out of bounds.
+    #[inline]
+    pub fn scroll_group_position_at_time(
+        &self,
+        scroll_group: Option<usize>,
+        timestamp: MapTimestamp,
+    ) -> Position {
+        self.immutable
+            .scroll_group_position_at_time(scroll_group, timestamp)
     }
 
     /// Returns the start timestamp of the first object.
@@ -552,7 +585,7 @@ impl GameState {
         self.immutable.min_regular
     }
 
-    /// Returns the long note, one of the ends of which has the minimum position.
+    /// Returns the long note which has the minimum position.
     #[inline]
     pub fn min_long_note(&self) -> Option<LongNoteCache> {
         self.immutable.min_long_note
@@ -564,7 +597,7 @@ impl GameState {
         self.immutable.max_regular
     }
 
-    /// Returns the long note, one of the ends of which has the maximum position.
+    /// Returns the long note which has the maximum position.
     #[inline]
     pub fn max_long_note(&self) -> Option<LongNoteCache> {
         self.immutable.max_long_note
@@ -598,31 +631,105 @@ impl GameState {
     ///
     /// This is mostly useful for long notes which change their start position while they are held.
     ///
-    /// `map_position` is the current map position.
+    /// `map_timestamp` is the current map timestamp. The position is computed with the scroll speed
+    /// changes of the object's scroll group.
     #[inline]
     pub fn object_start_position(
         &self,
         state: ObjectState,
         cache: ObjectCache,
-        map_position: Position,
+        map_timestamp: MapTimestamp,
     ) -> Position {
+        let scroll_group = cache.scroll_group();
+
         match state {
             // LNs "stick" to receptors when held.
             ObjectState::LongNote(LongNoteState::Held { .. }) => {
-                // TODO: this isn't quite correct with negative SVs.
-                map_position.max(cache.start_position())
+                // TODO: this isn't quite correct with negative SVs, long_note_geometry() should be
+                // used instead.
+                self.scroll_group_position_at_time(scroll_group, map_timestamp)
+                    .max(cache.start_position())
             }
             // LNs released prematurely remain at that position.
             ObjectState::LongNote(LongNoteState::Missed {
                 held_until: Some(held_until),
                 ..
-            }) => self.position_at_time(held_until),
+            }) => self.scroll_group_position_at_time(scroll_group, held_until),
             _ => cache.start_position(),
         }
     }
 
+    /// Returns the direction changes of a long note, sorted by timestamp.
+    #[inline]
+    pub fn long_note_direction_changes(&self, cache: &LongNoteCache) -> &[DirectionChange] {
+        self.immutable.long_note_direction_changes(cache)
+    }
+
+    /// Returns the current visual geometry of a long note.
+    ///
+    /// Unlike [`object_start_position()`](Self::object_start_position), this takes scroll speed
+    /// direction changes into account: the body of a long note which turns around extends past its
+    /// head or tail, and the part of a held long note that has already passed the receptor is
+    /// excluded.
+    ///
+    /// `timestamp` is the current map timestamp.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the object at `index` in `lane` is not a long note.
+    pub fn long_note_geometry(
+        &self,
+        lane: usize,
+        index: usize,
+        timestamp: MapTimestamp,
+    ) -> LongNoteGeometry {
+        let (start, end) = match self.immutable.chart.lanes[lane].objects[index] {
+            Object::LongNote { start, end } => (start, end),
+            Object::Regular { .. } => panic!("the object is not a long note"),
+        };
+        let cache = match self.immutable.lane_caches[lane].object_caches[index] {
+            ObjectCache::LongNote(cache) => cache,
+            ObjectCache::Regular(_) => unreachable!(),
+        };
+
+        // The timestamp from which the long note is still visible.
+        let head_timestamp = match self.lane_states[lane].object_states[index] {
+            // LNs "stick" to receptors when held.
+            ObjectState::LongNote(LongNoteState::Held { .. }) => timestamp.clamp(start, end),
+            // LNs released prematurely remain at that position.
+            ObjectState::LongNote(LongNoteState::Missed {
+                held_until: Some(held_until),
+                ..
+            }) => held_until,
+            _ => start,
+        };
+
+        let head_position = self.scroll_group_position_at_time(cache.scroll_group, head_timestamp);
+        let tail_position = cache.end_position;
+        let mut lowest_position = head_position.min(tail_position);
+        let mut highest_position = head_position.max(tail_position);
+        for change in self
+            .long_note_direction_changes(&cache)
+            .iter()
+            .filter(|change| change.timestamp > head_timestamp)
+        {
+            lowest_position = lowest_position.min(change.position);
+            highest_position = highest_position.max(change.position);
+        }
+
+        LongNoteGeometry {
+            head_position,
+            tail_position,
+            lowest_position,
+            highest_position,
+        }
+    }
+
     /// Updates the state to match the `latest` state.
     ///
+    /// If the `latest` state has been seeked or edited since the last update, all object states
+    /// are copied. The chart edits are picked up too, along with the rest of the immutable state.
+    ///
     /// # Panics
     ///
     /// Panics if the `latest` state is older than `self` (as indicated by `first_active_object` in
@@ -630,6 +737,18 @@ impl GameState {
     pub fn update_to_latest(&mut self, latest: &GameState) {
         self.timestamp_converter = latest.timestamp_converter;
         self.last_hits = latest.last_hits.clone();
+        self.health = latest.health;
+        self.pending_fail = latest.pending_fail;
+
+        if !Arc::ptr_eq(&self.immutable, &latest.immutable) {
+            self.immutable = latest.immutable.clone();
+        }
+
+        if self.seek_count != latest.seek_count {
+            self.seek_count = latest.seek_count;
+            self.lane_states.clone_from(&latest.lane_states);
+            return;
+        }
 
         for (lane, latest_lane) in self.lane_states.iter_mut().zip(latest.lane_states.iter()) {
             assert!(lane.first_active_object <= latest_lane.first_active_object);
@@ -678,6 +797,130 @@ impl GameState {
         }
     }
 
+    /// Seeks to `timestamp`, resetting the states of all objects.
+    ///
+    /// Objects which start before `timestamp` are marked as
+    /// [skipped](ObjectState::is_skipped) and become inactive, and all other objects are marked as
+    /// not hit. This works both forwards and backwards, which makes it possible to restart the
+    /// gameplay from any point in the map, for example, to practice a section in a loop.
+    ///
+    /// Skipped objects don't produce any events, so they don't count towards the score. A
+    /// [`Score`](crate::score::Score) created after seeking leaves them out of the total. The
+    /// [health](GameState::health) is reset back to full.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use plitki_core::map::{Lane, Map};
+    /// # use plitki_core::object::Object;
+    /// # use plitki_core::scroll::ScrollSpeedMultiplier;
+    /// # use plitki_core::state::GameState;
+    /// # use plitki_core::judgement::HitWindows;
+    /// # use plitki_core::timing::{GameTimestamp, MapTimestamp};
+    /// # let map = Map {
+    /// #     song_artist: None,
+    /// #     song_title: None,
+    /// #     difficulty_name: None,
+    /// #     background_file: None,
+    /// #     mapper: None,
+    /// #     audio_file: None,
+    /// #     timing_points: vec![],
+    /// #     scroll_speed_changes: vec![],
+    /// #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+    /// #     scroll_groups: vec![],
+    /// #     lanes: vec![Lane {
+    /// #         objects: vec![
+    /// #             Object::Regular { timestamp: MapTimestamp::from_millis(0) },
+    /// #             Object::Regular { timestamp: MapTimestamp::from_millis(1_000) },
+    /// #         ],
+    /// #     }],
+    /// # };
+    /// # let mut state = GameState::new(map, HitWindows::default()).unwrap();
+    /// state.seek(GameTimestamp::from_millis(500));
+    ///
+    /// assert!(state.lane_states[0].object_states[0].is_skipped());
+    /// assert_eq!(state.first_active_object(0), Some(1));
+    /// ```
+    pub fn seek(&mut self, timestamp: GameTimestamp) {
+        let map_timestamp = timestamp.to_map(&self.timestamp_converter);
+
+        for (lane_state, lane) in self.lane_states.iter_mut().zip(&self.immutable.chart.lanes) {
+            // Objects are sorted by start timestamp.
+            let first_active_object = lane
+                .objects
+                .partition_point(|object| object.start_timestamp() < map_timestamp);
+
+            for (index, (state, object)) in lane_state
+                .object_states
+                .iter_mut()
+                .zip(&lane.objects)
+                .enumerate()
+            {
+                let skipped = index < first_active_object;
+                *state = match (object, skipped) {
+                    (Object::Regular { .. }, false) => {
+                        ObjectState::Regular(RegularObjectState::NotHit)
+                    }
+                    (Object::Regular { .. }, true) => {
+                        ObjectState::Regular(RegularObjectState::Skipped)
+                    }
+                    (Object::LongNote { .. }, false) => {
+                        ObjectState::LongNote(LongNoteState::NotHit)
+                    }
+                    (Object::LongNote { .. }, true) => {
+                        ObjectState::LongNote(LongNoteState::Skipped)
+                    }
+                };
+            }
+
+            lane_state.first_active_object = first_active_object;
+        }
+
+        self.last_hits.clear();
+        if let Some(health) = &mut self.health {
+            health.reset();
+        }
+        self.pending_fail = None;
+        self.seek_count = self.seek_count.wrapping_add(1);
+    }
+
+    /// Returns `true` if the player has failed.
+    ///
+    /// This is always `false` if [`GameState::health`] is not set.
+    #[inline]
+    pub fn is_failed(&self) -> bool {
+        self.health.as_ref().is_some_and(Health::is_failed)
+    }
+
+    /// Returns the [`EventKind::Failed`] event if it's pending in `lane`.
+    fn take_fail_event(&mut self, lane: usize) -> Option<Event> {
+        match self.pending_fail {
+            Some((fail_lane, object_index)) if fail_lane == lane => {
+                self.pending_fail = None;
+                Some(Event {
+                    object_index,
+                    kind: EventKind::Failed,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Applies the `event` that happened in `lane` to the health.
+    fn update_health(&mut self, lane: usize, event: Event, timestamp: GameTimestamp) {
+        if let Some(health) = &mut self.health {
+            let failed = match event.kind {
+                EventKind::Hit(hit) => health.judge(hit.judgement, hit.timestamp),
+                EventKind::Miss => health.miss(timestamp),
+                EventKind::Failed => false,
+            };
+
+            if failed {
+                self.pending_fail = Some((lane, event.object_index));
+            }
+        }
+    }
+
     /// Updates the state for all lanes.
     ///
     /// Essentially, this is a way to signal "some time has passed". Stuff like missed objects is
@@ -692,7 +935,8 @@ impl GameState {
     /// # use plitki_core::map::Map;
     /// # use plitki_core::scroll::ScrollSpeedMultiplier;
     /// # use plitki_core::state::GameState;
-    /// # use plitki_core::timing::{GameTimestamp, GameTimestampDifference};
+    /// # use plitki_core::judgement::HitWindows;
+    /// # use plitki_core::timing::GameTimestamp;
     /// # let map = Map {
     /// #     song_artist: None,
     /// #     song_title: None,
@@ -703,9 +947,10 @@ impl GameState {
     /// #     timing_points: vec![],
     /// #     scroll_speed_changes: vec![],
     /// #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+    /// #     scroll_groups: vec![],
     /// #     lanes: vec![],
     /// # };
-    /// # let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+    /// # let mut state = GameState::new(map, HitWindows::default()).unwrap();
     /// # let timestamp = GameTimestamp::from_millis(0);
     /// while let Some(event) = state.update(timestamp) {
     ///     // Handle event.
@@ -736,7 +981,8 @@ impl GameState {
     /// # use plitki_core::map::{Lane, Map};
     /// # use plitki_core::scroll::ScrollSpeedMultiplier;
     /// # use plitki_core::state::GameState;
-    /// # use plitki_core::timing::{GameTimestamp, GameTimestampDifference};
+    /// # use plitki_core::judgement::HitWindows;
+    /// # use plitki_core::timing::GameTimestamp;
     /// # let map = Map {
     /// #     song_artist: None,
     /// #     song_title: None,
@@ -747,9 +993,10 @@ impl GameState {
     /// #     timing_points: vec![],
     /// #     scroll_speed_changes: vec![],
     /// #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+    /// #     scroll_groups: vec![],
     /// #     lanes: vec![Lane { objects: vec![] }],
     /// # };
-    /// # let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+    /// # let mut state = GameState::new(map, HitWindows::default()).unwrap();
     /// # let timestamp = GameTimestamp::from_millis(0);
     /// # let lane = 0;
     /// while let Some(event) = state.update_lane(lane, timestamp) {
@@ -757,22 +1004,40 @@ impl GameState {
     /// }
     /// ```
     pub fn update_lane(&mut self, lane: usize, timestamp: GameTimestamp) -> Option<Event> {
+        if self.is_failed() {
+            return self.take_fail_event(lane);
+        }
+
+        let event = self.update_lane_objects(lane, timestamp)?;
+        self.update_health(lane, event, timestamp);
+        Some(event)
+    }
+
+    fn update_lane_objects(&mut self, lane: usize, timestamp: GameTimestamp) -> Option<Event> {
         if !self.has_active_objects(lane) {
             return None;
         }
 
         let map_timestamp = timestamp.to_map(&self.timestamp_converter);
-        let map_hit_window = self.hit_window.to_map(&self.timestamp_converter);
+        let press_window = self.hit_windows.press.largest();
+        let release_window = self.hit_windows.release.largest();
+        let map_press_window = press_window.to_map(&self.timestamp_converter);
+        let map_release_window = release_window.to_map(&self.timestamp_converter);
 
         let lane_state = &mut self.lane_states[lane];
         let object_index = lane_state.first_active_object;
-        let object = &self.immutable.map.lanes[lane].objects[object_index];
+        let object = &self.immutable.chart.lanes[lane].objects[object_index];
         let state = &mut lane_state.object_states[object_index];
 
         // We want to increase first_active_object on every early return.
         lane_state.first_active_object += 1;
 
-        if object.end_timestamp().saturating_add(map_hit_window) < map_timestamp {
+        let map_end_window = match object {
+            Object::Regular { .. } => map_press_window,
+            Object::LongNote { .. } => map_release_window,
+        };
+
+        if object.end_timestamp().saturating_add(map_end_window) < map_timestamp {
             // The object can no longer be hit.
             match state {
                 ObjectState::Regular(state) => {
@@ -791,13 +1056,14 @@ impl GameState {
                     if let LongNoteState::Held { press_difference } = *state {
                         *state = LongNoteState::Hit {
                             press_difference,
-                            release_difference: self.hit_window,
+                            release_difference: release_window,
                         };
 
                         let hit = Hit {
-                            timestamp: (object.end_timestamp() + map_hit_window)
+                            timestamp: (object.end_timestamp() + map_release_window)
                                 .to_game(&self.timestamp_converter),
-                            difference: self.hit_window,
+                            difference: release_window,
+                            judgement: self.hit_windows.release.judge(release_window),
                         };
                         self.last_hits.push(hit);
 
@@ -824,7 +1090,7 @@ impl GameState {
             }
         }
 
-        if object.start_timestamp().saturating_add(map_hit_window) < map_timestamp {
+        if object.start_timestamp().saturating_add(map_press_window) < map_timestamp {
             // The object can no longer be hit.
             if let ObjectState::LongNote(state) = state {
                 // Mark this long note as missed.
@@ -868,7 +1134,8 @@ impl GameState {
     /// # use plitki_core::map::{Lane, Map};
     /// # use plitki_core::scroll::ScrollSpeedMultiplier;
     /// # use plitki_core::state::GameState;
-    /// # use plitki_core::timing::{GameTimestamp, GameTimestampDifference};
+    /// # use plitki_core::judgement::HitWindows;
+    /// # use plitki_core::timing::GameTimestamp;
     /// # let map = Map {
     /// #     song_artist: None,
     /// #     song_title: None,
@@ -879,9 +1146,10 @@ impl GameState {
     /// #     timing_points: vec![],
     /// #     scroll_speed_changes: vec![],
     /// #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+    /// #     scroll_groups: vec![],
     /// #     lanes: vec![Lane { objects: vec![] }],
     /// # };
-    /// # let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+    /// # let mut state = GameState::new(map, HitWindows::default()).unwrap();
     /// # let lane = 0;
     /// # let timestamp = GameTimestamp::from_millis(0);
     /// // Same timestamp as passed to `key_press()` right below.
@@ -894,18 +1162,32 @@ impl GameState {
     /// }
     /// ```
     pub fn key_press(&mut self, lane: usize, timestamp: GameTimestamp) -> Option<Event> {
+        if self.is_failed() {
+            return self.take_fail_event(lane);
+        }
+
+        let event = self.press(lane, timestamp)?;
+        self.update_health(lane, event, timestamp);
+        Some(event)
+    }
+
+    fn press(&mut self, lane: usize, timestamp: GameTimestamp) -> Option<Event> {
         while self.update(timestamp).is_some() {}
 
-        if !self.has_active_objects(lane) {
+        if !self.has_active_objects(lane) || self.is_failed() {
             return None;
         }
 
         let map_timestamp = timestamp.to_map(&self.timestamp_converter);
-        let map_hit_window = self.hit_window.to_map(&self.timestamp_converter);
+        let map_hit_window = self
+            .hit_windows
+            .press
+            .largest()
+            .to_map(&self.timestamp_converter);
 
         let lane_state = &mut self.lane_states[lane];
         let object_index = lane_state.first_active_object;
-        let object = &self.immutable.map.lanes[lane].objects[object_index];
+        let object = &self.immutable.chart.lanes[lane].objects[object_index];
         let state = &mut lane_state.object_states[object_index];
 
         if map_timestamp >= object.start_timestamp().saturating_sub(map_hit_window) {
@@ -930,6 +1212,7 @@ impl GameState {
             let hit = Hit {
                 timestamp,
                 difference,
+                judgement: self.hit_windows.press.judge(difference),
             };
             self.last_hits.push(hit);
 
@@ -953,7 +1236,8 @@ impl GameState {
     /// # use plitki_core::map::{Lane, Map};
     /// # use plitki_core::scroll::ScrollSpeedMultiplier;
     /// # use plitki_core::state::GameState;
-    /// # use plitki_core::timing::{GameTimestamp, GameTimestampDifference};
+    /// # use plitki_core::judgement::HitWindows;
+    /// # use plitki_core::timing::GameTimestamp;
     /// # let map = Map {
     /// #     song_artist: None,
     /// #     song_title: None,
@@ -964,9 +1248,10 @@ impl GameState {
     /// #     timing_points: vec![],
     /// #     scroll_speed_changes: vec![],
     /// #     initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+    /// #     scroll_groups: vec![],
     /// #     lanes: vec![Lane { objects: vec![] }],
     /// # };
-    /// # let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+    /// # let mut state = GameState::new(map, HitWindows::default()).unwrap();
     /// # let lane = 0;
     /// # let timestamp = GameTimestamp::from_millis(0);
     /// // Same timestamp as passed to `key_release()` right below.
@@ -979,18 +1264,32 @@ impl GameState {
     /// }
     /// ```
     pub fn key_release(&mut self, lane: usize, timestamp: GameTimestamp) -> Option<Event> {
+        if self.is_failed() {
+            return self.take_fail_event(lane);
+        }
+
+        let event = self.release(lane, timestamp)?;
+        self.update_health(lane, event, timestamp);
+        Some(event)
+    }
+
+    fn release(&mut self, lane: usize, timestamp: GameTimestamp) -> Option<Event> {
         while self.update(timestamp).is_some() {}
 
-        if !self.has_active_objects(lane) {
+        if !self.has_active_objects(lane) || self.is_failed() {
             return None;
         }
 
         let map_timestamp = timestamp.to_map(&self.timestamp_converter);
-        let map_hit_window = self.hit_window.to_map(&self.timestamp_converter);
+        let map_hit_window = self
+            .hit_windows
+            .release
+            .largest()
+            .to_map(&self.timestamp_converter);
 
         let lane_state = &mut self.lane_states[lane];
         let object_index = lane_state.first_active_object;
-        let object = &self.immutable.map.lanes[lane].objects[object_index];
+        let object = &self.immutable.chart.lanes[lane].objects[object_index];
         let state = &mut lane_state.object_states[object_index];
 
         if let ObjectState::LongNote(state) = state {
@@ -1010,6 +1309,7 @@ impl GameState {
                     let hit = Hit {
                         timestamp,
                         difference,
+                        judgement: self.hit_windows.release.judge(difference),
                     };
                     self.last_hits.push(hit);
 
@@ -1037,38 +1337,50 @@ impl GameState {
 }
 
 impl ImmutableGameState {
-    /// Returns the map position at the given map timestamp.
+    /// Returns the scroll speed changes of a scroll group along with their position cache.
     ///
-    /// The position takes scroll speed changes into account.
-    fn position_at_time(&self, timestamp: MapTimestamp) -> Position {
-        if self.position_cache.is_empty() {
-            return Position::zero()
-                + (timestamp - MapTimestamp::from_millis(0))
-                    * self.map.initial_scroll_speed_multiplier;
-        }
-
-        match self
-            .position_cache
-            .binary_search_by_key(&timestamp, |x| x.timestamp)
-        {
-            Ok(index) => self.position_cache[index].position,
-            Err(0) => {
-                self.position_cache[0].position
-                    + (timestamp - self.position_cache[0].timestamp)
-                        * self.map.initial_scroll_speed_multiplier
-            }
-            Err(index) => {
-                let cached_position = self.position_cache[index - 1];
-                let multiplier = self.map.scroll_speed_changes[index - 1].multiplier;
-                cached_position.position + (timestamp - cached_position.timestamp) * multiplier
+    /// `None` refers to the chart's own scroll speed changes.
+    fn scroll_speeds(&self, scroll_group: Option<usize>) -> ScrollSpeeds<'_> {
+        match scroll_group {
+            None => ScrollSpeeds {
+                scroll_speed_changes: &self.chart.scroll_speed_changes,
+                initial_scroll_speed_multiplier: self.chart.initial_scroll_speed_multiplier,
+                position_cache: &self.position_cache,
+            },
+            Some(index) => {
+                let group = &self.chart.scroll_groups[index];
+                ScrollSpeeds {
+                    scroll_speed_changes: &group.scroll_speed_changes,
+                    initial_scroll_speed_multiplier: group.initial_scroll_speed_multiplier,
+                    position_cache: &self.scroll_group_caches[index].position_cache,
+                }
             }
         }
     }
 
+    /// Returns the map position at the given map timestamp in a scroll group.
+    ///
+    /// The position takes scroll speed changes of the scroll group into account.
+    #[inline]
+    fn scroll_group_position_at_time(
+        &self,
+        scroll_group: Option<usize>,
+        timestamp: MapTimestamp,
+    ) -> Position {
+        self.scroll_speeds(scroll_group).position_at_time(timestamp)
+    }
+
+    /// Returns the direction changes of a long note, sorted by timestamp.
+    #[inline]
+    pub fn long_note_direction_changes(&self, cache: &LongNoteCache) -> &[DirectionChange] {
+        &self.direction_changes[cache.first_direction_change
+            ..cache.first_direction_change + cache.direction_change_count]
+    }
+
     /// Returns the start timestamp of the first object.
     #[inline]
     fn first_timestamp(&self) -> Option<MapTimestamp> {
-        self.map
+        self.chart
             .lanes
             .iter()
             .filter_map(|lane| lane.objects.first())
@@ -1079,7 +1391,7 @@ impl ImmutableGameState {
     /// Returns the end timestamp of the last object.
     #[inline]
     fn last_timestamp(&self) -> Option<MapTimestamp> {
-        self.map
+        self.chart
             .lanes
             .iter()
             .filter_map(|lane| lane.objects.last())
@@ -1090,7 +1402,525 @@ impl ImmutableGameState {
     /// Returns the number of lanes in the map.
     #[inline]
     pub fn lane_count(&self) -> usize {
-        self.map.lane_count()
+        self.chart.lane_count()
+    }
+
+    /// Returns the chart with the objects moved back into their scroll groups.
+    ///
+    /// This is the chart as it would be saved, for example, after editing it with
+    /// [`GameState::edit()`]. Passing it to [`GameState::new()`] results in the same immutable
+    /// state.
+    pub fn to_chart(&self) -> Chart {
+        let mut chart = self.chart.clone();
+        for group in &mut chart.scroll_groups {
+            group.lanes = (0..self.lane_count()).map(|_| Lane::default()).collect();
+        }
+
+        for (index, (lane, lane_cache)) in chart.lanes.iter_mut().zip(&self.lane_caches).enumerate()
+        {
+            let objects = core::mem::take(&mut lane.objects);
+            for (object, cache) in objects.into_iter().zip(&lane_cache.object_caches) {
+                match cache.scroll_group() {
+                    None => lane.objects.push(object),
+                    Some(group) => chart.scroll_groups[group].lanes[index].objects.push(object),
+                }
+            }
+        }
+
+        chart
+    }
+
+    /// Updates the caches after the chart has been edited.
+    pub(crate) fn update_caches(&mut self, changes: &ChartChanges) {
+        if let Some((scroll_group, from)) = changes.scroll_speed_changes {
+            self.update_position_cache(scroll_group, from);
+        }
+
+        if !changes.inserted_objects.is_empty()
+            || changes.removed_objects
+            || changes.scroll_speed_changes.is_some()
+        {
+            self.update_object_caches(|lane, index, object, scroll_group| {
+                changes.inserted_objects.contains(&(lane, index))
+                    || changes
+                        .scroll_speed_changes
+                        .is_some_and(|(changed_group, from)| {
+                            changed_group == scroll_group && is_after(object.end_timestamp(), from)
+                        })
+            });
+            self.update_extremes();
+        }
+
+        if changes.timing_points {
+            for (lane, lane_cache) in self.chart.lanes.iter().zip(&mut self.lane_caches) {
+                lane_cache.snaps =
+                    ObjectSnap::classify_all(&self.chart.timing_points, &lane.objects);
+            }
+        } else {
+            for &(lane, index) in &changes.inserted_objects {
+                self.lane_caches[lane].snaps[index] = ObjectSnap::classify(
+                    &self.chart.timing_points,
+                    self.chart.lanes[lane].objects[index],
+                    Snap::default_tolerance(),
+                );
+            }
+        }
+
+        if changes.timing_points || self.last_timestamp() != changes.last_timestamp {
+            self.update_timing_lines();
+        } else if let Some((scroll_group, from)) = changes.scroll_speed_changes {
+            // Timing line timestamps don't depend on scroll speed changes, only the positions do.
+            let mut timing_lines = match scroll_group {
+                None => core::mem::take(&mut self.timing_lines),
+                Some(index) => core::mem::take(&mut self.scroll_group_caches[index].timing_lines),
+            };
+            let scroll_speeds = self.scroll_speeds(scroll_group);
+            for line in timing_lines
+                .iter_mut()
+                .filter(|line| is_after(line.timestamp, from))
+            {
+                line.position = scroll_speeds.position_at_time(line.timestamp);
+            }
+
+            match scroll_group {
+                None => {
+                    self.timing_lines = timing_lines;
+                    self.update_max_timing_line();
+                }
+                Some(index) => self.scroll_group_caches[index].timing_lines = timing_lines,
+            }
+        }
+    }
+
+    /// Updates the position cache of a scroll group after its scroll speed changes at and after
+    /// `from` have been edited.
+    fn update_position_cache(&mut self, scroll_group: Option<usize>, from: MapTimestamp) {
+        let (changes, initial_scroll_speed_multiplier, position_cache) = match scroll_group {
+            None => (
+                &self.chart.scroll_speed_changes,
+                self.chart.initial_scroll_speed_multiplier,
+                &mut self.position_cache,
+            ),
+            Some(index) => {
+                let group = &self.chart.scroll_groups[index];
+                (
+                    &group.scroll_speed_changes,
+                    group.initial_scroll_speed_multiplier,
+                    &mut self.scroll_group_caches[index].position_cache,
+                )
+            }
+        };
+
+        // Positions are counted from zero timestamp, so edits before it can move everything.
+        let zero = MapTimestamp::from_milli_hundredths(0);
+        if from < zero {
+            *position_cache = compute_position_cache(changes, initial_scroll_speed_multiplier);
+            return;
+        }
+
+        // Changes before `from` and their positions are unaffected.
+        let first = changes.partition_point(|change| change.timestamp < from);
+        position_cache.truncate(first);
+
+        let (mut last_timestamp, mut last_position, mut last_multiplier) =
+            match first.checked_sub(1) {
+                Some(index) if position_cache[index].timestamp >= zero => (
+                    position_cache[index].timestamp,
+                    position_cache[index].position,
+                    changes[index].multiplier,
+                ),
+                Some(index) => (zero, Position::zero(), changes[index].multiplier),
+                None => (zero, Position::zero(), initial_scroll_speed_multiplier),
+            };
+        for change in &changes[first..] {
+            let position = last_position + (change.timestamp - last_timestamp) * last_multiplier;
+            position_cache.push(CachedPosition {
+                timestamp: change.timestamp,
+                position,
+            });
+
+            last_timestamp = change.timestamp;
+            last_position = position;
+            last_multiplier = change.multiplier;
+        }
+    }
+
+    /// Recomputes the caches of the objects for which `is_affected` returns `true`.
+    ///
+    /// `is_affected` is called with the lane, the object index, the object and its scroll group.
+    /// The direction changes are compacted along the way, dropping those of removed long notes.
+    fn update_object_caches(
+        &mut self,
+        mut is_affected: impl FnMut(usize, usize, Object, Option<usize>) -> bool,
+    ) {
+        let mut direction_changes = Vec::with_capacity(self.direction_changes.len());
+
+        for lane in 0..self.lane_count() {
+            for index in 0..self.chart.lanes[lane].objects.len() {
+                let object = self.chart.lanes[lane].objects[index];
+                let cache = self.lane_caches[lane].object_caches[index];
+                let scroll_group = cache.scroll_group();
+
+                let cache = if is_affected(lane, index, object, scroll_group) {
+                    self.scroll_speeds(scroll_group).object_cache(
+                        object,
+                        scroll_group,
+                        &mut direction_changes,
+                    )
+                } else if let ObjectCache::LongNote(mut cache) = cache {
+                    let first_direction_change = direction_changes.len();
+                    direction_changes.extend_from_slice(self.long_note_direction_changes(&cache));
+                    cache.first_direction_change = first_direction_change;
+                    ObjectCache::LongNote(cache)
+                } else {
+                    cache
+                };
+
+                self.lane_caches[lane].object_caches[index] = cache;
+            }
+        }
+
+        self.direction_changes = direction_changes;
+    }
+
+    /// Finds the objects with the minimum and maximum positions.
+    fn update_extremes(&mut self) {
+        self.min_regular = None;
+        self.min_long_note = None;
+        self.max_regular = None;
+        self.max_long_note = None;
+        self.min_position = None;
+        self.max_position = None;
+
+        for &cache in self.lane_caches.iter().flat_map(|lane| &lane.object_caches) {
+            let min = cache.lowest_position();
+            let max = cache.highest_position();
+
+            match cache {
+                ObjectCache::Regular(cache) => {
+                    if self.min_regular.is_none_or(|x| min < x.position) {
+                        self.min_regular = Some(cache);
+                    }
+                    if self.max_regular.is_none_or(|x| max > x.position) {
+                        self.max_regular = Some(cache);
+                    }
+                }
+                ObjectCache::LongNote(cache) => {
+                    if self.min_long_note.is_none_or(|x| min < x.lowest_position) {
+                        self.min_long_note = Some(cache);
+                    }
+                    if self.max_long_note.is_none_or(|x| max > x.highest_position) {
+                        self.max_long_note = Some(cache);
+                    }
+                }
+            }
+
+            self.min_position = Some(self.min_position.map_or(min, |x| x.min(min)));
+            self.max_position = Some(self.max_position.map_or(max, |x| x.max(max)));
+        }
+    }
+
+    /// Computes the timing lines of the chart and of all scroll groups.
+    fn update_timing_lines(&mut self) {
+        let last_timestamp = self.last_timestamp();
+        self.timing_lines = self
+            .scroll_speeds(None)
+            .timing_lines(&self.chart.timing_points, last_timestamp);
+        for index in 0..self.scroll_group_caches.len() {
+            self.scroll_group_caches[index].timing_lines = self
+                .scroll_speeds(Some(index))
+                .timing_lines(&self.chart.timing_points, last_timestamp);
+        }
+
+        self.update_max_timing_line();
+    }
+
+    /// Finds the timing line with the maximum position.
+    fn update_max_timing_line(&mut self) {
+        self.max_timing_line = None;
+        for &line in &self.timing_lines {
+            if self
+                .max_timing_line
+                .is_none_or(|max_timing_line| max_timing_line.position < line.position)
+            {
+                self.max_timing_line = Some(line);
+            }
+        }
+    }
+}
+
+/// Returns `true` if the position at `timestamp` depends on scroll speed changes at and after
+/// `from`.
+///
+/// Positions are counted from zero timestamp, so changes before it can affect any timestamp.
+fn is_after(timestamp: MapTimestamp, from: MapTimestamp) -> bool {
+    from < MapTimestamp::from_milli_hundredths(0) || timestamp >= from
+}
+
+/// Scroll speed changes of the chart or of a scroll group, along with their position cache.
+#[derive(Clone, Copy)]
+struct ScrollSpeeds<'a> {
+    scroll_speed_changes: &'a [ScrollSpeedChange],
+    initial_scroll_speed_multiplier: ScrollSpeedMultiplier,
+    position_cache: &'a [CachedPosition],
+}
+
+impl ScrollSpeeds<'_> {
+    /// Returns the map position at the given map timestamp.
+    ///
+    /// The position takes scroll speed changes into account.
+    fn position_at_time(self, timestamp: MapTimestamp) -> Position {
+        if self.position_cache.is_empty() {
+            return Position::zero()
+                + (timestamp - MapTimestamp::from_millis(0))
+                    * self.initial_scroll_speed_multiplier;
+        }
+
+        match self
+            .position_cache
+            .binary_search_by_key(&timestamp, |x| x.timestamp)
+        {
+            Ok(index) => self.position_cache[index].position,
+            Err(0) => {
+                self.position_cache[0].position
+                    + (timestamp - self.position_cache[0].timestamp)
+                        * self.initial_scroll_speed_multiplier
+            }
+            Err(index) => {
+                let cached_position = self.position_cache[index - 1];
+                let multiplier = self.scroll_speed_changes[index - 1].multiplier;
+                cached_position.position + (timestamp - cached_position.timestamp) * multiplier
+            }
+        }
+    }
+
+    /// Computes the cache of an object, appending the direction changes of a long note to
+    /// `direction_changes`.
+    fn object_cache(
+        self,
+        object: Object,
+        scroll_group: Option<usize>,
+        direction_changes: &mut Vec<DirectionChange>,
+    ) -> ObjectCache {
+        match object {
+            Object::Regular { timestamp } => ObjectCache::Regular(RegularObjectCache {
+                position: self.position_at_time(timestamp),
+                scroll_group,
+            }),
+            Object::LongNote { start, end } => ObjectCache::LongNote(self.long_note_cache(
+                start,
+                end,
+                scroll_group,
+                direction_changes,
+            )),
+        }
+    }
+
+    /// Computes the cache of a long note, appending its direction changes to `direction_changes`.
+    fn long_note_cache(
+        self,
+        start: MapTimestamp,
+        end: MapTimestamp,
+        scroll_group: Option<usize>,
+        direction_changes: &mut Vec<DirectionChange>,
+    ) -> LongNoteCache {
+        let start_position = self.position_at_time(start);
+        let end_position = self.position_at_time(end);
+        let first_direction_change = direction_changes.len();
+
+        let mut lowest_position = start_position.min(end_position);
+        let mut highest_position = start_position.max(end_position);
+
+        let zero = ScrollSpeedMultiplier::new(0);
+        let changes = &self.scroll_speed_changes;
+
+        // Index of the first scroll speed change past the long note start.
+        let first = changes.partition_point(|change| change.timestamp <= start);
+        // Direction at the long note start. Equal means the long note starts at a zero multiplier
+        // and has no direction yet.
+        let mut direction = first
+            .checked_sub(1)
+            .map_or(self.initial_scroll_speed_multiplier, |index| {
+                changes[index].multiplier
+            })
+            .cmp(&zero);
+
+        for (change, cached_position) in changes[first..]
+            .iter()
+            .zip(&self.position_cache[first..])
+            .take_while(|(change, _)| change.timestamp < end)
+        {
+            let new_direction = change.multiplier.cmp(&zero);
+            // Zero multipliers stop the long note without turning it around.
+            if new_direction == Ordering::Equal {
+                continue;
+            }
+
+            if direction != Ordering::Equal && new_direction != direction {
+                let position = cached_position.position;
+                direction_changes.push(DirectionChange {
+                    timestamp: change.timestamp,
+                    position,
+                });
+                lowest_position = lowest_position.min(position);
+                highest_position = highest_position.max(position);
+            }
+
+            direction = new_direction;
+        }
+
+        LongNoteCache {
+            start_position,
+            end_position,
+            lowest_position,
+            highest_position,
+            first_direction_change,
+            direction_change_count: direction_changes.len() - first_direction_change,
+            scroll_group,
+        }
+    }
+
+    /// Computes the timing lines, positioned with these scroll speed changes.
+    ///
+    /// `last_timestamp` is the end timestamp of the last object.
+    fn timing_lines(
+        self,
+        timing_points: &[TimingPoint],
+        last_timestamp: Option<MapTimestamp>,
+    ) -> Vec<TimingLine> {
+        let mut timing_lines = Vec::new();
+        for (i, timing_point) in timing_points.iter().enumerate() {
+            // +1 and -1 ms like osu! does it. TODO: do we want this here?
+            let end = if let Some(next_timing_point) = timing_points.get(i + 1) {
+                next_timing_point.timestamp - MapTimestampDifference::from_millis(1)
+            } else {
+                last_timestamp.unwrap_or_else(|| {
+                    timing_point
+                        .timestamp
+                        .saturating_add(MapTimestampDifference::from_millis(1))
+                })
+            }
+            .into_milli_hundredths();
+
+            let step = (i64::from(timing_point.signature.beat_count)
+                * i64::from(timing_point.beat_duration.into_milli_hundredths()))
+            .clamp(0, i64::from(i32::MAX)) as i32;
+
+            let mut timestamp = timing_point.timestamp.into_milli_hundredths();
+            while timestamp < end {
+                let line_timestamp = MapTimestamp::from_milli_hundredths(timestamp);
+                timing_lines.push(TimingLine {
+                    timestamp: line_timestamp,
+                    position: self.position_at_time(line_timestamp),
+                });
+
+                if step == 0 {
+                    break;
+                }
+
+                timestamp = timestamp.saturating_add(step);
+            }
+        }
+        timing_lines
+    }
+}
+
+/// Computes the position cache for scroll speed changes.
+///
+/// The scroll speed changes must be sorted and de-duplicated.
+fn compute_position_cache(
+    scroll_speed_changes: &[ScrollSpeedChange],
+    initial_scroll_speed_multiplier: ScrollSpeedMultiplier,
+) -> Vec<CachedPosition> {
+    let zero_timestamp_scroll_speed_change_index = match scroll_speed_changes
+        .binary_search_by_key(&MapTimestamp::from_milli_hundredths(0), |a| a.timestamp)
+    {
+        Ok(index) => Some(index),
+        Err(index) => {
+            if index == 0 {
+                None
+            } else {
+                Some(index - 1)
+            }
+        }
+    };
+
+    let mut position_cache = Vec::with_capacity(scroll_speed_changes.len());
+
+    // Compute positions for scroll speed changes before zero timestamp.
+    if let Some(index) = zero_timestamp_scroll_speed_change_index {
+        let mut last_timestamp = MapTimestamp::from_milli_hundredths(0);
+        let mut last_position = Position::zero();
+        for i in (0..=index).rev() {
+            let change = &scroll_speed_changes[i];
+            let position = last_position + (change.timestamp - last_timestamp) * change.multiplier;
+
+            // Zero timestamp always corresponds to zero position. But we cache it anyway to
+            // ensure the indices correspond to scroll speed change indices.
+            if change.timestamp == MapTimestamp::from_milli_hundredths(0) {
+                assert_eq!(position, Position::zero());
+            }
+
+            position_cache.push(CachedPosition {
+                timestamp: change.timestamp,
+                position,
+            });
+
+            last_timestamp = change.timestamp;
+            last_position = position;
+        }
+    }
+    position_cache.reverse();
+
+    // Compute positions for scroll speed changes past zero timestamp.
+    let mut last_timestamp = MapTimestamp::from_milli_hundredths(0);
+    let mut last_position = Position::zero();
+    let mut last_multiplier = zero_timestamp_scroll_speed_change_index
+        .map(|index| scroll_speed_changes[index].multiplier)
+        .unwrap_or(initial_scroll_speed_multiplier);
+    for change in &scroll_speed_changes[zero_timestamp_scroll_speed_change_index
+        .map(|x| x + 1)
+        .unwrap_or(0)..]
+    {
+        let position = last_position + (change.timestamp - last_timestamp) * last_multiplier;
+        position_cache.push(CachedPosition {
+            timestamp: change.timestamp,
+            position,
+        });
+
+        last_timestamp = change.timestamp;
+        last_position = position;
+        last_multiplier = change.multiplier;
+    }
+
+    position_cache
+}
+
+impl LaneState {
+    /// Inserts the state of a new object at `index`.
+    ///
+    /// Objects inserted before the first active object are marked as skipped.
+    pub(crate) fn insert_object(&mut self, index: usize, object: &Object) {
+        let skipped = index < self.first_active_object;
+        let state = match (object, skipped) {
+            (Object::Regular { .. }, false) => ObjectState::Regular(RegularObjectState::NotHit),
+            (Object::Regular { .. }, true) => ObjectState::Regular(RegularObjectState::Skipped),
+            (Object::LongNote { .. }, false) => ObjectState::LongNote(LongNoteState::NotHit),
+            (Object::LongNote { .. }, true) => ObjectState::LongNote(LongNoteState::Skipped),
+        };
+
+        self.object_states.insert(index, state);
+        if skipped {
+            self.first_active_object += 1;
+        }
+    }
+
+    /// Removes the state of the object at `index`.
+    pub(crate) fn remove_object(&mut self, index: usize) {
+        self.object_states.remove(index);
+        if index < self.first_active_object {
+            self.first_active_object -= 1;
+        }
     }
 }
 
@@ -1112,6 +1942,14 @@ impl ObjectState {
                 | Self::LongNote(LongNoteState::Missed { .. })
         )
     }
+
+    /// Returns `true` if the object was skipped over with [`GameState::seek()`].
+    pub fn is_skipped(&self) -> bool {
+        matches!(
+            self,
+            Self::Regular(RegularObjectState::Skipped) | Self::LongNote(LongNoteState::Skipped)
+        )
+    }
 }
 
 impl ObjectCache {
@@ -1121,7 +1959,7 @@ impl ObjectCache {
     #[inline]
     pub fn start_position(&self) -> Position {
         match *self {
-            ObjectCache::Regular(RegularObjectCache { position }) => position,
+            ObjectCache::Regular(RegularObjectCache { position, .. }) => position,
             ObjectCache::LongNote(LongNoteCache { start_position, .. }) => start_position,
         }
     }
@@ -1132,17 +1970,59 @@ impl ObjectCache {
     #[inline]
     pub fn end_position(&self) -> Position {
         match *self {
-            ObjectCache::Regular(RegularObjectCache { position }) => position,
+            ObjectCache::Regular(RegularObjectCache { position, .. }) => position,
             ObjectCache::LongNote(LongNoteCache { end_position, .. }) => end_position,
         }
     }
+
+    /// Returns the cached lowest position of the object.
+    ///
+    /// For long notes which turn around mid-way, this is lower than both the start and the end
+    /// positions.
+    #[inline]
+    pub fn lowest_position(&self) -> Position {
+        match *self {
+            ObjectCache::Regular(RegularObjectCache { position, .. }) => position,
+            ObjectCache::LongNote(LongNoteCache {
+                lowest_position, ..
+            }) => lowest_position,
+        }
+    }
+
+    /// Returns the cached highest position of the object.
+    ///
+    /// For long notes which turn around mid-way, this is higher than both the start and the end
+    /// positions.
+    #[inline]
+    pub fn highest_position(&self) -> Position {
+        match *self {
+            ObjectCache::Regular(RegularObjectCache { position, .. }) => position,
+            ObjectCache::LongNote(LongNoteCache {
+                highest_position, ..
+            }) => highest_position,
+        }
+    }
+
+    /// Returns the index of the object's scroll group in [`Chart::scroll_groups`].
+    ///
+    /// `None` means the object scrolls with the chart's own scroll speed changes.
+    #[inline]
+    pub fn scroll_group(&self) -> Option<usize> {
+        match *self {
+            ObjectCache::Regular(RegularObjectCache { scroll_group, .. })
+            | ObjectCache::LongNote(LongNoteCache { scroll_group, .. }) => scroll_group,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        map::{ArbitraryMapType, Lane, ScrollSpeedChange, TimeSignature, TimingPoint},
+        health::{FailMode, HealthModel, MAX_HEALTH},
+        map::{
+            ArbitraryMapType, Lane, Map, ScrollGroup, ScrollSpeedChange, TimeSignature, TimingPoint,
+        },
         scroll::ScrollSpeedMultiplier,
     };
     use alloc::vec;
@@ -1160,6 +2040,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![
                 Lane {
                     objects: vec![
@@ -1208,9 +2089,13 @@ mod tests {
             ],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
-        for lane in &state.immutable.map.lanes {
+        for lane in &state.immutable.chart.lanes {
             for xs in lane.objects.windows(2) {
                 let (a, b) = (xs[0], xs[1]);
                 assert!(a.start_timestamp() < b.start_timestamp());
@@ -1232,6 +2117,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![
                     Object::Regular {
@@ -1244,7 +2130,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(10_000));
 
         assert_eq!(
@@ -1261,6 +2151,7 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(10_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1277,6 +2168,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![
                     Object::Regular {
@@ -1289,7 +2181,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(20)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(20)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(10));
 
         assert_eq!(
@@ -1306,6 +2202,7 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(10),
             difference: GameTimestampDifference::from_millis(10),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1322,6 +2219,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::LongNote {
                     start: MapTimestamp::from_millis(5_000),
@@ -1330,7 +2228,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(5_000));
         state.key_release(0, GameTimestamp::from_millis(10_000));
 
@@ -1346,10 +2248,12 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(5_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(10_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1366,6 +2270,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::LongNote {
                     start: MapTimestamp::from_millis(5_000),
@@ -1374,7 +2279,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(5_000));
         state.key_release(0, GameTimestamp::from_millis(7_000));
 
@@ -1392,6 +2301,7 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(5_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1408,6 +2318,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::LongNote {
                     start: MapTimestamp::from_millis(5_000),
@@ -1416,7 +2327,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(123)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(123)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(5_000));
         state.key_release(0, GameTimestamp::from_millis(15_000));
 
@@ -1424,7 +2339,7 @@ mod tests {
             &state.lane_states[0].object_states[..],
             &[ObjectState::LongNote(LongNoteState::Hit {
                 press_difference: GameTimestampDifference::from_millis(0),
-                release_difference: state.hit_window,
+                release_difference: state.hit_windows.release.largest(),
             })][..]
         );
 
@@ -1432,10 +2347,12 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(5_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         hits.push(Hit {
-            timestamp: GameTimestamp::from_millis(10_000) + state.hit_window,
-            difference: state.hit_window,
+            timestamp: GameTimestamp::from_millis(10_000) + state.hit_windows.release.largest(),
+            difference: state.hit_windows.release.largest(),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1452,6 +2369,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::LongNote {
                     start: MapTimestamp::from_millis(5_000),
@@ -1460,7 +2378,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(7_000));
         state.key_release(0, GameTimestamp::from_millis(10_000));
 
@@ -1488,6 +2410,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::LongNote {
                     start: MapTimestamp::from_millis(5_000),
@@ -1496,7 +2419,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.key_press(0, GameTimestamp::from_millis(5_000));
 
         assert_eq!(
@@ -1510,6 +2437,7 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(5_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1526,6 +2454,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::LongNote {
                     start: MapTimestamp::from_millis(5_000),
@@ -1534,7 +2463,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         while state.update(GameTimestamp::from_millis(15_000)).is_some() {}
 
         assert_eq!(
@@ -1561,6 +2494,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::Regular {
                     timestamp: MapTimestamp::from_millis(20_000),
@@ -1568,7 +2502,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.timestamp_converter.global_offset = GameTimestampDifference::from_millis(10_000);
 
         state.key_press(0, GameTimestamp::from_millis(0));
@@ -1589,6 +2527,7 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(10_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
@@ -1605,6 +2544,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![Object::Regular {
                     timestamp: MapTimestamp::from_millis(20_000),
@@ -1612,7 +2552,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         state.timestamp_converter.local_offset = MapTimestampDifference::from_millis(-10_000);
 
         state.key_press(0, GameTimestamp::from_millis(0));
@@ -1633,10 +2577,121 @@ mod tests {
         hits.push(Hit {
             timestamp: GameTimestamp::from_millis(10_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_eq!(state.last_hits, hits);
     }
 
+    #[test]
+    fn game_state_rate() {
+        let map = Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(20_000),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(40_000),
+                    },
+                ],
+            }],
+        };
+
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(10)),
+        )
+        .unwrap();
+        state.timestamp_converter.rate = Rate::from_f32(2.);
+
+        // The hit window is in game time, so it spans 20 ms of map time at 2×.
+        state.key_press(0, GameTimestamp::from_millis(10_010));
+        state.key_press(0, GameTimestamp::from_millis(20_011));
+        assert_eq!(
+            &state.lane_states[0].object_states[..],
+            &[
+                ObjectState::Regular(RegularObjectState::Hit {
+                    difference: GameTimestampDifference::from_millis(10)
+                }),
+                ObjectState::Regular(RegularObjectState::Missed),
+            ][..]
+        );
+
+        let mut hits = CircularQueue::with_capacity(1);
+        hits.push(Hit {
+            timestamp: GameTimestamp::from_millis(10_010),
+            difference: GameTimestampDifference::from_millis(10),
+            judgement: Judgement::Marvelous,
+        });
+        assert_eq!(state.last_hits, hits);
+    }
+
+    #[test]
+    fn game_state_judgements() {
+        let map = Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(1_000),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(2_000),
+                        end: MapTimestamp::from_millis(3_000),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(4_000),
+                    },
+                ],
+            }],
+        };
+
+        let mut state = GameState::new(map, HitWindows::quaver_standard()).unwrap();
+
+        let judgement = |event: Option<Event>| match event.unwrap().kind {
+            EventKind::Hit(hit) => hit.judgement,
+            EventKind::Miss | EventKind::Failed => panic!("expected a hit"),
+        };
+
+        assert_eq!(
+            judgement(state.key_press(0, GameTimestamp::from_millis(1_030))),
+            Judgement::Perfect
+        );
+        assert_eq!(
+            judgement(state.key_press(0, GameTimestamp::from_millis(2_000))),
+            Judgement::Marvelous
+        );
+        // 60 ms is a Great for presses, but a Perfect for releases.
+        assert_eq!(
+            judgement(state.key_release(0, GameTimestamp::from_millis(3_060))),
+            Judgement::Perfect
+        );
+        // Pressing within the Miss window counts as a hit with a Miss judgement.
+        assert_eq!(
+            judgement(state.key_press(0, GameTimestamp::from_millis(3_850))),
+            Judgement::Miss
+        );
+    }
+
     #[test]
     fn game_state_update_to_latest() {
         let map = Map {
@@ -1649,6 +2704,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
                 objects: vec![
                     Object::Regular {
@@ -1661,7 +2717,11 @@ mod tests {
             }],
         };
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         let mut state2 = state.clone();
         state2.timestamp_converter.global_offset = GameTimestampDifference::from_millis(10_000);
@@ -1673,6 +2733,7 @@ mod tests {
         state2.last_hits.push(Hit {
             timestamp: GameTimestamp::from_millis(10_000),
             difference: GameTimestampDifference::from_millis(0),
+            judgement: Judgement::Marvelous,
         });
         assert_ne!(state, state2);
 
@@ -1680,9 +2741,69 @@ mod tests {
         assert_eq!(state, state2);
     }
 
-    #[test]
-    fn game_state_update_to_latest_last_first_active_object() {
-        let map = Map {
+    #[test]
+    fn game_state_update_to_latest_last_first_active_object() {
+        let map = Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane {
+                objects: vec![Object::Regular {
+                    timestamp: MapTimestamp::from_millis(20_000),
+                }],
+            }],
+        };
+
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
+
+        let mut state2 = state.clone();
+        state2.lane_states[0].first_active_object = 1;
+        assert_ne!(state, state2);
+
+        state.update_to_latest(&state2);
+        assert_eq!(state, state2);
+    }
+
+    #[test]
+    fn game_state_update_to_latest_zero_objects() {
+        let map = Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: Vec::new(),
+            scroll_speed_changes: Vec::new(),
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
+            lanes: vec![Lane { objects: vec![] }],
+        };
+
+        let mut state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
+        let state2 = state.clone();
+
+        state.update_to_latest(&state2);
+        assert_eq!(state, state2);
+    }
+
+    fn seek_map() -> Map {
+        Map {
             song_artist: None,
             song_title: None,
             difficulty_name: None,
@@ -1692,40 +2813,157 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: Vec::new(),
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: Vec::new(),
             lanes: vec![Lane {
-                objects: vec![Object::Regular {
-                    timestamp: MapTimestamp::from_millis(20_000),
-                }],
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(1_000),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(2_000),
+                        end: MapTimestamp::from_millis(3_000),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(4_000),
+                    },
+                ],
             }],
-        };
+        }
+    }
+
+    #[test]
+    fn game_state_seek() {
+        let mut state = GameState::new(seek_map(), HitWindows::quaver_standard()).unwrap();
+
+        // A long note that has already started is skipped too.
+        state.seek(GameTimestamp::from_millis(2_500));
+        assert_eq!(
+            &state.lane_states[0].object_states[..],
+            &[
+                ObjectState::Regular(RegularObjectState::Skipped),
+                ObjectState::LongNote(LongNoteState::Skipped),
+                ObjectState::Regular(RegularObjectState::NotHit),
+            ][..]
+        );
+        assert_eq!(state.first_active_object(0), Some(2));
+
+        // Skipped objects don't produce events.
+        assert_eq!(state.update(GameTimestamp::from_millis(3_500)), None);
+
+        assert!(state
+            .key_press(0, GameTimestamp::from_millis(4_000))
+            .is_some());
+        assert!(state.lane_states[0].object_states[2].is_hit());
+        assert!(!state.last_hits.is_empty());
+
+        // Seeking backwards resets the states.
+        state.seek(GameTimestamp::from_millis(2_000));
+        assert_eq!(
+            &state.lane_states[0].object_states[..],
+            &[
+                ObjectState::Regular(RegularObjectState::Skipped),
+                ObjectState::LongNote(LongNoteState::NotHit),
+                ObjectState::Regular(RegularObjectState::NotHit),
+            ][..]
+        );
+        assert_eq!(state.first_active_object(0), Some(1));
+        assert!(state.last_hits.is_empty());
+
+        state.seek(GameTimestamp::from_millis(0));
+        assert!(state.lane_states[0]
+            .object_states
+            .iter()
+            .all(|s| !s.is_skipped() && !s.is_hit() && !s.is_missed()));
+        assert_eq!(state.first_active_object(0), Some(0));
+    }
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+    #[test]
+    fn game_state_update_to_latest_after_seek() {
+        let mut state = GameState::new(seek_map(), HitWindows::quaver_standard()).unwrap();
+        state.seek(GameTimestamp::from_millis(4_000));
 
         let mut state2 = state.clone();
-        state2.lane_states[0].first_active_object = 1;
-        assert_ne!(state, state2);
+        state2.seek(GameTimestamp::from_millis(0));
+        state2.key_press(0, GameTimestamp::from_millis(1_000));
 
         state.update_to_latest(&state2);
         assert_eq!(state, state2);
     }
 
     #[test]
-    fn game_state_update_to_latest_zero_objects() {
-        let map = Map {
-            song_artist: None,
-            song_title: None,
-            difficulty_name: None,
-            background_file: None,
-            mapper: None,
-            audio_file: None,
-            timing_points: Vec::new(),
-            scroll_speed_changes: Vec::new(),
-            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
-            lanes: vec![Lane { objects: vec![] }],
-        };
+    fn game_state_sudden_death() {
+        let mut state = GameState::new(seek_map(), HitWindows::quaver_standard()).unwrap();
+        state.health = Some(Health::new(
+            HealthModel::quaver().with_fail_mode(FailMode::SuddenDeath),
+        ));
 
-        let mut state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
-        let state2 = state.clone();
+        let timestamp = GameTimestamp::from_millis(1_500);
+        assert_eq!(
+            state.update(timestamp),
+            Some(Event {
+                object_index: 0,
+                kind: EventKind::Miss,
+            })
+        );
+        assert!(state.is_failed());
+        assert_eq!(
+            state.update(timestamp),
+            Some(Event {
+                object_index: 0,
+                kind: EventKind::Failed,
+            })
+        );
+
+        // Nothing happens after failing.
+        assert_eq!(state.update(GameTimestamp::from_millis(10_000)), None);
+        assert_eq!(state.key_press(0, GameTimestamp::from_millis(2_000)), None);
+        assert_eq!(
+            state.lane_states[0].object_states[1],
+            ObjectState::LongNote(LongNoteState::NotHit)
+        );
+
+        // Seeking resets the health.
+        state.seek(GameTimestamp::from_millis(0));
+        assert!(!state.is_failed());
+        assert_eq!(state.health.unwrap().value(), MAX_HEALTH);
+    }
+
+    #[test]
+    fn game_state_perfect_only() {
+        let mut state = GameState::new(seek_map(), HitWindows::quaver_standard()).unwrap();
+        state.health = Some(Health::new(
+            HealthModel::quaver().with_fail_mode(FailMode::PerfectOnly),
+        ));
+
+        assert!(matches!(
+            state.key_press(0, GameTimestamp::from_millis(1_000)),
+            Some(Event {
+                kind: EventKind::Hit(_),
+                ..
+            })
+        ));
+        assert!(!state.is_failed());
+
+        // A Great press.
+        let event = state
+            .key_press(0, GameTimestamp::from_millis(2_060))
+            .unwrap();
+        assert_eq!(event.object_index, 1);
+        assert!(state.is_failed());
+
+        // The fail event is returned only for the lane of the failing object.
+        let mut state2 = state.clone();
+        assert_eq!(
+            state2.key_release(0, GameTimestamp::from_millis(3_000)),
+            Some(Event {
+                object_index: 1,
+                kind: EventKind::Failed,
+            })
+        );
+        assert_eq!(
+            state2.key_release(0, GameTimestamp::from_millis(3_000)),
+            None
+        );
 
         state.update_to_latest(&state2);
         assert_eq!(state, state2);
@@ -1772,14 +3010,19 @@ mod tests {
                 },
             ],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
             lanes: vec![Lane { objects: vec![] }],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(
             state.immutable.position_cache.len(),
-            state.immutable.map.scroll_speed_changes.len()
+            state.immutable.chart.scroll_speed_changes.len()
         );
         assert_eq!(
             &state.immutable.position_cache[..],
@@ -1837,10 +3080,15 @@ mod tests {
                 },
             ],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
             lanes: vec![Lane { objects: vec![] }],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(
             &state.immutable.position_cache[..],
@@ -1898,10 +3146,15 @@ mod tests {
                 },
             ],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1),
+            scroll_groups: vec![],
             lanes: vec![Lane { objects: vec![] }],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(
             state.position_at_time(MapTimestamp::from_milli_hundredths(-250)),
@@ -1966,6 +3219,7 @@ mod tests {
                 },
             ],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1),
+            scroll_groups: vec![],
             lanes: vec![
                 Lane {
                     objects: vec![
@@ -1987,30 +3241,330 @@ mod tests {
             ],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(
             state.immutable.lane_caches[0].object_caches[0],
             ObjectCache::Regular(RegularObjectCache {
-                position: Position::new(-950)
+                position: Position::new(-950),
+                scroll_group: None,
             })
         );
         assert_eq!(
             state.immutable.lane_caches[0].object_caches[1],
             ObjectCache::LongNote(LongNoteCache {
                 start_position: Position::new(700),
-                end_position: Position::new(2700)
+                end_position: Position::new(2700),
+                lowest_position: Position::new(700),
+                highest_position: Position::new(2700),
+                first_direction_change: 0,
+                direction_change_count: 0,
+                scroll_group: None,
             })
         );
         assert_eq!(
             state.immutable.lane_caches[1].object_caches[0],
             ObjectCache::LongNote(LongNoteCache {
                 start_position: Position::new(-250),
-                end_position: Position::new(950)
+                end_position: Position::new(950),
+                lowest_position: Position::new(-250),
+                highest_position: Position::new(950),
+                first_direction_change: 0,
+                direction_change_count: 0,
+                scroll_group: None,
             })
         );
     }
 
+    fn direction_change_map() -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: vec![],
+            scroll_speed_changes: vec![
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(100),
+                    multiplier: ScrollSpeedMultiplier::new(-1000),
+                },
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(200),
+                    multiplier: ScrollSpeedMultiplier::new(0),
+                },
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(250),
+                    multiplier: ScrollSpeedMultiplier::new(-1000),
+                },
+                ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(300),
+                    multiplier: ScrollSpeedMultiplier::new(1000),
+                },
+            ],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1000),
+            scroll_groups: vec![],
+            lanes: vec![Lane {
+                objects: vec![
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(0),
+                        end: MapTimestamp::from_millis(500),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(600),
+                        end: MapTimestamp::from_millis(700),
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn long_note_direction_changes() {
+        let state = GameState::new(
+            direction_change_map(),
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
+
+        let cache = match state.immutable.lane_caches[0].object_caches[0] {
+            ObjectCache::LongNote(cache) => cache,
+            _ => unreachable!(),
+        };
+        assert_eq!(cache.start_position, Position::new(0));
+        assert_eq!(cache.end_position, Position::new(15_000_000));
+        assert_eq!(cache.lowest_position, Position::new(-5_000_000));
+        assert_eq!(cache.highest_position, Position::new(15_000_000));
+        // The zero multiplier at 200 ms doesn't change the direction.
+        assert_eq!(
+            state.long_note_direction_changes(&cache),
+            &[
+                DirectionChange {
+                    timestamp: MapTimestamp::from_millis(100),
+                    position: Position::new(10_000_000),
+                },
+                DirectionChange {
+                    timestamp: MapTimestamp::from_millis(300),
+                    position: Position::new(-5_000_000),
+                },
+            ]
+        );
+
+        let cache = match state.immutable.lane_caches[0].object_caches[1] {
+            ObjectCache::LongNote(cache) => cache,
+            _ => unreachable!(),
+        };
+        assert_eq!(cache.lowest_position, cache.start_position);
+        assert_eq!(cache.highest_position, cache.end_position);
+        assert!(state.long_note_direction_changes(&cache).is_empty());
+    }
+
+    #[test]
+    fn long_note_geometry() {
+        let mut state = GameState::new(
+            direction_change_map(),
+            HitWindows::uniform(GameTimestampDifference::from_millis(10)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            state.long_note_geometry(0, 0, MapTimestamp::from_millis(150)),
+            LongNoteGeometry {
+                head_position: Position::new(0),
+                tail_position: Position::new(15_000_000),
+                lowest_position: Position::new(-5_000_000),
+                highest_position: Position::new(15_000_000),
+            }
+        );
+
+        state.key_press(0, GameTimestamp::from_millis(0));
+
+        // The part before the first direction change has passed the receptor.
+        assert_eq!(
+            state.long_note_geometry(0, 0, MapTimestamp::from_millis(150)),
+            LongNoteGeometry {
+                head_position: Position::new(5_000_000),
+                tail_position: Position::new(15_000_000),
+                lowest_position: Position::new(-5_000_000),
+                highest_position: Position::new(15_000_000),
+            }
+        );
+
+        // Both direction changes have passed the receptor.
+        assert_eq!(
+            state.long_note_geometry(0, 0, MapTimestamp::from_millis(400)),
+            LongNoteGeometry {
+                head_position: Position::new(5_000_000),
+                tail_position: Position::new(15_000_000),
+                lowest_position: Position::new(5_000_000),
+                highest_position: Position::new(15_000_000),
+            }
+        );
+    }
+
+    fn scroll_group_map() -> Map {
+        Map {
+            song_artist: None,
+            song_title: None,
+            difficulty_name: None,
+            background_file: None,
+            mapper: None,
+            audio_file: None,
+            timing_points: vec![TimingPoint {
+                timestamp: MapTimestamp::from_millis(0),
+                beat_duration: MapTimestampDifference::from_millis(25),
+                signature: TimeSignature {
+                    beat_count: 4,
+                    beat_unit: 4,
+                },
+            }],
+            scroll_speed_changes: vec![],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![ScrollGroup {
+                name: "fast".into(),
+                scroll_speed_changes: vec![ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(200),
+                    multiplier: ScrollSpeedMultiplier::new(1000),
+                }],
+                initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(2000),
+                lanes: vec![
+                    Lane {
+                        objects: vec![Object::LongNote {
+                            start: MapTimestamp::from_millis(200),
+                            end: MapTimestamp::from_millis(300),
+                        }],
+                    },
+                    Lane {
+                        objects: vec![Object::Regular {
+                            timestamp: MapTimestamp::from_millis(50),
+                        }],
+                    },
+                ],
+            }],
+            lanes: vec![
+                Lane {
+                    objects: vec![Object::Regular {
+                        timestamp: MapTimestamp::from_millis(100),
+                    }],
+                },
+                Lane { objects: vec![] },
+            ],
+        }
+    }
+
+    #[test]
+    fn game_state_scroll_groups() {
+        let state = GameState::new(
+            scroll_group_map(),
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
+
+        // The objects of the scroll group are moved into the lanes.
+        assert!(state.immutable.chart.scroll_groups[0].lanes.is_empty());
+        assert_eq!(
+            state.immutable.chart.lanes[0].objects,
+            vec![
+                Object::Regular {
+                    timestamp: MapTimestamp::from_millis(100),
+                },
+                Object::LongNote {
+                    start: MapTimestamp::from_millis(200),
+                    end: MapTimestamp::from_millis(300),
+                },
+            ]
+        );
+
+        assert_eq!(
+            state.immutable.lane_caches[0].object_caches,
+            vec![
+                ObjectCache::Regular(RegularObjectCache {
+                    position: Position::new(10_000_000),
+                    scroll_group: None,
+                }),
+                ObjectCache::LongNote(LongNoteCache {
+                    start_position: Position::new(40_000_000),
+                    end_position: Position::new(50_000_000),
+                    lowest_position: Position::new(40_000_000),
+                    highest_position: Position::new(50_000_000),
+                    first_direction_change: 0,
+                    direction_change_count: 0,
+                    scroll_group: Some(0),
+                }),
+            ]
+        );
+        assert_eq!(
+            state.immutable.lane_caches[1].object_caches,
+            vec![ObjectCache::Regular(RegularObjectCache {
+                position: Position::new(10_000_000),
+                scroll_group: Some(0),
+            })]
+        );
+
+        assert_eq!(
+            state.scroll_group_position_at_time(Some(0), MapTimestamp::from_millis(250)),
+            Position::new(45_000_000)
+        );
+        assert_eq!(
+            state.scroll_group_position_at_time(None, MapTimestamp::from_millis(250)),
+            state.position_at_time(MapTimestamp::from_millis(250))
+        );
+
+        // Held long notes stick to the receptor of their scroll group.
+        assert_eq!(
+            state.object_start_position(
+                ObjectState::LongNote(LongNoteState::Held {
+                    press_difference: GameTimestampDifference::from_millis(0),
+                }),
+                state.immutable.lane_caches[0].object_caches[1],
+                MapTimestamp::from_millis(250),
+            ),
+            Position::new(45_000_000)
+        );
+
+        let positions = |timing_lines: &[TimingLine]| {
+            timing_lines
+                .iter()
+                .map(|line| line.position)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            positions(&state.immutable.timing_lines),
+            vec![
+                Position::new(0),
+                Position::new(10_000_000),
+                Position::new(20_000_000),
+            ]
+        );
+        assert_eq!(
+            positions(&state.immutable.scroll_group_caches[0].timing_lines),
+            vec![
+                Position::new(0),
+                Position::new(20_000_000),
+                Position::new(40_000_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn game_state_new_with_too_many_scroll_group_lanes() {
+        let mut map = scroll_group_map();
+        map.scroll_groups[0].lanes.push(Lane::new());
+
+        assert!(matches!(
+            GameState::new(
+                map,
+                HitWindows::uniform(GameTimestampDifference::from_millis(0))
+            ),
+            Err(GameStateCreationError::ScrollGroupHasTooManyLanes(_, 0))
+        ));
+    }
+
     #[allow(clippy::inconsistent_digit_grouping)]
     #[test]
     fn game_state_timing_lines() {
@@ -2073,10 +3627,15 @@ mod tests {
             ],
             scroll_speed_changes: vec![],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1),
+            scroll_groups: vec![],
             lanes: vec![Lane { objects: vec![] }],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(
             &state.immutable.timing_lines[..],
@@ -2137,6 +3696,7 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: vec![],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
             lanes: vec![
                 Lane {
                     objects: vec![
@@ -2158,7 +3718,11 @@ mod tests {
             ],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(
             state.first_timestamp(),
@@ -2182,10 +3746,15 @@ mod tests {
             timing_points: Vec::new(),
             scroll_speed_changes: vec![],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
             lanes: vec![],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
 
         assert_eq!(state.first_timestamp(), None);
         assert_eq!(state.last_timestamp(), None);
@@ -2195,16 +3764,28 @@ mod tests {
     fn object_cache_methods() {
         let regular = ObjectCache::Regular(RegularObjectCache {
             position: Position::new(10),
+            scroll_group: None,
         });
         assert_eq!(regular.start_position(), Position::new(10));
         assert_eq!(regular.end_position(), Position::new(10));
+        assert_eq!(regular.lowest_position(), Position::new(10));
+        assert_eq!(regular.highest_position(), Position::new(10));
+        assert_eq!(regular.scroll_group(), None);
 
         let ln = ObjectCache::LongNote(LongNoteCache {
             start_position: Position::new(20),
             end_position: Position::new(30),
+            lowest_position: Position::new(15),
+            highest_position: Position::new(40),
+            first_direction_change: 0,
+            direction_change_count: 2,
+            scroll_group: Some(1),
         });
         assert_eq!(ln.start_position(), Position::new(20));
         assert_eq!(ln.end_position(), Position::new(30));
+        assert_eq!(ln.lowest_position(), Position::new(15));
+        assert_eq!(ln.highest_position(), Position::new(40));
+        assert_eq!(ln.scroll_group(), Some(1));
     }
 
     #[test]
@@ -2219,6 +3800,7 @@ mod tests {
             timing_points: vec![],
             scroll_speed_changes: vec![],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+            scroll_groups: vec![],
             lanes: vec![Lane {
                 objects: vec![
                     Object::LongNote {
@@ -2233,7 +3815,10 @@ mod tests {
         };
 
         assert!(matches!(
-            GameState::new(map, GameTimestampDifference::from_millis(0)),
+            GameState::new(
+                map,
+                HitWindows::uniform(GameTimestampDifference::from_millis(0))
+            ),
             Err(GameStateCreationError::MapHasOverlappingObjects(_, _, _))
         ));
     }
@@ -2250,6 +3835,7 @@ mod tests {
             timing_points: vec![],
             scroll_speed_changes: vec![],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(-1),
+            scroll_groups: vec![],
             lanes: vec![Lane {
                 objects: vec![
                     Object::Regular {
@@ -2262,11 +3848,16 @@ mod tests {
             }],
         };
 
-        let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+        let state = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        )
+        .unwrap();
         assert_eq!(
             state.min_regular(),
             Some(RegularObjectCache {
-                position: Position::new(0)
+                position: Position::new(0),
+                scroll_group: None,
             })
         );
     }
@@ -2300,25 +3891,29 @@ mod tests {
             ],
             scroll_speed_changes: vec![],
             initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(0),
+            scroll_groups: vec![],
             lanes: vec![],
         };
-        let _ = GameState::new(map, GameTimestampDifference::from_millis(0));
+        let _ = GameState::new(
+            map,
+            HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+        );
     }
 
     proptest! {
         #[test]
         fn game_state_new_doesnt_panic(map: Map) {
-            let _ = GameState::new(map, GameTimestampDifference::from_millis(0));
+            let _ = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0)));
         }
 
         #[test]
         fn game_state_new_with_valid_map_succeeds(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let _ = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let _ = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
         }
 
         #[test]
         fn min_regular(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.min_regular();
             let correct = state
@@ -2327,7 +3922,7 @@ mod tests {
                 .iter()
                 .flat_map(|lane| lane.object_caches.iter())
                 .filter(|object| matches!(object, ObjectCache::Regular(_)))
-                .min_by_key(|object| object.start_position().min(object.end_position()))
+                .min_by_key(|object| object.lowest_position())
                 .map(|object| if let ObjectCache::Regular(cache) = object {
                     *cache
                 } else {
@@ -2338,7 +3933,7 @@ mod tests {
 
         #[test]
         fn max_regular(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.max_regular();
             let correct = state
@@ -2347,7 +3942,9 @@ mod tests {
                 .iter()
                 .flat_map(|lane| lane.object_caches.iter())
                 .filter(|object| matches!(object, ObjectCache::Regular(_)))
-                .max_by_key(|object| object.start_position().max(object.end_position()))
+                // Take the first object in case of ties, like GameState does.
+                .rev()
+                .max_by_key(|object| object.highest_position())
                 .map(|object| if let ObjectCache::Regular(cache) = object {
                     *cache
                 } else {
@@ -2358,7 +3955,7 @@ mod tests {
 
         #[test]
         fn min_long_note(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.min_long_note();
             let correct = state
@@ -2367,7 +3964,7 @@ mod tests {
                 .iter()
                 .flat_map(|lane| lane.object_caches.iter())
                 .filter(|object| matches!(object, ObjectCache::LongNote(_)))
-                .min_by_key(|object| object.start_position().min(object.end_position()))
+                .min_by_key(|object| object.lowest_position())
                 .map(|object| if let ObjectCache::LongNote(cache) = object {
                     *cache
                 } else {
@@ -2378,7 +3975,7 @@ mod tests {
 
         #[test]
         fn max_long_note(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.max_long_note();
             let correct = state
@@ -2387,7 +3984,9 @@ mod tests {
                 .iter()
                 .flat_map(|lane| lane.object_caches.iter())
                 .filter(|object| matches!(object, ObjectCache::LongNote(_)))
-                .max_by_key(|object| object.start_position().max(object.end_position()))
+                // Take the first object in case of ties, like GameState does.
+                .rev()
+                .max_by_key(|object| object.highest_position())
                 .map(|object| if let ObjectCache::LongNote(cache) = object {
                     *cache
                 } else {
@@ -2398,7 +3997,7 @@ mod tests {
 
         #[test]
         fn min_position(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.min_position();
             let correct = state
@@ -2406,14 +4005,14 @@ mod tests {
                 .lane_caches
                 .iter()
                 .flat_map(|lane| lane.object_caches.iter())
-                .map(|object| object.start_position().min(object.end_position()))
+                .map(|object| object.lowest_position())
                 .min();
             prop_assert_eq!(result, correct);
         }
 
         #[test]
         fn max_position(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.max_position();
             let correct = state
@@ -2421,14 +4020,99 @@ mod tests {
                 .lane_caches
                 .iter()
                 .flat_map(|lane| lane.object_caches.iter())
-                .map(|object| object.start_position().max(object.end_position()))
+                .map(|object| object.highest_position())
                 .max();
             prop_assert_eq!(result, correct);
         }
 
+        #[test]
+        fn scroll_group_with_same_scroll_speeds(map in any_with::<Map>(ArbitraryMapType::Valid)) {
+            let mut grouped = map.clone();
+            let mut group = ScrollGroup {
+                name: "group".into(),
+                scroll_speed_changes: map.scroll_speed_changes.clone(),
+                initial_scroll_speed_multiplier: map.initial_scroll_speed_multiplier,
+                lanes: vec![Lane::new(); map.lane_count()],
+            };
+            // Move every other object into the scroll group.
+            for (lane, group_lane) in grouped.lanes.iter_mut().zip(&mut group.lanes) {
+                for (i, object) in core::mem::take(&mut lane.objects).into_iter().enumerate() {
+                    if i % 2 == 0 {
+                        lane.objects.push(object);
+                    } else {
+                        group_lane.objects.push(object);
+                    }
+                }
+            }
+            grouped.scroll_groups.push(group);
+
+            let hit_windows = HitWindows::uniform(GameTimestampDifference::from_millis(0));
+            let state = GameState::new(map, hit_windows).unwrap();
+            let grouped = GameState::new(grouped, hit_windows).unwrap();
+
+            let without_scroll_group = |cache: &ObjectCache| match *cache {
+                ObjectCache::Regular(cache) => ObjectCache::Regular(RegularObjectCache {
+                    scroll_group: None,
+                    ..cache
+                }),
+                ObjectCache::LongNote(cache) => ObjectCache::LongNote(LongNoteCache {
+                    scroll_group: None,
+                    ..cache
+                }),
+            };
+
+            prop_assert_eq!(&grouped.immutable.chart.lanes, &state.immutable.chart.lanes);
+            for (a, b) in grouped.immutable.lane_caches.iter().zip(&state.immutable.lane_caches) {
+                prop_assert!(a.object_caches.iter().map(without_scroll_group).eq(b.object_caches.iter().copied()));
+            }
+            prop_assert_eq!(&grouped.immutable.direction_changes, &state.immutable.direction_changes);
+            prop_assert_eq!(
+                &grouped.immutable.scroll_group_caches[0].timing_lines,
+                &state.immutable.timing_lines
+            );
+        }
+
+        #[test]
+        fn long_note_extent(map in any_with::<Map>(ArbitraryMapType::Valid)) {
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
+
+            for (lane, lane_cache) in state.immutable.chart.lanes.iter().zip(&state.immutable.lane_caches) {
+                for (object, cache) in lane.objects.iter().zip(&lane_cache.object_caches) {
+                    let (start, end, cache) = match (*object, *cache) {
+                        (Object::LongNote { start, end }, ObjectCache::LongNote(cache)) => (start, end, cache),
+                        _ => continue,
+                    };
+
+                    // Positions are piecewise linear, so the extremes are at the ends or at scroll
+                    // speed changes.
+                    let positions: Vec<_> = state
+                        .immutable
+                        .chart
+                        .scroll_speed_changes
+                        .iter()
+                        .map(|change| change.timestamp)
+                        .filter(|&timestamp| timestamp > start && timestamp < end)
+                        .chain([start, end])
+                        .map(|timestamp| state.position_at_time(timestamp))
+                        .collect();
+                    prop_assert_eq!(Some(cache.lowest_position), positions.iter().copied().min());
+                    prop_assert_eq!(Some(cache.highest_position), positions.iter().copied().max());
+
+                    let changes = state.long_note_direction_changes(&cache);
+                    for change in changes {
+                        prop_assert!(change.timestamp > start && change.timestamp < end);
+                        prop_assert_eq!(change.position, state.position_at_time(change.timestamp));
+                    }
+                    for ab in changes.windows(2) {
+                        prop_assert!(ab[0].timestamp < ab[1].timestamp);
+                    }
+                }
+            }
+        }
+
         #[test]
         fn max_timing_line(map in any_with::<Map>(ArbitraryMapType::Valid)) {
-            let state = GameState::new(map, GameTimestampDifference::from_millis(0)).unwrap();
+            let state = GameState::new(map, HitWindows::uniform(GameTimestampDifference::from_millis(0))).unwrap();
 
             let result = state.max_timing_line();
             let correct = state
@@ -2443,10 +4127,10 @@ mod tests {
         #[test]
         fn update_doesnt_panic(
             map in any_with::<Map>(ArbitraryMapType::Valid),
-            hit_window: GameTimestampDifference,
+            hit_windows: HitWindows,
             timestamps: Vec<GameTimestamp>,
         ) {
-            let mut state = GameState::new(map, hit_window).unwrap();
+            let mut state = GameState::new(map, hit_windows).unwrap();
             for timestamp in timestamps {
                 while state.update(timestamp).is_some() {}
             }
@@ -2455,10 +4139,10 @@ mod tests {
         #[test]
         fn intermediate_updates_are_unnecessary(
             map in any_with::<Map>(ArbitraryMapType::Valid),
-            hit_window: GameTimestampDifference,
+            hit_windows: HitWindows,
             timestamps in any_with::<Vec<GameTimestamp>>(size_range(1..100).lift()),
         ) {
-            let mut state = GameState::new(map, hit_window).unwrap();
+            let mut state = GameState::new(map, hit_windows).unwrap();
             let mut state2 = state.clone();
 
             for &timestamp in &timestamps {
@@ -2473,9 +4157,11 @@ mod tests {
         #[test]
         fn gameplay_doesnt_panic(
             (map, events) in valid_map_with_events(),
-            hit_window: GameTimestampDifference,
+            hit_windows: HitWindows,
+            timestamp_converter: TimestampConverter,
         ) {
-            let mut state = GameState::new(map, hit_window).unwrap();
+            let mut state = GameState::new(map, hit_windows).unwrap();
+            state.timestamp_converter = timestamp_converter;
 
             // If additional validation is added to key_press and key_release, this might need
             // further filtering to e.g. exclude pressing keys that are already pressed or releasing
@@ -2492,7 +4178,7 @@ mod tests {
         #[test]
         fn all_objects_are_eventually_missed(map in any_with::<Map>(ArbitraryMapType::ValidWithObjects)) {
             let hit_window = GameTimestampDifference::from_millis(123);
-            let mut state = GameState::new(map, hit_window).unwrap();
+            let mut state = GameState::new(map, HitWindows::uniform(hit_window)).unwrap();
 
             let last_timestamp = state.last_timestamp().unwrap();
             prop_assume!(
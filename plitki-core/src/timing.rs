@@ -67,8 +67,22 @@ pub struct TimestampConverter {
     /// mistake or audio playback differences between different games. The local offset is affected
     /// by rate.
     pub local_offset: MapTimestampDifference,
+
+    /// Playback rate.
+    ///
+    /// At rates above 1× the map plays faster, so one unit of game time corresponds to more than
+    /// one unit of map time.
+    pub rate: Rate,
 }
 
+/// Playback rate.
+///
+/// The rate ranges from 1 to 2<sup>16</sup>-1. The value of 1000 is equivalent to a rate of 1×, so
+/// the value of 1500 means 1.5× and the value of 800 means 0.8×.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct Rate(#[cfg_attr(test, proptest(strategy = "1..=u16::MAX"))] u16);
+
 /// The error type returned when a duration to timestamp conversion fails.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TryFromDurationError(());
@@ -199,6 +213,23 @@ impl MapTimestamp {
         ))
     }
 
+    /// Creates a new `MapTimestamp` from the specified number of seconds, rounded to the nearest
+    /// <sup>1</sup>⁄<sub>100</sub>th of a millisecond and saturating at the bounds.
+    ///
+    /// NaN is converted to zero.
+    #[inline]
+    pub fn saturating_from_secs_f64(seconds: f64) -> Self {
+        let milli_hundredths = seconds * 1000_00.;
+        // Round half away from zero by hand as `f64::round()` needs `std`.
+        let milli_hundredths = if milli_hundredths < 0. {
+            milli_hundredths - 0.5
+        } else {
+            milli_hundredths + 0.5
+        };
+        // Float to int `as` casts saturate.
+        Self::saturating_from_milli_hundredths(milli_hundredths as i32)
+    }
+
     /// Returns the timestamp as the number of <sup>1</sup>⁄<sub>100</sub>ths of a millisecond.
     #[inline]
     pub fn into_milli_hundredths(self) -> i32 {
@@ -375,6 +406,72 @@ impl GameTimestampDifference {
     }
 }
 
+impl Rate {
+    /// Creates a new `Rate` with bounds checking.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is zero.
+    #[inline]
+    pub fn new(value: u16) -> Self {
+        assert!(value > 0, "rate must be positive");
+
+        Self(value)
+    }
+
+    /// Converts an `f32` to a `Rate` with bounds checking.
+    ///
+    /// The value is in the conventional range (so `1.0` is the rate of 1×).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the converted `value` is outside of the valid `Rate` range.
+    #[inline]
+    pub fn from_f32(value: f32) -> Self {
+        let value = value * 1000.;
+        assert!(value < 65536.);
+        assert!(value >= 1.);
+
+        Self(value as u16)
+    }
+
+    /// Converts `Rate` to an `f32`.
+    ///
+    /// The returned value is in the conventional range (so `1.0` is the rate of 1×).
+    #[inline]
+    pub fn as_f32(self) -> f32 {
+        f32::from(self.0) / 1000.
+    }
+
+    /// Returns the rate in thousandths.
+    #[inline]
+    pub fn into_thousandths(self) -> u16 {
+        self.0
+    }
+
+    /// Scales a game time value into map time, rounding towards negative infinity.
+    #[inline]
+    fn game_to_map(self, value: i64) -> i64 {
+        (value * i64::from(self.0)).div_euclid(1000)
+    }
+
+    /// Scales a map time value into game time, rounding towards positive infinity.
+    ///
+    /// Rounding in the opposite direction from [`Rate::game_to_map()`] is what makes the
+    /// round-trip from the coarser time domain lossless.
+    #[inline]
+    fn map_to_game(self, value: i64) -> i64 {
+        -(-value * 1000).div_euclid(i64::from(self.0))
+    }
+}
+
+impl Default for Rate {
+    #[inline]
+    fn default() -> Self {
+        Self(1000)
+    }
+}
+
 impl TryFrom<Duration> for Timestamp {
     type Error = TryFromDurationError;
 
@@ -477,69 +574,93 @@ impl GameTimestampDifference {
 impl TimestampConverter {
     /// Converts a game timestamp into a map timestamp.
     ///
-    /// Takes global and local offsets into account. For differences (which do _not_ need to
-    /// consider global and local offsets) use [`Self::game_to_map_difference()`].
+    /// Takes global and local offsets and rate into account. For differences (which do _not_ need
+    /// to consider global and local offsets) use [`Self::game_to_map_difference()`].
+    ///
+    /// The result is rounded towards negative infinity. When the rate is 1× or above, converting
+    /// the result back with [`Self::map_to_game()`] gives the original timestamp.
     ///
     /// The conversion uses saturating arithmetic in case the timestamp or the offsets are too
     /// large.
     #[inline]
     pub fn game_to_map(&self, timestamp: GameTimestamp) -> MapTimestamp {
-        // Sacrificing a bit of type safety here for saturating arithmetic.
-        let global = self.global_offset.into_milli_hundredths();
-        let local = self.local_offset.into_milli_hundredths();
-        let timestamp = timestamp.into_milli_hundredths();
+        // Sacrificing a bit of type safety here for saturating arithmetic. All of the values fit
+        // into 32 bits and the rate fits into 16 bits, so none of the i64 operations can overflow.
+        let global = i64::from(self.global_offset.into_milli_hundredths());
+        let local = i64::from(self.local_offset.into_milli_hundredths());
+        let timestamp = i64::from(timestamp.into_milli_hundredths());
 
-        // MapTimestamp((timestamp + global).0) - local
-        MapTimestamp::saturating_from_milli_hundredths(
-            timestamp.saturating_add(global.saturating_sub(local)),
-        )
+        // MapTimestamp((timestamp + global) * rate) - local
+        let map = self.rate.game_to_map(timestamp + global) - local;
+        MapTimestamp::saturating_from_milli_hundredths(saturate_i64(map))
     }
 
     /// Converts a map timestamp into a game timestamp.
     ///
-    /// Takes global and local offsets into account. For differences (which do _not_ need to
-    /// consider global offset) use [`Self::map_to_game_difference()`].
+    /// Takes global and local offsets and rate into account. For differences (which do _not_ need
+    /// to consider global offset) use [`Self::map_to_game_difference()`].
+    ///
+    /// The result is rounded towards positive infinity. When the rate is 1× or below, converting
+    /// the result back with [`Self::game_to_map()`] gives the original timestamp.
     ///
     /// The conversion uses saturating arithmetic in case the timestamp or the offsets are too
     /// large.
     #[inline]
     pub fn map_to_game(&self, timestamp: MapTimestamp) -> GameTimestamp {
         // Sacrificing a bit of type safety here for saturating arithmetic.
-        let global = self.global_offset.into_milli_hundredths();
-        let local = self.local_offset.into_milli_hundredths();
-        let timestamp = timestamp.into_milli_hundredths();
+        let global = i64::from(self.global_offset.into_milli_hundredths());
+        let local = i64::from(self.local_offset.into_milli_hundredths());
+        let timestamp = i64::from(timestamp.into_milli_hundredths());
 
-        // GameTimestamp((timestamp + local).0) - global
-        GameTimestamp::saturating_from_milli_hundredths(
-            timestamp.saturating_add(local.saturating_sub(global)),
-        )
+        // GameTimestamp((timestamp + local).0 / rate) - global
+        let game = self.rate.map_to_game(timestamp + local) - global;
+        GameTimestamp::saturating_from_milli_hundredths(saturate_i64(game))
     }
 
     /// Converts a game difference into a map difference.
     ///
-    /// Difference conversion does _not_ consider global and local offsets. For timestamps (which
-    /// need to consider global and local offsets) use [`Self::game_to_map()`].
+    /// Difference conversion does _not_ consider global and local offsets, but does consider rate.
+    /// For timestamps (which need to consider global and local offsets) use
+    /// [`Self::game_to_map()`].
+    ///
+    /// The result is rounded the same way as in [`Self::game_to_map()`] and saturates at the
+    /// numeric bounds.
     #[inline]
     pub fn game_to_map_difference(
         &self,
         difference: GameTimestampDifference,
     ) -> MapTimestampDifference {
-        MapTimestampDifference(difference.0)
+        let difference = i64::from(difference.into_milli_hundredths());
+        MapTimestampDifference::from_milli_hundredths(saturate_i64(
+            self.rate.game_to_map(difference),
+        ))
     }
 
     /// Converts a map difference into a game difference.
     ///
-    /// Difference conversion does _not_ consider global and local offsets. For timestamps (which
-    /// need to consider global and local offsets) use [`Self::map_to_game()`].
+    /// Difference conversion does _not_ consider global and local offsets, but does consider rate.
+    /// For timestamps (which need to consider global and local offsets) use
+    /// [`Self::map_to_game()`].
+    ///
+    /// The result is rounded the same way as in [`Self::map_to_game()`] and saturates at the
+    /// numeric bounds.
     #[inline]
     pub fn map_to_game_difference(
         &self,
         difference: MapTimestampDifference,
     ) -> GameTimestampDifference {
-        GameTimestampDifference(difference.0)
+        let difference = i64::from(difference.into_milli_hundredths());
+        GameTimestampDifference::from_milli_hundredths(saturate_i64(
+            self.rate.map_to_game(difference),
+        ))
     }
 }
 
+#[inline]
+fn saturate_i64(value: i64) -> i32 {
+    value.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -558,6 +679,21 @@ mod tests {
         assert_eq!(timestamp, Err(TryFromDurationError(())));
     }
 
+    #[test]
+    fn map_timestamp_from_secs_f64() {
+        let from_secs = MapTimestamp::saturating_from_secs_f64;
+        assert_eq!(from_secs(1.5), MapTimestamp::from_millis(1500));
+        assert_eq!(from_secs(0.000_004), MapTimestamp::zero());
+        assert_eq!(from_secs(0.000_006), MapTimestamp::from_milli_hundredths(1));
+        assert_eq!(
+            from_secs(-0.000_006),
+            MapTimestamp::from_milli_hundredths(-1)
+        );
+        assert_eq!(from_secs(1e10), MapTimestamp(Timestamp::MAX));
+        assert_eq!(from_secs(f64::NEG_INFINITY), MapTimestamp(Timestamp::MIN));
+        assert_eq!(from_secs(f64::NAN), MapTimestamp::zero());
+    }
+
     #[test]
     fn map_to_game_and_back_matches() {
         for global in -10..10 {
@@ -566,6 +702,7 @@ mod tests {
                     let converter = TimestampConverter {
                         global_offset: GameTimestampDifference::from_milli_hundredths(global),
                         local_offset: MapTimestampDifference::from_milli_hundredths(local),
+                        rate: Rate::default(),
                     };
                     let timestamp = MapTimestamp::from_milli_hundredths(timestamp);
                     let result = timestamp.to_game(&converter).to_map(&converter);
@@ -575,6 +712,52 @@ mod tests {
         }
     }
 
+    #[test]
+    fn map_to_game_with_rate() {
+        let converter = TimestampConverter {
+            global_offset: GameTimestampDifference::from_millis(0),
+            local_offset: MapTimestampDifference::from_millis(0),
+            rate: Rate::from_f32(1.5),
+        };
+
+        assert_eq!(
+            MapTimestamp::from_millis(1500).to_game(&converter),
+            GameTimestamp::from_millis(1000)
+        );
+        assert_eq!(
+            GameTimestamp::from_millis(1000).to_map(&converter),
+            MapTimestamp::from_millis(1500)
+        );
+        assert_eq!(
+            MapTimestampDifference::from_millis(-1500).to_game(&converter),
+            GameTimestampDifference::from_millis(-1000)
+        );
+        assert_eq!(
+            GameTimestampDifference::from_millis(-1000).to_map(&converter),
+            MapTimestampDifference::from_millis(-1500)
+        );
+    }
+
+    #[test]
+    fn rate_affects_local_offset_but_not_global_offset() {
+        let converter = TimestampConverter {
+            global_offset: GameTimestampDifference::from_millis(100),
+            local_offset: MapTimestampDifference::from_millis(100),
+            rate: Rate::from_f32(2.),
+        };
+
+        // (1000 + 100) * 2 - 100
+        assert_eq!(
+            GameTimestamp::from_millis(1000).to_map(&converter),
+            MapTimestamp::from_millis(2100)
+        );
+        // (2100 + 100) / 2 - 100
+        assert_eq!(
+            MapTimestamp::from_millis(2100).to_game(&converter),
+            GameTimestamp::from_millis(1000)
+        );
+    }
+
     proptest! {
         #[allow(clippy::inconsistent_digit_grouping)]
         #[test]
@@ -608,5 +791,55 @@ mod tests {
         fn converting_game_to_map_doesnt_panic(timestamp: GameTimestamp, converter: TimestampConverter) {
             let _ = timestamp.to_map(&converter);
         }
+
+        #[test]
+        fn converting_differences_doesnt_panic(
+            map: MapTimestampDifference,
+            game: GameTimestampDifference,
+            converter: TimestampConverter,
+        ) {
+            let _ = map.to_game(&converter);
+            let _ = game.to_map(&converter);
+        }
+
+        #[test]
+        fn map_to_game_and_back_is_lossless_at_low_rates(
+            timestamp in -(2i32.pow(25))..2i32.pow(25),
+            global in -(2i32.pow(24))..2i32.pow(24),
+            local in -(2i32.pow(24))..2i32.pow(24),
+            rate in 100..=1000u16,
+        ) {
+            let converter = TimestampConverter {
+                global_offset: GameTimestampDifference::from_milli_hundredths(global),
+                local_offset: MapTimestampDifference::from_milli_hundredths(local),
+                rate: Rate::new(rate),
+            };
+
+            let timestamp = MapTimestamp::from_milli_hundredths(timestamp);
+            prop_assert_eq!(timestamp.to_game(&converter).to_map(&converter), timestamp);
+
+            let difference = MapTimestampDifference::from_milli_hundredths(timestamp.into_milli_hundredths());
+            prop_assert_eq!(difference.to_game(&converter).to_map(&converter), difference);
+        }
+
+        #[test]
+        fn game_to_map_and_back_is_lossless_at_high_rates(
+            timestamp in -(2i32.pow(22))..2i32.pow(22),
+            global in -(2i32.pow(22))..2i32.pow(22),
+            local in -(2i32.pow(22))..2i32.pow(22),
+            rate in 1000..=u16::MAX,
+        ) {
+            let converter = TimestampConverter {
+                global_offset: GameTimestampDifference::from_milli_hundredths(global),
+                local_offset: MapTimestampDifference::from_milli_hundredths(local),
+                rate: Rate::new(rate),
+            };
+
+            let timestamp = GameTimestamp::from_milli_hundredths(timestamp);
+            prop_assert_eq!(timestamp.to_map(&converter).to_game(&converter), timestamp);
+
+            let difference = GameTimestampDifference::from_milli_hundredths(timestamp.into_milli_hundredths());
+            prop_assert_eq!(difference.to_map(&converter).to_game(&converter), difference);
+        }
     }
 }
@@ -1,5 +1,6 @@
 use gtk::glib;
 use gtk::subclass::prelude::*;
+use plitki_core::judgement::Judgement;
 use plitki_core::state::Hit;
 use plitki_core::timing::GameTimestamp;
 
@@ -90,15 +91,13 @@ mod imp {
                     - (timestamp - hit.timestamp).into_milli_hundredths() as f32 / 100000.)
                     .clamp(0., 1.);
 
-                // Quaver Standard judgements.
-                let color = match diff.abs() / 100 {
-                    0..=18 => gdk::RGBA::new(0.98, 1., 0.71, alpha),
-                    19..=43 => gdk::RGBA::new(1., 0.91, 0.42, alpha),
-                    44..=76 => gdk::RGBA::new(0.34, 1., 0.43, alpha),
-                    77..=106 => gdk::RGBA::new(0., 0.82, 1., alpha),
-                    107..=127 => gdk::RGBA::new(0.85, 0.42, 0.81, alpha),
-                    128..=164 => gdk::RGBA::new(0.98, 0.39, 0.36, alpha),
-                    _ => gdk::RGBA::new(1., 1., 1., alpha),
+                let color = match hit.judgement {
+                    Judgement::Marvelous => gdk::RGBA::new(0.98, 1., 0.71, alpha),
+                    Judgement::Perfect => gdk::RGBA::new(1., 0.91, 0.42, alpha),
+                    Judgement::Great => gdk::RGBA::new(0.34, 1., 0.43, alpha),
+                    Judgement::Good => gdk::RGBA::new(0., 0.82, 1., alpha),
+                    Judgement::Okay => gdk::RGBA::new(0.85, 0.42, 0.81, alpha),
+                    Judgement::Miss => gdk::RGBA::new(0.98, 0.39, 0.36, alpha),
                 };
 
                 snapshot.append_color(
@@ -1,5 +1,6 @@
 use gtk::glib;
 use gtk::subclass::prelude::*;
+use plitki_core::judgement::Judgement as HitJudgement;
 use plitki_core::state::Hit;
 use plitki_core::timing::GameTimestamp;
 
@@ -73,18 +74,14 @@ mod imp {
             let alpha = (1. - (timestamp - hit.timestamp).into_milli_hundredths() as f32 / 25000.)
                 .clamp(0., 1.);
 
-            let diff = hit.difference.into_milli_hundredths();
-
-            // Quaver Standard judgements.
-            let color = match diff.abs() / 100 {
+            let color = match hit.judgement {
                 // Marvellous judgements are hidden.
-                0..=18 => gdk::RGBA::new(0.98, 1., 0.71, alpha),
-                19..=43 => gdk::RGBA::new(1., 0.91, 0.42, alpha),
-                44..=76 => gdk::RGBA::new(0.34, 1., 0.43, alpha),
-                77..=106 => gdk::RGBA::new(0., 0.82, 1., alpha),
-                107..=127 => gdk::RGBA::new(0.85, 0.42, 0.81, alpha),
-                128..=164 => gdk::RGBA::new(0.98, 0.39, 0.36, alpha),
-                _ => gdk::RGBA::new(1., 1., 1., alpha),
+                HitJudgement::Marvelous => gdk::RGBA::new(0.98, 1., 0.71, alpha),
+                HitJudgement::Perfect => gdk::RGBA::new(1., 0.91, 0.42, alpha),
+                HitJudgement::Great => gdk::RGBA::new(0.34, 1., 0.43, alpha),
+                HitJudgement::Good => gdk::RGBA::new(0., 0.82, 1., alpha),
+                HitJudgement::Okay => gdk::RGBA::new(0.85, 0.42, 0.81, alpha),
+                HitJudgement::Miss => gdk::RGBA::new(0.98, 0.39, 0.36, alpha),
             };
 
             snapshot.append_color(
@@ -98,7 +95,7 @@ mod imp {
         pub fn update(&self, timestamp: GameTimestamp, last_hit: Option<Hit>) {
             if let Some(last_hit) = last_hit {
                 // Ignore marvellous judgements.
-                if last_hit.difference.into_milli_hundredths().abs() / 100 > 18 {
+                if last_hit.judgement != HitJudgement::Marvelous {
                     self.last_hit.set(Some(last_hit));
                 }
             }
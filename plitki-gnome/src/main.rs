@@ -6,7 +6,7 @@ use std::rc::Rc;
 
 use adw::prelude::*;
 use gtk::{gdk, gio};
-use plitki_audio::AudioEngine;
+use plitki_audio::{AudioEngine, AudioError, Backend, DeviceSettings};
 use tracing_subscriber::prelude::*;
 use window::Window;
 
@@ -17,7 +17,6 @@ mod hit_error;
 mod hit_light;
 mod judgement;
 mod key_binding_indicator;
-mod statistics;
 mod window;
 
 fn main() {
@@ -55,10 +54,37 @@ fn main() {
     app.run();
 }
 
-fn on_open(app: &adw::Application, files: &[gio::File], _hint: &str) {
-    let audio = Rc::new(AudioEngine::new());
+/// Creates the audio engine, falling back to no sound if the output device can't be used.
+///
+/// Returns the error with the output device, if any, so it can be shown to the user.
+fn create_audio_engine() -> (Rc<AudioEngine>, Option<AudioError>) {
+    match AudioEngine::with_backend(Backend::Device(DeviceSettings::default())) {
+        Ok(audio) => (Rc::new(audio), None),
+        Err(err) => {
+            warn!("error opening the output device, the audio will not be heard: {err}");
+            let audio = AudioEngine::with_backend(Backend::Null {
+                channels: 2,
+                sample_rate: 44100,
+            })
+            .expect("the null backend can't fail");
+            (Rc::new(audio), Some(err))
+        }
+    }
+}
+
+fn create_window(app: &adw::Application) -> Window {
+    let (audio, err) = create_audio_engine();
 
     let window = Window::new(app, audio);
+    if let Some(err) = err {
+        window.show_audio_error(&err);
+    }
+
+    window
+}
+
+fn on_open(app: &adw::Application, files: &[gio::File], _hint: &str) {
+    let window = create_window(app);
 
     if let Some(file) = files.get(0) {
         window.open_file(file.clone());
@@ -85,9 +111,7 @@ fn on_startup(app: &adw::Application) {
 }
 
 fn on_activate(app: &adw::Application) {
-    let audio = Rc::new(AudioEngine::new());
-
-    let window = Window::new(app, audio);
+    let window = create_window(app);
 
     window.present();
 }
@@ -5,7 +5,7 @@ use glib::clone;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::{gio, glib};
-use plitki_audio::{rodio, AudioEngine};
+use plitki_audio::{rodio, AudioEngine, AudioError};
 
 #[derive(Debug, Clone, glib::SharedBoxed)]
 #[shared_boxed_type(name = "BoxedAudioEngine")]
@@ -13,6 +13,7 @@ pub(crate) struct BoxedAudioEngine(Rc<AudioEngine>);
 
 mod imp {
     use std::cell::Cell;
+    use std::collections::HashMap;
     use std::io::Cursor;
     use std::time::Duration;
 
@@ -21,7 +22,13 @@ mod imp {
     use gtk::{gdk, gdk_pixbuf, CompositeTemplate};
     use once_cell::sync::Lazy;
     use once_cell::unsync::OnceCell;
-    use plitki_core::map::Map;
+    use plitki_audio::Track;
+    use plitki_core::autoplay::Autoplay;
+    use plitki_core::health::{FailMode, Health, HealthModel};
+    use plitki_core::judgement::{HitWindows, Judgement as HitJudgement};
+    use plitki_core::map::{Map, Mapset};
+    use plitki_core::replay::ReplayInput;
+    use plitki_core::score::{Score, ScoringSystem};
     use plitki_core::scroll::ScrollSpeed;
     use plitki_core::state::{Event, EventKind, GameState, Hit};
     use plitki_core::timing::{
@@ -30,7 +37,6 @@ mod imp {
     use plitki_gtk::playfield::Playfield;
     use plitki_gtk::skin::{LaneSkin, Skin};
     use plitki_gtk::state::State;
-    use rodio::Source;
 
     use super::*;
     use crate::accuracy::Accuracy;
@@ -40,7 +46,6 @@ mod imp {
     use crate::hit_light::HitLight;
     use crate::judgement::Judgement;
     use crate::key_binding_indicator::KeyBindingIndicator;
-    use crate::statistics::Statistics;
 
     #[derive(Debug, Default, CompositeTemplate)]
     #[template(resource = "/plitki-gnome/window.ui")]
@@ -71,15 +76,30 @@ mod imp {
         global_offset_adjustment: TemplateChild<gtk::Adjustment>,
         #[template_child]
         skin_combo_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        autoplay_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        fail_mode_combo_row: TemplateChild<adw::ComboRow>,
 
-        statistics: RefCell<Statistics>,
+        score: RefCell<Option<Score>>,
+        autoplay: RefCell<Option<Autoplay>>,
 
         audio: OnceCell<Rc<AudioEngine>>,
+        // Contents of the audio file of the current map.
+        audio_file: RefCell<Option<Vec<u8>>>,
         volume: Cell<f32>,
         starting_silence: Cell<Duration>,
+        // Game timestamp at which the player failed, the gameplay stops there.
+        failed_at: Cell<Option<GameTimestamp>>,
+        // Game timestamp of the last state update, used to notice the A–B loop wrapping around.
+        last_timestamp: Cell<Option<GameTimestamp>>,
+        // Start of the A–B loop, set before the end.
+        loop_start: Cell<Option<GameTimestamp>>,
+        ab_loop: Cell<Option<(GameTimestamp, GameTimestamp)>>,
 
         offset_toast: RefCell<Option<adw::Toast>>,
         scroll_speed_toast: RefCell<Option<adw::Toast>>,
+        audio_error_toast: RefCell<Option<adw::Toast>>,
 
         // GTK key events have key repeat, so filter that out manually using this array.
         is_lane_pressed: RefCell<[bool; 7]>,
@@ -124,6 +144,10 @@ mod imp {
                 )));
             self.skin_combo_row.set_model(Some(&skin_model));
 
+            let fail_mode_model =
+                gtk::StringList::new(&["No Fail", "Health", "Sudden Death", "Perfect Only"]);
+            self.fail_mode_combo_row.set_model(Some(&fail_mode_model));
+
             self.pref_window.set_transient_for(Some(&*obj));
 
             // Set up the drop target.
@@ -255,6 +279,16 @@ mod imp {
             self.global_offset_adjustment
                 .set_value(settings.int("device-offset") as f64);
             self.set_volume(settings.double("volume").clamp(0., 1.) as f32);
+            self.autoplay_switch
+                .set_active(settings.boolean("autoplay"));
+
+            let fail_mode = match &*settings.string("fail-mode") {
+                "health" => 1,
+                "sudden-death" => 2,
+                "perfect-only" => 3,
+                _ => 0,
+            };
+            self.fail_mode_combo_row.set_selected(fail_mode);
             self.map_background
                 .set_dim(settings.double("background-dim").clamp(0., 1.) as f32);
 
@@ -282,6 +316,18 @@ mod imp {
                 )
                 .unwrap();
             settings.set_double("volume", self.volume().into()).unwrap();
+            settings
+                .set_boolean("autoplay", self.autoplay_switch.is_active())
+                .unwrap();
+
+            let fail_mode = match self.fail_mode_combo_row.selected() {
+                0 => "no-fail",
+                1 => "health",
+                2 => "sudden-death",
+                3 => "perfect-only",
+                _ => unreachable!(),
+            };
+            settings.set_string("fail-mode", fail_mode).unwrap();
             settings
                 .set_double("background-dim", self.map_background.dim().into())
                 .unwrap();
@@ -324,7 +370,7 @@ mod imp {
         }
 
         pub async fn open_file(&self, file: &gio::File) {
-            // Load the .qua.
+            // Load the map.
             let (contents, _) = match file.load_contents_future().await {
                 Ok(x) => x,
                 Err(err) => {
@@ -333,49 +379,164 @@ mod imp {
                 }
             };
 
-            let qua = match plitki_map_qua::from_reader(&contents[..]) {
-                Ok(x) => x,
-                Err(err) => {
-                    warn!("could not open file as .qua: {err:?}");
-                    return;
+            let extension = file.basename().and_then(|name| {
+                name.extension()
+                    .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            });
+
+            // Audio and background files of a mapset archive, which are read along with the map.
+            let mut archive_files = None;
+
+            let map: Map = match extension.as_deref() {
+                Some("qp" | "osz") => {
+                    let mut mapset =
+                        match plitki_mapset::MapsetArchive::from_archive(Cursor::new(contents)) {
+                            Ok(x) => x,
+                            Err(err) => {
+                                warn!("could not open file as a mapset: {err:?}");
+                                return;
+                            }
+                        };
+
+                    for err in mapset.invalid_difficulties() {
+                        warn!("skipped invalid difficulty: {err:?}");
+                    }
+
+                    if mapset.difficulties().is_empty() {
+                        warn!("mapset has no difficulties");
+                        return;
+                    }
+
+                    let difficulty = match mapset.load(0) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not load difficulty: {err:?}");
+                            return;
+                        }
+                    };
+
+                    archive_files = Some((difficulty.audio, difficulty.background));
+                    difficulty.map
                 }
-            };
+                Some("osu") => {
+                    let osu = match plitki_map_osu::from_reader(&contents[..]) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not open file as .osu: {err:?}");
+                            return;
+                        }
+                    };
+
+                    match Map::try_from(osu) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not convert .osu: {err:?}");
+                            return;
+                        }
+                    }
+                }
+                Some("sm" | "ssc") => {
+                    let simfile = match plitki_map_sm::from_reader(&contents[..]) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not open file as a simfile: {err:?}");
+                            return;
+                        }
+                    };
+
+                    if simfile.charts.is_empty() {
+                        warn!("simfile has no charts");
+                        return;
+                    }
+
+                    let conversion = simfile.convert(0);
+                    if !conversion.unsupported.is_empty() {
+                        warn!("left out unsupported: {:?}", conversion.unsupported);
+                    }
+
+                    conversion.map
+                }
+                Some(ext @ ("bms" | "bme" | "bml" | "pms")) => {
+                    let seed = glib::real_time() as u64;
+                    let bms = match plitki_map_bms::from_reader(&contents[..], seed) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not open file as a BMS: {err:?}");
+                            return;
+                        }
+                    };
+
+                    let layout = if ext == "pms" {
+                        plitki_map_bms::Layout::PopN9K
+                    } else {
+                        bms.detect_layout()
+                    };
+
+                    bms.convert(layout).map
+                }
+                _ => {
+                    let qua = match plitki_map_qua::from_reader(&contents[..]) {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not open file as .qua: {err:?}");
+                            return;
+                        }
+                    };
 
-            let map: Map = qua.try_into().unwrap();
+                    match qua.try_into() {
+                        Ok(x) => x,
+                        Err(err) => {
+                            warn!("could not convert .qua: {err:?}");
+                            return;
+                        }
+                    }
+                }
+            };
 
             let map_dir = file.parent();
 
             // Load the audio file.
-            let track = if let Some(name) = &map.audio_file {
+            let audio = if let Some((audio, _)) = &mut archive_files {
+                audio.take()
+            } else if let Some(name) = &map.audio_file {
                 if let Some(dir) = &map_dir {
                     let file = dir.child(name);
                     match file.load_contents_future().await {
-                        Ok((contents, _)) => {
-                            let contents = Cursor::new(contents);
-                            match rodio::Decoder::new(contents) {
-                                Ok(x) => Some(x),
-                                Err(err) => {
-                                    warn!("error decoding audio file: {err:?}");
-                                    None
-                                }
-                            }
-                        }
+                        Ok((contents, _)) => Some(contents),
                         Err(err) => {
                             warn!("error reading audio file: {err:?}");
                             None
                         }
                     }
                 } else {
-                    warn!(".qua file has no parent dir");
+                    warn!("map file has no parent dir");
                     None
                 }
             } else {
-                warn!("map has no audio file set");
                 None
             };
 
+            let audio = if let Some(contents) = audio {
+                match Track::new(contents.clone()) {
+                    Ok(_) => Some(contents),
+                    Err(err) => {
+                        warn!("error decoding audio file: {err:?}");
+                        None
+                    }
+                }
+            } else {
+                if map.audio_file.is_none() {
+                    warn!("map has no audio file set");
+                }
+                None
+            };
+
+            // Split the song metadata from the chart, which is all the game state needs.
+            let mut mapset = Mapset::from(map);
+            let difficulty = mapset.difficulties.remove(0);
+
             let mut game_state =
-                match GameState::new(map, GameTimestampDifference::from_millis(164)) {
+                match GameState::new(difficulty.chart, HitWindows::quaver_standard()) {
                     Ok(x) => x,
                     Err(err) => {
                         warn!("map is invalid: {err:?}");
@@ -383,8 +544,7 @@ mod imp {
                     }
                 };
 
-            let map = &game_state.immutable.map;
-            let title = match (&map.song_artist, &map.song_title) {
+            let title = match (&mapset.song_artist, &mapset.song_title) {
                 (None, None) => "Plitki".to_owned(),
                 (None, Some(title)) => title.clone(),
                 (Some(artist), None) => artist.clone(),
@@ -392,22 +552,45 @@ mod imp {
             };
             self.gameplay_window_title.set_title(&title);
 
-            self.map_background.set_paintable(
-                map.background_file
+            let background = if let Some((_, background)) = archive_files {
+                background.and_then(|contents| {
+                    let stream =
+                        gio::MemoryInputStream::from_bytes(&glib::Bytes::from_owned(contents));
+                    gdk_pixbuf::Pixbuf::from_stream(&stream, gio::Cancellable::NONE)
+                        .ok()
+                        .map(|pixbuf| gdk::Texture::for_pixbuf(&pixbuf))
+                })
+            } else {
+                mapset
+                    .background_file
                     .as_deref()
                     .zip(map_dir)
                     .map(|(name, dir)| dir.child(name))
-                    .and_then(|file| gdk::Texture::from_file(&file).ok()),
-            );
+                    .and_then(|file| gdk::Texture::from_file(&file).ok())
+            };
+            self.map_background.set_paintable(background);
 
-            let lane_count = map.lane_count();
+            let lane_count = game_state.lane_count();
 
             self.gameplay_window_title
-                .set_subtitle(map.difficulty_name.as_deref().unwrap_or(""));
+                .set_subtitle(difficulty.name.as_deref().unwrap_or(""));
 
             game_state.timestamp_converter.global_offset =
                 GameTimestampDifference::from_millis(self.global_offset_adjustment.value() as i32);
 
+            let fail_mode = match self.fail_mode_combo_row.selected() {
+                1 => Some(FailMode::Normal),
+                2 => Some(FailMode::SuddenDeath),
+                3 => Some(FailMode::PerfectOnly),
+                _ => None,
+            };
+            game_state.health =
+                fail_mode.map(|mode| Health::new(HealthModel::quaver().with_fail_mode(mode)));
+            self.failed_at.set(None);
+            self.last_timestamp.set(None);
+            self.loop_start.set(None);
+            self.ab_loop.set(None);
+
             let starting_silence = if let Some(first_timestamp) = game_state.first_timestamp() {
                 let first_timestamp = game_state.timestamp_converter.map_to_game(first_timestamp);
                 let start_at = first_timestamp - GameTimestampDifference::from_millis(3000);
@@ -423,6 +606,17 @@ mod imp {
             };
             self.starting_silence.set(starting_silence);
 
+            let score = Score::new(ScoringSystem::Quaver, &game_state);
+            self.accuracy.set_accuracy(score.accuracy() as f32);
+            self.combo.set_combo(score.combo());
+            self.score.replace(Some(score));
+
+            let autoplay = self
+                .autoplay_switch
+                .is_active()
+                .then(|| Autoplay::new(&game_state));
+            self.autoplay.replace(autoplay);
+
             let state = State::new(game_state);
             self.playfield.set_state(Some(state));
 
@@ -455,39 +649,125 @@ mod imp {
             let mut is_lane_pressed = self.is_lane_pressed.borrow_mut();
             *is_lane_pressed = [false; 7];
 
-            self.statistics.replace(Statistics::new());
-            self.accuracy
-                .set_accuracy(self.statistics.borrow().accuracy());
-            self.combo.set_combo(0);
-
             // Start the audio.
+            self.audio_file.replace(audio);
+            self.play_audio(None, None);
+        }
+
+        /// Starts the audio from `start`, or from the beginning, looping until `loop_end` if set.
+        fn play_audio(&self, start: Option<GameTimestamp>, loop_end: Option<GameTimestamp>) {
             let engine = self.audio.get().unwrap();
-            if let Some(track) = track {
-                engine.play_track(track.delay(starting_silence));
-            } else {
-                engine.play_track(rodio::source::Zero::<f32>::new(2, 44100));
+            let start = start.map_or(Duration::ZERO, |start| self.track_time(start));
+            let end = loop_end.map(|end| self.track_time(end));
+
+            // The track seeks with the demuxer rather than decoding everything before the start.
+            let result = match &*self.audio_file.borrow() {
+                Some(contents) => Track::new(contents.clone()).and_then(|track| {
+                    let track = track.with_leading_silence(self.starting_silence.get());
+                    match end {
+                        Some(end) => engine.play_decoded_track_loop(track, start, end),
+                        None => engine.play_decoded_track(track, start),
+                    }
+                }),
+                None => {
+                    let track = rodio::source::Zero::<f32>::new(2, 44100);
+                    match end {
+                        Some(end) => engine.play_track_loop(track, start, end),
+                        None => engine.play_track_from(track, start),
+                    }
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                warn!("error playing audio file: {err:?}");
             }
         }
 
-        fn process_event(&self, lane: usize, event: Event) {
-            match event.kind {
-                EventKind::Miss => {
-                    self.combo.set_combo(0);
-                }
-                EventKind::Hit(Hit { difference, .. }) => {
-                    if difference.into_milli_hundredths().abs() / 100 <= 127 {
-                        self.combo.set_combo(self.combo.combo() + 1);
-                    } else {
-                        self.combo.set_combo(0);
-                    }
+        /// Restarts the gameplay from `timestamp`, skipping all objects before it.
+        fn seek(&self, timestamp: GameTimestamp) {
+            let Some(state) = self.playfield.state() else {
+                return;
+            };
+
+            {
+                let mut game_state = state.game_state_mut();
+                game_state.seek(timestamp);
+
+                let score = Score::new(ScoringSystem::Quaver, &game_state);
+                self.accuracy.set_accuracy(score.accuracy() as f32);
+                self.combo.set_combo(score.combo());
+                self.score.replace(Some(score));
+
+                let mut autoplay = self.autoplay.borrow_mut();
+                if autoplay.is_some() {
+                    *autoplay = Some(Autoplay::new(&game_state));
                 }
             }
 
+            *self.is_lane_pressed.borrow_mut() = [false; 7];
+            self.failed_at.set(None);
+            self.last_timestamp.set(Some(timestamp));
+
+            self.playfield.set_game_timestamp(timestamp);
+            self.playfield.update_object_states();
+        }
+
+        /// Sets the start or the end of the A–B loop at `timestamp`.
+        fn set_loop_point(&self, timestamp: GameTimestamp, is_end: bool) {
+            if !is_end {
+                self.loop_start.set(Some(timestamp));
+                return;
+            }
+
+            let Some(start) = self.loop_start.get() else {
+                return;
+            };
+            if timestamp <= start {
+                return;
+            }
+
+            self.ab_loop.set(Some((start, timestamp)));
+            self.seek(start);
+            self.play_audio(Some(start), Some(timestamp));
+        }
+
+        /// Stops the A–B loop and continues playing from `timestamp`.
+        fn clear_loop(&self, timestamp: GameTimestamp) {
+            self.loop_start.set(None);
+            if self.ab_loop.take().is_none() {
+                return;
+            }
+
+            // The music stays stopped after failing.
+            if self.failed_at.get().is_none() {
+                self.play_audio(Some(timestamp), None);
+            }
+        }
+
+        fn process_event(&self, lane: usize, event: Event) {
+            if event.kind == EventKind::Failed {
+                self.on_failed();
+            }
+
+            if let (Some(state), Some(score)) =
+                (self.playfield.state(), &mut *self.score.borrow_mut())
+            {
+                score.process_event(&state.game_state(), lane, event);
+                self.combo.set_combo(score.combo());
+                self.accuracy.set_accuracy(score.accuracy() as f32);
+            }
+
             self.playfield.update_object_state(lane, event.object_index);
+        }
+
+        fn on_failed(&self) {
+            self.failed_at.set(Some(self.game_timestamp()));
+
+            // Stop the music.
+            let engine = self.audio.get().unwrap();
+            engine.play_track(rodio::source::Zero::<f32>::new(2, 44100));
 
-            let mut statistics = self.statistics.borrow_mut();
-            statistics.process_event(event.kind);
-            self.accuracy.set_accuracy(statistics.accuracy());
+            self.toast_overlay.add_toast(&adw::Toast::new("Failed"));
         }
 
         fn hit_light_for_lane(&self, lane: usize) -> HitLight {
@@ -500,6 +780,52 @@ mod imp {
 
         #[instrument(skip_all)]
         fn update_state(&self, timestamp: GameTimestamp) {
+            // The audio jumps back when the A–B loop wraps around.
+            if let Some((start, _)) = self.ab_loop.get() {
+                let wrapped = self
+                    .last_timestamp
+                    .get()
+                    .is_some_and(|last| timestamp < last);
+                if self.failed_at.get().is_none() && wrapped {
+                    self.seek(start);
+                }
+            }
+            self.last_timestamp.set(Some(timestamp));
+
+            while let Some(input) = {
+                let mut autoplay = self.autoplay.borrow_mut();
+                autoplay
+                    .as_mut()
+                    .and_then(|autoplay| autoplay.next_input(timestamp))
+            } {
+                self.update_lanes(input.timestamp);
+                self.apply_input(input);
+            }
+
+            self.update_lanes(timestamp);
+        }
+
+        fn apply_input(&self, input: ReplayInput) {
+            let Some(state) = self.playfield.state() else {
+                return;
+            };
+
+            let event = {
+                let mut game_state = state.game_state_mut();
+                input.apply(&mut game_state)
+            };
+
+            if let Some(event) = event {
+                self.process_event(input.lane, event);
+
+                let hit_light = self.hit_light_for_lane(input.lane);
+                let css_class = hit_light_css_class(event.kind);
+                hit_light.set_css_classes(&[css_class]);
+                hit_light.fire();
+            }
+        }
+
+        fn update_lanes(&self, timestamp: GameTimestamp) {
             let Some(lane_count) = self.playfield.state().map(|s| s.lane_count()) else {
                 return;
             };
@@ -568,6 +894,10 @@ mod imp {
         fn on_tick_callback(&self, clock: &gdk::FrameClock) {
             self.update_mouse_inactivity(clock);
 
+            if let Some(err) = self.audio.get().unwrap().take_error() {
+                self.show_audio_error(&err);
+            }
+
             let game_timestamp = self.game_timestamp();
 
             self.playfield.set_game_timestamp(game_timestamp);
@@ -587,6 +917,11 @@ mod imp {
         }
 
         fn game_timestamp(&self) -> GameTimestamp {
+            // The gameplay stops after failing.
+            if let Some(timestamp) = self.failed_at.get() {
+                return timestamp;
+            }
+
             let audio_time_passed = self.audio.get().unwrap().track_time();
             let audio_time_passed = Timestamp::try_from(audio_time_passed)
                 .unwrap()
@@ -599,6 +934,37 @@ mod imp {
             ))
         }
 
+        /// Converts a game timestamp into the track time, which includes the starting silence.
+        fn track_time(&self, timestamp: GameTimestamp) -> Duration {
+            let starting_silence = Timestamp::try_from(self.starting_silence.get())
+                .unwrap()
+                .into_milli_hundredths();
+            let track_time = timestamp
+                .0
+                .into_milli_hundredths()
+                .saturating_add(starting_silence)
+                .max(0);
+            Duration::try_from(Timestamp::from_milli_hundredths(track_time)).unwrap()
+        }
+
+        pub fn show_audio_error(&self, err: &AudioError) {
+            let title = glib::markup_escape_text(&format!("Audio error: {err}"));
+
+            let mut toast = self.audio_error_toast.borrow_mut();
+            if let Some(toast) = &*toast {
+                toast.set_title(&title);
+            } else {
+                let obj = self.obj();
+                let new_toast = adw::Toast::new(&title);
+                new_toast.set_timeout(0);
+                new_toast.connect_dismissed(clone!(@weak obj => move |_| {
+                    obj.imp().audio_error_toast.replace(None);
+                }));
+                self.toast_overlay.add_toast(&new_toast);
+                *toast = Some(new_toast);
+            }
+        }
+
         fn show_local_offset_toast(&self) {
             let Some(state) = self.playfield.state() else {
                 return;
@@ -716,6 +1082,26 @@ mod imp {
             }
         }
 
+        fn maybe_handle_loop_key(&self, key: gdk::Key) -> bool {
+            if !matches!(
+                key,
+                gdk::Key::bracketleft | gdk::Key::bracketright | gdk::Key::backslash
+            ) || self.playfield.state().is_none()
+            {
+                return false;
+            }
+
+            let timestamp = self.game_timestamp();
+            self.update_state(timestamp);
+
+            match key {
+                gdk::Key::bracketleft => self.set_loop_point(timestamp, false),
+                gdk::Key::bracketright => self.set_loop_point(timestamp, true),
+                _ => self.clear_loop(timestamp),
+            }
+            true
+        }
+
         fn lane_for_key(&self, key: gdk::Key) -> Option<usize> {
             let lane = match self
                 .playfield
@@ -760,21 +1146,32 @@ mod imp {
                 return gtk::Inhibit(true);
             }
 
+            // Handle A–B loop keys.
+            if self.maybe_handle_loop_key(key) {
+                return gtk::Inhibit(true);
+            }
+
+            // Gameplay keys do nothing during autoplay.
+            if self.autoplay.borrow().is_some() {
+                return gtk::Inhibit(false);
+            }
+
             // Handle gameplay keys.
             let lane = match self.lane_for_key(key) {
                 Some(x) => x,
                 None => return gtk::Inhibit(false),
             };
 
-            let mut is_lane_pressed = self.is_lane_pressed.borrow_mut();
-            if is_lane_pressed[lane] {
+            if self.is_lane_pressed.borrow()[lane] {
                 return gtk::Inhibit(false);
             }
-            is_lane_pressed[lane] = true;
 
+            // Update the state first, as it can seek and reset the pressed lanes.
             let timestamp = self.game_timestamp();
             self.update_state(timestamp);
 
+            self.is_lane_pressed.borrow_mut()[lane] = true;
+
             let hit_light = self.hit_light_for_lane(lane);
 
             let Some(state) = self.playfield.state() else {
@@ -800,11 +1197,10 @@ mod imp {
                 return;
             };
 
-            let mut is_lane_pressed = self.is_lane_pressed.borrow_mut();
-            if !is_lane_pressed[lane] {
+            if !self.is_lane_pressed.borrow()[lane] {
                 return;
             }
-            is_lane_pressed[lane] = false;
+            self.is_lane_pressed.borrow_mut()[lane] = false;
 
             let timestamp = self.game_timestamp();
             self.update_state(timestamp);
@@ -872,6 +1268,8 @@ mod imp {
                 ln_head: load_texture(&format!("{}/4k/note-holdhitobject-{}.png", path, lane + 1)),
                 ln_body: load_texture(&format!("{}/4k/note-holdbody-{}.png", path, lane + 1)),
                 ln_tail: load_texture(&format!("{}/4k/note-holdend-{}.png", path, lane + 1)),
+                snap_objects: HashMap::new(),
+                snap_ln_heads: HashMap::new(),
             };
 
             element.push(lane_skin);
@@ -885,6 +1283,8 @@ mod imp {
                 ln_head: load_texture(&format!("{}/7k/note-holdhitobject-{}.png", path, lane + 1)),
                 ln_body: load_texture(&format!("{}/7k/note-holdbody-{}.png", path, lane + 1)),
                 ln_tail: load_texture(&format!("{}/7k/note-holdend-{}.png", path, lane + 1)),
+                snap_objects: HashMap::new(),
+                snap_ln_heads: HashMap::new(),
             };
 
             element.push(lane_skin);
@@ -897,18 +1297,15 @@ mod imp {
 
     fn hit_light_css_class(event_kind: EventKind) -> &'static str {
         match event_kind {
-            EventKind::Miss => "judge-miss",
-            EventKind::Hit(Hit { difference, .. }) => {
-                match difference.into_milli_hundredths().abs() / 100 {
-                    0..=18 => "judge-marv",
-                    19..=43 => "judge-perf",
-                    44..=76 => "judge-great",
-                    77..=106 => "judge-good",
-                    107..=127 => "judge-okay",
-                    128..=164 => "judge-miss",
-                    _ => "",
-                }
-            }
+            EventKind::Miss | EventKind::Failed => "judge-miss",
+            EventKind::Hit(Hit { judgement, .. }) => match judgement {
+                HitJudgement::Marvelous => "judge-marv",
+                HitJudgement::Perfect => "judge-perf",
+                HitJudgement::Great => "judge-great",
+                HitJudgement::Good => "judge-good",
+                HitJudgement::Okay => "judge-okay",
+                HitJudgement::Miss => "judge-miss",
+            },
         }
     }
 }
@@ -936,6 +1333,11 @@ impl Window {
         self.imp().set_volume(value);
     }
 
+    /// Shows an error with the audio output to the user.
+    pub fn show_audio_error(&self, err: &AudioError) {
+        self.imp().show_audio_error(err);
+    }
+
     pub fn open_file(&self, file: gio::File) {
         glib::MainContext::default().spawn_local(
             clone!(@strong self as obj => async move { obj.imp().open_file(&file).await; }),
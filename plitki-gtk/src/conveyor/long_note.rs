@@ -2,6 +2,7 @@ use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::{gdk, glib};
 use plitki_core::scroll::Position;
+use plitki_core::snap::Snap;
 
 use super::note::Note;
 use crate::conveyor::widget::{ConveyorWidget, ConveyorWidgetExt};
@@ -23,6 +24,8 @@ mod imp {
         tail: RefCell<gtk::Widget>,
         body: RefCell<gtk::Widget>,
         length: Cell<i32>,
+        head_offset: Cell<i32>,
+        tail_offset: Cell<i32>,
     }
 
     impl Default for LongNote {
@@ -32,6 +35,8 @@ mod imp {
                 tail: RefCell::new(gtk::Picture::new().upcast()),
                 body: RefCell::new(gtk::Picture::new().upcast()),
                 length: Default::default(),
+                head_offset: Default::default(),
+                tail_offset: Default::default(),
             }
         }
     }
@@ -80,6 +85,14 @@ mod imp {
                         .minimum(0)
                         .explicit_notify()
                         .build(),
+                    glib::ParamSpecInt::builder("head-offset")
+                        .minimum(0)
+                        .explicit_notify()
+                        .build(),
+                    glib::ParamSpecInt::builder("tail-offset")
+                        .minimum(0)
+                        .explicit_notify()
+                        .build(),
                 ]
             });
             PROPERTIES.as_ref()
@@ -91,6 +104,8 @@ mod imp {
                 "tail" => self.tail().to_value(),
                 "body" => self.body().to_value(),
                 "length" => self.length().to_value(),
+                "head-offset" => self.head_offset().to_value(),
+                "tail-offset" => self.tail_offset().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -101,6 +116,8 @@ mod imp {
                 "tail" => self.set_tail(value.get().unwrap()),
                 "body" => self.set_body(value.get().unwrap()),
                 "length" => self.set_length(value.get().unwrap()),
+                "head-offset" => self.set_head_offset(value.get().unwrap()),
+                "tail-offset" => self.set_tail_offset(value.get().unwrap()),
                 _ => unimplemented!(),
             }
         }
@@ -204,6 +221,8 @@ mod imp {
             let body = self.body.borrow();
             let body = &*body;
             let length = self.length.get();
+            let head_start = self.head_start();
+            let tail_start = self.tail_start();
 
             // We really want to allocate natural heights. Find the largest width that we can use
             // within the given allocation that still lets us use natural heights. Otherwise bail
@@ -227,16 +246,30 @@ mod imp {
 
             // Allocate the head.
             let nat_head = head.measure(gtk::Orientation::Vertical, width).1;
-            head.size_allocate(&gdk::Rectangle::new(0, 0, width, nat_head), -1);
+            head.size_allocate(&gdk::Rectangle::new(0, head_start, width, nat_head), -1);
 
             // Allocate the tail.
-            let tail_start = length;
             let nat_tail = tail.measure(gtk::Orientation::Vertical, width).1;
             tail.size_allocate(&gdk::Rectangle::new(0, tail_start, width, nat_tail), -1);
 
-            // Allocate the body, if it fits.
-            let body_start = nat_head / 2;
-            let body_end = (length + nat_tail / 2).max(body_start);
+            // Allocate the body, if it fits. The body starts and ends in the middle of the head or
+            // the tail if they are at the very ends, and otherwise it extends past them where the
+            // long note turns around.
+            let body_start = if head_start == 0 {
+                nat_head / 2
+            } else if tail_start == 0 {
+                nat_tail / 2
+            } else {
+                0
+            };
+            let body_end = if tail_start == length {
+                length + nat_tail / 2
+            } else if head_start == length {
+                length + nat_head / 2
+            } else {
+                length
+            }
+            .max(body_start);
             let body_height = body_end - body_start;
 
             let body_min_height = body.measure(gtk::Orientation::Vertical, width).0;
@@ -267,15 +300,21 @@ mod imp {
                 widget.snapshot_child(body, snapshot);
             }
 
-            let bounds = tail.compute_bounds(&*widget).unwrap();
-            snapshot.push_clip(&graphene::Rect::new(
-                bounds.x(),
-                (head.allocated_height() / 2) as f32,
-                bounds.width(),
-                bounds.y() + bounds.height(),
-            ));
-            widget.snapshot_child(tail, snapshot);
-            snapshot.pop();
+            // Don't let the tail stick out past the head. When the long note turns around, the
+            // head is somewhere in the middle of the body, so there's nothing to clip against.
+            if self.head_start() == 0 {
+                let bounds = tail.compute_bounds(&*widget).unwrap();
+                snapshot.push_clip(&graphene::Rect::new(
+                    bounds.x(),
+                    (head.allocated_height() / 2) as f32,
+                    bounds.width(),
+                    bounds.y() + bounds.height(),
+                ));
+                widget.snapshot_child(tail, snapshot);
+                snapshot.pop();
+            } else {
+                widget.snapshot_child(tail, snapshot);
+            }
 
             widget.snapshot_child(head, snapshot);
         }
@@ -371,6 +410,44 @@ mod imp {
             }
         }
 
+        pub fn head_offset(&self) -> i32 {
+            self.head_offset.get()
+        }
+
+        pub fn set_head_offset(&self, offset: i32) {
+            if self.head_offset.get() != offset {
+                self.head_offset.set(offset);
+
+                let obj = self.obj();
+                obj.notify("head-offset");
+                obj.queue_resize();
+            }
+        }
+
+        pub fn tail_offset(&self) -> i32 {
+            self.tail_offset.get()
+        }
+
+        pub fn set_tail_offset(&self, offset: i32) {
+            if self.tail_offset.get() != offset {
+                self.tail_offset.set(offset);
+
+                let obj = self.obj();
+                obj.notify("tail-offset");
+                obj.queue_resize();
+            }
+        }
+
+        /// Returns the head y coordinate, counting from the start of the long note.
+        fn head_start(&self) -> i32 {
+            self.head_offset.get().min(self.length.get())
+        }
+
+        /// Returns the tail y coordinate, counting from the start of the long note.
+        fn tail_start(&self) -> i32 {
+            (self.length.get() - self.tail_offset.get()).max(0)
+        }
+
         fn height_for_width(&self, width: i32) -> (i32, i32) {
             let head = self.head.borrow();
             let head = &*head;
@@ -381,12 +458,18 @@ mod imp {
             let (min_head, nat_head, _, _) = head.measure(gtk::Orientation::Vertical, width);
             let (min_tail, nat_tail, _, _) = tail.measure(gtk::Orientation::Vertical, width);
 
-            let tail_start = length;
+            let head_start = self.head_start();
+            let tail_start = self.tail_start();
 
-            // Body will be hidden if it doesn't fit, so no need to consider it.
+            // Body will be hidden if it doesn't fit, so no need to consider it, except for the part
+            // that sticks out past the head and the tail when the long note turns around.
             (
-                min_head.max(tail_start + min_tail),
-                nat_head.max(tail_start + nat_tail),
+                (head_start + min_head)
+                    .max(tail_start + min_tail)
+                    .max(length),
+                (head_start + nat_head)
+                    .max(tail_start + nat_tail)
+                    .max(length),
             )
         }
     }
@@ -486,8 +569,30 @@ impl LongNote {
         self.imp().set_length(length);
     }
 
-    pub fn set_skin(&self, skin: Option<&LaneSkin>) {
-        let ln_head = skin.map(|s| &s.ln_head);
+    /// Returns the distance from the start of the long note to its head.
+    ///
+    /// This is non-zero when the long note turns around and its body extends past the head.
+    pub fn head_offset(&self) -> i32 {
+        self.imp().head_offset()
+    }
+
+    pub fn set_head_offset(&self, offset: i32) {
+        self.imp().set_head_offset(offset);
+    }
+
+    /// Returns the distance from the tail to the end of the long note.
+    ///
+    /// This is non-zero when the long note turns around and its body extends past the tail.
+    pub fn tail_offset(&self) -> i32 {
+        self.imp().tail_offset()
+    }
+
+    pub fn set_tail_offset(&self, offset: i32) {
+        self.imp().set_tail_offset(offset);
+    }
+
+    pub fn set_skin(&self, skin: Option<&LaneSkin>, snap: Snap) {
+        let ln_head = skin.map(|s| s.ln_head(snap));
         let ln_tail = skin.map(|s| &s.ln_tail);
         let ln_body = skin.map(|s| &s.ln_body);
 
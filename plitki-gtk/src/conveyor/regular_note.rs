@@ -1,6 +1,7 @@
 use gtk::glib;
 use gtk::subclass::prelude::*;
 use plitki_core::scroll::Position;
+use plitki_core::snap::Snap;
 
 use super::note::Note;
 use crate::conveyor::widget::{ConveyorWidget, ConveyorWidgetExt};
@@ -51,8 +52,8 @@ mod imp {
     impl NoteImpl for RegularNote {}
 
     impl RegularNote {
-        pub fn set_skin(&self, skin: Option<&LaneSkin>) {
-            let texture = skin.map(|s| &s.object);
+        pub fn set_skin(&self, skin: Option<&LaneSkin>, snap: Snap) {
+            let texture = skin.map(|s| s.object(snap));
             self.picture.get().unwrap().set_paintable(texture);
         }
     }
@@ -70,7 +71,7 @@ impl RegularNote {
         widget
     }
 
-    pub fn set_skin(&self, skin: Option<&LaneSkin>) {
-        self.imp().set_skin(skin);
+    pub fn set_skin(&self, skin: Option<&LaneSkin>, snap: Snap) {
+        self.imp().set_skin(skin, snap);
     }
 }
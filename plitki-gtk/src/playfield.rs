@@ -18,7 +18,11 @@ mod imp {
     use once_cell::sync::Lazy;
     use once_cell::unsync::OnceCell;
     use plitki_core::scroll::{Position, ScrollSpeed};
-    use plitki_core::state::{LongNoteCache, ObjectCache, RegularObjectCache};
+    use plitki_core::snap::ObjectSnap;
+    use plitki_core::state::{
+        GameState, LongNoteCache, LongNoteGeometry, ObjectCache, RegularObjectCache,
+    };
+    use plitki_core::timing::MapTimestamp;
 
     use super::*;
     use crate::conveyor::long_note::LongNote;
@@ -44,10 +48,10 @@ mod imp {
             }
         }
 
-        fn set_skin(&self, skin: Option<&LaneSkin>) {
+        fn set_skin(&self, skin: Option<&LaneSkin>, snap: ObjectSnap) {
             match self {
-                NoteWidget::Regular(regular) => regular.set_skin(skin),
-                NoteWidget::Long(long) => long.set_skin(skin),
+                NoteWidget::Regular(regular) => regular.set_skin(skin, snap.start),
+                NoteWidget::Long(long) => long.set_skin(skin, snap.start),
             }
         }
 
@@ -66,6 +70,8 @@ mod imp {
         notes: Vec<Vec<NoteWidget>>,
         lanes: Vec<Lane>,
         map_position: Position,
+        /// Current positions of the scroll groups.
+        scroll_group_positions: Vec<Position>,
 
         /// Cached min and nat widths for each lane.
         ///
@@ -73,6 +79,16 @@ mod imp {
         lane_sizes: Vec<(i32, i32)>,
     }
 
+    impl Data {
+        /// Converts a position in a scroll group into a position on the lane conveyors.
+        fn scroll_group_to_map(&self, position: Position, scroll_group: Option<usize>) -> Position {
+            match scroll_group {
+                Some(group) => position + (self.map_position - self.scroll_group_positions[group]),
+                None => position,
+            }
+        }
+    }
+
     #[derive(Debug)]
     pub struct Playfield {
         data: RefCell<Option<Data>>,
@@ -343,11 +359,19 @@ mod imp {
                 let obj = self.obj();
                 obj.notify("game-timestamp");
 
-                let mut data = self.data.borrow_mut();
-                if let Some(data) = &mut *data {
+                let mut scroll_groups_moved = false;
+                if let Some(data) = &mut *self.data.borrow_mut() {
                     let game_state = data.state.game_state();
                     let map_timestamp = value.to_map(&game_state.timestamp_converter);
                     let position = game_state.position_at_time(map_timestamp);
+                    let scroll_group_positions = scroll_group_positions(&game_state, map_timestamp);
+
+                    // Notes in scroll groups move relative to the lanes.
+                    scroll_groups_moved = !scroll_group_positions.is_empty()
+                        && (data.map_position != position
+                            || data.scroll_group_positions != scroll_group_positions);
+                    data.scroll_group_positions = scroll_group_positions;
+
                     if data.map_position != position {
                         data.map_position = position;
                         self.timing_line_conveyor
@@ -359,6 +383,10 @@ mod imp {
                         }
                     }
                 }
+
+                if scroll_groups_moved {
+                    self.update_scroll_group_notes();
+                }
             }
         }
 
@@ -414,12 +442,12 @@ mod imp {
                         .object_caches
                         .iter()
                         .map(|&object| match object {
-                            ObjectCache::Regular(RegularObjectCache { position }) => {
+                            ObjectCache::Regular(RegularObjectCache { position, .. }) => {
                                 NoteWidget::Regular(RegularNote::new(position))
                             }
-                            ObjectCache::LongNote(LongNoteCache { start_position, .. }) => {
-                                NoteWidget::Long(LongNote::new(start_position))
-                            }
+                            ObjectCache::LongNote(LongNoteCache {
+                                lowest_position, ..
+                            }) => NoteWidget::Long(LongNote::new(lowest_position)),
                         })
                         .collect();
 
@@ -436,11 +464,12 @@ mod imp {
                 })
                 .collect();
 
-            let map_position = game_state.position_at_time(
-                self.game_timestamp
-                    .get()
-                    .to_map(&game_state.timestamp_converter),
-            );
+            let map_timestamp = self
+                .game_timestamp
+                .get()
+                .to_map(&game_state.timestamp_converter);
+            let map_position = game_state.position_at_time(map_timestamp);
+            let scroll_group_positions = scroll_group_positions(&game_state, map_timestamp);
             self.timing_line_conveyor
                 .get()
                 .unwrap()
@@ -458,11 +487,13 @@ mod imp {
                 notes,
                 lanes,
                 map_position,
+                scroll_group_positions,
             };
 
             self.data.replace(Some(data));
 
             self.update_object_states();
+            self.update_scroll_group_notes();
             self.update_skin();
 
             obj.notify("state");
@@ -497,8 +528,9 @@ mod imp {
 
             for (lane, lane_notes) in data.notes.iter().enumerate() {
                 let lane_skin = store.map(|s| s.get(lane_count, lane));
-                for widget in lane_notes {
-                    widget.set_skin(lane_skin);
+                let snaps = &game_state.immutable.lane_caches[lane].snaps;
+                for (widget, &snap) in lane_notes.iter().zip(snaps) {
+                    widget.set_skin(lane_skin, snap);
                 }
             }
         }
@@ -514,16 +546,21 @@ mod imp {
             let obj_state = &game_state.lane_states[lane].object_states[index];
 
             if let ObjectCache::LongNote(_) = obj_cache {
-                let long_note = widget.as_long().unwrap();
-                let start_position =
-                    game_state.object_start_position(*obj_state, *obj_cache, data.map_position);
-                long_note.set_position(start_position);
-                let length = (obj_cache.end_position() - start_position) * self.scroll_speed.get();
-                long_note.set_length(to_pixels(length));
+                let map_timestamp = self
+                    .game_timestamp
+                    .get()
+                    .to_map(&game_state.timestamp_converter);
+                let geometry = game_state.long_note_geometry(lane, index, map_timestamp);
+                self.update_long_note(
+                    data,
+                    widget.as_long().unwrap(),
+                    geometry,
+                    obj_cache.scroll_group(),
+                );
             }
 
             let note = widget.as_note();
-            note.set_hidden(obj_state.is_hit());
+            note.set_hidden(obj_state.is_hit() || obj_state.is_skipped());
             note.set_missed(obj_state.is_missed());
         }
 
@@ -532,33 +569,93 @@ mod imp {
                 return;
             };
             let game_state = data.state.game_state();
+            let map_timestamp = self
+                .game_timestamp
+                .get()
+                .to_map(&game_state.timestamp_converter);
 
             for (lane, lane_notes) in data.notes.iter().enumerate() {
-                for ((widget, obj_cache), obj_state) in lane_notes
+                for (index, ((widget, obj_cache), obj_state)) in lane_notes
                     .iter()
                     .zip(&game_state.immutable.lane_caches[lane].object_caches)
                     .zip(&game_state.lane_states[lane].object_states)
+                    .enumerate()
                 {
                     if let ObjectCache::LongNote(_) = obj_cache {
-                        let long_note = widget.as_long().unwrap();
-                        let start_position = game_state.object_start_position(
-                            *obj_state,
-                            *obj_cache,
-                            data.map_position,
+                        let geometry = game_state.long_note_geometry(lane, index, map_timestamp);
+                        self.update_long_note(
+                            data,
+                            widget.as_long().unwrap(),
+                            geometry,
+                            obj_cache.scroll_group(),
                         );
-                        long_note.set_position(start_position);
-                        let length =
-                            (obj_cache.end_position() - start_position) * self.scroll_speed.get();
-                        long_note.set_length(to_pixels(length));
                     }
 
                     let note = widget.as_note();
-                    note.set_hidden(obj_state.is_hit());
+                    note.set_hidden(obj_state.is_hit() || obj_state.is_skipped());
                     note.set_missed(obj_state.is_missed());
                 }
             }
         }
 
+        /// Moves the notes in scroll groups according to the current scroll group positions.
+        fn update_scroll_group_notes(&self) {
+            let Some(data) = &*self.data.borrow() else {
+                return;
+            };
+            let game_state = data.state.game_state();
+            let map_timestamp = self
+                .game_timestamp
+                .get()
+                .to_map(&game_state.timestamp_converter);
+
+            for (lane, lane_notes) in data.notes.iter().enumerate() {
+                for (index, (widget, obj_cache)) in lane_notes
+                    .iter()
+                    .zip(&game_state.immutable.lane_caches[lane].object_caches)
+                    .enumerate()
+                {
+                    let scroll_group = obj_cache.scroll_group();
+                    if scroll_group.is_none() {
+                        continue;
+                    }
+
+                    match widget {
+                        NoteWidget::Regular(note) => note.set_position(
+                            data.scroll_group_to_map(obj_cache.start_position(), scroll_group),
+                        ),
+                        NoteWidget::Long(long_note) => {
+                            let geometry =
+                                game_state.long_note_geometry(lane, index, map_timestamp);
+                            self.update_long_note(data, long_note, geometry, scroll_group);
+                        }
+                    }
+
+                    // Make the lane pick up the new position.
+                    widget.as_note().queue_resize();
+                }
+            }
+        }
+
+        fn update_long_note(
+            &self,
+            data: &Data,
+            long_note: &LongNote,
+            geometry: LongNoteGeometry,
+            scroll_group: Option<usize>,
+        ) {
+            let scroll_speed = self.scroll_speed.get();
+            let lowest = geometry.lowest_position;
+            let highest = geometry.highest_position;
+
+            // The widget spans the whole body, which may extend past the head and the tail when
+            // the long note turns around.
+            long_note.set_position(data.scroll_group_to_map(lowest, scroll_group));
+            long_note.set_length(to_pixels((highest - lowest) * scroll_speed));
+            long_note.set_head_offset(to_pixels((geometry.head_position - lowest) * scroll_speed));
+            long_note.set_tail_offset(to_pixels((highest - geometry.tail_position) * scroll_speed));
+        }
+
         #[instrument("Playfield::refresh_lane_sizes", skip_all)]
         fn refresh_lane_sizes(&self, data: &mut Data) {
             for (size, lane) in data.lane_sizes.iter_mut().zip(data.lanes.iter()) {
@@ -604,6 +701,15 @@ mod imp {
         }
     }
 
+    fn scroll_group_positions(
+        game_state: &GameState,
+        map_timestamp: MapTimestamp,
+    ) -> Vec<Position> {
+        (0..game_state.immutable.scroll_group_caches.len())
+            .map(|group| game_state.scroll_group_position_at_time(Some(group), map_timestamp))
+            .collect()
+    }
+
     fn compute_lane_widths(data: &Data, width: i32) -> impl Iterator<Item = i32> + '_ {
         // When the playfield is smaller or bigger than its natural size, we want all lanes to be
         // smaller or bigger in the same proportion. However, when making the playfield smaller, the
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 use glib::subclass::prelude::*;
 use gtk::gdk;
+use plitki_core::snap::Snap;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LaneSkin {
@@ -10,6 +11,22 @@ pub struct LaneSkin {
     pub ln_head: gdk::Texture,
     pub ln_body: gdk::Texture,
     pub ln_tail: gdk::Texture,
+    /// Regular object textures for notes of a particular snap, used instead of `object`.
+    pub snap_objects: HashMap<Snap, gdk::Texture>,
+    /// Long note head textures for long notes of a particular snap, used instead of `ln_head`.
+    pub snap_ln_heads: HashMap<Snap, gdk::Texture>,
+}
+
+impl LaneSkin {
+    /// Returns the regular object texture for `snap`.
+    pub fn object(&self, snap: Snap) -> &gdk::Texture {
+        self.snap_objects.get(&snap).unwrap_or(&self.object)
+    }
+
+    /// Returns the long note head texture for `snap`.
+    pub fn ln_head(&self, snap: Snap) -> &gdk::Texture {
+        self.snap_ln_heads.get(&snap).unwrap_or(&self.ln_head)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, glib::Boxed)]
@@ -5,11 +5,13 @@ use gtk::{gio, glib};
 
 mod imp {
     use std::cell::{Cell, RefCell};
+    use std::collections::HashMap;
     use std::time::Duration;
 
     use anyhow::{anyhow, Context};
     use gtk::{gdk, gdk_pixbuf, CompositeTemplate, TickCallbackId};
     use once_cell::unsync::OnceCell;
+    use plitki_core::judgement::HitWindows;
     use plitki_core::map::Map;
     use plitki_core::state::GameState;
     use plitki_core::timing::{GameTimestampDifference, Timestamp};
@@ -42,6 +44,8 @@ mod imp {
                 ln_head: load_texture(&format!("{}/4k/note-holdhitobject-{}.png", path, lane + 1)),
                 ln_body: load_texture(&format!("{}/4k/note-holdbody-{}.png", path, lane + 1)),
                 ln_tail: load_texture(&format!("{}/4k/note-holdend-{}.png", path, lane + 1)),
+                snap_objects: HashMap::new(),
+                snap_ln_heads: HashMap::new(),
             };
 
             element.push(lane_skin);
@@ -55,6 +59,8 @@ mod imp {
                 ln_head: load_texture(&format!("{}/7k/note-holdhitobject-{}.png", path, lane + 1)),
                 ln_body: load_texture(&format!("{}/7k/note-holdbody-{}.png", path, lane + 1)),
                 ln_tail: load_texture(&format!("{}/7k/note-holdend-{}.png", path, lane + 1)),
+                snap_objects: HashMap::new(),
+                snap_ln_heads: HashMap::new(),
             };
 
             element.push(lane_skin);
@@ -142,8 +148,11 @@ mod imp {
             let map: Map = qua
                 .try_into()
                 .with_context(|| "couldn't convert the map to plitki's format")?;
-            let game_state = GameState::new(map, GameTimestampDifference::from_millis(0))
-                .map_err(|_| anyhow!("map has invalid objects"))?;
+            let game_state = GameState::new(
+                map,
+                HitWindows::uniform(GameTimestampDifference::from_millis(0)),
+            )
+            .map_err(|_| anyhow!("map has invalid objects"))?;
 
             self.adjustment_timestamp.configure(
                 game_state
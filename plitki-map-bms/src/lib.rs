@@ -0,0 +1,796 @@
+//! Reading of the BMS map format (`.bms`, `.bme`, `.bml` and `.pms`).
+//!
+//! BMS maps are keysounded: instead of a single audio track, every object plays its own sample.
+//! [`Bms::convert`] returns the samples alongside the `Map` in [`Conversion`].
+
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    error, fmt,
+    io::{self, Read},
+};
+
+use plitki_core::{
+    map::{Lane, Map, ScrollSpeedChange, TimeSignature, TimingPoint},
+    object::Object,
+    rng::SplitMix64,
+    scroll::ScrollSpeedMultiplier,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+
+/// BPM used when the file doesn't specify one.
+pub const DEFAULT_BPM: f64 = 130.;
+
+/// Channel of background keysounds.
+const CHANNEL_BGM: u8 = 1;
+/// Channel of measure length changes.
+const CHANNEL_MEASURE_LENGTH: u8 = 2;
+/// Channel of BPM changes in hexadecimal.
+const CHANNEL_BPM: u8 = 3;
+/// Channel of BPM changes referencing `#BPMxx`.
+const CHANNEL_EXTENDED_BPM: u8 = 8;
+/// Channel of stops referencing `#STOPxx`.
+const CHANNEL_STOP: u8 = 9;
+/// Offset from a visible note channel to its long note channel.
+const LONG_NOTE_CHANNEL_OFFSET: u8 = 40;
+
+/// Lane layout of a map.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Layout {
+    /// Scratch and 5 keys.
+    Beat5K,
+    /// Scratch and 7 keys.
+    Beat7K,
+    /// Two sides of scratch and 5 keys.
+    Beat10K,
+    /// Two sides of scratch and 7 keys.
+    Beat14K,
+    /// 9 buttons of pop'n music (`.pms`).
+    PopN9K,
+}
+
+impl Layout {
+    /// Returns the visible note channels in lane order.
+    pub fn channels(self) -> &'static [u8] {
+        match self {
+            Layout::Beat5K => &[16, 11, 12, 13, 14, 15],
+            Layout::Beat7K => &[16, 11, 12, 13, 14, 15, 18, 19],
+            Layout::Beat10K => &[16, 11, 12, 13, 14, 15, 21, 22, 23, 24, 25, 26],
+            Layout::Beat14K => &[
+                16, 11, 12, 13, 14, 15, 18, 19, 21, 22, 23, 24, 25, 28, 29, 26,
+            ],
+            Layout::PopN9K => &[11, 12, 13, 14, 15, 22, 23, 24, 25],
+        }
+    }
+
+    /// Returns the lane count of the layout.
+    #[inline]
+    pub fn lane_count(self) -> usize {
+        self.channels().len()
+    }
+
+    /// Returns the lane of a visible or long note channel, or `None` if the layout doesn't have
+    /// it.
+    fn lane(self, channel: u8) -> Option<usize> {
+        let channel = if channel > LONG_NOTE_CHANNEL_OFFSET {
+            channel - LONG_NOTE_CHANNEL_OFFSET
+        } else {
+            channel
+        };
+        self.channels().iter().position(|&x| x == channel)
+    }
+}
+
+/// A value placed in a measure of a channel.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Event {
+    pub measure: u32,
+    /// Channel, like `11` for the first key.
+    pub channel: u8,
+    /// Index of the value within its measure.
+    pub index: u32,
+    /// Number of values the measure was split into.
+    pub count: u32,
+    /// Value, like a `#WAVxx` index; BPM in channel `03`.
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bms {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub artist: Option<String>,
+    pub genre: Option<String>,
+    /// `1` for single play, `3` for double play.
+    pub player: Option<i32>,
+    /// `1` to `5`, from Beginner to Insane.
+    pub difficulty: Option<i32>,
+    pub play_level: Option<i32>,
+    pub stage_file: Option<String>,
+    pub back_bmp: Option<String>,
+    /// Initial BPM.
+    pub bpm: Option<f64>,
+    /// Value in a visible note channel which ends the long note started by the previous note.
+    pub ln_obj: Option<u16>,
+    /// Keysound files.
+    pub wavs: HashMap<u16, String>,
+    /// BPMs for channel `08`.
+    pub bpms: HashMap<u16, f64>,
+    /// Stop durations for channel `09` in <sup>1</sup>⁄<sub>192</sub>ths of a 4/4 measure.
+    pub stops: HashMap<u16, f64>,
+    /// Measure lengths relative to 4/4, for measures which aren't 4/4.
+    pub measure_lengths: BTreeMap<u32, f64>,
+    /// Events from all channels, in the file order.
+    pub events: Vec<Event>,
+}
+
+/// A keysound played at a given time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keysound {
+    pub timestamp: MapTimestamp,
+    pub file: String,
+}
+
+/// Result of converting a `Bms` into a `Map`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub map: Map,
+    /// Keysound file of every object, indexed the same way as `map.lanes[lane].objects[object]`.
+    pub keysounds: Vec<Vec<Option<String>>>,
+    /// Keysounds which play by themselves, sorted by timestamp.
+    pub background_keysounds: Vec<Keysound>,
+}
+
+/// Error returned when reading a BMS fails.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error occurred.
+    Io(io::Error),
+    /// The line with this number (starting from 1) couldn't be parsed.
+    InvalidLine(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::InvalidLine(line) => write!(f, "invalid line {}", line),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// State of an `#IF` block.
+struct Branch {
+    /// Whether lines in the current branch are used.
+    active: bool,
+    /// Whether one of the branches of this block has been taken.
+    taken: bool,
+}
+
+/// Evaluates `#RANDOM` blocks.
+struct Random {
+    rng: SplitMix64,
+    /// Generated value of each nested `#RANDOM`.
+    values: Vec<u64>,
+    branches: Vec<Branch>,
+}
+
+impl Random {
+    fn is_active(&self) -> bool {
+        self.branches.iter().all(|x| x.active)
+    }
+
+    fn condition(&self, value: u64) -> bool {
+        self.values.last() == Some(&value)
+    }
+
+    /// Handles a control flow command, returning `false` if `key` isn't one.
+    fn command(&mut self, key: &str, value: &str) -> Result<bool, ()> {
+        let number = || value.trim().parse::<u64>().map_err(|_| ());
+
+        match key {
+            "RANDOM" | "SETRANDOM" => {
+                let max = number()?;
+                let value = if !self.is_active() || max == 0 {
+                    0
+                } else if key == "RANDOM" {
+                    self.rng.next_u64() % max + 1
+                } else {
+                    max
+                };
+
+                // Plenty of files don't close their #RANDOM, so a new one at the same nesting
+                // level replaces the previous one.
+                if self.values.len() > self.branches.len() {
+                    *self.values.last_mut().unwrap() = value;
+                } else {
+                    self.values.push(value);
+                }
+            }
+            "ENDRANDOM" => {
+                if self.values.len() > self.branches.len() {
+                    self.values.pop();
+                }
+            }
+            "IF" => {
+                let active = self.condition(number()?);
+                self.branches.push(Branch {
+                    active,
+                    taken: active,
+                });
+            }
+            "ELSEIF" => {
+                let condition = self.condition(number()?);
+                let branch = self.branches.last_mut().ok_or(())?;
+                branch.active = !branch.taken && condition;
+                branch.taken |= branch.active;
+            }
+            "ELSE" => {
+                let branch = self.branches.last_mut().ok_or(())?;
+                branch.active = !branch.taken;
+                branch.taken = true;
+            }
+            "ENDIF" | "END" => {
+                self.branches.pop().ok_or(())?;
+            }
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+}
+
+/// Parses a two-character base-36 index like `0Z`.
+fn parse_base36(value: &str) -> Option<u16> {
+    if value.len() != 2 || !value.bytes().all(|x| x.is_ascii_alphanumeric()) {
+        return None;
+    }
+    u16::from_str_radix(value, 36).ok()
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+impl Bms {
+    /// Returns the most likely layout of the map.
+    ///
+    /// `.pms` maps can't always be told apart from 10K maps, so prefer [`Layout::PopN9K`] for
+    /// files with the `.pms` extension.
+    pub fn detect_layout(&self) -> Layout {
+        let is_used = |channel: u8| {
+            self.events
+                .iter()
+                .any(|x| x.channel == channel || x.channel == channel + LONG_NOTE_CHANNEL_OFFSET)
+        };
+
+        let is_double = self.player == Some(3) || (21..=29).any(is_used);
+        let is_seven = [18, 19, 28, 29].iter().any(|&x| is_used(x));
+
+        let only_pop_n_channels = (11..=29)
+            .filter(|x| !matches!(x, 11..=15 | 22..=25))
+            .all(|x| !is_used(x));
+        if only_pop_n_channels && (22..=25).any(is_used) {
+            return Layout::PopN9K;
+        }
+
+        match (is_double, is_seven) {
+            (false, false) => Layout::Beat5K,
+            (false, true) => Layout::Beat7K,
+            (true, false) => Layout::Beat10K,
+            (true, true) => Layout::Beat14K,
+        }
+    }
+
+    /// Converts the map into a `Map` with the given layout.
+    ///
+    /// Notes in channels that `layout` doesn't have are left out. Objects overlapping an earlier
+    /// object in the same lane are left out too, as they can't be played.
+    pub fn convert(&self, layout: Layout) -> Conversion {
+        let timing = Timing::new(self);
+
+        // Objects.
+        let mut lane_events: Vec<Vec<(f64, &Event)>> = vec![Vec::new(); layout.lane_count()];
+        for event in &self.events {
+            if event.value == 0 {
+                continue;
+            }
+
+            if let Some(lane) = layout.lane(event.channel) {
+                lane_events[lane].push((timing.event_beat(event), event));
+            }
+        }
+
+        let mut lanes = Vec::with_capacity(layout.lane_count());
+        let mut keysounds = Vec::with_capacity(layout.lane_count());
+        for mut events in lane_events {
+            events.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut objects: Vec<(Object, Option<String>)> = Vec::new();
+            // Start of the long note from a long note channel.
+            let mut long_note_start: Option<(f64, u16)> = None;
+            for (beat, event) in events {
+                let keysound = |value| self.wavs.get(&value).cloned();
+                let timestamp = timing.timestamp(beat);
+
+                if event.channel > LONG_NOTE_CHANNEL_OFFSET {
+                    match long_note_start.take() {
+                        Some((start, value)) => objects.push((
+                            Object::LongNote {
+                                start: timing.timestamp(start),
+                                end: timestamp,
+                            },
+                            keysound(value),
+                        )),
+                        None => long_note_start = Some((beat, event.value)),
+                    }
+                } else if Some(event.value) == self.ln_obj {
+                    // Turn the previous regular object into a long note ending here.
+                    if let Some((object, _)) = objects.last_mut() {
+                        if let Object::Regular { timestamp: start } = *object {
+                            *object = Object::LongNote {
+                                start,
+                                end: timestamp,
+                            };
+                        }
+                    }
+                } else {
+                    objects.push((Object::Regular { timestamp }, keysound(event.value)));
+                }
+            }
+
+            // A long note without an end is played as a regular object.
+            if let Some((start, value)) = long_note_start {
+                objects.push((
+                    Object::Regular {
+                        timestamp: timing.timestamp(start),
+                    },
+                    self.wavs.get(&value).cloned(),
+                ));
+            }
+
+            objects.sort_by_key(|(object, _)| object.start_timestamp());
+
+            let mut lane = Lane::new();
+            let mut lane_keysounds = Vec::with_capacity(objects.len());
+            for (object, keysound) in objects {
+                if let Some(last) = lane.objects.last() {
+                    if last.end_timestamp() >= object.start_timestamp() {
+                        continue;
+                    }
+                }
+
+                lane.objects.push(object);
+                lane_keysounds.push(keysound);
+            }
+
+            lanes.push(lane);
+            keysounds.push(lane_keysounds);
+        }
+
+        let mut background_keysounds: Vec<_> = self
+            .events
+            .iter()
+            .filter(|x| x.channel == CHANNEL_BGM)
+            .filter_map(|x| {
+                Some(Keysound {
+                    timestamp: timing.timestamp(timing.event_beat(x)),
+                    file: self.wavs.get(&x.value)?.clone(),
+                })
+            })
+            .collect();
+        background_keysounds.sort_by_key(|x| x.timestamp);
+
+        let mut map = Map {
+            song_artist: self.artist.clone(),
+            song_title: self.title.clone(),
+            difficulty_name: match self.difficulty {
+                Some(1) => Some("Beginner".to_owned()),
+                Some(2) => Some("Normal".to_owned()),
+                Some(3) => Some("Hyper".to_owned()),
+                Some(4) => Some("Another".to_owned()),
+                Some(5) => Some("Insane".to_owned()),
+                _ => self.subtitle.clone(),
+            },
+            mapper: None,
+            background_file: self.back_bmp.clone().or_else(|| self.stage_file.clone()),
+            audio_file: None,
+            timing_points: timing.timing_points(self),
+            scroll_speed_changes: timing.scroll_speed_changes(),
+            initial_scroll_speed_multiplier: timing.multiplier(timing.initial_bpm),
+            scroll_groups: Vec::new(),
+            lanes,
+        };
+        map.sort_and_dedup_timing_points();
+        map.sort_and_dedup_scroll_speed_changes();
+
+        Conversion {
+            map,
+            keysounds,
+            background_keysounds,
+        }
+    }
+}
+
+/// Beat-to-time conversion of a `Bms`.
+struct Timing {
+    /// Start beat of every measure, plus the end of the last one.
+    measure_starts: Vec<f64>,
+    initial_bpm: f64,
+    /// BPM changes sorted by beat.
+    bpms: Vec<(f64, f64)>,
+    /// Stops in seconds, sorted by beat.
+    stops: Vec<(f64, f64)>,
+    base_bpm: f64,
+}
+
+impl Timing {
+    fn new(bms: &Bms) -> Self {
+        let measure_count = bms.events.iter().map(|x| x.measure + 1).max().unwrap_or(0);
+
+        let mut measure_starts = Vec::with_capacity(measure_count as usize + 1);
+        let mut beat = 0.;
+        for measure in 0..=measure_count {
+            measure_starts.push(beat);
+            beat += 4. * bms.measure_length(measure);
+        }
+
+        let initial_bpm = bms
+            .bpm
+            .filter(|&x| x > 0. && x.is_finite())
+            .unwrap_or(DEFAULT_BPM);
+
+        let mut timing = Self {
+            measure_starts,
+            initial_bpm,
+            bpms: Vec::new(),
+            stops: Vec::new(),
+            base_bpm: initial_bpm,
+        };
+
+        let mut bpms: Vec<_> = bms
+            .events
+            .iter()
+            .filter_map(|x| {
+                let bpm = match x.channel {
+                    CHANNEL_BPM => f64::from(x.value),
+                    CHANNEL_EXTENDED_BPM => *bms.bpms.get(&x.value)?,
+                    _ => return None,
+                };
+                Some((timing.event_beat(x), bpm))
+            })
+            .filter(|&(_, bpm)| bpm > 0. && bpm.is_finite())
+            .collect();
+        bpms.sort_by(|a, b| a.0.total_cmp(&b.0));
+        timing.bpms = bpms;
+
+        let mut stops: Vec<_> = bms
+            .events
+            .iter()
+            .filter(|x| x.channel == CHANNEL_STOP)
+            .filter_map(|x| {
+                let beat = timing.event_beat(x);
+                let beats = bms.stops.get(&x.value)? / 48.;
+                Some((beat, beats * 60. / timing.bpm_at(beat)))
+            })
+            .filter(|&(_, duration)| duration > 0. && duration.is_finite())
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        timing.stops = stops;
+
+        timing.base_bpm = timing.compute_base_bpm();
+        timing
+    }
+
+    fn event_beat(&self, event: &Event) -> f64 {
+        let measure = event.measure as usize;
+        let start = self.measure_starts[measure];
+        let length = self.measure_starts[measure + 1] - start;
+        start + length * f64::from(event.index) / f64::from(event.count)
+    }
+
+    /// Returns the BPM in effect at `beat`.
+    fn bpm_at(&self, beat: f64) -> f64 {
+        self.bpms
+            .iter()
+            .take_while(|x| x.0 <= beat)
+            .last()
+            .map_or(self.initial_bpm, |x| x.1)
+    }
+
+    /// Returns the BPM which is in effect the longest.
+    fn compute_base_bpm(&self) -> f64 {
+        let end = *self.measure_starts.last().unwrap();
+
+        let mut durations: Vec<(f64, f64)> = Vec::new();
+        let mut last = (0., self.initial_bpm);
+        for &(beat, bpm) in self.bpms.iter().chain(Some(&(end, 0.))) {
+            let duration = (beat - last.0) * 60. / last.1;
+            #[allow(clippy::float_cmp)]
+            match durations.iter_mut().find(|(value, _)| *value == last.1) {
+                Some((_, total)) => *total += duration,
+                None => durations.push((last.1, duration)),
+            }
+            last = (beat, bpm);
+        }
+
+        durations
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(self.initial_bpm, |(value, _)| value)
+    }
+
+    /// Returns the time in seconds of notes on `beat`, before the stops on `beat`.
+    fn time(&self, beat: f64) -> f64 {
+        let mut time = 0.;
+        let mut last = (0., self.initial_bpm);
+        for &(change_beat, bpm) in &self.bpms {
+            if change_beat >= beat {
+                break;
+            }
+            time += (change_beat - last.0) * 60. / last.1;
+            last = (change_beat, bpm);
+        }
+        time += (beat - last.0) * 60. / last.1;
+
+        let stops: f64 = self
+            .stops
+            .iter()
+            .take_while(|x| x.0 < beat)
+            .map(|x| x.1)
+            .sum();
+
+        time + stops
+    }
+
+    fn timestamp(&self, beat: f64) -> MapTimestamp {
+        MapTimestamp::saturating_from_secs_f64(self.time(beat))
+    }
+
+    /// Returns the total stop duration on `beat`.
+    fn stop_at(&self, beat: f64) -> f64 {
+        #[allow(clippy::float_cmp)]
+        self.stops.iter().filter(|x| x.0 == beat).map(|x| x.1).sum()
+    }
+
+    fn multiplier(&self, bpm: f64) -> ScrollSpeedMultiplier {
+        ScrollSpeedMultiplier::saturating_from_f32((bpm / self.base_bpm) as f32)
+    }
+
+    fn timing_points(&self, bms: &Bms) -> Vec<TimingPoint> {
+        let timing_point = |beat: f64, time: f64| {
+            let measure = self
+                .measure_starts
+                .iter()
+                .rposition(|&x| x <= beat)
+                .unwrap_or(0) as u32;
+
+            TimingPoint {
+                timestamp: MapTimestamp::saturating_from_secs_f64(time),
+                beat_duration: MapTimestampDifference::from_milli_hundredths(
+                    (60_000_00. / self.bpm_at(beat))
+                        .round()
+                        .min(f64::from(i32::MAX)) as i32,
+                ),
+                signature: TimeSignature {
+                    beat_count: (4. * bms.measure_length(measure)).round().clamp(1., 255.) as u8,
+                    beat_unit: 4,
+                },
+            }
+        };
+
+        let mut timing_points = vec![timing_point(0., self.time(0.))];
+
+        for &(beat, _) in &self.bpms {
+            timing_points.push(timing_point(beat, self.time(beat)));
+        }
+
+        // Stops shift the beats, so restart the beat lines after them.
+        for &(beat, _) in &self.stops {
+            timing_points.push(timing_point(beat, self.time(beat) + self.stop_at(beat)));
+        }
+
+        // Restart the beat lines on every measure length change.
+        let mut last_length = 1.;
+        for (measure, &beat) in self.measure_starts.iter().enumerate() {
+            let length = bms.measure_length(measure as u32);
+            #[allow(clippy::float_cmp)]
+            if length != last_length {
+                timing_points.push(timing_point(beat, self.time(beat) + self.stop_at(beat)));
+                last_length = length;
+            }
+        }
+
+        timing_points
+    }
+
+    fn scroll_speed_changes(&self) -> Vec<ScrollSpeedChange> {
+        let mut changes = Vec::new();
+
+        for &(beat, bpm) in &self.bpms {
+            changes.push((beat, self.time(beat), self.multiplier(bpm)));
+        }
+
+        for &(beat, duration) in &self.stops {
+            let time = self.time(beat);
+            changes.push((beat, time, ScrollSpeedMultiplier::new(0)));
+            changes.push((beat, time + duration, self.multiplier(self.bpm_at(beat))));
+        }
+
+        // Keep the changes in order for the same timestamp, so that the stops come last.
+        changes.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        changes
+            .into_iter()
+            .map(|(_, time, multiplier)| ScrollSpeedChange {
+                timestamp: MapTimestamp::saturating_from_secs_f64(time),
+                multiplier,
+            })
+            .collect()
+    }
+}
+
+impl Bms {
+    /// Returns the length of `measure` relative to 4/4.
+    #[inline]
+    pub fn measure_length(&self, measure: u32) -> f64 {
+        self.measure_lengths
+            .get(&measure)
+            .copied()
+            .filter(|&x| x > 0. && x.is_finite())
+            .unwrap_or(1.)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), ()> {
+        // Channel data: #mmmcc:data
+        let bytes = line.as_bytes();
+        if bytes.len() > 6 && bytes[6] == b':' && bytes[1..4].iter().all(u8::is_ascii_digit) {
+            let measure: u32 = line[1..4].parse().map_err(|_| ())?;
+            let channel = &line[4..6];
+            let data = line[7..].trim();
+
+            // Skip channels which aren't decimal, like the various extensions.
+            let channel: u8 = match channel.parse() {
+                Ok(x) => x,
+                Err(_) => return Ok(()),
+            };
+
+            if channel == CHANNEL_MEASURE_LENGTH {
+                let length = data.parse().map_err(|_| ())?;
+                self.measure_lengths.insert(measure, length);
+                return Ok(());
+            }
+
+            let data: Vec<_> = data.chars().filter(|c| !c.is_whitespace()).collect();
+            if data.len() % 2 != 0 {
+                return Err(());
+            }
+
+            let count = (data.len() / 2) as u32;
+            for (index, pair) in data.chunks(2).enumerate() {
+                let pair: String = pair.iter().collect();
+                let value = if channel == CHANNEL_BPM {
+                    u16::from_str_radix(&pair, 16).map_err(|_| ())?
+                } else {
+                    parse_base36(&pair).ok_or(())?
+                };
+
+                if value != 0 {
+                    self.events.push(Event {
+                        measure,
+                        channel,
+                        index: index as u32,
+                        count,
+                        value,
+                    });
+                }
+            }
+
+            return Ok(());
+        }
+
+        // Header: #KEY value
+        let line = &line[1..];
+        let (key, value) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+        let key = key.to_ascii_uppercase();
+
+        let number = || value.parse::<f64>().map_err(|_| ());
+        let int = || value.parse::<i32>().map_err(|_| ());
+
+        match &key[..] {
+            "TITLE" => self.title = parse_string(value),
+            "SUBTITLE" => self.subtitle = parse_string(value),
+            "ARTIST" => self.artist = parse_string(value),
+            "GENRE" => self.genre = parse_string(value),
+            "PLAYER" => self.player = Some(int()?),
+            "DIFFICULTY" => self.difficulty = Some(int()?),
+            "PLAYLEVEL" => self.play_level = Some(int()?),
+            "STAGEFILE" => self.stage_file = parse_string(value),
+            "BACKBMP" => self.back_bmp = parse_string(value),
+            "BPM" => self.bpm = Some(number()?),
+            "LNOBJ" => self.ln_obj = Some(parse_base36(value).ok_or(())?),
+            _ => {
+                if let Some(index) = key.strip_prefix("WAV").and_then(parse_base36) {
+                    self.wavs.insert(index, value.to_owned());
+                } else if let Some(index) = key.strip_prefix("BPM").and_then(parse_base36) {
+                    self.bpms.insert(index, number()?);
+                } else if let Some(index) = key.strip_prefix("STOP").and_then(parse_base36) {
+                    self.stops.insert(index, number()?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Deserializes a `Bms` from an IO stream.
+///
+/// `#RANDOM` blocks are evaluated with a pseudorandom number generator initialized with `seed`,
+/// so the same seed always produces the same `Bms`.
+pub fn from_reader<R: Read>(mut reader: R, seed: u64) -> Result<Bms, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    // Plenty of BMS files are in Shift JIS; their metadata can't be read, but everything else
+    // can.
+    let text = String::from_utf8_lossy(&bytes);
+
+    let mut bms = Bms::default();
+    let mut random = Random {
+        rng: SplitMix64::new(seed),
+        values: Vec::new(),
+        branches: Vec::new(),
+    };
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if !line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line = |_| Error::InvalidLine(index + 1);
+
+        let command = &line[1..];
+        let (key, value) = match command.find(char::is_whitespace) {
+            Some(index) => (&command[..index], &command[index..]),
+            None => (command, ""),
+        };
+        if random
+            .command(&key.to_ascii_uppercase(), value)
+            .map_err(invalid_line)?
+        {
+            continue;
+        }
+
+        if random.is_active() {
+            bms.parse_line(line).map_err(invalid_line)?;
+        }
+    }
+
+    Ok(bms)
+}
@@ -0,0 +1,319 @@
+use std::{collections::BTreeMap, fs::File};
+
+extern crate plitki_map_bms;
+use plitki_map_bms::{from_reader, Bms, Error, Event, Keysound, Layout};
+
+use plitki_core::{
+    judgement::HitWindows,
+    map::{Lane, Map, ScrollSpeedChange, TimeSignature, TimingPoint},
+    object::Object,
+    scroll::ScrollSpeedMultiplier,
+    state::GameState,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+use pretty_assertions::assert_eq;
+use proptest::prelude::*;
+
+fn event(measure: u32, channel: u8, index: u32, count: u32, value: u16) -> Event {
+    Event {
+        measure,
+        channel,
+        index,
+        count,
+        value,
+    }
+}
+
+fn timing_point(millis: i32, beat_duration: i32, beat_count: u8) -> TimingPoint {
+    TimingPoint {
+        timestamp: MapTimestamp::from_millis(millis),
+        beat_duration: MapTimestampDifference::from_millis(beat_duration),
+        signature: TimeSignature {
+            beat_count,
+            beat_unit: 4,
+        },
+    }
+}
+
+fn scroll_speed_change(millis: i32, multiplier: i32) -> ScrollSpeedChange {
+    ScrollSpeedChange {
+        timestamp: MapTimestamp::from_millis(millis),
+        multiplier: ScrollSpeedMultiplier::new(multiplier),
+    }
+}
+
+fn regular(millis: i32) -> Object {
+    Object::Regular {
+        timestamp: MapTimestamp::from_millis(millis),
+    }
+}
+
+fn long_note(start: i32, end: i32) -> Object {
+    Object::LongNote {
+        start: MapTimestamp::from_millis(start),
+        end: MapTimestamp::from_millis(end),
+    }
+}
+
+fn wav(file: &str) -> Option<String> {
+    Some(file.to_owned())
+}
+
+#[test]
+fn parse_sample() {
+    let file = File::open("tests/data/sample.bme").unwrap();
+    let bms = from_reader(file, 0).unwrap();
+
+    let gt = Bms {
+        title: Some("Sample Map".to_owned()),
+        subtitle: None,
+        artist: Some("Unknown".to_owned()),
+        genre: Some("Test".to_owned()),
+        player: Some(1),
+        difficulty: Some(2),
+        play_level: Some(3),
+        stage_file: Some("stage.png".to_owned()),
+        back_bmp: None,
+        bpm: Some(120.),
+        ln_obj: Some(36 * 36 - 1),
+        wavs: vec![
+            (1, "kick.wav".to_owned()),
+            (2, "snare.wav".to_owned()),
+            (3, "hat.wav".to_owned()),
+            (4, "song.ogg".to_owned()),
+        ]
+        .into_iter()
+        .collect(),
+        bpms: vec![(1, 240.)].into_iter().collect(),
+        stops: vec![(1, 48.)].into_iter().collect(),
+        measure_lengths: vec![(4, 0.75)].into_iter().collect::<BTreeMap<_, _>>(),
+        events: vec![
+            event(0, 1, 0, 1, 4),
+            event(1, 11, 0, 4, 1),
+            event(1, 11, 2, 4, 2),
+            event(1, 13, 0, 4, 2),
+            event(1, 13, 2, 4, 36 * 36 - 1),
+            event(1, 16, 0, 1, 3),
+            event(1, 52, 0, 4, 1),
+            event(1, 52, 2, 4, 1),
+            event(2, 8, 1, 2, 1),
+            event(3, 9, 0, 1, 1),
+            event(3, 14, 0, 1, 3),
+            event(3, 15, 1, 2, 3),
+            event(4, 3, 0, 1, 0x78),
+            event(4, 18, 0, 1, 1),
+            event(5, 19, 0, 1, 2),
+            event(5, 12, 0, 1, 2),
+        ],
+    };
+
+    assert_eq!(bms, gt);
+    assert_eq!(bms.detect_layout(), Layout::Beat7K);
+}
+
+#[test]
+fn convert_sample() {
+    let file = File::open("tests/data/sample.bme").unwrap();
+    let bms = from_reader(file, 0).unwrap();
+    let conversion = bms.convert(Layout::Beat7K);
+
+    let gt = Map {
+        song_artist: Some("Unknown".to_owned()),
+        song_title: Some("Sample Map".to_owned()),
+        difficulty_name: Some("Normal".to_owned()),
+        mapper: None,
+        background_file: Some("stage.png".to_owned()),
+        audio_file: None,
+        timing_points: vec![
+            timing_point(0, 500, 4),
+            timing_point(5000, 250, 4),
+            timing_point(5750, 250, 4),
+            timing_point(6750, 500, 3),
+            timing_point(8250, 500, 4),
+        ],
+        scroll_speed_changes: vec![
+            scroll_speed_change(5000, 2000),
+            scroll_speed_change(5500, 0),
+            scroll_speed_change(5750, 2000),
+            scroll_speed_change(6750, 1000),
+        ],
+        initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1000),
+        scroll_groups: vec![],
+        lanes: vec![
+            Lane {
+                objects: vec![regular(2000)],
+            },
+            Lane {
+                objects: vec![regular(2000), regular(3000)],
+            },
+            Lane {
+                objects: vec![long_note(2000, 3000), regular(8250)],
+            },
+            Lane {
+                objects: vec![long_note(2000, 3000)],
+            },
+            Lane {
+                objects: vec![regular(5500)],
+            },
+            Lane {
+                objects: vec![regular(6250)],
+            },
+            Lane {
+                objects: vec![regular(6750)],
+            },
+            Lane {
+                objects: vec![regular(8250)],
+            },
+        ],
+    };
+
+    assert_eq!(conversion.map, gt);
+    assert_eq!(
+        conversion.keysounds,
+        vec![
+            vec![wav("hat.wav")],
+            vec![wav("kick.wav"), wav("snare.wav")],
+            vec![wav("kick.wav"), wav("snare.wav")],
+            vec![wav("snare.wav")],
+            vec![wav("hat.wav")],
+            vec![wav("hat.wav")],
+            vec![wav("kick.wav")],
+            vec![wav("snare.wav")],
+        ]
+    );
+    assert_eq!(
+        conversion.background_keysounds,
+        vec![Keysound {
+            timestamp: MapTimestamp::from_millis(0),
+            file: "song.ogg".to_owned(),
+        }]
+    );
+}
+
+#[test]
+fn random_depends_on_seed() {
+    let text = "#RANDOM 2\n#IF 1\n#00111:01\n#ELSE\n#00112:01\n#ENDIF\n#ENDRANDOM\n";
+
+    let channels: Vec<_> = (0..16)
+        .map(|seed| from_reader(text.as_bytes(), seed).unwrap().events[0].channel)
+        .collect();
+    assert!(channels.contains(&11));
+    assert!(channels.contains(&12));
+
+    for seed in 0..16 {
+        assert_eq!(
+            from_reader(text.as_bytes(), seed).unwrap(),
+            from_reader(text.as_bytes(), seed).unwrap()
+        );
+    }
+}
+
+#[test]
+fn nested_random() {
+    let text = "\
+#SETRANDOM 2
+#IF 1
+#00111:01
+#ELSEIF 2
+#SETRANDOM 1
+#IF 1
+#00112:01
+#ENDIF
+#ENDRANDOM
+#00113:01
+#ELSE
+#00114:01
+#ENDIF
+#ENDRANDOM
+";
+    let bms = from_reader(text.as_bytes(), 0).unwrap();
+    let channels: Vec<_> = bms.events.iter().map(|x| x.channel).collect();
+    assert_eq!(channels, vec![12, 13]);
+}
+
+#[test]
+fn detect_layout() {
+    let layout = |text: &str| from_reader(text.as_bytes(), 0).unwrap().detect_layout();
+
+    assert_eq!(layout("#00111:01\n#00116:01\n"), Layout::Beat5K);
+    assert_eq!(layout("#00111:01\n#00159:0101\n"), Layout::Beat7K);
+    assert_eq!(layout("#00111:01\n#00126:01\n"), Layout::Beat10K);
+    assert_eq!(layout("#PLAYER 3\n#00118:01\n"), Layout::Beat14K);
+    assert_eq!(layout("#00111:01\n#00125:01\n"), Layout::PopN9K);
+}
+
+#[test]
+fn unterminated_long_note_is_regular() {
+    let bms = from_reader("#BPM 60\n#00151:0001\n".as_bytes(), 0).unwrap();
+    let conversion = bms.convert(Layout::Beat5K);
+    assert_eq!(conversion.map.lanes[1].objects, vec![regular(6000)]);
+}
+
+#[test]
+fn parse_errors() {
+    let error = from_reader("#TITLE Foo\n#00111:010\n".as_bytes(), 0).unwrap_err();
+    assert!(matches!(error, Error::InvalidLine(2)));
+
+    let error = from_reader("#BPM fast\n".as_bytes(), 0).unwrap_err();
+    assert!(matches!(error, Error::InvalidLine(1)));
+
+    let error = from_reader("#ENDIF\n".as_bytes(), 0).unwrap_err();
+    assert!(matches!(error, Error::InvalidLine(1)));
+}
+
+prop_compose! {
+    fn arbitrary_channel_line()(
+        measure in 0..20u32,
+        channel in prop::sample::select(vec![
+            1u8, 3, 8, 9, 11, 12, 13, 14, 15, 16, 18, 19, 21, 22, 23, 24, 25, 26, 28, 29, 51, 52,
+            56, 62, 69,
+        ]),
+        values in prop::collection::vec(0..4u16, 1..8),
+    ) -> String {
+        let data: String = values.iter().map(|x| format!("{:02}", x)).collect();
+        format!("#{:03}{:02}:{}\n", measure, channel, data)
+    }
+}
+
+prop_compose! {
+    fn arbitrary_bms()(
+        bpm in 1..300u32,
+        ln_obj in 0..4u16,
+        measure_lengths in prop::collection::vec((0..20u32, 1..16u32), 0..4),
+        lines in prop::collection::vec(arbitrary_channel_line(), 0..64),
+    ) -> String {
+        let mut text = format!(
+            "#BPM {}\n#LNOBJ {:02}\n#WAV01 a.wav\n#WAV02 b.wav\n#BPM01 180\n#STOP01 96\n#STOP02 0\n",
+            bpm,
+            ln_obj,
+        );
+        for (measure, length) in measure_lengths {
+            text += &format!("#{:03}02:{}\n", measure, f64::from(length) / 8.);
+        }
+        text.extend(lines);
+        text
+    }
+}
+
+proptest! {
+    #[test]
+    fn converted_maps_are_playable(text in arbitrary_bms()) {
+        let bms = from_reader(text.as_bytes(), 0).unwrap();
+        let layout = bms.detect_layout();
+        let conversion = bms.convert(layout);
+
+        prop_assert_eq!(conversion.map.lane_count(), layout.lane_count());
+        for (lane, keysounds) in conversion.map.lanes.iter().zip(&conversion.keysounds) {
+            prop_assert_eq!(lane.objects.len(), keysounds.len());
+        }
+
+        prop_assert!(GameState::new(conversion.map, HitWindows::quaver_standard()).is_ok());
+    }
+
+    #[test]
+    fn from_reader_doesnt_panic(text in "\\PC*", seed: u64) {
+        if let Ok(bms) = from_reader(text.as_bytes(), seed) {
+            bms.convert(bms.detect_layout());
+        }
+    }
+}
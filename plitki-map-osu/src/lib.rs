@@ -0,0 +1,783 @@
+//! Reading and writing of the osu!mania `.osu` map format.
+//!
+//! Only the parts of the format relevant to osu!mania are kept: sections and keys which plitki
+//! doesn't use (`[Editor]`, `[Colours]`, storyboard events and so on) are skipped when reading.
+
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use std::{
+    convert::{TryFrom, TryInto},
+    error, fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+use plitki_core::{
+    map::{
+        denormalize_scroll_speed_changes, normalize_scroll_speed_changes, Lane, Map,
+        ScrollSpeedChange, TimeSignature,
+    },
+    object::Object,
+    scroll::ScrollSpeedMultiplier,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+
+/// The `Mode` value of osu!mania maps.
+pub const MODE_MANIA: i32 = 3;
+
+/// The `.osu` format version written by [`to_writer`].
+pub const FORMAT_VERSION: u32 = 14;
+
+/// Hit object type bit for regular objects (hit circles).
+const TYPE_CIRCLE: i32 = 1;
+/// Hit object type bit for osu!mania long notes (hold notes).
+const TYPE_HOLD: i32 = 128;
+
+/// Width of the osu! playfield, used for mapping hit object `x` to a column.
+const PLAYFIELD_WIDTH: i32 = 512;
+
+/// Highest lane count of osu!mania maps, with dual stages.
+pub const MAX_LANE_COUNT: usize = 18;
+
+/// Error returned when converting between a `.osu` and a `Map` fails.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConversionError {
+    /// The map is not an osu!mania map, contains the `Mode` value.
+    NotMania(i32),
+    /// The lane count (`CircleSize`) is not between 1 and [`MAX_LANE_COUNT`].
+    InvalidLaneCount(f32),
+    /// A timing point has a beat length which doesn't give a finite BPM or SV multiplier.
+    InvalidBeatLength(f64),
+    /// A timing point has a meter which doesn't fit into a time signature.
+    InvalidMeter(i32),
+    /// A time signature doesn't count quarter notes, which `.osu` can't represent.
+    UnsupportedTimeSignature(TimeSignature),
+    /// A time in milliseconds is NaN or doesn't fit into a `MapTimestamp`.
+    TimestampOutOfRange(f64),
+    /// A long note ends at or before its start.
+    InvalidLongNote { start_time: i32, end_time: i32 },
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::NotMania(mode) => write!(f, "not an osu!mania map: mode {}", mode),
+            ConversionError::InvalidLaneCount(circle_size) => {
+                write!(f, "invalid lane count: {}", circle_size)
+            }
+            ConversionError::InvalidBeatLength(beat_length) => {
+                write!(f, "invalid beat length: {}", beat_length)
+            }
+            ConversionError::InvalidMeter(meter) => write!(f, "invalid meter: {}", meter),
+            ConversionError::UnsupportedTimeSignature(signature) => write!(
+                f,
+                "unsupported time signature: {}/{}",
+                signature.beat_count, signature.beat_unit
+            ),
+            ConversionError::TimestampOutOfRange(time) => {
+                write!(f, "timestamp out of range: {} ms", time)
+            }
+            ConversionError::InvalidLongNote {
+                start_time,
+                end_time,
+            } => write!(
+                f,
+                "long note ends at or before its start: {} ms to {} ms",
+                start_time, end_time
+            ),
+        }
+    }
+}
+
+impl error::Error for ConversionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingPoint {
+    /// Start time in milliseconds.
+    pub time: f64,
+    /// Duration of a beat in milliseconds for uninherited timing points; negative inverse SV
+    /// multiplier in percent for inherited timing points.
+    pub beat_length: f64,
+    pub meter: i32,
+    pub sample_set: i32,
+    pub sample_index: i32,
+    pub volume: i32,
+    pub uninherited: bool,
+    pub effects: i32,
+}
+
+impl TimingPoint {
+    /// Returns the BPM of an uninherited timing point.
+    #[inline]
+    pub fn bpm(&self) -> f64 {
+        60_000. / self.beat_length
+    }
+
+    /// Returns the SV multiplier of an inherited timing point.
+    #[inline]
+    pub fn sv_multiplier(&self) -> f64 {
+        -100. / self.beat_length
+    }
+
+    fn uninherited(time: f64, beat_length: f64, meter: i32) -> Self {
+        Self {
+            time,
+            beat_length,
+            meter,
+            sample_set: 0,
+            sample_index: 0,
+            volume: 100,
+            uninherited: true,
+            effects: 0,
+        }
+    }
+
+    fn inherited(time: f64, sv_multiplier: f64) -> Self {
+        Self {
+            beat_length: -100. / sv_multiplier,
+            uninherited: false,
+            ..Self::uninherited(time, 0., 4)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HitObject {
+    pub x: i32,
+    pub y: i32,
+    /// Start time in milliseconds.
+    pub start_time: i32,
+    pub object_type: i32,
+    pub hit_sound: i32,
+    /// End time in milliseconds, only meaningful for long notes.
+    pub end_time: i32,
+    pub hit_sample: String,
+}
+
+impl HitObject {
+    /// Returns `true` if the hit object is a long note.
+    #[inline]
+    pub fn is_long_note(&self) -> bool {
+        self.object_type & TYPE_HOLD != 0
+    }
+
+    /// Returns the column of the hit object for the given lane count.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lane_count` is zero.
+    #[inline]
+    pub fn column(&self, lane_count: usize) -> usize {
+        assert!(lane_count > 0);
+
+        let x = i64::from(self.x.max(0));
+        let column = (x * lane_count as i64 / i64::from(PLAYFIELD_WIDTH)) as usize;
+        column.min(lane_count - 1)
+    }
+
+    /// Returns the `x` coordinate at the center of `column`.
+    #[inline]
+    pub fn column_x(column: usize, lane_count: usize) -> i32 {
+        (i64::from(PLAYFIELD_WIDTH) * (2 * column as i64 + 1) / (2 * lane_count as i64)) as i32
+    }
+}
+
+impl TryFrom<&HitObject> for Object {
+    type Error = ConversionError;
+
+    #[inline]
+    fn try_from(hit_object: &HitObject) -> Result<Self, Self::Error> {
+        let start = timestamp_from_millis(hit_object.start_time)?;
+
+        if hit_object.is_long_note() {
+            if hit_object.end_time <= hit_object.start_time {
+                return Err(ConversionError::InvalidLongNote {
+                    start_time: hit_object.start_time,
+                    end_time: hit_object.end_time,
+                });
+            }
+
+            Ok(Object::LongNote {
+                start,
+                end: timestamp_from_millis(hit_object.end_time)?,
+            })
+        } else {
+            Ok(Object::Regular { timestamp: start })
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Osu {
+    pub format_version: u32,
+
+    // [General]
+    pub audio_file: Option<String>,
+    pub audio_lead_in: i32,
+    pub preview_time: i32,
+    pub mode: i32,
+
+    // [Metadata]
+    pub title: Option<String>,
+    pub title_unicode: Option<String>,
+    pub artist: Option<String>,
+    pub artist_unicode: Option<String>,
+    pub creator: Option<String>,
+    pub version: Option<String>,
+    pub source: Option<String>,
+    pub tags: Option<String>,
+
+    // [Difficulty]
+    pub hp_drain_rate: f32,
+    /// Lane count for osu!mania maps.
+    pub circle_size: f32,
+    pub overall_difficulty: f32,
+
+    // [Events]
+    pub background_file: Option<String>,
+
+    pub timing_points: Vec<TimingPoint>,
+    pub hit_objects: Vec<HitObject>,
+}
+
+impl Default for Osu {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            audio_file: None,
+            audio_lead_in: 0,
+            preview_time: -1,
+            mode: 0,
+            title: None,
+            title_unicode: None,
+            artist: None,
+            artist_unicode: None,
+            creator: None,
+            version: None,
+            source: None,
+            tags: None,
+            hp_drain_rate: 5.,
+            circle_size: 5.,
+            overall_difficulty: 5.,
+            background_file: None,
+            timing_points: Vec::new(),
+            hit_objects: Vec::new(),
+        }
+    }
+}
+
+impl Osu {
+    /// Returns the lane count of an osu!mania map.
+    #[inline]
+    pub fn lane_count(&self) -> usize {
+        self.circle_size.round().max(1.) as usize
+    }
+}
+
+/// Converts a time in milliseconds into a `MapTimestamp`.
+fn timestamp(millis: f64) -> MapTimestamp {
+    MapTimestamp::saturating_from_milli_hundredths((millis * 100.).round() as i32)
+}
+
+/// Converts a time in milliseconds into a `MapTimestamp`, failing if it's out of range.
+fn checked_timestamp(millis: f64) -> Result<MapTimestamp, ConversionError> {
+    let milli_hundredths = (millis * 100.).round();
+    if !milli_hundredths.is_finite()
+        || milli_hundredths < f64::from(i32::MIN)
+        || milli_hundredths > f64::from(i32::MAX)
+    {
+        return Err(ConversionError::TimestampOutOfRange(millis));
+    }
+
+    MapTimestamp::checked_from_milli_hundredths(milli_hundredths as i32)
+        .ok_or(ConversionError::TimestampOutOfRange(millis))
+}
+
+/// Converts a time in whole milliseconds into a `MapTimestamp`, failing if it's out of range.
+fn timestamp_from_millis(millis: i32) -> Result<MapTimestamp, ConversionError> {
+    millis
+        .checked_mul(100)
+        .and_then(MapTimestamp::checked_from_milli_hundredths)
+        .ok_or(ConversionError::TimestampOutOfRange(f64::from(millis)))
+}
+
+/// Converts a `MapTimestamp` into a time in milliseconds.
+fn millis(timestamp: MapTimestamp) -> f64 {
+    f64::from(timestamp.into_milli_hundredths()) / 100.
+}
+
+/// Returns the end of the last object in the lanes.
+fn last_object_end(lanes: &[Lane]) -> Option<MapTimestamp> {
+    lanes
+        .iter()
+        .flat_map(|lane| &lane.objects)
+        .map(Object::end_timestamp)
+        .max()
+}
+
+impl TryFrom<Osu> for Map {
+    type Error = ConversionError;
+
+    #[inline]
+    fn try_from(osu: Osu) -> Result<Self, Self::Error> {
+        if osu.mode != MODE_MANIA {
+            return Err(ConversionError::NotMania(osu.mode));
+        }
+
+        let circle_size = osu.circle_size.round();
+        if !(1. ..=MAX_LANE_COUNT as f32).contains(&circle_size) {
+            return Err(ConversionError::InvalidLaneCount(osu.circle_size));
+        }
+
+        // NaNs and infinities can't be converted into integer time and scroll speed, so check for
+        // them first.
+        for x in &osu.timing_points {
+            checked_timestamp(x.time)?;
+
+            let value = if x.uninherited {
+                x.bpm()
+            } else {
+                x.sv_multiplier()
+            };
+            if !(value as f32).is_finite() {
+                return Err(ConversionError::InvalidBeatLength(x.beat_length));
+            }
+        }
+
+        let lane_count = osu.lane_count();
+        let mut lanes = vec![Lane::new(); lane_count];
+        for hit_object in &osu.hit_objects {
+            lanes[hit_object.column(lane_count)]
+                .objects
+                .push(hit_object.try_into()?);
+        }
+
+        let (uninherited, inherited): (Vec<_>, Vec<_>) =
+            osu.timing_points.iter().partition(|x| x.uninherited);
+
+        let timing_points: Vec<_> = uninherited
+            .iter()
+            .map(|x| {
+                Ok(plitki_core::map::TimingPoint {
+                    timestamp: timestamp(x.time),
+                    beat_duration: MapTimestampDifference::from_milli_hundredths(
+                        (x.beat_length * 100.).round() as i32,
+                    ),
+                    signature: TimeSignature {
+                        beat_count: u8::try_from(x.meter)
+                            .map_err(|_| ConversionError::InvalidMeter(x.meter))?,
+                        beat_unit: 4,
+                    },
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let svs: Vec<_> = inherited
+            .iter()
+            .map(|x| ScrollSpeedChange {
+                timestamp: timestamp(x.time),
+                multiplier: ScrollSpeedMultiplier::saturating_from_f32(x.sv_multiplier() as f32),
+            })
+            .collect();
+
+        // In osu! the BPM affects the scroll speed.
+        let (initial_scroll_speed_multiplier, scroll_speed_changes) =
+            normalize_scroll_speed_changes(&timing_points, &svs, last_object_end(&lanes));
+
+        Ok(Self {
+            song_artist: osu.artist,
+            song_title: osu.title,
+            difficulty_name: osu.version,
+            background_file: osu.background_file,
+            mapper: osu.creator,
+            audio_file: osu.audio_file,
+            timing_points,
+            scroll_speed_changes,
+            initial_scroll_speed_multiplier,
+            scroll_groups: Vec::new(),
+            lanes,
+        })
+    }
+}
+
+impl TryFrom<Map> for Osu {
+    type Error = ConversionError;
+
+    #[inline]
+    fn try_from(mut map: Map) -> Result<Self, Self::Error> {
+        // osu! has no scroll groups, so their objects scroll with the map's own SVs.
+        map.flatten_scroll_groups();
+
+        let lane_count = map.lane_count();
+
+        let mut timing_points: Vec<_> = map
+            .timing_points
+            .iter()
+            .map(|x| {
+                if x.signature.beat_unit != 4 {
+                    return Err(ConversionError::UnsupportedTimeSignature(x.signature));
+                }
+
+                Ok(TimingPoint::uninherited(
+                    millis(x.timestamp),
+                    f64::from(x.beat_duration.into_milli_hundredths()) / 100.,
+                    i32::from(x.signature.beat_count),
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let svs = denormalize_scroll_speed_changes(
+            &map.timing_points,
+            &map.scroll_speed_changes,
+            map.initial_scroll_speed_multiplier,
+            last_object_end(&map.lanes),
+        );
+        timing_points.extend(svs.iter().map(|x| {
+            TimingPoint::inherited(millis(x.timestamp), f64::from(x.multiplier.as_f32()))
+        }));
+
+        // Uninherited timing points come first, so the sort keeps them before inherited timing
+        // points at the same time, like osu! expects.
+        timing_points.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let mut hit_objects: Vec<_> = map
+            .lanes
+            .iter()
+            .enumerate()
+            .flat_map(|(column, lane)| {
+                let x = HitObject::column_x(column, lane_count);
+
+                lane.objects.iter().map(move |object| {
+                    let (object_type, end_time) = match *object {
+                        Object::Regular { .. } => (TYPE_CIRCLE, 0),
+                        Object::LongNote { end, .. } => (TYPE_HOLD, end.as_millis()),
+                    };
+
+                    HitObject {
+                        x,
+                        y: 192,
+                        start_time: object.start_timestamp().as_millis(),
+                        object_type,
+                        hit_sound: 0,
+                        end_time,
+                        hit_sample: "0:0:0:0:".to_owned(),
+                    }
+                })
+            })
+            .collect();
+        hit_objects.sort_by_key(|x| x.start_time);
+
+        Ok(Self {
+            mode: MODE_MANIA,
+            audio_file: map.audio_file,
+            title: map.song_title,
+            artist: map.song_artist,
+            creator: map.mapper,
+            version: map.difficulty_name,
+            circle_size: lane_count as f32,
+            background_file: map.background_file,
+            timing_points,
+            hit_objects,
+            ..Self::default()
+        })
+    }
+}
+
+/// Error returned when reading a `.osu` fails.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error occurred.
+    Io(io::Error),
+    /// The file doesn't start with an `osu file format` header.
+    InvalidHeader,
+    /// The line with this number (starting from 1) couldn't be parsed.
+    InvalidLine(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::InvalidHeader => f.write_str("missing or invalid osu file format header"),
+            Error::InvalidLine(line) => write!(f, "invalid line {}", line),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Section {
+    General,
+    Metadata,
+    Difficulty,
+    Events,
+    TimingPoints,
+    HitObjects,
+    Other,
+}
+
+/// Parses an integer, accepting (and truncating) fractional values like osu! does.
+fn parse_int(value: &str) -> Option<i32> {
+    let value = value.trim();
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<f64>().ok().map(|x| x as i32))
+}
+
+fn parse_timing_point(line: &str) -> Option<TimingPoint> {
+    let fields: Vec<_> = line.split(',').map(str::trim).collect();
+    if fields.len() < 2 {
+        return None;
+    }
+
+    let beat_length: f64 = fields[1].parse().ok()?;
+    let field = |index: usize, default: i32| {
+        fields
+            .get(index)
+            .map_or(Some(default), |value| parse_int(value))
+    };
+
+    Some(TimingPoint {
+        time: fields[0].parse().ok()?,
+        beat_length,
+        meter: field(2, 4)?,
+        sample_set: field(3, 0)?,
+        sample_index: field(4, 0)?,
+        volume: field(5, 100)?,
+        // Old files don't have this field and mark inherited timing points with a negative beat
+        // length instead.
+        uninherited: field(6, (beat_length >= 0.) as i32)? != 0,
+        effects: field(7, 0)?,
+    })
+}
+
+fn parse_hit_object(line: &str) -> Option<HitObject> {
+    let fields: Vec<_> = line.splitn(6, ',').collect();
+    if fields.len() < 5 {
+        return None;
+    }
+
+    let object_type = parse_int(fields[3])?;
+    let rest = fields.get(5).copied().unwrap_or("");
+
+    let (end_time, hit_sample) = if object_type & TYPE_HOLD != 0 {
+        let (end_time, hit_sample) = match rest.find(':') {
+            Some(index) => (&rest[..index], &rest[index + 1..]),
+            None => (rest, ""),
+        };
+        (parse_int(end_time)?, hit_sample)
+    } else {
+        (0, rest)
+    };
+
+    Some(HitObject {
+        x: parse_int(fields[0])?,
+        y: parse_int(fields[1])?,
+        start_time: parse_int(fields[2])?,
+        object_type,
+        hit_sound: parse_int(fields[4])?,
+        end_time,
+        hit_sample: hit_sample.trim().to_owned(),
+    })
+}
+
+fn parse_background(line: &str) -> Option<String> {
+    let fields: Vec<_> = line.split(',').map(str::trim).collect();
+    match fields[..] {
+        ["0", _, file, ..] | ["Background", _, file, ..] => Some(file.trim_matches('"').to_owned()),
+        _ => None,
+    }
+}
+
+/// Deserializes an `Osu` from an IO stream.
+pub fn from_reader<R: Read>(reader: R) -> Result<Osu, Error> {
+    let mut lines = BufReader::new(reader).lines().enumerate();
+
+    let mut osu = Osu::default();
+
+    // The header is the first non-empty line.
+    loop {
+        let line = match lines.next() {
+            Some((_, line)) => line?,
+            None => return Err(Error::InvalidHeader),
+        };
+        let line = line.trim_start_matches('\u{feff}').trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        osu.format_version = line
+            .strip_prefix("osu file format v")
+            .and_then(|version| version.parse().ok())
+            .ok_or(Error::InvalidHeader)?;
+        break;
+    }
+
+    let mut section = Section::Other;
+    for (index, line) in lines {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        let invalid_line = || Error::InvalidLine(index + 1);
+
+        if line.starts_with('[') && line.ends_with(']') {
+            section = match &line[1..line.len() - 1] {
+                "General" => Section::General,
+                "Metadata" => Section::Metadata,
+                "Difficulty" => Section::Difficulty,
+                "Events" => Section::Events,
+                "TimingPoints" => Section::TimingPoints,
+                "HitObjects" => Section::HitObjects,
+                _ => Section::Other,
+            };
+            continue;
+        }
+
+        match section {
+            Section::General | Section::Metadata | Section::Difficulty => {
+                let index = line.find(':').ok_or_else(invalid_line)?;
+                let key = line[..index].trim();
+                let value = line[index + 1..].trim();
+
+                let string = || Some(value.to_owned());
+                let int = || parse_int(value).ok_or_else(invalid_line);
+                let float = || value.parse().map_err(|_| invalid_line());
+
+                match (section, key) {
+                    (Section::General, "AudioFilename") => osu.audio_file = string(),
+                    (Section::General, "AudioLeadIn") => osu.audio_lead_in = int()?,
+                    (Section::General, "PreviewTime") => osu.preview_time = int()?,
+                    (Section::General, "Mode") => osu.mode = int()?,
+                    (Section::Metadata, "Title") => osu.title = string(),
+                    (Section::Metadata, "TitleUnicode") => osu.title_unicode = string(),
+                    (Section::Metadata, "Artist") => osu.artist = string(),
+                    (Section::Metadata, "ArtistUnicode") => osu.artist_unicode = string(),
+                    (Section::Metadata, "Creator") => osu.creator = string(),
+                    (Section::Metadata, "Version") => osu.version = string(),
+                    (Section::Metadata, "Source") => osu.source = string(),
+                    (Section::Metadata, "Tags") => osu.tags = string(),
+                    (Section::Difficulty, "HPDrainRate") => osu.hp_drain_rate = float()?,
+                    (Section::Difficulty, "CircleSize") => osu.circle_size = float()?,
+                    (Section::Difficulty, "OverallDifficulty") => osu.overall_difficulty = float()?,
+                    _ => {}
+                }
+            }
+            Section::Events => {
+                if osu.background_file.is_none() {
+                    osu.background_file = parse_background(line);
+                }
+            }
+            Section::TimingPoints => osu
+                .timing_points
+                .push(parse_timing_point(line).ok_or_else(invalid_line)?),
+            Section::HitObjects => osu
+                .hit_objects
+                .push(parse_hit_object(line).ok_or_else(invalid_line)?),
+            Section::Other => {}
+        }
+    }
+
+    Ok(osu)
+}
+
+/// Serializes an `Osu` into the IO stream.
+pub fn to_writer<W: Write>(writer: W, osu: &Osu) -> io::Result<()> {
+    let mut w = io::BufWriter::new(writer);
+
+    fn write_option<W: Write>(
+        w: &mut W,
+        key: &str,
+        separator: &str,
+        value: &Option<String>,
+    ) -> io::Result<()> {
+        if let Some(value) = value {
+            writeln!(w, "{}:{}{}", key, separator, value)?;
+        }
+        Ok(())
+    }
+
+    writeln!(w, "osu file format v{}", osu.format_version)?;
+
+    writeln!(w)?;
+    writeln!(w, "[General]")?;
+    write_option(&mut w, "AudioFilename", " ", &osu.audio_file)?;
+    writeln!(w, "AudioLeadIn: {}", osu.audio_lead_in)?;
+    writeln!(w, "PreviewTime: {}", osu.preview_time)?;
+    writeln!(w, "Mode: {}", osu.mode)?;
+
+    writeln!(w)?;
+    writeln!(w, "[Metadata]")?;
+    write_option(&mut w, "Title", "", &osu.title)?;
+    write_option(&mut w, "TitleUnicode", "", &osu.title_unicode)?;
+    write_option(&mut w, "Artist", "", &osu.artist)?;
+    write_option(&mut w, "ArtistUnicode", "", &osu.artist_unicode)?;
+    write_option(&mut w, "Creator", "", &osu.creator)?;
+    write_option(&mut w, "Version", "", &osu.version)?;
+    write_option(&mut w, "Source", "", &osu.source)?;
+    write_option(&mut w, "Tags", "", &osu.tags)?;
+
+    writeln!(w)?;
+    writeln!(w, "[Difficulty]")?;
+    writeln!(w, "HPDrainRate:{}", osu.hp_drain_rate)?;
+    writeln!(w, "CircleSize:{}", osu.circle_size)?;
+    writeln!(w, "OverallDifficulty:{}", osu.overall_difficulty)?;
+
+    writeln!(w)?;
+    writeln!(w, "[Events]")?;
+    writeln!(w, "//Background and Video events")?;
+    if let Some(background_file) = &osu.background_file {
+        writeln!(w, "0,0,\"{}\",0,0", background_file)?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "[TimingPoints]")?;
+    for x in &osu.timing_points {
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{},{}",
+            x.time,
+            x.beat_length,
+            x.meter,
+            x.sample_set,
+            x.sample_index,
+            x.volume,
+            x.uninherited as i32,
+            x.effects,
+        )?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "[HitObjects]")?;
+    for x in &osu.hit_objects {
+        write!(
+            w,
+            "{},{},{},{},{},",
+            x.x, x.y, x.start_time, x.object_type, x.hit_sound
+        )?;
+        if x.is_long_note() {
+            write!(w, "{}:", x.end_time)?;
+        }
+        writeln!(w, "{}", x.hit_sample)?;
+    }
+
+    w.flush()
+}
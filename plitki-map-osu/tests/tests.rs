@@ -0,0 +1,645 @@
+use std::{cmp::Ordering, convert::TryFrom, fs::File};
+
+extern crate plitki_map_osu;
+use plitki_map_osu::{
+    from_reader, to_writer, ConversionError, Error, HitObject, Osu, TimingPoint, MODE_MANIA,
+};
+
+use plitki_core::{
+    map::{Lane, Map, ScrollSpeedChange, TimeSignature},
+    object::Object,
+    scroll::ScrollSpeedMultiplier,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+use pretty_assertions::assert_eq;
+use proptest::prelude::*;
+
+fn uninherited(time: f64, beat_length: f64, meter: i32) -> TimingPoint {
+    TimingPoint {
+        time,
+        beat_length,
+        meter,
+        sample_set: 0,
+        sample_index: 0,
+        volume: 100,
+        uninherited: true,
+        effects: 0,
+    }
+}
+
+fn inherited(time: f64, beat_length: f64) -> TimingPoint {
+    TimingPoint {
+        meter: 4,
+        uninherited: false,
+        ..uninherited(time, beat_length, 4)
+    }
+}
+
+fn circle(x: i32, start_time: i32) -> HitObject {
+    HitObject {
+        x,
+        y: 192,
+        start_time,
+        object_type: 1,
+        hit_sound: 0,
+        end_time: 0,
+        hit_sample: "0:0:0:0:".to_owned(),
+    }
+}
+
+fn hold(x: i32, start_time: i32, end_time: i32) -> HitObject {
+    HitObject {
+        object_type: 128,
+        end_time,
+        ..circle(x, start_time)
+    }
+}
+
+#[test]
+fn parse_sample() {
+    let file = File::open("tests/data/sample.osu").unwrap();
+    let osu = from_reader(file).unwrap();
+
+    let sample_timing_point = |x: TimingPoint| TimingPoint {
+        sample_set: 2,
+        volume: 70,
+        ..x
+    };
+
+    let gt = Osu {
+        format_version: 14,
+        audio_file: Some("song.mp3".to_owned()),
+        audio_lead_in: 0,
+        preview_time: 500,
+        mode: MODE_MANIA,
+        title: Some("Sample Map".to_owned()),
+        title_unicode: Some("Sample Map".to_owned()),
+        artist: Some("Unknown".to_owned()),
+        artist_unicode: Some("Unknown".to_owned()),
+        creator: Some("YaLTeR".to_owned()),
+        version: Some("Easy".to_owned()),
+        source: Some("".to_owned()),
+        tags: Some("sample test".to_owned()),
+        hp_drain_rate: 8.,
+        circle_size: 4.,
+        overall_difficulty: 7.5,
+        background_file: Some("background.png".to_owned()),
+        timing_points: vec![
+            sample_timing_point(uninherited(0., 600., 4)),
+            sample_timing_point(uninherited(200., 300., 3)),
+            sample_timing_point(TimingPoint {
+                meter: 3,
+                ..inherited(300., -50.)
+            }),
+            sample_timing_point(TimingPoint {
+                effects: 1,
+                ..uninherited(400., 300., 4)
+            }),
+            sample_timing_point(inherited(700., -200.)),
+        ],
+        hit_objects: vec![
+            circle(448, 0),
+            circle(64, 601),
+            circle(192, 601),
+            hold(320, 601, 939),
+            hold(448, 601, 939),
+            HitObject {
+                object_type: 5,
+                hit_sound: 2,
+                ..circle(64, 939)
+            },
+            hold(192, 939, 1278),
+            circle(320, 1278),
+            HitObject {
+                hit_sample: "1:0:0:0:".to_owned(),
+                ..hold(448, 1194, 1363)
+            },
+        ],
+    };
+
+    assert_eq!(osu, gt);
+}
+
+#[test]
+fn convert() {
+    let file = File::open("tests/data/sample.osu").unwrap();
+    let osu = from_reader(file).unwrap();
+    let map = Map::try_from(osu).unwrap();
+
+    let gt = Map {
+        song_artist: Some("Unknown".to_owned()),
+        song_title: Some("Sample Map".to_owned()),
+        difficulty_name: Some("Easy".to_owned()),
+        background_file: Some("background.png".to_owned()),
+        mapper: Some("YaLTeR".to_owned()),
+        audio_file: Some("song.mp3".to_owned()),
+        timing_points: vec![
+            plitki_core::map::TimingPoint {
+                timestamp: MapTimestamp::from_millis(0),
+                beat_duration: MapTimestampDifference::from_millis(600),
+                signature: TimeSignature {
+                    beat_count: 4,
+                    beat_unit: 4,
+                },
+            },
+            plitki_core::map::TimingPoint {
+                timestamp: MapTimestamp::from_millis(200),
+                beat_duration: MapTimestampDifference::from_millis(300),
+                signature: TimeSignature {
+                    beat_count: 3,
+                    beat_unit: 4,
+                },
+            },
+            plitki_core::map::TimingPoint {
+                timestamp: MapTimestamp::from_millis(400),
+                beat_duration: MapTimestampDifference::from_millis(300),
+                signature: TimeSignature {
+                    beat_count: 4,
+                    beat_unit: 4,
+                },
+            },
+        ],
+        scroll_speed_changes: vec![
+            ScrollSpeedChange {
+                timestamp: MapTimestamp::from_millis(200),
+                multiplier: ScrollSpeedMultiplier::default(),
+            },
+            ScrollSpeedChange {
+                timestamp: MapTimestamp::from_millis(300),
+                multiplier: ScrollSpeedMultiplier::new(2000),
+            },
+            ScrollSpeedChange {
+                timestamp: MapTimestamp::from_millis(400),
+                multiplier: ScrollSpeedMultiplier::default(),
+            },
+            ScrollSpeedChange {
+                timestamp: MapTimestamp::from_millis(700),
+                multiplier: ScrollSpeedMultiplier::new(500),
+            },
+        ],
+        initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(500),
+        scroll_groups: vec![],
+        lanes: vec![
+            Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(601),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(939),
+                    },
+                ],
+            },
+            Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(601),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(939),
+                        end: MapTimestamp::from_millis(1278),
+                    },
+                ],
+            },
+            Lane {
+                objects: vec![
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(601),
+                        end: MapTimestamp::from_millis(939),
+                    },
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(1278),
+                    },
+                ],
+            },
+            Lane {
+                objects: vec![
+                    Object::Regular {
+                        timestamp: MapTimestamp::from_millis(0),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(601),
+                        end: MapTimestamp::from_millis(939),
+                    },
+                    Object::LongNote {
+                        start: MapTimestamp::from_millis(1194),
+                        end: MapTimestamp::from_millis(1363),
+                    },
+                ],
+            },
+        ],
+    };
+
+    assert_eq!(map, gt);
+}
+
+#[test]
+fn column_math() {
+    // 7K column boundaries.
+    let columns: Vec<_> = [
+        0, 73, 74, 146, 147, 219, 220, 292, 293, 365, 366, 438, 439, 511, 512,
+    ]
+    .iter()
+    .map(|&x| circle(x, 0).column(7))
+    .collect();
+    assert_eq!(columns, [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 6]);
+
+    // Out of bounds positions are clamped.
+    assert_eq!(circle(-10, 0).column(4), 0);
+    assert_eq!(circle(1000, 0).column(4), 3);
+
+    for lane_count in 1..=18 {
+        for column in 0..lane_count {
+            let x = HitObject::column_x(column, lane_count);
+            assert_eq!(circle(x, 0).column(lane_count), column);
+        }
+    }
+}
+
+#[test]
+fn old_timing_points() {
+    let osu = from_reader(
+        &b"osu file format v3\n\n[TimingPoints]\n100.5,500\n200,-50,4\n\n[HitObjects]\n"[..],
+    )
+    .unwrap();
+
+    assert_eq!(osu.format_version, 3);
+    assert_eq!(
+        osu.timing_points,
+        vec![uninherited(100.5, 500., 4), inherited(200., -50.)]
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert!(matches!(from_reader(&b""[..]), Err(Error::InvalidHeader)));
+    assert!(matches!(
+        from_reader(&b"[General]\nMode: 3\n"[..]),
+        Err(Error::InvalidHeader)
+    ));
+    assert!(matches!(
+        from_reader(&b"osu file format v14\n\n[General]\nMode: mania\n"[..]),
+        Err(Error::InvalidLine(4))
+    ));
+    assert!(matches!(
+        from_reader(&b"osu file format v14\n[HitObjects]\n64,192\n"[..]),
+        Err(Error::InvalidLine(3))
+    ));
+}
+
+#[test]
+fn sv_without_timing_points() {
+    let osu = Osu {
+        mode: MODE_MANIA,
+        circle_size: 4.,
+        timing_points: vec![inherited(100., -50.)],
+        ..Osu::default()
+    };
+
+    let map = Map::try_from(osu.clone()).unwrap();
+    assert_eq!(
+        map.scroll_speed_changes,
+        vec![ScrollSpeedChange {
+            timestamp: MapTimestamp::from_millis(100),
+            multiplier: ScrollSpeedMultiplier::new(2000),
+        }]
+    );
+    assert_eq!(
+        map.initial_scroll_speed_multiplier,
+        ScrollSpeedMultiplier::default()
+    );
+
+    let osu2 = Osu::try_from(map).unwrap();
+    assert_eq!(osu, osu2);
+}
+
+#[test]
+fn conversion_errors() {
+    let osu = Osu {
+        mode: MODE_MANIA,
+        circle_size: 4.,
+        hit_objects: vec![circle(64, 20_000_000)],
+        ..Osu::default()
+    };
+    assert_eq!(
+        Map::try_from(osu.clone()),
+        Err(ConversionError::TimestampOutOfRange(20_000_000.))
+    );
+
+    let hit_objects = vec![hold(64, 100, 100)];
+    assert_eq!(
+        Map::try_from(Osu {
+            hit_objects,
+            ..osu.clone()
+        }),
+        Err(ConversionError::InvalidLongNote {
+            start_time: 100,
+            end_time: 100,
+        })
+    );
+
+    assert_eq!(
+        Map::try_from(Osu {
+            mode: 0,
+            hit_objects: vec![],
+            ..osu.clone()
+        }),
+        Err(ConversionError::NotMania(0))
+    );
+
+    assert_eq!(
+        Map::try_from(Osu {
+            circle_size: 1e9,
+            hit_objects: vec![],
+            ..osu.clone()
+        }),
+        Err(ConversionError::InvalidLaneCount(1e9))
+    );
+
+    assert_eq!(
+        Map::try_from(Osu {
+            timing_points: vec![uninherited(0., 0., 4)],
+            hit_objects: vec![],
+            ..osu.clone()
+        }),
+        Err(ConversionError::InvalidBeatLength(0.))
+    );
+
+    assert_eq!(
+        Map::try_from(Osu {
+            timing_points: vec![uninherited(0., 500., 256)],
+            hit_objects: vec![],
+            ..osu
+        }),
+        Err(ConversionError::InvalidMeter(256))
+    );
+
+    let signature = TimeSignature {
+        beat_count: 6,
+        beat_unit: 8,
+    };
+    let map = Map {
+        timing_points: vec![plitki_core::map::TimingPoint {
+            timestamp: MapTimestamp::zero(),
+            beat_duration: MapTimestampDifference::from_millis(250),
+            signature,
+        }],
+        scroll_speed_changes: vec![],
+        initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+        scroll_groups: vec![],
+        lanes: vec![Lane::new(); 4],
+        song_artist: None,
+        song_title: None,
+        difficulty_name: None,
+        background_file: None,
+        mapper: None,
+        audio_file: None,
+    };
+    assert_eq!(
+        Osu::try_from(map),
+        Err(ConversionError::UnsupportedTimeSignature(signature))
+    );
+}
+
+fn hit_object_compare(a: &HitObject, b: &HitObject) -> Ordering {
+    a.start_time
+        .cmp(&b.start_time)
+        .then(a.x.cmp(&b.x))
+        .then(a.end_time.cmp(&b.end_time))
+}
+
+/// Sorts timing points and removes inherited timing points which don't affect the map.
+fn normalize_timing_points(osu: &mut Osu) {
+    osu.timing_points
+        .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+    let (uninherited, inherited): (Vec<_>, Vec<_>) =
+        osu.timing_points.drain(..).partition(|x| x.uninherited);
+
+    let mut timing_points = uninherited.clone();
+    let mut current_sv_multiplier = 1.;
+    let mut next_uninherited_index = 0;
+
+    #[allow(clippy::float_cmp)]
+    for mut i in 0..inherited.len() {
+        let mut sv = &inherited[i];
+        loop {
+            // Take the last SV at this timestamp.
+            if i == inherited.len() - 1 || inherited[i + 1].time > sv.time {
+                break;
+            }
+
+            i += 1;
+            sv = &inherited[i];
+        }
+
+        // Timing points reset the SV multiplier.
+        while next_uninherited_index < uninherited.len()
+            && uninherited[next_uninherited_index].time <= sv.time
+        {
+            next_uninherited_index += 1;
+            current_sv_multiplier = 1.;
+        }
+
+        // Skip SVs which don't change the multiplier.
+        if sv.sv_multiplier() != current_sv_multiplier {
+            current_sv_multiplier = sv.sv_multiplier();
+            timing_points.push(sv.clone());
+        }
+    }
+
+    timing_points.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .unwrap()
+            .then(b.uninherited.cmp(&a.uninherited))
+    });
+    osu.timing_points = timing_points;
+}
+
+prop_compose! {
+    fn arbitrary_hit_object(lane_count: usize)
+                           (start_time in 0..2i32.pow(30) / 100, // TODO
+                            is_long_note in any::<bool>())
+                           (start_time in Just(start_time),
+                            is_long_note in Just(is_long_note),
+                            column in 0..lane_count,
+                            end_time in if is_long_note {
+                                (start_time..2i32.pow(30) / 100).boxed()
+                            } else {
+                                Just(0).boxed()
+                            })
+                           -> HitObject {
+        let x = HitObject::column_x(column, lane_count);
+        if is_long_note {
+            hold(x, start_time, end_time)
+        } else {
+            circle(x, start_time)
+        }
+    }
+}
+
+prop_compose! {
+    fn arbitrary_uninherited_timing_point()
+                                         (time in -1000..1000, // TODO
+                                          // Use BPMs which give exactly-representable floats.
+                                          // Only use 2 orders of magnitude so the SVs are always
+                                          // representable.
+                                          beat_length in prop::sample::select(&[937.5, 468.75][..]),
+                                          meter in 1..i32::from(u8::MAX))
+                                         -> TimingPoint {
+        uninherited(f64::from(time) * 100., beat_length, meter)
+    }
+}
+
+prop_compose! {
+    fn arbitrary_inherited_timing_point(first_sv_time: f64)
+                                       (time in first_sv_time as i32 / 100..first_sv_time as i32 / 100 + 1000, // TODO
+                                        // Use exactly-representable SV multipliers.
+                                        beat_length in prop::sample::select(
+                                            &[-12.5, -25., -50., -100., -200.][..]
+                                        ))
+                                       -> TimingPoint {
+        inherited(f64::from(time) * 100., beat_length)
+    }
+}
+
+/// Strings which survive the `.osu` line format.
+fn arbitrary_string() -> impl Strategy<Value = String> {
+    "[^\\s\",]+( [^\\s\",]+)*"
+}
+
+prop_compose! {
+    fn arbitrary_osu()
+                    (lane_count in 1..=10usize,
+                     timing_points in prop::collection::vec(arbitrary_uninherited_timing_point(), 1..64))
+                    (lane_count in Just(lane_count),
+                     title in prop::option::of(arbitrary_string()),
+                     artist in prop::option::of(arbitrary_string()),
+                     creator in prop::option::of(arbitrary_string()),
+                     version in prop::option::of(arbitrary_string()),
+                     background_file in prop::option::of(arbitrary_string()),
+                     audio_file in prop::option::of(arbitrary_string()),
+                     inherited in prop::collection::vec(
+                         arbitrary_inherited_timing_point(timing_points[0].time),
+                         0..64,
+                     ),
+                     mut timing_points in Just(timing_points),
+                     hit_objects in prop::collection::vec(arbitrary_hit_object(lane_count), 0..64))
+                    -> Osu {
+        timing_points.extend(inherited);
+
+        Osu {
+            mode: MODE_MANIA,
+            title,
+            artist,
+            creator,
+            version,
+            circle_size: lane_count as f32,
+            background_file,
+            audio_file,
+            timing_points,
+            hit_objects,
+            ..Osu::default()
+        }
+    }
+}
+
+/// Returns a strategy for `f64` which includes special values like NaN and infinities.
+fn arbitrary_f64() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        any::<f64>(),
+        -1000f64..1000.,
+        prop::sample::select(&[0., f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 1e10, -1e10][..]),
+    ]
+}
+
+prop_compose! {
+    fn arbitrary_invalid_osu()
+                            (mode in prop_oneof![Just(MODE_MANIA), any::<i32>()],
+                             circle_size in prop_oneof![0f32..20., any::<f32>()],
+                             timing_points in prop::collection::vec(
+                                 (arbitrary_f64(), arbitrary_f64(), any::<i32>(), any::<bool>()),
+                                 0..8,
+                             ),
+                             hit_objects in prop::collection::vec(
+                                 (any::<i32>(), any::<i32>(), any::<bool>(), any::<i32>()),
+                                 0..8,
+                             ))
+                            -> Osu {
+        Osu {
+            mode,
+            circle_size,
+            timing_points: timing_points
+                .into_iter()
+                .map(|(time, beat_length, meter, is_uninherited)| TimingPoint {
+                    uninherited: is_uninherited,
+                    ..uninherited(time, beat_length, meter)
+                })
+                .collect(),
+            hit_objects: hit_objects
+                .into_iter()
+                .map(|(x, start_time, is_long_note, end_time)| {
+                    if is_long_note {
+                        hold(x, start_time, end_time)
+                    } else {
+                        circle(x, start_time)
+                    }
+                })
+                .collect(),
+            ..Osu::default()
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn osu_to_map_and_back(mut osu in arbitrary_osu()) {
+        let map = Map::try_from(osu.clone()).unwrap();
+        let mut osu2 = Osu::try_from(map).unwrap();
+
+        osu.hit_objects.sort_unstable_by(hit_object_compare);
+        osu2.hit_objects.sort_unstable_by(hit_object_compare);
+        normalize_timing_points(&mut osu);
+        normalize_timing_points(&mut osu2);
+
+        prop_assert_eq!(osu, osu2);
+    }
+
+    #[test]
+    fn osu_serialize_deserialize(
+        mut osu in arbitrary_osu(),
+        title_unicode in prop::option::of(arbitrary_string()),
+        artist_unicode in prop::option::of(arbitrary_string()),
+        source in prop::option::of(arbitrary_string()),
+        tags in prop::option::of(arbitrary_string()),
+        preview_time: i32,
+        overall_difficulty in 0..=20u8,
+    ) {
+        osu.title_unicode = title_unicode;
+        osu.artist_unicode = artist_unicode;
+        osu.source = source;
+        osu.tags = tags;
+        osu.preview_time = preview_time;
+        osu.overall_difficulty = f32::from(overall_difficulty) / 2.;
+
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &osu).unwrap();
+        let osu2 = from_reader(&buf[..]).unwrap();
+
+        prop_assert_eq!(osu, osu2);
+    }
+
+    #[test]
+    fn from_reader_and_conversion_dont_panic(text in "\\PC*") {
+        let text = format!("osu file format v14\n{}", text);
+        if let Ok(osu) = from_reader(text.as_bytes()) {
+            let _ = Map::try_from(osu);
+        }
+    }
+
+    #[test]
+    fn conversion_doesnt_panic(osu in arbitrary_invalid_osu()) {
+        let _ = Map::try_from(osu);
+    }
+}
@@ -1,21 +1,90 @@
 #![allow(clippy::inconsistent_digit_grouping)]
 
 use std::{
-    collections::HashMap,
-    fmt,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    error, fmt,
     io::{Read, Write},
+    mem,
+    ops::BitOr,
 };
 
 use plitki_core::{
-    map::{Lane, Map, ScrollSpeedChange, TimeSignature},
+    map::{
+        denormalize_scroll_speed_changes, normalize_scroll_speed_changes, Chart, Difficulty, Lane,
+        Map, Mapset, ScrollSpeedChange, TimeSignature,
+    },
     object::Object,
     scroll::ScrollSpeedMultiplier,
-    timing::{MapTimestamp, MapTimestampDifference},
+    timing::{MapTimestamp, MapTimestampDifference, Timestamp},
 };
-use serde::{de, Deserialize, Deserializer, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Error returned when converting a `Qua` into a `Mapset` or a `Map` fails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConversionError {
+    /// A timing point has a NaN or infinite BPM.
+    InvalidBpm(f32),
+    /// A slider velocity has a NaN or infinite multiplier.
+    InvalidScrollVelocity(f32),
+    /// A hit object is in a lane that the game mode doesn't have.
+    InvalidLane(i32),
+    /// A time in milliseconds is NaN or doesn't fit into a `MapTimestamp`.
+    TimestampOutOfRange(f64),
+    /// A long note ends at or before its start.
+    InvalidLongNote { start_time: i32, end_time: i32 },
+}
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::InvalidBpm(bpm) => write!(f, "invalid BPM: {}", bpm),
+            ConversionError::InvalidScrollVelocity(multiplier) => {
+                write!(f, "invalid scroll velocity: {}", multiplier)
+            }
+            ConversionError::InvalidLane(lane) => write!(f, "invalid lane: {}", lane),
+            ConversionError::TimestampOutOfRange(time) => {
+                write!(f, "timestamp out of range: {} ms", time)
+            }
+            ConversionError::InvalidLongNote {
+                start_time,
+                end_time,
+            } => write!(
+                f,
+                "long note ends at or before its start: {} ms to {} ms",
+                start_time, end_time
+            ),
+        }
+    }
+}
+
+impl error::Error for ConversionError {}
+
+/// Converts a time in milliseconds into a `MapTimestamp`.
+fn timestamp(millis: f32) -> Result<MapTimestamp, ConversionError> {
+    let milli_hundredths = millis * 100.;
+    if !milli_hundredths.is_finite()
+        || milli_hundredths < i32::MIN as f32
+        || milli_hundredths > i32::MAX as f32
+    {
+        return Err(ConversionError::TimestampOutOfRange(f64::from(millis)));
+    }
+
+    MapTimestamp::checked_from_milli_hundredths(milli_hundredths as i32)
+        .ok_or(ConversionError::TimestampOutOfRange(f64::from(millis)))
+}
+
+/// Converts a time in whole milliseconds into a `MapTimestamp`.
+fn timestamp_from_millis(millis: i32) -> Result<MapTimestamp, ConversionError> {
+    millis
+        .checked_mul(100)
+        .and_then(MapTimestamp::checked_from_milli_hundredths)
+        .ok_or(ConversionError::TimestampOutOfRange(f64::from(millis)))
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
 pub enum GameMode {
+    #[default]
     Keys4,
     Keys7,
 }
@@ -95,11 +164,17 @@ where
     Ok(if value == 0 { 4 } else { value })
 }
 
-impl From<TimingPoint> for plitki_core::map::TimingPoint {
+impl TryFrom<TimingPoint> for plitki_core::map::TimingPoint {
+    type Error = ConversionError;
+
     #[inline]
-    fn from(timing_point: TimingPoint) -> Self {
-        Self {
-            timestamp: MapTimestamp::from_milli_hundredths((timing_point.start_time * 100.) as i32),
+    fn try_from(timing_point: TimingPoint) -> Result<Self, Self::Error> {
+        if !timing_point.bpm.is_finite() {
+            return Err(ConversionError::InvalidBpm(timing_point.bpm));
+        }
+
+        Ok(Self {
+            timestamp: timestamp(timing_point.start_time)?,
             beat_duration: MapTimestampDifference::from_milli_hundredths(
                 if timing_point.bpm == 0. {
                     i32::MAX
@@ -111,7 +186,7 @@ impl From<TimingPoint> for plitki_core::map::TimingPoint {
                 beat_count: timing_point.signature as u8,
                 beat_unit: 4,
             },
-        }
+        })
     }
 }
 
@@ -136,13 +211,19 @@ pub struct SliderVelocity {
     pub multiplier: f32,
 }
 
-impl From<SliderVelocity> for ScrollSpeedChange {
+impl TryFrom<SliderVelocity> for ScrollSpeedChange {
+    type Error = ConversionError;
+
     #[inline]
-    fn from(x: SliderVelocity) -> Self {
-        Self {
-            timestamp: MapTimestamp::from_milli_hundredths((x.start_time * 100.) as i32),
-            multiplier: ScrollSpeedMultiplier::saturating_from_f32(x.multiplier),
+    fn try_from(x: SliderVelocity) -> Result<Self, Self::Error> {
+        if !x.multiplier.is_finite() {
+            return Err(ConversionError::InvalidScrollVelocity(x.multiplier));
         }
+
+        Ok(Self {
+            timestamp: timestamp(x.start_time)?,
+            multiplier: ScrollSpeedMultiplier::saturating_from_f32(x.multiplier),
+        })
     }
 }
 
@@ -156,7 +237,127 @@ impl From<ScrollSpeedChange> for SliderVelocity {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+/// Hit sounds played by a hit object.
+///
+/// This is a set of flags, combined with `|`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct HitSounds(u8);
+
+impl HitSounds {
+    pub const NORMAL: Self = Self(1 << 0);
+    pub const WHISTLE: Self = Self(1 << 1);
+    pub const FINISH: Self = Self(1 << 2);
+    pub const CLAP: Self = Self(1 << 3);
+
+    const NAMES: [(Self, &'static str); 4] = [
+        (Self::NORMAL, "Normal"),
+        (Self::WHISTLE, "Whistle"),
+        (Self::FINISH, "Finish"),
+        (Self::CLAP, "Clap"),
+    ];
+
+    /// Returns `true` if no hit sounds are set.
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all hit sounds in `other` are set.
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for HitSounds {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl Serialize for HitSounds {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Quaver writes the flags as a comma-separated list of names.
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        serializer.serialize_str(&names.join(", "))
+    }
+}
+
+impl<'de> Deserialize<'de> for HitSounds {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct NamesOrNumber;
+
+        impl<'de> de::Visitor<'de> for NamesOrNumber {
+            type Value = HitSounds;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("comma-separated hit sound names or u8")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<HitSounds, E>
+            where
+                E: de::Error,
+            {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .try_fold(HitSounds::default(), |hit_sounds, name| {
+                        let flag = HitSounds::NAMES
+                            .iter()
+                            .find(|(_, x)| *x == name)
+                            .ok_or_else(|| {
+                                de::Error::invalid_value(de::Unexpected::Str(name), &self)
+                            })?
+                            .0;
+                        Ok(hit_sounds | flag)
+                    })
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<HitSounds, E>
+            where
+                E: de::Error,
+            {
+                if value >= 1 << HitSounds::NAMES.len() {
+                    return Err(de::Error::invalid_value(
+                        de::Unexpected::Unsigned(value),
+                        &self,
+                    ));
+                }
+
+                Ok(HitSounds(value as u8))
+            }
+        }
+
+        deserializer.deserialize_any(NamesOrNumber)
+    }
+}
+
+/// A custom audio sample played by a hit object.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct KeySound {
+    /// Index of the sample in `Qua::custom_audio_samples`, starting from 1.
+    #[serde(default, rename = "Sample")]
+    pub sample: i32,
+    /// Volume from 0 to 100.
+    #[serde(default, rename = "Volume")]
+    pub volume: i32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct HitObject {
     #[serde(default, rename = "StartTime")]
     pub start_time: i32,
@@ -164,6 +365,24 @@ pub struct HitObject {
     pub lane: i32,
     #[serde(default, rename = "EndTime")]
     pub end_time: i32,
+    #[serde(default, rename = "HitSound", skip_serializing_if = "is_default")]
+    pub hit_sound: HitSounds,
+    #[serde(default, rename = "KeySounds", skip_serializing_if = "Vec::is_empty")]
+    pub key_sounds: Vec<KeySound>,
+    /// Index of the editor layer, where 0 is the default layer.
+    #[serde(default, rename = "EditorLayer", skip_serializing_if = "is_default")]
+    pub editor_layer: i32,
+    /// Name of the timing group in `Qua::timing_groups`, or `None` for the default group.
+    #[serde(
+        default,
+        rename = "TimingGroup",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timing_group: Option<String>,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 impl HitObject {
@@ -174,445 +393,461 @@ impl HitObject {
     }
 }
 
-impl From<HitObject> for Object {
+impl TryFrom<HitObject> for Object {
+    type Error = ConversionError;
+
     #[inline]
-    fn from(hit_object: HitObject) -> Self {
-        // TODO: this shouldn't panic and should probably be TryFrom instead.
+    fn try_from(hit_object: HitObject) -> Result<Self, Self::Error> {
+        let start = timestamp_from_millis(hit_object.start_time)?;
+
         if hit_object.is_long_note() {
-            Object::LongNote {
-                start: MapTimestamp::from_millis(hit_object.start_time),
-                end: MapTimestamp::from_millis(hit_object.end_time),
+            if hit_object.end_time <= hit_object.start_time {
+                return Err(ConversionError::InvalidLongNote {
+                    start_time: hit_object.start_time,
+                    end_time: hit_object.end_time,
+                });
             }
+
+            Ok(Object::LongNote {
+                start,
+                end: timestamp_from_millis(hit_object.end_time)?,
+            })
         } else {
-            Object::Regular {
-                timestamp: MapTimestamp::from_millis(hit_object.start_time),
-            }
+            Ok(Object::Regular { timestamp: start })
         }
     }
 }
 
+/// Name of the timing group which hit objects without a timing group belong to.
+///
+/// Its slider velocities are `Qua::slider_velocities`. When converting to a `Map`, they are used
+/// if `Qua::slider_velocities` is empty.
+pub const DEFAULT_TIMING_GROUP: &str = "$Default";
+
+/// Name of the timing group which applies to the whole map.
+///
+/// When converting to a `Map`, it becomes a scroll group without objects so that it isn't lost.
+pub const GLOBAL_TIMING_GROUP: &str = "$Global";
+
+/// A group of hit objects which share their timing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TimingGroup {
+    /// A group of hit objects with their own slider velocities.
+    ScrollGroup(ScrollGroup),
+}
+
+/// A timing group with its own slider velocities.
+///
+/// The slider velocities are always in the normalized form (BPM does not affect SV).
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ScrollGroup {
+    #[serde(default, rename = "InitialScrollVelocity")]
+    pub initial_scroll_velocity: f32,
+    #[serde(default, rename = "ScrollVelocities")]
+    pub scroll_velocities: Vec<SliderVelocity>,
+    /// Color in the `"r,g,b"` form.
+    #[serde(default, rename = "ColorRgb", skip_serializing_if = "Option::is_none")]
+    pub color_rgb: Option<String>,
+}
+
+/// An editor layer which hit objects can be grouped into.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct EditorLayer {
+    #[serde(rename = "Name")]
+    pub name: Option<String>,
+    #[serde(default, rename = "Hidden")]
+    pub hidden: bool,
+    /// Color in the `"r,g,b"` form.
+    #[serde(rename = "ColorRgb")]
+    pub color_rgb: Option<String>,
+}
+
+/// An editor bookmark.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct Bookmark {
+    #[serde(default, rename = "StartTime")]
+    pub start_time: i32,
+    #[serde(rename = "Note")]
+    pub note: Option<String>,
+}
+
+/// A custom audio sample file used by key sounds and sound effects.
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct CustomAudioSample {
+    /// Path relative to the map directory.
+    #[serde(default, rename = "Path")]
+    pub path: String,
+    /// Whether the sample pitch is unaffected by the playback rate.
+    #[serde(default, rename = "UnaffectedByRate")]
+    pub unaffected_by_rate: bool,
+}
+
+/// A custom audio sample played at a given time.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct SoundEffect {
+    #[serde(default, rename = "StartTime")]
+    pub start_time: f32,
+    /// Index of the sample in `Qua::custom_audio_samples`, starting from 1.
+    #[serde(default, rename = "Sample")]
+    pub sample: i32,
+    /// Volume from 0 to 100.
+    #[serde(default, rename = "Volume")]
+    pub volume: i32,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Qua {
+    #[serde(rename = "AudioFile")]
+    pub audio_file: Option<String>,
+    #[serde(default, rename = "SongPreviewTime")]
+    pub song_preview_time: i32,
+    #[serde(rename = "BackgroundFile")]
+    pub background_file: Option<String>,
+    #[serde(
+        default,
+        rename = "BannerFile",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub banner_file: Option<String>,
+    /// Online map ID, or -1 if the map isn't submitted.
+    #[serde(default = "default_id", rename = "MapId")]
+    pub map_id: i32,
+    /// Online mapset ID, or -1 if the mapset isn't submitted.
+    #[serde(default = "default_id", rename = "MapSetId")]
+    pub map_set_id: i32,
     #[serde(rename = "Mode")]
     pub mode: GameMode,
     #[serde(rename = "Title")]
     pub title: Option<String>,
     #[serde(rename = "Artist")]
     pub artist: Option<String>,
+    #[serde(default, rename = "Source")]
+    pub source: Option<String>,
+    #[serde(default, rename = "Tags")]
+    pub tags: Option<String>,
     #[serde(rename = "Creator")]
     pub creator: Option<String>,
     #[serde(rename = "DifficultyName")]
     pub difficulty_name: Option<String>,
-    #[serde(rename = "BackgroundFile")]
-    pub background_file: Option<String>,
-    #[serde(rename = "AudioFile")]
-    pub audio_file: Option<String>,
+    #[serde(default, rename = "Description")]
+    pub description: Option<String>,
+    #[serde(default, rename = "Genre", skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    #[serde(default, rename = "LegacyLNRendering")]
+    pub legacy_ln_rendering: bool,
     #[serde(default, rename = "BPMDoesNotAffectScrollVelocity")]
     pub bpm_does_not_affect_scroll_velocity: bool,
     #[serde(default, rename = "InitialScrollVelocity")]
     pub initial_scroll_velocity: f32,
+    /// Whether the map has an extra scratch lane after the lanes of the game mode.
+    #[serde(default, rename = "HasScratchKey")]
+    pub has_scratch_key: bool,
+    #[serde(default, rename = "EditorLayers")]
+    pub editor_layers: Vec<EditorLayer>,
+    #[serde(default, rename = "Bookmarks")]
+    pub bookmarks: Vec<Bookmark>,
+    #[serde(default, rename = "CustomAudioSamples")]
+    pub custom_audio_samples: Vec<CustomAudioSample>,
+    #[serde(default, rename = "SoundEffects")]
+    pub sound_effects: Vec<SoundEffect>,
     #[serde(rename = "TimingPoints")]
     pub timing_points: Vec<TimingPoint>,
     #[serde(rename = "SliderVelocities")]
     pub slider_velocities: Vec<SliderVelocity>,
+    /// Timing groups by name.
+    #[serde(
+        default,
+        rename = "TimingGroups",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub timing_groups: BTreeMap<String, TimingGroup>,
     #[serde(rename = "HitObjects")]
     pub hit_objects: Vec<HitObject>,
 }
 
-impl Qua {
-    /// Returns the lane count of the map.
-    #[inline]
-    pub fn lane_count(&self) -> usize {
-        self.mode.lane_count()
-    }
-
-    /// Computes the base BPM for the scroll speed multiplier.
-    ///
-    /// The base BPM corresponds to the multiplier of `1.0`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if there are no objects or if the timing points are not sorted by the start time.
-    pub fn base_bpm(&self) -> f32 {
-        let last_object_end_time = self
-            .hit_objects
-            .iter()
-            .map(|x| {
-                if x.is_long_note() {
-                    x.end_time
-                } else {
-                    x.start_time
-                }
-            })
-            .max()
-            .unwrap() as f32;
-
-        let mut durations = HashMap::new();
-        self.timing_points
-            .iter()
-            .enumerate()
-            .rev()
-            .filter(|(_, x)| x.start_time <= last_object_end_time)
-            .fold(last_object_end_time, |last_time, (i, timing_point)| {
-                let start_time = if i == 0 { 0. } else { timing_point.start_time };
-                let duration = last_time - timing_point.start_time;
-                assert!(duration >= 0.);
-
-                *durations.entry(timing_point.bpm.to_bits()).or_insert(0.) += duration;
-
-                start_time
-            });
+fn default_id() -> i32 {
+    -1
+}
 
-        if durations.is_empty() {
-            self.timing_points[0].bpm
-        } else {
-            let bits = *durations
-                .iter()
-                .max_by(|(bits_1, duration_1), (bits_2, duration_2)| {
-                    duration_1
-                        .partial_cmp(duration_2)
-                        .unwrap()
-                        // TODO: this is here so that in case multiple timing points have the same
-                        // duration the same one is returned every time (since the HashMap
-                        // iteration order is unstable). Quaver seems to have the same issue. Need
-                        // to see which one osu! picks in this case.
-                        .then(
-                            f32::from_bits(**bits_1)
-                                .partial_cmp(&f32::from_bits(**bits_2))
-                                .unwrap(),
-                        )
-                })
-                .unwrap()
-                .0;
-            f32::from_bits(bits)
+impl Default for Qua {
+    fn default() -> Self {
+        Self {
+            audio_file: None,
+            song_preview_time: 0,
+            background_file: None,
+            banner_file: None,
+            map_id: default_id(),
+            map_set_id: default_id(),
+            mode: GameMode::default(),
+            title: None,
+            artist: None,
+            source: None,
+            tags: None,
+            creator: None,
+            difficulty_name: None,
+            description: None,
+            genre: None,
+            legacy_ln_rendering: false,
+            bpm_does_not_affect_scroll_velocity: false,
+            initial_scroll_velocity: 0.,
+            has_scratch_key: false,
+            editor_layers: Vec::new(),
+            bookmarks: Vec::new(),
+            custom_audio_samples: Vec::new(),
+            sound_effects: Vec::new(),
+            timing_points: Vec::new(),
+            slider_velocities: Vec::new(),
+            timing_groups: BTreeMap::new(),
+            hit_objects: Vec::new(),
         }
     }
+}
 
-    /// Converts slider velocities to the normalized form (BPM does not affect SV).
-    pub fn normalize_svs(&mut self) {
-        if self.bpm_does_not_affect_scroll_velocity {
-            // Already normalized.
-            return;
-        }
-
-        self.timing_points
-            .sort_by(|a, b| a.start_time.partial_cmp(&b.start_time).unwrap());
-        self.slider_velocities
-            .sort_by(|a, b| a.start_time.partial_cmp(&b.start_time).unwrap());
-
-        // TODO: this fallback isn't really justified...
-        let base_bpm = if self.hit_objects.is_empty() {
-            self.timing_points[0].bpm
-        } else {
-            self.base_bpm()
-        };
-
-        let mut scroll_speed_changes = Vec::new();
-        let mut current_bpm = self.timing_points.first().unwrap().bpm;
-        let mut current_sv_index = 0;
-        let mut current_sv_start_time = None;
-        let mut current_sv_multiplier = 1.;
-        let mut current_adjusted_sv_multiplier = None;
-        let mut initial_scroll_speed_multiplier = None;
-
-        for (i, timing_point) in self.timing_points.iter().enumerate() {
-            let mut next_timing_point_has_same_timestamp = false;
-            if let Some(next_point) = self.timing_points.get(i + 1) {
-                #[allow(clippy::float_cmp)]
-                if timing_point.start_time == next_point.start_time {
-                    next_timing_point_has_same_timestamp = true;
-                }
-            }
-
-            loop {
-                if current_sv_index >= self.slider_velocities.len() {
-                    break;
-                }
-
-                let sv = self.slider_velocities[current_sv_index];
-                if sv.start_time > timing_point.start_time {
-                    break;
-                }
-
-                // If there are more timing points on this timestamp, the SV only applies on the
-                // very last one, so skip it for now.
-                #[allow(clippy::float_cmp)]
-                if next_timing_point_has_same_timestamp && sv.start_time == timing_point.start_time
-                {
-                    break;
-                }
-
-                if sv.start_time < timing_point.start_time {
-                    let multiplier = sv.multiplier * (current_bpm / base_bpm);
-
-                    if current_adjusted_sv_multiplier.is_none() {
-                        current_adjusted_sv_multiplier = Some(multiplier);
-                        initial_scroll_speed_multiplier = Some(multiplier);
-                    }
-
-                    #[allow(clippy::float_cmp)]
-                    if multiplier != current_adjusted_sv_multiplier.unwrap() {
-                        scroll_speed_changes.push(SliderVelocity {
-                            start_time: sv.start_time,
-                            multiplier,
-                        });
-                        current_adjusted_sv_multiplier = Some(multiplier);
-                    }
-                }
-
-                current_sv_start_time = Some(sv.start_time);
-                current_sv_multiplier = sv.multiplier;
-                current_sv_index += 1;
-            }
-
-            // Timing points reset the previous SV multiplier.
-            if current_sv_start_time
-                .map(|x| x < timing_point.start_time)
-                .unwrap_or(true)
-            {
-                current_sv_multiplier = 1.;
-            }
-
-            current_bpm = timing_point.bpm;
-
-            let multiplier = current_sv_multiplier * (current_bpm / base_bpm);
-
-            if current_adjusted_sv_multiplier.is_none() {
-                current_adjusted_sv_multiplier = Some(multiplier);
-                initial_scroll_speed_multiplier = Some(multiplier);
-            }
-
-            #[allow(clippy::float_cmp)]
-            if multiplier != current_adjusted_sv_multiplier.unwrap() {
-                scroll_speed_changes.push(SliderVelocity {
-                    start_time: timing_point.start_time,
-                    multiplier,
-                });
-                current_adjusted_sv_multiplier = Some(multiplier);
-            }
-        }
-
-        for sv in &self.slider_velocities[current_sv_index..] {
-            let multiplier = sv.multiplier * (current_bpm / base_bpm);
-            #[allow(clippy::float_cmp)]
-            if multiplier != current_adjusted_sv_multiplier.unwrap() {
-                scroll_speed_changes.push(SliderVelocity {
-                    start_time: sv.start_time,
-                    multiplier,
-                });
-                current_adjusted_sv_multiplier = Some(multiplier);
-            }
-        }
-
-        self.bpm_does_not_affect_scroll_velocity = true;
-        self.initial_scroll_velocity = initial_scroll_speed_multiplier.unwrap_or(1.);
-        self.slider_velocities = scroll_speed_changes;
+impl Qua {
+    /// Returns the lane count of the map, including the scratch lane.
+    #[inline]
+    pub fn lane_count(&self) -> usize {
+        self.mode.lane_count() + usize::from(self.has_scratch_key)
     }
+}
 
-    /// Converts slider velocities to the denormalized form (BPM affects SV).
-    pub fn denormalize_svs(&mut self) {
-        if !self.bpm_does_not_affect_scroll_velocity {
-            // Already denormalized.
-            return;
+impl TryFrom<Qua> for Mapset {
+    type Error = ConversionError;
+
+    /// Converts the `Qua` into a `Mapset` with a single difficulty.
+    fn try_from(mut qua: Qua) -> Result<Self, Self::Error> {
+        // The default timing group holds the map's own SVs, which are normalized like in every
+        // other timing group. Use them unless the map has SVs of its own.
+        let mut timing_groups = mem::take(&mut qua.timing_groups);
+        if let Some(TimingGroup::ScrollGroup(group)) = timing_groups.remove(DEFAULT_TIMING_GROUP) {
+            if qua.slider_velocities.is_empty() {
+                qua.bpm_does_not_affect_scroll_velocity = true;
+                qua.initial_scroll_velocity = group.initial_scroll_velocity;
+                qua.slider_velocities = group.scroll_velocities;
+            }
         }
 
-        self.timing_points
-            .sort_by(|a, b| a.start_time.partial_cmp(&b.start_time).unwrap());
-        self.slider_velocities
-            .sort_by(|a, b| a.start_time.partial_cmp(&b.start_time).unwrap());
-
-        // TODO: this fallback isn't really justified...
-        let base_bpm = if self.hit_objects.is_empty() {
-            self.timing_points[0].bpm
-        } else {
-            self.base_bpm()
-        };
-
-        let mut slider_velocities = Vec::new();
-        let mut current_bpm = self
+        let timing_points: Vec<plitki_core::map::TimingPoint> = qua
             .timing_points
-            .first()
-            .map(|x| x.bpm)
-            .unwrap_or(base_bpm);
-        let mut current_sv_index = 0;
-        let mut current_sv_multiplier = self.initial_scroll_velocity;
-        let mut current_adjusted_sv_multiplier = None;
-
-        #[allow(clippy::float_cmp)]
-        for (i, timing_point) in self.timing_points.iter().enumerate() {
-            loop {
-                if current_sv_index >= self.slider_velocities.len() {
-                    break;
-                }
-
-                let sv = &self.slider_velocities[current_sv_index];
-                if sv.start_time > timing_point.start_time {
-                    break;
+            .drain(..)
+            .map(TryFrom::try_from)
+            .collect::<Result<_, _>>()?;
+        let normalize = !qua.bpm_does_not_affect_scroll_velocity;
+        let min_start_time = (Timestamp::MIN.into_milli_hundredths() / 100) as f32;
+        let svs: Vec<ScrollSpeedChange> = qua
+            .slider_velocities
+            .drain(..)
+            .map(|mut sv| {
+                // Normalizing turns the first SV into the initial scroll speed multiplier, so SVs
+                // which start too early aren't an error there.
+                if normalize && sv.start_time.is_finite() && sv.start_time < min_start_time {
+                    sv.start_time = min_start_time;
                 }
-
-                if sv.start_time < timing_point.start_time {
-                    let multiplier = sv.multiplier / (current_bpm / base_bpm);
-
-                    if current_adjusted_sv_multiplier.is_none()
-                        || multiplier != current_adjusted_sv_multiplier.unwrap()
-                    {
-                        if current_adjusted_sv_multiplier.is_none()
-                            && sv.multiplier != self.initial_scroll_velocity
-                        {
-                            // Insert an SV 1 ms earlier to simulate the initial scroll speed
-                            // multiplier.
-                            slider_velocities.push(SliderVelocity {
-                                start_time: sv.start_time - 1.,
-                                multiplier: self.initial_scroll_velocity / (current_bpm / base_bpm),
-                            });
-                        }
-
-                        slider_velocities.push(SliderVelocity {
-                            start_time: sv.start_time,
-                            multiplier,
-                        });
-                        current_adjusted_sv_multiplier = Some(multiplier);
-                    }
-                }
-
-                current_sv_multiplier = sv.multiplier;
-                current_sv_index += 1;
-            }
-
-            current_bpm = timing_point.bpm;
-
-            if current_adjusted_sv_multiplier.is_none()
-                && current_sv_multiplier != self.initial_scroll_velocity
-            {
-                // Insert an SV 1 ms earlier to simulate the initial scroll speed multiplier.
-                slider_velocities.push(SliderVelocity {
-                    start_time: timing_point.start_time - 1.,
-                    multiplier: self.initial_scroll_velocity / (current_bpm / base_bpm),
-                });
-            }
-
-            // Timing points reset the SV multiplier.
-            current_adjusted_sv_multiplier = Some(1.);
-
-            // Skip over multiple timing points at the same timestamp.
-            if i + 1 < self.timing_points.len()
-                && self.timing_points[i + 1].start_time == timing_point.start_time
-            {
-                continue;
-            }
-
-            let multiplier = current_sv_multiplier / (current_bpm / base_bpm);
-            if multiplier != current_adjusted_sv_multiplier.unwrap() {
-                slider_velocities.push(SliderVelocity {
-                    start_time: timing_point.start_time,
-                    multiplier,
-                });
-                current_adjusted_sv_multiplier = Some(multiplier);
-            }
+                ScrollSpeedChange::try_from(sv)
+            })
+            .collect::<Result<_, _>>()?;
+        if !qua.initial_scroll_velocity.is_finite() {
+            return Err(ConversionError::InvalidScrollVelocity(
+                qua.initial_scroll_velocity,
+            ));
         }
 
-        #[allow(clippy::float_cmp)]
-        for sv in &self.slider_velocities[current_sv_index..] {
-            let multiplier = sv.multiplier / (current_bpm / base_bpm);
-            if multiplier != current_adjusted_sv_multiplier.unwrap() {
-                slider_velocities.push(SliderVelocity {
-                    start_time: sv.start_time,
-                    multiplier,
-                });
-                current_adjusted_sv_multiplier = Some(multiplier);
+        // The global timing group has no plitki equivalent, so it's kept as a scroll group without
+        // objects.
+        let mut scroll_groups = Vec::new();
+        let mut scroll_group_indices = HashMap::new();
+        for (name, group) in timing_groups {
+            let TimingGroup::ScrollGroup(group) = group;
+            if !group.initial_scroll_velocity.is_finite() {
+                return Err(ConversionError::InvalidScrollVelocity(
+                    group.initial_scroll_velocity,
+                ));
             }
-        }
 
-        self.bpm_does_not_affect_scroll_velocity = false;
-        self.initial_scroll_velocity = 0.;
-        self.slider_velocities = slider_velocities;
-    }
-}
-
-impl From<Qua> for Map {
-    #[inline]
-    fn from(mut qua: Qua) -> Self {
-        // TODO: this shouldn't panic and should probably be TryFrom instead.
-        qua.normalize_svs();
+            scroll_group_indices.insert(name.clone(), scroll_groups.len());
+            scroll_groups.push(plitki_core::map::ScrollGroup {
+                name,
+                scroll_speed_changes: group
+                    .scroll_velocities
+                    .into_iter()
+                    .map(TryFrom::try_from)
+                    .collect::<Result<_, _>>()?,
+                initial_scroll_speed_multiplier: ScrollSpeedMultiplier::saturating_from_f32(
+                    group.initial_scroll_velocity,
+                ),
+                lanes: vec![Lane::new(); qua.lane_count()],
+            });
+        }
 
         let mut lanes = vec![Lane::new(); qua.lane_count()];
         for hit_object in qua.hit_objects.drain(..) {
-            assert!(hit_object.lane > 0);
-            lanes[(hit_object.lane - 1) as usize]
-                .objects
-                .push(hit_object.into());
+            // Hit objects in unknown timing groups fall back to the default group, like in Quaver.
+            let lanes = match hit_object
+                .timing_group
+                .as_ref()
+                .and_then(|name| scroll_group_indices.get(name))
+            {
+                Some(&index) => &mut scroll_groups[index].lanes,
+                None => &mut lanes,
+            };
+
+            let lane = usize::try_from(hit_object.lane)
+                .ok()
+                .and_then(|lane| lane.checked_sub(1))
+                .and_then(|lane| lanes.get_mut(lane))
+                .ok_or(ConversionError::InvalidLane(hit_object.lane))?;
+            lane.objects.push(Object::try_from(hit_object)?);
         }
 
-        Self {
+        let (initial_scroll_speed_multiplier, scroll_speed_changes) =
+            if qua.bpm_does_not_affect_scroll_velocity {
+                (
+                    ScrollSpeedMultiplier::saturating_from_f32(qua.initial_scroll_velocity),
+                    svs,
+                )
+            } else {
+                let end = lanes
+                    .iter()
+                    .chain(scroll_groups.iter().flat_map(|group| &group.lanes))
+                    .flat_map(|lane| &lane.objects)
+                    .map(Object::end_timestamp)
+                    .max();
+                normalize_scroll_speed_changes(&timing_points, &svs, end)
+            };
+
+        let chart = Chart {
+            timing_points,
+            scroll_speed_changes,
+            initial_scroll_speed_multiplier,
+            scroll_groups,
+            lanes,
+        };
+
+        Ok(Self {
             song_artist: qua.artist,
             song_title: qua.title,
-            difficulty_name: qua.difficulty_name,
             background_file: qua.background_file,
-            mapper: qua.creator,
             audio_file: qua.audio_file,
-            timing_points: qua.timing_points.into_iter().map(Into::into).collect(),
-            scroll_speed_changes: qua.slider_velocities.into_iter().map(Into::into).collect(),
-            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::saturating_from_f32(
-                qua.initial_scroll_velocity,
-            ),
-            lanes,
-        }
+            difficulties: vec![Difficulty {
+                name: qua.difficulty_name,
+                mapper: qua.creator,
+                chart,
+            }],
+        })
+    }
+}
+
+impl TryFrom<Qua> for Map {
+    type Error = ConversionError;
+
+    #[inline]
+    fn try_from(qua: Qua) -> Result<Self, Self::Error> {
+        Ok(Mapset::try_from(qua)?.into_maps().remove(0))
     }
 }
 
 impl From<Map> for Qua {
     #[inline]
-    fn from(map: Map) -> Self {
+    fn from(mut map: Map) -> Self {
         // TODO: this shouldn't panic and should probably be TryFrom instead.
+        let (mode, has_scratch_key) = match map.lane_count() {
+            4 => (GameMode::Keys4, false),
+            5 => (GameMode::Keys4, true),
+            7 => (GameMode::Keys7, false),
+            8 => (GameMode::Keys7, true),
+            _ => panic!("Invalid lane count: {}", map.lane_count()),
+        };
+
+        // TODO: remove when Quaver is updated. Timing groups only support normalized SVs.
+        let bpm_does_not_affect_scroll_velocity = !map.scroll_groups.is_empty();
+        if !bpm_does_not_affect_scroll_velocity {
+            let end = map
+                .lanes
+                .iter()
+                .flat_map(|lane| &lane.objects)
+                .map(Object::end_timestamp)
+                .max();
+            map.scroll_speed_changes = denormalize_scroll_speed_changes(
+                &map.timing_points,
+                &map.scroll_speed_changes,
+                map.initial_scroll_speed_multiplier,
+                end,
+            );
+        }
+
         let mut qua = Self {
-            mode: match map.lane_count() {
-                4 => GameMode::Keys4,
-                7 => GameMode::Keys7,
-                _ => panic!("Invalid lane count: {}", map.lane_count()),
-            },
+            mode,
+            has_scratch_key,
             artist: map.song_artist,
             title: map.song_title,
             difficulty_name: map.difficulty_name,
             background_file: map.background_file,
             creator: map.mapper,
             audio_file: map.audio_file,
-            bpm_does_not_affect_scroll_velocity: true,
-            initial_scroll_velocity: map.initial_scroll_speed_multiplier.as_f32(),
+            bpm_does_not_affect_scroll_velocity,
+            initial_scroll_velocity: if bpm_does_not_affect_scroll_velocity {
+                map.initial_scroll_speed_multiplier.as_f32()
+            } else {
+                0.
+            },
             timing_points: map.timing_points.into_iter().map(Into::into).collect(),
             slider_velocities: map
                 .scroll_speed_changes
                 .into_iter()
                 .map(Into::into)
                 .collect(),
-            hit_objects: map
-                .lanes
-                .into_iter()
-                .enumerate()
-                .flat_map(|(lane, Lane { objects })| {
-                    let lane = lane as i32 + 1;
-
-                    objects.into_iter().map(move |object| match object {
-                        Object::Regular { timestamp } => HitObject {
-                            start_time: timestamp.as_millis(),
-                            lane,
-                            end_time: 0,
-                        },
-                        Object::LongNote { start, end } => HitObject {
-                            start_time: start.as_millis(),
-                            lane,
-                            end_time: end.as_millis(),
-                        },
-                    })
-                })
-                .collect(),
+            hit_objects: hit_objects(map.lanes, None).collect(),
+            ..Self::default()
         };
 
-        // TODO: remove when Quaver is updated.
-        qua.denormalize_svs();
+        for group in map.scroll_groups {
+            qua.hit_objects
+                .extend(hit_objects(group.lanes, Some(group.name.clone())));
+            qua.timing_groups.insert(
+                group.name,
+                TimingGroup::ScrollGroup(ScrollGroup {
+                    initial_scroll_velocity: group.initial_scroll_speed_multiplier.as_f32(),
+                    scroll_velocities: group
+                        .scroll_speed_changes
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                    color_rgb: None,
+                }),
+            );
+        }
         qua
     }
 }
 
+/// Converts the objects in `lanes` into hit objects in the given timing group.
+fn hit_objects(lanes: Vec<Lane>, timing_group: Option<String>) -> impl Iterator<Item = HitObject> {
+    lanes
+        .into_iter()
+        .enumerate()
+        .flat_map(move |(lane, Lane { objects })| {
+            let lane = lane as i32 + 1;
+            let timing_group = timing_group.clone();
+
+            objects.into_iter().map(move |object| match object {
+                Object::Regular { timestamp } => HitObject {
+                    start_time: timestamp.as_millis(),
+                    lane,
+                    end_time: 0,
+                    timing_group: timing_group.clone(),
+                    ..HitObject::default()
+                },
+                Object::LongNote { start, end } => HitObject {
+                    start_time: start.as_millis(),
+                    lane,
+                    end_time: end.as_millis(),
+                    timing_group: timing_group.clone(),
+                    ..HitObject::default()
+                },
+            })
+        })
+}
+
 /// Deserializes a `Qua` from an IO stream of YAML.
 pub fn from_reader<R: Read>(reader: R) -> Result<Qua, serde_yaml::Error> {
     serde_yaml::from_reader(reader)
@@ -1,12 +1,18 @@
-use std::{cmp::Ordering, fs::File};
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    fs::{self, File},
+};
 
 extern crate plitki_map_qua;
 use plitki_map_qua::{
-    from_reader, to_writer, GameMode, HitObject, Qua, SliderVelocity, TimingPoint,
+    from_reader, to_writer, Bookmark, ConversionError, CustomAudioSample, EditorLayer, GameMode,
+    HitObject, HitSounds, KeySound, Qua, ScrollGroup, SliderVelocity, SoundEffect, TimingGroup,
+    TimingPoint, GLOBAL_TIMING_GROUP,
 };
 
 use plitki_core::{
-    map::{Lane, Map, ScrollSpeedChange, TimeSignature},
+    map::{Chart, Lane, Map, Mapset, ScrollSpeedChange, TimeSignature},
     object::Object,
     scroll::ScrollSpeedMultiplier,
     timing::{MapTimestamp, MapTimestampDifference},
@@ -71,48 +77,58 @@ fn parse_sample() {
                 start_time: 0,
                 lane: 4,
                 end_time: 0,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 601,
                 lane: 2,
                 end_time: 0,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 601,
                 lane: 1,
                 end_time: 0,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 601,
                 lane: 4,
                 end_time: 939,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 601,
                 lane: 3,
                 end_time: 939,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 939,
                 lane: 2,
                 end_time: 1278,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 939,
                 lane: 1,
                 end_time: 0,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 1194,
                 lane: 4,
                 end_time: 1363,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 1278,
                 lane: 3,
                 end_time: 0,
+                ..HitObject::default()
             },
         ],
+        ..Qua::default()
     };
 
     assert_eq!(qua, gt);
@@ -122,7 +138,7 @@ fn parse_sample() {
 fn convert() {
     let file = File::open("tests/data/sample.qua").unwrap();
     let qua = from_reader(file).unwrap();
-    let map: Map = qua.into();
+    let map = Map::try_from(qua).unwrap();
 
     let gt = Map {
         song_artist: Some("Unknown".to_owned()),
@@ -192,6 +208,7 @@ fn convert() {
             },
         ],
         initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(500),
+        scroll_groups: vec![],
         lanes: vec![
             Lane {
                 objects: vec![
@@ -256,45 +273,406 @@ fn parse_actual_map() {
 fn convert_actual_map() {
     let file = File::open("tests/data/actual_map.qua").unwrap();
     let qua = from_reader(file).unwrap();
-    let _map: Map = qua.into();
+    let _map = Map::try_from(qua).unwrap();
 }
 
 #[test]
-fn base_bpm_no_durations() {
-    let qua = Qua {
-        mode: GameMode::Keys4,
-        title: None,
-        artist: None,
-        creator: None,
-        difficulty_name: None,
-        background_file: None,
-        audio_file: None,
-        bpm_does_not_affect_scroll_velocity: false,
-        initial_scroll_velocity: 0.,
+fn convert_to_mapset() {
+    let file = File::open("tests/data/sample.qua").unwrap();
+    let qua = from_reader(file).unwrap();
+    let mapset = Mapset::try_from(qua.clone()).unwrap();
+
+    assert_eq!(mapset.song_artist.as_deref(), Some("Unknown"));
+    assert_eq!(mapset.song_title.as_deref(), Some("Sample Map"));
+    assert_eq!(mapset.background_file.as_deref(), Some("background.png"));
+    assert_eq!(mapset.audio_file.as_deref(), Some("song.mp3"));
+    assert_eq!(mapset.difficulties.len(), 1);
+    assert_eq!(mapset.difficulties[0].name.as_deref(), Some("Easy"));
+    assert_eq!(mapset.difficulties[0].mapper.as_deref(), Some("YaLTeR"));
+
+    let map = Map::try_from(qua).unwrap();
+    assert_eq!(mapset.difficulties[0].chart, Chart::from(map.clone()));
+    assert_eq!(mapset.into_maps(), vec![map]);
+}
+
+const TIMING_GROUPS: &str = "\
+Mode: Keys4
+BPMDoesNotAffectScrollVelocity: true
+InitialScrollVelocity: 1
+TimingPoints: []
+SliderVelocities: []
+TimingGroups:
+  $Default: !ScrollGroup
+    InitialScrollVelocity: 1
+    ScrollVelocities: []
+  $Global: !ScrollGroup
+    InitialScrollVelocity: 1
+    ScrollVelocities: []
+  fast: !ScrollGroup
+    InitialScrollVelocity: 2
+    ScrollVelocities:
+    - StartTime: 100
+      Multiplier: 0.5
+    ColorRgb: 255,0,0
+HitObjects:
+- StartTime: 10
+  Lane: 1
+- StartTime: 20
+  Lane: 2
+  TimingGroup: fast
+- StartTime: 30
+  Lane: 3
+  TimingGroup: unknown
+";
+
+#[test]
+fn parse_timing_groups() {
+    let qua = from_reader(TIMING_GROUPS.as_bytes()).unwrap();
+
+    assert_eq!(qua.timing_groups.len(), 3);
+    assert_eq!(
+        qua.timing_groups["fast"],
+        TimingGroup::ScrollGroup(ScrollGroup {
+            initial_scroll_velocity: 2.,
+            scroll_velocities: vec![SliderVelocity {
+                start_time: 100.,
+                multiplier: 0.5,
+            }],
+            color_rgb: Some("255,0,0".to_owned()),
+        })
+    );
+    assert_eq!(qua.hit_objects[0].timing_group, None);
+    assert_eq!(qua.hit_objects[1].timing_group.as_deref(), Some("fast"));
+}
+
+#[test]
+fn convert_timing_groups() {
+    let qua = from_reader(TIMING_GROUPS.as_bytes()).unwrap();
+    let map = Map::try_from(qua).unwrap();
+
+    let regular = |millis| Object::Regular {
+        timestamp: MapTimestamp::from_millis(millis),
+    };
+
+    // Objects in unknown timing groups end up in the default group.
+    assert_eq!(
+        map.lanes,
+        vec![
+            Lane {
+                objects: vec![regular(10)],
+            },
+            Lane::new(),
+            Lane {
+                objects: vec![regular(30)],
+            },
+            Lane::new(),
+        ]
+    );
+    assert_eq!(
+        map.scroll_groups,
+        vec![
+            plitki_core::map::ScrollGroup {
+                name: GLOBAL_TIMING_GROUP.to_owned(),
+                scroll_speed_changes: vec![],
+                initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1000),
+                lanes: vec![Lane::new(); 4],
+            },
+            plitki_core::map::ScrollGroup {
+                name: "fast".to_owned(),
+                scroll_speed_changes: vec![ScrollSpeedChange {
+                    timestamp: MapTimestamp::from_millis(100),
+                    multiplier: ScrollSpeedMultiplier::new(500),
+                }],
+                initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(2000),
+                lanes: vec![
+                    Lane::new(),
+                    Lane {
+                        objects: vec![regular(20)],
+                    },
+                    Lane::new(),
+                    Lane::new(),
+                ],
+            },
+        ]
+    );
+
+    // Timing groups survive a round-trip.
+    let mut buf = Vec::new();
+    to_writer(&mut buf, &Qua::from(map.clone())).unwrap();
+    let qua = from_reader(&buf[..]).unwrap();
+    assert_eq!(qua.hit_objects[2].timing_group.as_deref(), Some("fast"));
+    assert_eq!(Map::try_from(qua).unwrap(), map);
+}
+
+const DEFAULT_AND_GLOBAL_TIMING_GROUPS: &str = "\
+Mode: Keys4
+BPMDoesNotAffectScrollVelocity: true
+InitialScrollVelocity: 1
+TimingPoints:
+- Bpm: 120
+SliderVelocities: []
+TimingGroups:
+  $Default: !ScrollGroup
+    InitialScrollVelocity: 0.5
+    ScrollVelocities:
+    - StartTime: 100
+      Multiplier: 2
+  $Global: !ScrollGroup
+    InitialScrollVelocity: 1
+    ScrollVelocities:
+    - StartTime: 200
+      Multiplier: 0.25
+HitObjects:
+- StartTime: 10
+  Lane: 1
+";
+
+#[test]
+fn default_and_global_timing_groups_round_trip() {
+    let qua = from_reader(DEFAULT_AND_GLOBAL_TIMING_GROUPS.as_bytes()).unwrap();
+    let map = Map::try_from(qua).unwrap();
+
+    // The default timing group holds the map's own SVs.
+    assert_eq!(
+        map.initial_scroll_speed_multiplier,
+        ScrollSpeedMultiplier::new(500)
+    );
+    assert_eq!(
+        map.scroll_speed_changes,
+        vec![ScrollSpeedChange {
+            timestamp: MapTimestamp::from_millis(100),
+            multiplier: ScrollSpeedMultiplier::new(2000),
+        }]
+    );
+
+    // The global timing group is kept.
+    assert_eq!(
+        map.scroll_groups,
+        vec![plitki_core::map::ScrollGroup {
+            name: GLOBAL_TIMING_GROUP.to_owned(),
+            scroll_speed_changes: vec![ScrollSpeedChange {
+                timestamp: MapTimestamp::from_millis(200),
+                multiplier: ScrollSpeedMultiplier::new(250),
+            }],
+            initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(1000),
+            lanes: vec![Lane::new(); 4],
+        }]
+    );
+
+    let mut buf = Vec::new();
+    to_writer(&mut buf, &Qua::from(map.clone())).unwrap();
+
+    let golden = fs::read_to_string("tests/data/timing_groups_written.qua").unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), golden);
+    assert_eq!(
+        Map::try_from(from_reader(golden.as_bytes()).unwrap()).unwrap(),
+        map
+    );
+}
+
+#[test]
+fn parse_full_map() {
+    let file = File::open("tests/data/full_map.qua").unwrap();
+    let qua = from_reader(file).unwrap();
+
+    let gt = Qua {
+        audio_file: Some("audio.mp3".to_owned()),
+        song_preview_time: 12345,
+        background_file: Some("bg.jpg".to_owned()),
+        banner_file: Some("banner.jpg".to_owned()),
+        map_id: 903,
+        map_set_id: 411,
+        mode: GameMode::Keys7,
+        title: Some("Sample Map".to_owned()),
+        artist: Some("Unknown".to_owned()),
+        source: Some("Nowhere".to_owned()),
+        tags: Some("sample test keysounds".to_owned()),
+        creator: Some("YaLTeR".to_owned()),
+        difficulty_name: Some("Hard".to_owned()),
+        description: Some("A map which uses every field.".to_owned()),
+        genre: Some("Test".to_owned()),
+        legacy_ln_rendering: true,
+        bpm_does_not_affect_scroll_velocity: true,
+        initial_scroll_velocity: 1.5,
+        has_scratch_key: true,
+        editor_layers: vec![
+            EditorLayer {
+                name: Some("Chords".to_owned()),
+                hidden: true,
+                color_rgb: Some("255,0,128".to_owned()),
+            },
+            EditorLayer {
+                name: Some("Scratch".to_owned()),
+                hidden: false,
+                color_rgb: Some("0,255,0".to_owned()),
+            },
+        ],
+        bookmarks: vec![
+            Bookmark {
+                start_time: 1000,
+                note: Some("Chorus".to_owned()),
+            },
+            Bookmark {
+                start_time: 2000,
+                note: None,
+            },
+        ],
+        custom_audio_samples: vec![
+            CustomAudioSample {
+                path: "kick.wav".to_owned(),
+                unaffected_by_rate: false,
+            },
+            CustomAudioSample {
+                path: "vocal.ogg".to_owned(),
+                unaffected_by_rate: true,
+            },
+        ],
+        sound_effects: vec![SoundEffect {
+            start_time: 500.,
+            sample: 2,
+            volume: 80,
+        }],
         timing_points: vec![
             TimingPoint {
-                start_time: 100.0,
-                bpm: 1.0,
-                signature: 0,
+                start_time: 100.,
+                bpm: 150.,
+                signature: 4,
             },
             TimingPoint {
-                start_time: 200.0,
-                bpm: 2.0,
-                signature: 0,
+                start_time: 2100.,
+                bpm: 75.5,
+                signature: 3,
             },
         ],
-        slider_velocities: vec![],
+        slider_velocities: vec![SliderVelocity {
+            start_time: 1500.,
+            multiplier: 0.5,
+        }],
+        timing_groups: vec![(
+            "Chords".to_owned(),
+            TimingGroup::ScrollGroup(ScrollGroup {
+                initial_scroll_velocity: 2.,
+                scroll_velocities: vec![SliderVelocity {
+                    start_time: 1000.,
+                    multiplier: -1.,
+                }],
+                color_rgb: Some("255,0,128".to_owned()),
+            }),
+        )]
+        .into_iter()
+        .collect(),
+        hit_objects: vec![
+            HitObject {
+                start_time: 100,
+                lane: 1,
+                end_time: 0,
+                hit_sound: HitSounds::default(),
+                key_sounds: vec![],
+                editor_layer: 0,
+                timing_group: None,
+            },
+            HitObject {
+                start_time: 500,
+                lane: 8,
+                end_time: 900,
+                hit_sound: HitSounds::WHISTLE | HitSounds::CLAP,
+                key_sounds: vec![KeySound {
+                    sample: 1,
+                    volume: 100,
+                }],
+                editor_layer: 2,
+                timing_group: None,
+            },
+            HitObject {
+                start_time: 1000,
+                lane: 4,
+                end_time: 0,
+                hit_sound: HitSounds::NORMAL,
+                key_sounds: vec![
+                    KeySound {
+                        sample: 1,
+                        volume: 50,
+                    },
+                    KeySound {
+                        sample: 2,
+                        volume: 100,
+                    },
+                ],
+                editor_layer: 1,
+                timing_group: Some("Chords".to_owned()),
+            },
+        ],
+    };
+
+    assert_eq!(qua, gt);
+    assert_eq!(qua.lane_count(), 8);
+}
+
+#[test]
+fn write_full_map() {
+    let file = File::open("tests/data/full_map.qua").unwrap();
+    let qua = from_reader(file).unwrap();
+
+    let mut buf = Vec::new();
+    to_writer(&mut buf, &qua).unwrap();
+
+    let golden = fs::read_to_string("tests/data/full_map_written.qua").unwrap();
+    assert_eq!(String::from_utf8(buf).unwrap(), golden);
+    assert_eq!(from_reader(golden.as_bytes()).unwrap(), qua);
+}
+
+#[test]
+fn write_actual_map() {
+    let file = File::open("tests/data/actual_map.qua").unwrap();
+    let qua = from_reader(file).unwrap();
+
+    let mut buf = Vec::new();
+    to_writer(&mut buf, &qua).unwrap();
+    assert_eq!(from_reader(&buf[..]).unwrap(), qua);
+}
+
+#[test]
+fn parse_hit_sounds() {
+    let parse = |value: &str| {
+        let text = format!("Lane: 1\nHitSound: {}\n", value);
+        serde_yaml::from_str::<HitObject>(&text).map(|x| x.hit_sound)
+    };
+
+    assert_eq!(parse("Normal").unwrap(), HitSounds::NORMAL);
+    assert_eq!(
+        parse("Finish, Whistle").unwrap(),
+        HitSounds::WHISTLE | HitSounds::FINISH
+    );
+    assert_eq!(parse("10").unwrap(), HitSounds::WHISTLE | HitSounds::CLAP);
+    assert!(parse("Drum").is_err());
+    assert!(parse("16").is_err());
+}
+
+#[test]
+fn convert_scratch_key() {
+    let qua = Qua {
+        has_scratch_key: true,
         hit_objects: vec![HitObject {
-            start_time: 0,
-            lane: 0,
+            start_time: 100,
+            lane: 5,
             end_time: 0,
+            ..HitObject::default()
         }],
+        ..Qua::default()
     };
 
-    #[allow(clippy::float_cmp)]
-    {
-        assert_eq!(qua.base_bpm(), 1.);
-    }
+    let map = Map::try_from(qua).unwrap();
+    assert_eq!(map.lane_count(), 5);
+    assert_eq!(
+        map.lanes[4].objects,
+        vec![Object::Regular {
+            timestamp: MapTimestamp::from_millis(100)
+        }]
+    );
+
+    let qua: Qua = map.into();
+    assert_eq!(qua.mode, GameMode::Keys4);
+    assert!(qua.has_scratch_key);
 }
 
 #[test]
@@ -330,16 +708,19 @@ fn timing_points_override_svs() {
                 start_time: 0,
                 lane: 1,
                 end_time: 0,
+                ..HitObject::default()
             },
             HitObject {
                 start_time: 11,
                 lane: 1,
                 end_time: 0,
+                ..HitObject::default()
             },
         ],
+        ..Qua::default()
     };
 
-    let map: Map = qua.into();
+    let map = Map::try_from(qua).unwrap();
 
     let gt = Map {
         song_artist: None,
@@ -377,6 +758,7 @@ fn timing_points_override_svs() {
             },
         ],
         initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+        scroll_groups: vec![],
         lanes: vec![
             Lane {
                 objects: vec![
@@ -419,9 +801,10 @@ fn sv_before_first_timing_point() {
             multiplier: 10.0,
         }],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.into();
+    let map = Map::try_from(qua).unwrap();
 
     let gt = Map {
         song_artist: None,
@@ -443,6 +826,7 @@ fn sv_before_first_timing_point() {
             multiplier: ScrollSpeedMultiplier::default(),
         }],
         initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(10_000),
+        scroll_groups: vec![],
         lanes: vec![
             Lane { objects: vec![] },
             Lane { objects: vec![] },
@@ -476,9 +860,10 @@ fn sv_at_first_timing_point() {
             multiplier: 10.0,
         }],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.into();
+    let map = Map::try_from(qua).unwrap();
 
     let gt = Map {
         song_artist: None,
@@ -497,6 +882,7 @@ fn sv_at_first_timing_point() {
         }],
         scroll_speed_changes: vec![],
         initial_scroll_speed_multiplier: ScrollSpeedMultiplier::new(10_000),
+        scroll_groups: vec![],
         lanes: vec![
             Lane { objects: vec![] },
             Lane { objects: vec![] },
@@ -534,9 +920,10 @@ fn sv_too_large() {
         ],
         slider_velocities: vec![],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.into();
+    let map = Map::try_from(qua).unwrap();
 
     let gt = Map {
         song_artist: None,
@@ -568,6 +955,7 @@ fn sv_too_large() {
             multiplier: ScrollSpeedMultiplier::new(2i32.pow(24) - 1),
         }],
         initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+        scroll_groups: vec![],
         lanes: vec![
             Lane { objects: vec![] },
             Lane { objects: vec![] },
@@ -598,9 +986,10 @@ fn proptest_regression_1() {
         }],
         slider_velocities: vec![],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -631,9 +1020,10 @@ fn proptest_regression_2() {
             multiplier: -8.0,
         }],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -664,9 +1054,10 @@ fn proptest_regression_3() {
             multiplier: -8.0,
         }],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -708,9 +1099,10 @@ fn proptest_regression_4() {
             },
         ],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -754,10 +1146,12 @@ fn proptest_regression_5() {
             start_time: 0,
             lane: 1,
             end_time: 0,
+            ..HitObject::default()
         }],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -805,10 +1199,12 @@ fn proptest_regression_6() {
             start_time: 37920,
             lane: 1,
             end_time: 0,
+            ..HitObject::default()
         }],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -853,10 +1249,12 @@ fn proptest_regression_7() {
             start_time: 0,
             lane: 1,
             end_time: 201_025,
+            ..HitObject::default()
         }],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -900,9 +1298,10 @@ fn proptest_regression_8() {
             multiplier: 2.0,
         }],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
-    let map: Map = qua.clone().into();
+    let map = Map::try_from(qua.clone()).unwrap();
     let mut qua2: Qua = map.into();
 
     qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -917,6 +1316,115 @@ fn proptest_regression_8() {
     assert_eq!(qua, qua2);
 }
 
+fn empty_qua() -> Qua {
+    Qua {
+        mode: GameMode::Keys4,
+        title: None,
+        artist: None,
+        creator: None,
+        difficulty_name: None,
+        background_file: None,
+        audio_file: None,
+        bpm_does_not_affect_scroll_velocity: false,
+        initial_scroll_velocity: 0.,
+        timing_points: vec![],
+        slider_velocities: vec![],
+        hit_objects: vec![],
+        ..Qua::default()
+    }
+}
+
+#[test]
+fn convert_empty() {
+    let map = Map::try_from(empty_qua()).unwrap();
+    assert_eq!(map.lane_count(), 4);
+    assert_eq!(
+        map.initial_scroll_speed_multiplier,
+        ScrollSpeedMultiplier::new(1000)
+    );
+}
+
+#[test]
+fn conversion_errors() {
+    let with_timing_point = |start_time, bpm| Qua {
+        timing_points: vec![TimingPoint {
+            start_time,
+            bpm,
+            signature: 4,
+        }],
+        ..empty_qua()
+    };
+    let with_sv = |start_time, multiplier| Qua {
+        slider_velocities: vec![SliderVelocity {
+            start_time,
+            multiplier,
+        }],
+        ..empty_qua()
+    };
+    let with_hit_object = |start_time, lane, end_time| Qua {
+        hit_objects: vec![HitObject {
+            start_time,
+            lane,
+            end_time,
+            ..HitObject::default()
+        }],
+        ..empty_qua()
+    };
+
+    assert!(matches!(
+        Map::try_from(with_timing_point(0., f32::NAN)),
+        Err(ConversionError::InvalidBpm(_))
+    ));
+    assert!(matches!(
+        Map::try_from(with_timing_point(0., f32::INFINITY)),
+        Err(ConversionError::InvalidBpm(_))
+    ));
+    assert!(matches!(
+        Map::try_from(with_timing_point(f32::NAN, 120.)),
+        Err(ConversionError::TimestampOutOfRange(_))
+    ));
+    assert!(matches!(
+        Map::try_from(with_timing_point(1e10, 120.)),
+        Err(ConversionError::TimestampOutOfRange(_))
+    ));
+    assert!(matches!(
+        Map::try_from(with_sv(0., f32::NEG_INFINITY)),
+        Err(ConversionError::InvalidScrollVelocity(_))
+    ));
+    assert!(matches!(
+        Map::try_from(with_sv(f32::INFINITY, 1.)),
+        Err(ConversionError::TimestampOutOfRange(_))
+    ));
+    assert_eq!(
+        Map::try_from(with_hit_object(0, 0, 0)),
+        Err(ConversionError::InvalidLane(0))
+    );
+    assert_eq!(
+        Map::try_from(with_hit_object(0, 5, 0)),
+        Err(ConversionError::InvalidLane(5))
+    );
+    assert_eq!(
+        Map::try_from(with_hit_object(2i32.pow(30), 1, 0)),
+        Err(ConversionError::TimestampOutOfRange(f64::from(
+            2i32.pow(30)
+        )))
+    );
+    assert_eq!(
+        Map::try_from(with_hit_object(100, 1, 100)),
+        Err(ConversionError::InvalidLongNote {
+            start_time: 100,
+            end_time: 100,
+        })
+    );
+    assert_eq!(
+        Map::try_from(with_hit_object(100, 1, 50)),
+        Err(ConversionError::InvalidLongNote {
+            start_time: 100,
+            end_time: 50,
+        })
+    );
+}
+
 fn hit_object_compare(a: &HitObject, b: &HitObject) -> Ordering {
     a.start_time
         .cmp(&b.start_time)
@@ -1003,6 +1511,7 @@ fn normalize_svs_test() {
             },
         ],
         hit_objects: vec![],
+        ..Qua::default()
     };
 
     normalize_svs(&mut qua);
@@ -1024,12 +1533,12 @@ fn normalize_svs_test() {
 
 prop_compose! {
     fn arbitrary_hit_object(mode: GameMode)
-                           (start_time in 0..2i32.pow(30) / 100, // TODO
+                           (start_time in 0..2i32.pow(30) / 100 - 1, // TODO
                             is_long_note in any::<bool>())
                            (start_time in Just(start_time),
                             lane in 1..=mode.lane_count(),
                             end_time in if is_long_note {
-                                (start_time..2i32.pow(30) / 100).boxed()
+                                (start_time + 1..2i32.pow(30) / 100).boxed()
                             } else {
                                 Just(0).boxed()
                             })
@@ -1038,6 +1547,7 @@ prop_compose! {
             start_time,
             lane: lane as i32,
             end_time,
+            ..HitObject::default()
         }
     }
 }
@@ -1109,6 +1619,181 @@ prop_compose! {
             hit_objects,
             timing_points,
             slider_velocities,
+            ..Qua::default()
+        }
+    }
+}
+
+fn arbitrary_hit_sounds() -> impl Strategy<Value = HitSounds> {
+    prop::sample::subsequence(
+        vec![
+            HitSounds::NORMAL,
+            HitSounds::WHISTLE,
+            HitSounds::FINISH,
+            HitSounds::CLAP,
+        ],
+        0..=4,
+    )
+    .prop_map(|flags| flags.into_iter().fold(HitSounds::default(), |a, b| a | b))
+}
+
+prop_compose! {
+    fn arbitrary_key_sound()(sample in 1..8, volume in 0..=100) -> KeySound {
+        KeySound { sample, volume }
+    }
+}
+
+prop_compose! {
+    fn arbitrary_full_hit_object(hit_object: HitObject)
+                                (hit_sound in arbitrary_hit_sounds(),
+                                 key_sounds in prop::collection::vec(arbitrary_key_sound(), 0..3),
+                                 editor_layer in 0..4)
+                                -> HitObject {
+        HitObject {
+            hit_sound,
+            key_sounds,
+            editor_layer,
+            ..hit_object.clone()
+        }
+    }
+}
+
+prop_compose! {
+    fn arbitrary_editor_layer()(name in prop::option::of(any::<String>()),
+                                hidden in any::<bool>(),
+                                color in prop::option::of((0..=255u8, 0..=255u8, 0..=255u8)))
+                               -> EditorLayer {
+        EditorLayer {
+            name,
+            hidden,
+            color_rgb: color.map(|(r, g, b)| format!("{},{},{}", r, g, b)),
+        }
+    }
+}
+
+prop_compose! {
+    fn arbitrary_full_qua()
+                         (qua in arbitrary_qua())
+                         (hit_objects in qua
+                             .hit_objects
+                             .iter()
+                             .cloned()
+                             .map(arbitrary_full_hit_object)
+                             .collect::<Vec<_>>(),
+                          qua in Just(qua),
+                          song_preview_time in any::<i32>(),
+                          banner_file in prop::option::of(any::<String>()),
+                          map_id in any::<i32>(),
+                          map_set_id in any::<i32>(),
+                          source in prop::option::of(any::<String>()),
+                          tags in prop::option::of(any::<String>()),
+                          description in prop::option::of(any::<String>()),
+                          genre in prop::option::of(any::<String>()),
+                          legacy_ln_rendering in any::<bool>(),
+                          has_scratch_key in any::<bool>(),
+                          editor_layers in prop::collection::vec(arbitrary_editor_layer(), 0..4),
+                          bookmarks in prop::collection::vec(
+                              (any::<i32>(), prop::option::of(any::<String>()))
+                                  .prop_map(|(start_time, note)| Bookmark { start_time, note }),
+                              0..4,
+                          ),
+                          custom_audio_samples in prop::collection::vec(
+                              (any::<String>(), any::<bool>()).prop_map(
+                                  |(path, unaffected_by_rate)| CustomAudioSample {
+                                      path,
+                                      unaffected_by_rate,
+                                  },
+                              ),
+                              0..4,
+                          ),
+                          sound_effects in prop::collection::vec(
+                              (-1000..1000, 1..8, 0..=100).prop_map(|(start_time, sample, volume)| {
+                                  SoundEffect {
+                                      start_time: start_time as f32 * 100.,
+                                      sample,
+                                      volume,
+                                  }
+                              }),
+                              0..4,
+                          ))
+                         -> Qua {
+        Qua {
+            song_preview_time,
+            banner_file,
+            map_id,
+            map_set_id,
+            source,
+            tags,
+            description,
+            genre,
+            legacy_ln_rendering,
+            has_scratch_key,
+            editor_layers,
+            bookmarks,
+            custom_audio_samples,
+            sound_effects,
+            hit_objects,
+            ..qua
+        }
+    }
+}
+
+/// Returns a strategy for `f32` which includes special values like NaN and infinities.
+fn arbitrary_f32() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        any::<f32>(),
+        -1000f32..1000.,
+        prop::sample::select(&[0., f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 1e10, -1e10][..]),
+    ]
+}
+
+prop_compose! {
+    fn arbitrary_invalid_qua()
+                            (mode in arbitrary_game_mode(),
+                             bpm_does_not_affect_scroll_velocity in any::<bool>(),
+                             initial_scroll_velocity in arbitrary_f32(),
+                             timing_points in prop::collection::vec(
+                                 (arbitrary_f32(), arbitrary_f32(), 0..300i32),
+                                 0..8,
+                             ),
+                             slider_velocities in prop::collection::vec(
+                                 (arbitrary_f32(), arbitrary_f32()),
+                                 0..8,
+                             ),
+                             hit_objects in prop::collection::vec(
+                                 (any::<i32>(), -1..9i32, prop_oneof![Just(0), any::<i32>()]),
+                                 0..8,
+                             ))
+                            -> Qua {
+        Qua {
+            mode,
+            bpm_does_not_affect_scroll_velocity,
+            initial_scroll_velocity,
+            timing_points: timing_points
+                .into_iter()
+                .map(|(start_time, bpm, signature)| TimingPoint {
+                    start_time,
+                    bpm,
+                    signature,
+                })
+                .collect(),
+            slider_velocities: slider_velocities
+                .into_iter()
+                .map(|(start_time, multiplier)| SliderVelocity {
+                    start_time,
+                    multiplier,
+                })
+                .collect(),
+            hit_objects: hit_objects
+                .into_iter()
+                .map(|(start_time, lane, end_time)| HitObject {
+                    start_time,
+                    lane,
+                    end_time,
+                    ..HitObject::default()
+                })
+                .collect(),
+            ..empty_qua()
         }
     }
 }
@@ -1116,7 +1801,7 @@ prop_compose! {
 proptest! {
     #[test]
     fn qua_to_map_and_back(mut qua in arbitrary_qua()) {
-        let map: Map = qua.clone().into();
+        let map = Map::try_from(qua.clone()).unwrap();
         let mut qua2: Qua = map.into();
 
         qua.hit_objects.sort_unstable_by(hit_object_compare);
@@ -1130,7 +1815,7 @@ proptest! {
     }
 
     #[test]
-    fn qua_serialize_deserialize(mut qua in arbitrary_qua()) {
+    fn qua_serialize_deserialize(mut qua in arbitrary_full_qua()) {
         let mut buf = Vec::new();
         to_writer(&mut buf, &qua).unwrap();
         let mut qua2 = from_reader(&buf[..]).unwrap();
@@ -1140,4 +1825,21 @@ proptest! {
 
         prop_assert_eq!(qua, qua2);
     }
+
+    #[test]
+    fn from_reader_and_conversion_dont_panic(text in "\\PC*") {
+        if let Ok(qua) = from_reader(text.as_bytes()) {
+            let _ = Map::try_from(qua);
+        }
+    }
+
+    #[test]
+    fn conversion_doesnt_panic(qua in arbitrary_invalid_qua()) {
+        let mut buf = Vec::new();
+        to_writer(&mut buf, &qua).unwrap();
+
+        if let Ok(qua) = from_reader(&buf[..]) {
+            let _ = Map::try_from(qua);
+        }
+    }
 }
@@ -0,0 +1,869 @@
+//! Reading of the StepMania `.sm` and `.ssc` map formats.
+//!
+//! A simfile contains any number of charts (difficulties), each of which can be converted into a
+//! `Map` with [`Simfile::convert`]. Notes which plitki can't represent, such as mines and lifts,
+//! are reported in [`Conversion::unsupported`] rather than silently dropped.
+
+#![allow(clippy::inconsistent_digit_grouping)]
+
+use std::{
+    error, fmt,
+    io::{self, Read},
+};
+
+use plitki_core::{
+    map::{Lane, Map, ScrollSpeedChange, TimeSignature, TimingPoint},
+    object::Object,
+    scroll::ScrollSpeedMultiplier,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+
+mod msd;
+
+/// A value attached to a beat, like a BPM change or a stop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatValue {
+    pub beat: f64,
+    pub value: f64,
+}
+
+/// A `#SPEEDS` change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Speed {
+    pub beat: f64,
+    /// Scroll speed ratio to change to.
+    pub ratio: f64,
+    /// Duration of the transition to the new ratio.
+    pub duration: f64,
+    /// Whether `duration` is in seconds rather than beats.
+    pub in_seconds: bool,
+}
+
+/// Timing data of a simfile or of a single chart.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TimingData {
+    /// Negated time of beat 0, in seconds.
+    pub offset: f64,
+    /// BPM changes.
+    pub bpms: Vec<BeatValue>,
+    /// Pauses in seconds which happen after the notes on their beat.
+    pub stops: Vec<BeatValue>,
+    /// Pauses in seconds which happen before the notes on their beat.
+    pub delays: Vec<BeatValue>,
+    /// Lengths in beats of skipped sections (`#WARPS`).
+    pub warps: Vec<BeatValue>,
+    /// Scroll rate changes which apply to the scrolling of the notes.
+    pub scrolls: Vec<BeatValue>,
+    /// Scroll speed changes.
+    pub speeds: Vec<Speed>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum NoteKind {
+    Tap,
+    HoldHead,
+    RollHead,
+    /// Hold or roll tail.
+    Tail,
+    Mine,
+    Lift,
+    Fake,
+    AutoKeysound,
+}
+
+impl NoteKind {
+    fn from_char(c: char) -> Option<Option<Self>> {
+        Some(match c {
+            '0' => None,
+            '1' => Some(NoteKind::Tap),
+            '2' => Some(NoteKind::HoldHead),
+            '3' => Some(NoteKind::Tail),
+            '4' => Some(NoteKind::RollHead),
+            'M' | 'm' => Some(NoteKind::Mine),
+            'L' | 'l' => Some(NoteKind::Lift),
+            'F' | 'f' => Some(NoteKind::Fake),
+            'K' | 'k' => Some(NoteKind::AutoKeysound),
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    pub beat: f64,
+    pub column: usize,
+    pub kind: NoteKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    /// Steps type, like `dance-single` or `pump-double`.
+    pub steps_type: String,
+    pub description: String,
+    /// Difficulty, like `Beginner` or `Challenge`.
+    pub difficulty: String,
+    pub meter: i32,
+    pub credit: Option<String>,
+    /// Chart-specific timing data of `.ssc` charts with split timing.
+    pub timing: Option<TimingData>,
+    pub lane_count: usize,
+    /// Notes, sorted by beat and column.
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simfile {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub artist: Option<String>,
+    pub credit: Option<String>,
+    pub music: Option<String>,
+    pub background: Option<String>,
+    pub timing: TimingData,
+    pub charts: Vec<Chart>,
+}
+
+/// Something in a chart that couldn't be converted to a `Map`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unsupported {
+    /// A note of a kind that plitki doesn't have, like a mine or a lift.
+    Note(Note),
+    /// A note inside a warp, which StepMania skips.
+    WarpedNote(Note),
+}
+
+/// Result of converting a chart into a `Map`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub map: Map,
+    /// Things which were left out of `map` because plitki doesn't support them.
+    pub unsupported: Vec<Unsupported>,
+}
+
+/// Error returned when reading a simfile fails.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error occurred.
+    Io(io::Error),
+    /// The value of the tag with this name couldn't be parsed.
+    InvalidValue(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::InvalidValue(tag) => write!(f, "invalid #{} value", tag),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl Chart {
+    fn new() -> Self {
+        Self {
+            steps_type: String::new(),
+            description: String::new(),
+            difficulty: String::new(),
+            meter: 0,
+            credit: None,
+            timing: None,
+            lane_count: 0,
+            notes: Vec::new(),
+        }
+    }
+}
+
+impl TimingData {
+    /// Returns the BPM which is in effect the longest between `start` and `end`.
+    fn base_bpm(&self, start: f64, end: f64) -> f64 {
+        let mut durations: Vec<(f64, f64)> = Vec::new();
+
+        for (i, bpm) in self.bpms.iter().enumerate() {
+            let bpm_start = if i == 0 { start } else { bpm.beat.max(start) };
+            let bpm_end = self.bpms.get(i + 1).map_or(end, |next| next.beat.min(end));
+            if bpm_end <= bpm_start || bpm.value <= 0. {
+                continue;
+            }
+
+            let duration = (bpm_end - bpm_start) * 60. / bpm.value;
+            #[allow(clippy::float_cmp)]
+            match durations.iter_mut().find(|(value, _)| *value == bpm.value) {
+                Some((_, total)) => *total += duration,
+                None => durations.push((bpm.value, duration)),
+            }
+        }
+
+        durations
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(value, _)| value)
+            .or_else(|| self.bpms.first().map(|x| x.value))
+            .unwrap_or(60.)
+    }
+}
+
+/// Timing data prepared for converting beats into times.
+///
+/// Negative BPMs, stops and delays make the time go back, while `#WARPS` and zero BPMs take no
+/// time at all. The beats skipped this way are warps, and their notes aren't played.
+struct Timeline<'a> {
+    timing: &'a TimingData,
+    /// Beat ranges which take no time, sorted and merged.
+    skipped: Vec<(f64, f64)>,
+    /// Latest time reached before the first BPM change.
+    initial_peak: f64,
+    /// Beats where the time can jump or go back, each with the latest time reached up to and
+    /// including it, sorted by beat.
+    peaks: Vec<(f64, f64)>,
+}
+
+impl<'a> Timeline<'a> {
+    #[allow(clippy::float_cmp)]
+    fn new(timing: &'a TimingData) -> Self {
+        let mut skipped: Vec<(f64, f64)> = timing
+            .warps
+            .iter()
+            .filter(|x| x.value > 0.)
+            .map(|x| (x.beat, x.beat + x.value))
+            .collect();
+        for (i, bpm) in timing.bpms.iter().enumerate() {
+            if bpm.value == 0. {
+                let start = if i == 0 { f64::NEG_INFINITY } else { bpm.beat };
+                let end = timing
+                    .bpms
+                    .get(i + 1)
+                    .map_or(f64::INFINITY, |next| next.beat);
+                skipped.push((start, end));
+            }
+        }
+        skipped.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(skipped.len());
+        for (start, end) in skipped {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        // With a negative first BPM, the time before it goes back from infinity.
+        let initial_peak = match timing.bpms.first() {
+            Some(bpm) if bpm.value < 0. => f64::INFINITY,
+            _ => f64::NEG_INFINITY,
+        };
+
+        let mut timeline = Self {
+            timing,
+            skipped: merged,
+            initial_peak,
+            peaks: Vec::new(),
+        };
+
+        let mut beats: Vec<f64> = timing
+            .bpms
+            .iter()
+            .chain(&timing.stops)
+            .chain(&timing.delays)
+            .map(|x| x.beat)
+            .chain(
+                timeline
+                    .skipped
+                    .iter()
+                    .flat_map(|&(start, end)| [start, end]),
+            )
+            .filter(|x| x.is_finite())
+            .collect();
+        beats.sort_by(f64::total_cmp);
+        beats.dedup();
+
+        let mut peak = initial_peak;
+        for beat in beats {
+            let arrival = timeline.arrival_time(beat);
+            let delayed = arrival + pause_at(&timing.delays, beat);
+            let stopped = delayed + pause_at(&timing.stops, beat);
+            peak = peak.max(arrival).max(delayed).max(stopped);
+            timeline.peaks.push((beat, peak));
+        }
+
+        timeline
+    }
+
+    /// Returns the time in seconds at which `beat` is reached, before the delays on `beat` and
+    /// the stops on `beat`.
+    #[allow(clippy::float_cmp)]
+    fn arrival_time(&self, beat: f64) -> f64 {
+        let timing = self.timing;
+
+        let mut elapsed = 0.;
+        let (from, to) = if beat < 0. { (beat, 0.) } else { (0., beat) };
+
+        for (i, bpm) in timing.bpms.iter().enumerate() {
+            // The first BPM also applies before its beat.
+            let start = if i == 0 { f64::NEG_INFINITY } else { bpm.beat };
+            let end = timing
+                .bpms
+                .get(i + 1)
+                .map_or(f64::INFINITY, |next| next.beat);
+
+            let (start, end) = (start.max(from), end.min(to));
+            if end <= start {
+                continue;
+            }
+
+            let length = end - start - self.skipped_length(start, end);
+            if length > 0. && bpm.value != 0. {
+                elapsed += length * 60. / bpm.value;
+            }
+        }
+
+        if beat < 0. {
+            elapsed = -elapsed;
+        }
+
+        let pauses: f64 = timing
+            .stops
+            .iter()
+            .chain(&timing.delays)
+            .filter(|x| x.beat < beat)
+            .map(|x| x.value)
+            .sum();
+
+        elapsed + pauses - timing.offset
+    }
+
+    /// Returns the number of beats between `start` and `end` which take no time.
+    fn skipped_length(&self, start: f64, end: f64) -> f64 {
+        self.skipped
+            .iter()
+            .map(|&(skip_start, skip_end)| (skip_end.min(end) - skip_start.max(start)).max(0.))
+            .sum()
+    }
+
+    /// Returns the time in seconds of notes on `beat`.
+    fn note_time(&self, beat: f64) -> f64 {
+        self.arrival_time(beat) + pause_at(&self.timing.delays, beat)
+    }
+
+    /// Returns the latest time in seconds reached before the notes on `beat`.
+    ///
+    /// This is where a warp containing `beat` ends.
+    fn peak_before(&self, beat: f64) -> f64 {
+        let index = self.peaks.partition_point(|x| x.0 < beat);
+        let peak = match index.checked_sub(1) {
+            Some(index) => self.peaks[index].1,
+            None => self.initial_peak,
+        };
+        peak.max(self.arrival_time(beat))
+    }
+
+    /// Returns whether the notes on `beat` are inside a warp.
+    ///
+    /// Like in StepMania, a warp starts right at the beat where the time starts going back.
+    fn is_warped(&self, beat: f64) -> bool {
+        if self
+            .skipped
+            .iter()
+            .any(|&(start, end)| start <= beat && beat < end)
+        {
+            return true;
+        }
+
+        let bpm = value_at(&self.timing.bpms, beat)
+            .or_else(|| self.timing.bpms.first().map(|x| x.value))
+            .unwrap_or(0.);
+        if bpm < 0. || pause_at(&self.timing.stops, beat) < 0. {
+            return true;
+        }
+
+        MapTimestamp::saturating_from_secs_f64(self.note_time(beat))
+            < MapTimestamp::saturating_from_secs_f64(self.peak_before(beat))
+    }
+
+    /// Returns `time` of something on `beat`, moved to the end of the warp if `beat` is inside
+    /// one.
+    fn unwarped(&self, beat: f64, time: f64) -> f64 {
+        time.max(self.peak_before(beat))
+    }
+}
+
+/// Returns the value on `beat`, or `0` if there's none.
+fn pause_at(values: &[BeatValue], beat: f64) -> f64 {
+    #[allow(clippy::float_cmp)]
+    values
+        .iter()
+        .filter(|x| x.beat == beat)
+        .map(|x| x.value)
+        .sum()
+}
+
+/// Adds `object` made from `note` to the end of `objects`.
+///
+/// If the object would overlap the previous one, which can happen at the end of a warp, the note
+/// is reported as warped instead.
+fn push_object(
+    objects: &mut Vec<Object>,
+    object: Object,
+    note: Note,
+    unsupported: &mut Vec<Unsupported>,
+) {
+    match objects.last() {
+        Some(last) if last.end_timestamp() >= object.start_timestamp() => {
+            unsupported.push(Unsupported::WarpedNote(note))
+        }
+        _ => objects.push(object),
+    }
+}
+
+/// Returns the last value at or before `beat`.
+fn value_at(values: &[BeatValue], beat: f64) -> Option<f64> {
+    values
+        .iter()
+        .take_while(|x| x.beat <= beat)
+        .last()
+        .map(|x| x.value)
+}
+
+impl Simfile {
+    /// Converts the chart at `index` into a `Map`.
+    ///
+    /// Holds and rolls become long notes. Stops, delays, scrolls and speeds become scroll speed
+    /// changes, relative to the BPM that lasts the longest. Speed changes take effect instantly
+    /// rather than over their duration.
+    ///
+    /// Notes inside warps, which come from `#WARPS` and from negative BPMs, stops and delays, are
+    /// left out like StepMania does, and reported in [`Conversion::unsupported`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn convert(&self, index: usize) -> Conversion {
+        let chart = &self.charts[index];
+        let timing = chart.timing.as_ref().unwrap_or(&self.timing);
+
+        let timeline = Timeline::new(timing);
+        let mut unsupported = Vec::new();
+
+        // Objects.
+        let mut lanes = vec![Lane::new(); chart.lane_count];
+        let mut heads: Vec<Option<Note>> = vec![None; chart.lane_count];
+
+        for note in &chart.notes {
+            let objects = &mut lanes[note.column].objects;
+            let head = &mut heads[note.column];
+
+            match note.kind {
+                NoteKind::Tap | NoteKind::HoldHead | NoteKind::RollHead => {
+                    // A head without a tail is played as a tap.
+                    if let Some(head) = head.take() {
+                        let object = Object::Regular {
+                            timestamp: MapTimestamp::saturating_from_secs_f64(
+                                timeline.note_time(head.beat),
+                            ),
+                        };
+                        push_object(objects, object, head, &mut unsupported);
+                    }
+
+                    if timeline.is_warped(note.beat) {
+                        unsupported.push(Unsupported::WarpedNote(*note));
+                    } else if note.kind == NoteKind::Tap {
+                        let object = Object::Regular {
+                            timestamp: MapTimestamp::saturating_from_secs_f64(
+                                timeline.note_time(note.beat),
+                            ),
+                        };
+                        push_object(objects, object, *note, &mut unsupported);
+                    } else {
+                        *head = Some(*note);
+                    }
+                }
+                NoteKind::Tail => {
+                    // Tails without a head don't mean anything.
+                    if let Some(head) = head.take() {
+                        let start =
+                            MapTimestamp::saturating_from_secs_f64(timeline.note_time(head.beat));
+                        // A tail inside a warp ends the long note where the warp ends.
+                        let end = timeline.unwarped(note.beat, timeline.note_time(note.beat));
+                        let end = MapTimestamp::saturating_from_secs_f64(end);
+
+                        let object = if end > start {
+                            Object::LongNote { start, end }
+                        } else {
+                            Object::Regular { timestamp: start }
+                        };
+                        push_object(objects, object, head, &mut unsupported);
+                    }
+                }
+                NoteKind::Mine | NoteKind::Lift | NoteKind::Fake | NoteKind::AutoKeysound => {
+                    unsupported.push(Unsupported::Note(*note))
+                }
+            }
+        }
+
+        for (lane, head) in lanes.iter_mut().zip(heads) {
+            if let Some(head) = head {
+                let object = Object::Regular {
+                    timestamp: MapTimestamp::saturating_from_secs_f64(
+                        timeline.note_time(head.beat),
+                    ),
+                };
+                push_object(&mut lane.objects, object, head, &mut unsupported);
+            }
+        }
+
+        // Timing points.
+        let mut timing_points = Vec::new();
+        let mut push_timing_point = |beat: f64, time: f64| {
+            if let Some(bpm) = value_at(&timing.bpms, beat).filter(|&x| x > 0.) {
+                timing_points.push(TimingPoint {
+                    timestamp: MapTimestamp::saturating_from_secs_f64(
+                        timeline.unwarped(beat, time),
+                    ),
+                    beat_duration: MapTimestampDifference::from_milli_hundredths(
+                        (60_000_00. / bpm).round().min(f64::from(i32::MAX)) as i32,
+                    ),
+                    signature: TimeSignature {
+                        beat_count: 4,
+                        beat_unit: 4,
+                    },
+                });
+            }
+        };
+
+        for bpm in &timing.bpms {
+            push_timing_point(bpm.beat, timeline.note_time(bpm.beat));
+        }
+
+        // Stops and delays shift the beats, so restart the beat lines after them.
+        for pause in timing.stops.iter().chain(&timing.delays) {
+            if pause.value > 0. {
+                push_timing_point(
+                    pause.beat,
+                    timeline.arrival_time(pause.beat)
+                        + pause_at(&timing.stops, pause.beat)
+                        + pause_at(&timing.delays, pause.beat),
+                );
+            }
+        }
+
+        // Scroll speed changes.
+        let first_beat = chart.notes.first().map_or(0., |x| x.beat.min(0.));
+        let last_beat = chart.notes.last().map_or(0., |x| x.beat);
+        let base_bpm = timing.base_bpm(first_beat, last_beat);
+
+        let multiplier_at = |beat: f64| {
+            let bpm = value_at(&timing.bpms, beat)
+                .or_else(|| timing.bpms.first().map(|x| x.value))
+                .unwrap_or(base_bpm)
+                .max(0.);
+            let scroll = value_at(&timing.scrolls, beat).unwrap_or(1.);
+            let speed = timing
+                .speeds
+                .iter()
+                .take_while(|x| x.beat <= beat)
+                .last()
+                .map_or(1., |x| x.ratio);
+
+            ScrollSpeedMultiplier::saturating_from_f32((bpm / base_bpm * scroll * speed) as f32)
+        };
+
+        let mut beats: Vec<f64> = timing
+            .bpms
+            .iter()
+            .chain(&timing.stops)
+            .chain(&timing.delays)
+            .chain(&timing.scrolls)
+            .map(|x| x.beat)
+            .chain(timing.speeds.iter().map(|x| x.beat))
+            .collect();
+        beats.sort_by(f64::total_cmp);
+        beats.dedup();
+
+        let mut scroll_speed_changes = Vec::new();
+        for beat in beats {
+            let mut time = timeline.arrival_time(beat);
+
+            let pause = pause_at(&timing.stops, beat) + pause_at(&timing.delays, beat);
+            if pause > 0. {
+                scroll_speed_changes.push(ScrollSpeedChange {
+                    timestamp: MapTimestamp::saturating_from_secs_f64(
+                        timeline.unwarped(beat, time),
+                    ),
+                    multiplier: ScrollSpeedMultiplier::new(0),
+                });
+            }
+            time += pause;
+
+            scroll_speed_changes.push(ScrollSpeedChange {
+                timestamp: MapTimestamp::saturating_from_secs_f64(timeline.unwarped(beat, time)),
+                multiplier: multiplier_at(beat),
+            });
+        }
+
+        let mut map = Map {
+            song_artist: self.artist.clone(),
+            song_title: self.title.clone(),
+            difficulty_name: non_empty(&chart.difficulty),
+            // `.sm` charts commonly have the author as the description.
+            mapper: chart
+                .credit
+                .clone()
+                .or_else(|| non_empty(&chart.description))
+                .or_else(|| self.credit.clone()),
+            background_file: self.background.clone(),
+            audio_file: self.music.clone(),
+            timing_points,
+            scroll_speed_changes,
+            initial_scroll_speed_multiplier: multiplier_at(f64::NEG_INFINITY),
+            scroll_groups: Vec::new(),
+            lanes,
+        };
+        map.sort_and_dedup_timing_points();
+        map.sort_and_dedup_scroll_speed_changes();
+
+        Conversion { map, unsupported }
+    }
+}
+
+/// Returns the lane count of a steps type, if it's known.
+fn steps_type_lane_count(steps_type: &str) -> Option<usize> {
+    Some(match steps_type {
+        "dance-single" => 4,
+        "dance-double" | "dance-couple" | "dance-routine" => 8,
+        "dance-solo" => 6,
+        "dance-threepanel" => 3,
+        "pump-single" => 5,
+        "pump-halfdouble" => 6,
+        "pump-double" | "pump-couple" | "pump-routine" => 10,
+        "kb7-single" => 7,
+        _ => return None,
+    })
+}
+
+/// Parses a number, rejecting NaNs and infinities.
+fn parse_number(tag: &str, value: &str) -> Result<f64, Error> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|x: &f64| x.is_finite())
+        .ok_or_else(|| Error::InvalidValue(tag.to_owned()))
+}
+
+/// Parses a `beat=value,beat=value` list, returning it sorted by beat.
+fn parse_beat_values(tag: &str, value: &str) -> Result<Vec<BeatValue>, Error> {
+    let mut values = value
+        .split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| {
+            let mut parts = x.split('=');
+            let beat = parse_number(tag, parts.next().unwrap())?;
+            let value = parse_number(tag, parts.next().unwrap_or(""))?;
+            Ok::<_, Error>(BeatValue { beat, value })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    values.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+    Ok(values)
+}
+
+fn parse_speeds(tag: &str, value: &str) -> Result<Vec<Speed>, Error> {
+    let mut speeds = value
+        .split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| {
+            let parts: Vec<_> = x.split('=').collect();
+            if parts.len() < 2 {
+                return Err(Error::InvalidValue(tag.to_owned()));
+            }
+
+            Ok::<_, Error>(Speed {
+                beat: parse_number(tag, parts[0])?,
+                ratio: parse_number(tag, parts[1])?,
+                duration: parts.get(2).map_or(Ok(0.), |x| parse_number(tag, x))?,
+                in_seconds: parts.get(3).map_or(Ok(0.), |x| parse_number(tag, x))? != 0.,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    speeds.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+    Ok(speeds)
+}
+
+/// Parses note data into notes and the lane count.
+fn parse_notes(tag: &str, value: &str, steps_type: &str) -> Result<(Vec<Note>, usize), Error> {
+    let invalid = || Error::InvalidValue(tag.to_owned());
+
+    let mut notes = Vec::new();
+    let mut lane_count = steps_type_lane_count(steps_type);
+
+    for (measure_index, measure) in value.split(',').enumerate() {
+        let rows: Vec<_> = measure
+            .lines()
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .collect();
+
+        for (row_index, row) in rows.iter().enumerate() {
+            let beat = (measure_index * 4) as f64 + (row_index * 4) as f64 / rows.len() as f64;
+
+            let mut column = 0;
+            let mut chars = row.chars();
+            while let Some(c) = chars.next() {
+                // Skip .ssc keysound indices and attacks.
+                if c == '[' || c == '{' {
+                    let end = if c == '[' { ']' } else { '}' };
+                    if !chars.any(|c| c == end) {
+                        return Err(invalid());
+                    }
+                    continue;
+                }
+
+                let kind = NoteKind::from_char(c).ok_or_else(invalid)?;
+                if let Some(kind) = kind {
+                    notes.push(Note { beat, column, kind });
+                }
+                column += 1;
+            }
+
+            match lane_count {
+                Some(count) if count != column => return Err(invalid()),
+                _ => lane_count = Some(column),
+            }
+        }
+    }
+
+    Ok((notes, lane_count.unwrap_or(0)))
+}
+
+fn is_timing_tag(tag: &str) -> bool {
+    matches!(
+        tag,
+        "OFFSET" | "BPMS" | "STOPS" | "FREEZES" | "DELAYS" | "WARPS" | "SCROLLS" | "SPEEDS"
+    )
+}
+
+/// Applies a timing tag to `timing`, ignoring other tags.
+fn parse_timing_tag(timing: &mut TimingData, tag: &str, value: &str) -> Result<(), Error> {
+    match tag {
+        "OFFSET" => timing.offset = parse_number(tag, value)?,
+        "BPMS" => timing.bpms = parse_beat_values(tag, value)?,
+        "STOPS" | "FREEZES" => timing.stops = parse_beat_values(tag, value)?,
+        "DELAYS" => timing.delays = parse_beat_values(tag, value)?,
+        "WARPS" => timing.warps = parse_beat_values(tag, value)?,
+        "SCROLLS" => timing.scrolls = parse_beat_values(tag, value)?,
+        "SPEEDS" => timing.speeds = parse_speeds(tag, value)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns `value` as `Some` trimmed string, or `None` if it's empty.
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+/// Deserializes a `Simfile` from an IO stream of `.sm` or `.ssc`.
+pub fn from_reader<R: Read>(mut reader: R) -> Result<Simfile, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    // Simfiles are supposed to be UTF-8, but plenty of old ones aren't.
+    let text = String::from_utf8_lossy(&bytes);
+
+    let mut simfile = Simfile {
+        title: None,
+        subtitle: None,
+        artist: None,
+        credit: None,
+        music: None,
+        background: None,
+        timing: TimingData::default(),
+        charts: Vec::new(),
+    };
+
+    // The chart currently being read in `.ssc`.
+    let mut chart: Option<Chart> = None;
+
+    for params in msd::parse(&text) {
+        let tag = params[0].trim().to_ascii_uppercase();
+        // Text values can contain unescaped colons.
+        let value = params[1..].join(":");
+
+        if let Some(chart) = &mut chart {
+            match &tag[..] {
+                "STEPSTYPE" => chart.steps_type = value.trim().to_owned(),
+                "DESCRIPTION" => chart.description = value.trim().to_owned(),
+                "DIFFICULTY" => chart.difficulty = value.trim().to_owned(),
+                "METER" => chart.meter = parse_number(&tag, &value)? as i32,
+                "CREDIT" => chart.credit = non_empty(&value),
+                "NOTES" | "NOTES2" => {
+                    let (notes, lane_count) = parse_notes(&tag, &value, &chart.steps_type)?;
+                    chart.notes = notes;
+                    chart.lane_count = lane_count;
+                }
+                _ if is_timing_tag(&tag) => {
+                    // Split timing starts out as a copy of the song timing.
+                    let timing = chart.timing.get_or_insert_with(|| simfile.timing.clone());
+                    parse_timing_tag(timing, &tag, &value)?;
+                }
+                _ => {}
+            }
+        }
+
+        match &tag[..] {
+            "NOTEDATA" => {
+                simfile.charts.extend(chart.take());
+                chart = Some(Chart::new());
+            }
+            // `.sm` charts are self-contained.
+            "NOTES" if chart.is_none() => {
+                if params.len() < 7 {
+                    return Err(Error::InvalidValue(tag));
+                }
+
+                let steps_type = params[1].trim().to_owned();
+                let (notes, lane_count) = parse_notes(&tag, &params[6], &steps_type)?;
+                simfile.charts.push(Chart {
+                    steps_type,
+                    description: params[2].trim().to_owned(),
+                    difficulty: params[3].trim().to_owned(),
+                    meter: parse_number(&tag, &params[4]).unwrap_or(0.) as i32,
+                    credit: None,
+                    timing: None,
+                    lane_count,
+                    notes,
+                });
+            }
+            _ if chart.is_some() => {}
+            "TITLE" => simfile.title = non_empty(&value),
+            "SUBTITLE" => simfile.subtitle = non_empty(&value),
+            "ARTIST" => simfile.artist = non_empty(&value),
+            "CREDIT" => simfile.credit = non_empty(&value),
+            "MUSIC" => simfile.music = non_empty(&value),
+            "BACKGROUND" => simfile.background = non_empty(&value),
+            _ => parse_timing_tag(&mut simfile.timing, &tag, &value)?,
+        }
+    }
+
+    simfile.charts.extend(chart);
+
+    Ok(simfile)
+}
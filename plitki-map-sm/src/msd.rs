@@ -0,0 +1,101 @@
+//! Parsing of the MSD format that `.sm` and `.ssc` are built on.
+//!
+//! An MSD file is a list of `#TAG:param:param:...;` values. Comments start with `//` and go until
+//! the end of the line, and a backslash escapes the next character.
+
+/// Parses `text` into a list of values, each of which is a list of params, the first param being
+/// the tag.
+pub(crate) fn parse(text: &str) -> Vec<Vec<String>> {
+    let mut values = Vec::new();
+
+    let mut params: Vec<String> = Vec::new();
+    let mut in_value = false;
+    // Whether only whitespace has been seen since the last line break.
+    let mut at_line_start = true;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '/' && chars.peek() == Some(&'/') {
+            // Skip the comment, leaving the line break in place.
+            while chars.next_if(|&c| c != '\n').is_some() {}
+            continue;
+        }
+
+        if !in_value {
+            if c == '#' {
+                in_value = true;
+                params.push(String::new());
+            }
+            at_line_start = c == '\n';
+            continue;
+        }
+
+        match c {
+            // Some files are missing semicolons; a `#` starting a line begins the next value.
+            '#' if at_line_start => {
+                values.push(std::mem::take(&mut params));
+                params.push(String::new());
+            }
+            ':' => params.push(String::new()),
+            ';' => {
+                values.push(std::mem::take(&mut params));
+                in_value = false;
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    params.last_mut().unwrap().push(c);
+                }
+            }
+            c => params.last_mut().unwrap().push(c),
+        }
+
+        if c == '\n' {
+            at_line_start = true;
+        } else if !c.is_whitespace() {
+            at_line_start = false;
+        }
+    }
+
+    if in_value {
+        values.push(params);
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let values = parse(
+            "// comment\n#TITLE:Foo\\:Bar; #BPMS:0=120,\n4=140 // comment\n;\n#NOTES:a:b:\n0000\n",
+        );
+        assert_eq!(
+            values,
+            vec![
+                vec!["TITLE".to_owned(), "Foo:Bar".to_owned()],
+                vec!["BPMS".to_owned(), "0=120,\n4=140 \n".to_owned()],
+                vec![
+                    "NOTES".to_owned(),
+                    "a".to_owned(),
+                    "b".to_owned(),
+                    "\n0000\n".to_owned()
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        let values = parse("#TITLE:Foo\n  #ARTIST:Bar;");
+        assert_eq!(
+            values,
+            vec![
+                vec!["TITLE".to_owned(), "Foo\n  ".to_owned()],
+                vec!["ARTIST".to_owned(), "Bar".to_owned()],
+            ]
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use std::fs::File;
+
+extern crate plitki_map_sm;
+use plitki_map_sm::{
+    from_reader, BeatValue, Chart, Error, Note, NoteKind, Simfile, Speed, TimingData, Unsupported,
+};
+
+use plitki_core::{
+    judgement::HitWindows,
+    map::{Lane, Map, ScrollSpeedChange, TimeSignature, TimingPoint},
+    object::Object,
+    scroll::ScrollSpeedMultiplier,
+    state::GameState,
+    timing::{MapTimestamp, MapTimestampDifference},
+};
+use pretty_assertions::assert_eq;
+use proptest::prelude::*;
+
+fn beat_value(beat: f64, value: f64) -> BeatValue {
+    BeatValue { beat, value }
+}
+
+fn note(beat: f64, column: usize, kind: NoteKind) -> Note {
+    Note { beat, column, kind }
+}
+
+fn timing_point(millis: i32, beat_duration: i32) -> TimingPoint {
+    TimingPoint {
+        timestamp: MapTimestamp::from_millis(millis),
+        beat_duration: MapTimestampDifference::from_millis(beat_duration),
+        signature: TimeSignature {
+            beat_count: 4,
+            beat_unit: 4,
+        },
+    }
+}
+
+fn scroll_speed_change(millis: i32, multiplier: i32) -> ScrollSpeedChange {
+    ScrollSpeedChange {
+        timestamp: MapTimestamp::from_millis(millis),
+        multiplier: ScrollSpeedMultiplier::new(multiplier),
+    }
+}
+
+fn regular(millis: i32) -> Object {
+    Object::Regular {
+        timestamp: MapTimestamp::from_millis(millis),
+    }
+}
+
+fn long_note(start: i32, end: i32) -> Object {
+    Object::LongNote {
+        start: MapTimestamp::from_millis(start),
+        end: MapTimestamp::from_millis(end),
+    }
+}
+
+#[test]
+fn parse_sample_sm() {
+    let file = File::open("tests/data/sample.sm").unwrap();
+    let simfile = from_reader(file).unwrap();
+
+    let gt = Simfile {
+        title: Some("Sample Map".to_owned()),
+        subtitle: None,
+        artist: Some("Unknown".to_owned()),
+        credit: Some("YaLTeR".to_owned()),
+        music: Some("song.ogg".to_owned()),
+        background: Some("background.png".to_owned()),
+        timing: TimingData {
+            offset: -0.1,
+            bpms: vec![beat_value(0., 120.), beat_value(4., 240.)],
+            stops: vec![beat_value(2., 0.5)],
+            ..TimingData::default()
+        },
+        charts: vec![
+            Chart {
+                steps_type: "dance-single".to_owned(),
+                description: "Author".to_owned(),
+                difficulty: "Easy".to_owned(),
+                meter: 3,
+                credit: None,
+                timing: None,
+                lane_count: 4,
+                notes: vec![
+                    note(0., 0, NoteKind::Tap),
+                    note(1., 1, NoteKind::Tap),
+                    note(2., 2, NoteKind::Tap),
+                    note(3., 3, NoteKind::Tap),
+                    note(4., 0, NoteKind::HoldHead),
+                    note(6., 0, NoteKind::Tail),
+                    note(7., 0, NoteKind::Mine),
+                ],
+            },
+            Chart {
+                steps_type: "dance-double".to_owned(),
+                description: "".to_owned(),
+                difficulty: "Hard".to_owned(),
+                meter: 8,
+                credit: None,
+                timing: None,
+                lane_count: 8,
+                notes: vec![
+                    note(0., 0, NoteKind::Tap),
+                    note(0., 7, NoteKind::Tap),
+                    note(1., 1, NoteKind::RollHead),
+                    note(2., 1, NoteKind::Tail),
+                    note(3., 4, NoteKind::Lift),
+                ],
+            },
+        ],
+    };
+
+    assert_eq!(simfile, gt);
+}
+
+#[test]
+fn convert_sample_sm() {
+    let file = File::open("tests/data/sample.sm").unwrap();
+    let simfile = from_reader(file).unwrap();
+
+    let conversion = simfile.convert(0);
+
+    let gt = Map {
+        song_artist: Some("Unknown".to_owned()),
+        song_title: Some("Sample Map".to_owned()),
+        difficulty_name: Some("Easy".to_owned()),
+        mapper: Some("Author".to_owned()),
+        background_file: Some("background.png".to_owned()),
+        audio_file: Some("song.ogg".to_owned()),
+        timing_points: vec![
+            timing_point(100, 500),
+            // Beat lines restart after the stop.
+            timing_point(1600, 500),
+            timing_point(2600, 250),
+        ],
+        scroll_speed_changes: vec![
+            scroll_speed_change(1100, 0),
+            scroll_speed_change(1600, 1000),
+            scroll_speed_change(2600, 2000),
+        ],
+        initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+        scroll_groups: vec![],
+        lanes: vec![
+            Lane {
+                objects: vec![regular(100), long_note(2600, 3100)],
+            },
+            Lane {
+                objects: vec![regular(600)],
+            },
+            Lane {
+                // The stop happens after the note on its beat.
+                objects: vec![regular(1100)],
+            },
+            Lane {
+                objects: vec![regular(2100)],
+            },
+        ],
+    };
+
+    assert_eq!(conversion.map, gt);
+    assert_eq!(
+        conversion.unsupported,
+        vec![Unsupported::Note(note(7., 0, NoteKind::Mine))]
+    );
+
+    let conversion = simfile.convert(1);
+    assert_eq!(conversion.map.lane_count(), 8);
+    assert_eq!(conversion.map.mapper, Some("YaLTeR".to_owned()));
+    assert_eq!(conversion.map.lanes[0].objects, vec![regular(100)]);
+    assert_eq!(conversion.map.lanes[1].objects, vec![long_note(600, 1100)]);
+    assert_eq!(conversion.map.lanes[7].objects, vec![regular(100)]);
+    assert_eq!(
+        conversion.unsupported,
+        vec![Unsupported::Note(note(3., 4, NoteKind::Lift))]
+    );
+}
+
+#[test]
+fn parse_sample_ssc() {
+    let file = File::open("tests/data/sample.ssc").unwrap();
+    let simfile = from_reader(file).unwrap();
+
+    let song_timing = TimingData {
+        offset: 0.,
+        bpms: vec![beat_value(0., 60.)],
+        stops: vec![],
+        delays: vec![beat_value(1., 0.25)],
+        warps: vec![],
+        scrolls: vec![beat_value(0., 1.), beat_value(2., 0.5)],
+        speeds: vec![
+            Speed {
+                beat: 0.,
+                ratio: 1.,
+                duration: 0.,
+                in_seconds: false,
+            },
+            Speed {
+                beat: 3.,
+                ratio: 2.,
+                duration: 1.,
+                in_seconds: true,
+            },
+        ],
+    };
+
+    assert_eq!(simfile.subtitle, Some("Sub".to_owned()));
+    assert_eq!(simfile.timing, song_timing);
+    assert_eq!(simfile.charts.len(), 2);
+
+    let chart = &simfile.charts[0];
+    assert_eq!(chart.steps_type, "dance-single");
+    assert_eq!(chart.description, "Some description");
+    assert_eq!(chart.difficulty, "Challenge");
+    assert_eq!(chart.meter, 12);
+    assert_eq!(chart.credit, Some("Stepper".to_owned()));
+    assert_eq!(chart.timing, None);
+    assert_eq!(chart.lane_count, 4);
+
+    // Split timing inherits what it doesn't override.
+    let chart = &simfile.charts[1];
+    assert_eq!(chart.steps_type, "pump-single");
+    assert_eq!(
+        chart.timing,
+        Some(TimingData {
+            bpms: vec![beat_value(0., 120.)],
+            warps: vec![beat_value(2.5, 0.5)],
+            ..song_timing
+        })
+    );
+    assert_eq!(chart.lane_count, 5);
+    assert_eq!(
+        chart.notes,
+        vec![
+            note(0., 0, NoteKind::Tap),
+            note(1., 1, NoteKind::Fake),
+            note(2., 2, NoteKind::AutoKeysound),
+            note(3., 3, NoteKind::Tap),
+        ]
+    );
+}
+
+#[test]
+fn convert_sample_ssc() {
+    let file = File::open("tests/data/sample.ssc").unwrap();
+    let simfile = from_reader(file).unwrap();
+
+    let conversion = simfile.convert(0);
+
+    let gt = Map {
+        song_artist: Some("Unknown".to_owned()),
+        song_title: Some("Sample Map".to_owned()),
+        difficulty_name: Some("Challenge".to_owned()),
+        mapper: Some("Stepper".to_owned()),
+        background_file: None,
+        audio_file: Some("song.ogg".to_owned()),
+        timing_points: vec![timing_point(0, 1000), timing_point(1250, 1000)],
+        scroll_speed_changes: vec![
+            // The delay.
+            scroll_speed_change(1000, 0),
+            scroll_speed_change(1250, 1000),
+            // The scroll.
+            scroll_speed_change(2250, 500),
+            // The speed.
+            scroll_speed_change(3250, 1000),
+        ],
+        initial_scroll_speed_multiplier: ScrollSpeedMultiplier::default(),
+        scroll_groups: vec![],
+        lanes: vec![
+            Lane {
+                objects: vec![regular(0)],
+            },
+            Lane {
+                // The delay happens before the note on its beat.
+                objects: vec![regular(1250)],
+            },
+            Lane {
+                objects: vec![regular(2250)],
+            },
+            Lane {
+                objects: vec![regular(3250)],
+            },
+        ],
+    };
+
+    assert_eq!(conversion.map, gt);
+    assert_eq!(conversion.unsupported, vec![]);
+
+    let conversion = simfile.convert(1);
+    assert_eq!(conversion.map.lanes[0].objects, vec![regular(0)]);
+    // The warp skips half a beat.
+    assert_eq!(conversion.map.lanes[3].objects, vec![regular(1500)]);
+    assert_eq!(
+        conversion.unsupported,
+        vec![
+            Unsupported::Note(note(1., 1, NoteKind::Fake)),
+            Unsupported::Note(note(2., 2, NoteKind::AutoKeysound)),
+        ]
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert!(matches!(
+        from_reader(&b"#BPMS:0=abc;"[..]),
+        Err(Error::InvalidValue(tag)) if tag == "BPMS"
+    ));
+    assert!(matches!(
+        from_reader(&b"#BPMS:nan=120,1=120;"[..]),
+        Err(Error::InvalidValue(tag)) if tag == "BPMS"
+    ));
+    assert!(matches!(
+        from_reader(&b"#OFFSET:inf;"[..]),
+        Err(Error::InvalidValue(tag)) if tag == "OFFSET"
+    ));
+    assert!(matches!(
+        from_reader(&b"#NOTES:dance-single:::1::\n0000\n00000\n;"[..]),
+        Err(Error::InvalidValue(tag)) if tag == "NOTES"
+    ));
+    assert!(matches!(
+        from_reader(&b"#NOTES:dance-single:::1::\n0X00\n;"[..]),
+        Err(Error::InvalidValue(tag)) if tag == "NOTES"
+    ));
+}
+
+#[test]
+fn unterminated_hold_is_a_tap() {
+    let simfile = from_reader(
+        &b"#BPMS:0=60;\n#NOTES:dance-single:::1::\n2000\n0000\n1000\n0000\n,\n0200\n;"[..],
+    )
+    .unwrap();
+
+    let map = simfile.convert(0).map;
+    assert_eq!(map.lanes[0].objects, vec![regular(0), regular(2000)]);
+    assert_eq!(map.lanes[1].objects, vec![regular(4000)]);
+}
+
+#[test]
+fn negative_bpm_warp() {
+    let simfile = from_reader(
+        &b"#BPMS:0=120,2=-120,3=120;\n#NOTES:dance-single:::1::\n\
+           1000\n1000\n1000\n1000\n,\n1000\n1000\n1000\n1000\n;"[..],
+    )
+    .unwrap();
+
+    let conversion = simfile.convert(0);
+    assert_eq!(
+        conversion.map.lanes[0].objects,
+        vec![
+            regular(0),
+            regular(500),
+            regular(1000),
+            regular(1500),
+            regular(2000),
+            regular(2500),
+        ]
+    );
+    // The warp lasts from beat 2 until the time gets back to where it was, at beat 4.
+    assert_eq!(
+        conversion.unsupported,
+        vec![
+            Unsupported::WarpedNote(note(2., 0, NoteKind::Tap)),
+            Unsupported::WarpedNote(note(3., 0, NoteKind::Tap)),
+        ]
+    );
+    assert!(GameState::new(conversion.map, HitWindows::quaver_standard()).is_ok());
+}
+
+#[test]
+fn warps_and_negative_stops() {
+    let simfile = from_reader(
+        &b"#BPMS:0=60;\n#WARPS:1=1;\n#STOPS:4=-1;\n#NOTES:dance-single:::1::\n\
+           1000\n1000\n1000\n1000\n,\n1000\n1000\n1000\n1000\n;"[..],
+    )
+    .unwrap();
+
+    let conversion = simfile.convert(0);
+    assert_eq!(
+        conversion.map.lanes[0].objects,
+        vec![
+            regular(0),
+            regular(1000),
+            regular(2000),
+            regular(3000),
+            regular(4000),
+            regular(5000),
+        ]
+    );
+    assert_eq!(
+        conversion.unsupported,
+        vec![
+            Unsupported::WarpedNote(note(1., 0, NoteKind::Tap)),
+            Unsupported::WarpedNote(note(4., 0, NoteKind::Tap)),
+        ]
+    );
+    assert!(GameState::new(conversion.map, HitWindows::quaver_standard()).is_ok());
+}
+
+/// Returns note data with the given rows of every measure.
+fn note_data(lane_count: usize, measures: &[Vec<Vec<Option<NoteKind>>>]) -> String {
+    let mut data = String::new();
+    for (i, measure) in measures.iter().enumerate() {
+        if i > 0 {
+            data.push_str(",\n");
+        }
+
+        for row in measure {
+            for column in 0..lane_count {
+                data.push(match row.get(column).copied().flatten() {
+                    None => '0',
+                    Some(NoteKind::Tap) => '1',
+                    Some(NoteKind::HoldHead) => '2',
+                    Some(NoteKind::Tail) => '3',
+                    Some(NoteKind::RollHead) => '4',
+                    Some(NoteKind::Mine) => 'M',
+                    Some(NoteKind::Lift) => 'L',
+                    Some(NoteKind::Fake) => 'F',
+                    Some(NoteKind::AutoKeysound) => 'K',
+                });
+            }
+            data.push('\n');
+        }
+    }
+    data
+}
+
+fn arbitrary_note_kind() -> impl Strategy<Value = Option<NoteKind>> {
+    prop_oneof![
+        4 => Just(None),
+        2 => Just(Some(NoteKind::Tap)),
+        1 => Just(Some(NoteKind::HoldHead)),
+        1 => Just(Some(NoteKind::RollHead)),
+        1 => Just(Some(NoteKind::Tail)),
+        1 => Just(Some(NoteKind::Mine)),
+        1 => Just(Some(NoteKind::Lift)),
+    ]
+}
+
+fn arbitrary_beat_values(values: impl Strategy<Value = f64>) -> impl Strategy<Value = String> {
+    prop::collection::vec((0..64u32, values), 0..8).prop_map(|values| {
+        values
+            .into_iter()
+            .map(|(beat, value)| format!("{}={}", f64::from(beat) / 4., value))
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+}
+
+/// Returns a strategy for number text, including special values like NaN and infinities.
+fn arbitrary_number() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<f64>().prop_map(|x| x.to_string()),
+        (-1000f64..1000.).prop_map(|x| x.to_string()),
+        prop::sample::select(&["nan", "NaN", "inf", "-inf", "1e400", "-1e400", "0", "-0"][..])
+            .prop_map(str::to_owned),
+    ]
+}
+
+/// Returns a strategy for a simfile with arbitrary numbers in the timing tags.
+fn arbitrary_timing_text() -> impl Strategy<Value = String> {
+    let beat_values = prop::collection::vec((arbitrary_number(), arbitrary_number()), 0..4)
+        .prop_map(|values| {
+            values
+                .into_iter()
+                .map(|(beat, value)| format!("{}={}", beat, value))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+    let tag = prop::sample::select(&["BPMS", "STOPS", "DELAYS", "WARPS", "SCROLLS", "SPEEDS"][..]);
+
+    (
+        arbitrary_number(),
+        prop::collection::vec((tag, beat_values), 0..6),
+    )
+        .prop_map(|(offset, tags)| {
+            let tags: String = tags
+                .into_iter()
+                .map(|(tag, value)| format!("#{}:{};\n", tag, value))
+                .collect();
+            format!(
+                "#OFFSET:{};\n{}#NOTES:\n:::1::\n1000\n0100\n,\n2000\n3000\n;",
+                offset, tags,
+            )
+        })
+}
+
+prop_compose! {
+    fn arbitrary_simfile()
+                        (lane_count in 1..=10usize)
+                        (bpms in arbitrary_beat_values(prop_oneof![
+                             3 => 1f64..1000.,
+                             1 => -1000f64..-1.,
+                             1 => Just(0.),
+                         ]),
+                         stops in arbitrary_beat_values(-10f64..10.),
+                         delays in arbitrary_beat_values(-10f64..10.),
+                         warps in arbitrary_beat_values(-1f64..4.),
+                         scrolls in arbitrary_beat_values(-4f64..4.),
+                         offset in -10f64..10.,
+                         measures in prop::collection::vec(
+                             prop::sample::select(&[1usize, 2, 3, 4, 8, 12, 16, 24, 48, 64, 192][..])
+                                 .prop_flat_map(move |row_count| prop::collection::vec(
+                                     prop::collection::vec(arbitrary_note_kind(), lane_count),
+                                     row_count,
+                                 )),
+                             0..16,
+                         ),
+                         lane_count in Just(lane_count))
+                        -> String {
+        format!(
+            "#OFFSET:{};\n#BPMS:0=120,{};\n#STOPS:{};\n#DELAYS:{};\n#WARPS:{};\n#SCROLLS:{};\n\
+             #NOTES:\n:::1::\n{};",
+            offset,
+            bpms,
+            stops,
+            delays,
+            warps,
+            scrolls,
+            note_data(lane_count, &measures),
+        )
+    }
+}
+
+proptest! {
+    #[test]
+    fn converted_charts_are_playable(simfile in arbitrary_simfile()) {
+        let simfile = from_reader(simfile.as_bytes()).unwrap();
+        let conversion = simfile.convert(0);
+
+        let unsupported_note_count = simfile.charts[0]
+            .notes
+            .iter()
+            .filter(|x| matches!(x.kind, NoteKind::Mine | NoteKind::Lift))
+            .count();
+        let reported_note_count = conversion
+            .unsupported
+            .iter()
+            .filter(|x| matches!(x, Unsupported::Note(_)))
+            .count();
+        prop_assert_eq!(reported_note_count, unsupported_note_count);
+
+        prop_assert!(GameState::new(conversion.map, HitWindows::quaver_standard()).is_ok());
+    }
+
+    #[test]
+    fn from_reader_doesnt_panic(text in "\\PC*") {
+        if let Ok(simfile) = from_reader(text.as_bytes()) {
+            for i in 0..simfile.charts.len() {
+                simfile.convert(i);
+            }
+        }
+    }
+
+    #[test]
+    fn timing_numbers_dont_panic(text in arbitrary_timing_text()) {
+        if let Ok(simfile) = from_reader(text.as_bytes()) {
+            for i in 0..simfile.charts.len() {
+                simfile.convert(i);
+            }
+        }
+    }
+}
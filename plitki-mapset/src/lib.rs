@@ -0,0 +1,437 @@
+//! Loading of mapsets: the difficulties of a song together with their audio and background files.
+//!
+//! Mapsets are distributed as zip archives, `.qp` for Quaver and `.osz` for osu!. They are read
+//! in memory without extracting them to disk. Extracted mapsets are read from a directory.
+
+use std::{
+    convert::TryFrom,
+    error, fmt, fs,
+    io::{self, Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use plitki_core::map::Map;
+use plitki_map_qua::ConversionError;
+use zip::{result::ZipError, ZipArchive};
+
+/// Format of a difficulty file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Format {
+    /// Quaver `.qua`.
+    Qua,
+    /// osu!mania `.osu`.
+    Osu,
+}
+
+impl Format {
+    /// Returns the format of a file with the given name, or `None` if it's not a difficulty.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let extension = Path::new(name).extension()?.to_str()?;
+        if extension.eq_ignore_ascii_case("qua") {
+            Some(Format::Qua)
+        } else if extension.eq_ignore_ascii_case("osu") {
+            Some(Format::Osu)
+        } else {
+            None
+        }
+    }
+}
+
+/// Metadata shared by the difficulties of a mapset, taken from the first difficulty.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MapsetInfo {
+    pub song_artist: Option<String>,
+    pub song_title: Option<String>,
+    /// Path of the audio file relative to the mapset root.
+    pub audio_file: Option<String>,
+    /// Path of the background file relative to the mapset root.
+    pub background_file: Option<String>,
+}
+
+/// Metadata of a difficulty in a mapset.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DifficultyInfo {
+    /// Path of the difficulty file relative to the mapset root.
+    pub path: String,
+    pub format: Format,
+    pub difficulty_name: Option<String>,
+    pub mapper: Option<String>,
+    pub lane_count: usize,
+}
+
+/// A difficulty loaded from a mapset archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedDifficulty {
+    pub map: Map,
+    /// Contents of the audio file, or `None` if the map doesn't have one.
+    pub audio: Option<Vec<u8>>,
+    /// Contents of the background file, or `None` if the map doesn't have one or it's missing from
+    /// the mapset.
+    pub background: Option<Vec<u8>>,
+}
+
+/// Error returned when loading a mapset fails.
+#[derive(Debug)]
+pub enum Error {
+    /// An IO error occurred.
+    Io(io::Error),
+    /// The archive couldn't be read.
+    Zip(ZipError),
+    /// The file with this path is not in the mapset.
+    MissingFile(String),
+    /// The path points outside of the mapset.
+    InvalidPath(String),
+    /// The `.qua` difficulty with this path couldn't be parsed.
+    Qua(String, serde_yaml::Error),
+    /// The `.qua` difficulty with this path couldn't be converted.
+    QuaConversion(String, ConversionError),
+    /// The `.osu` difficulty with this path couldn't be parsed.
+    Osu(String, plitki_map_osu::Error),
+    /// The `.osu` difficulty with this path couldn't be converted.
+    OsuConversion(String, plitki_map_osu::ConversionError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "IO error: {}", err),
+            Error::Zip(err) => write!(f, "error reading the archive: {}", err),
+            Error::MissingFile(path) => write!(f, "file not found in the mapset: {}", path),
+            Error::InvalidPath(path) => write!(f, "path points outside of the mapset: {}", path),
+            Error::Qua(path, err) => write!(f, "error parsing {}: {}", path, err),
+            Error::QuaConversion(path, err) => write!(f, "error converting {}: {}", path, err),
+            Error::Osu(path, err) => write!(f, "error parsing {}: {}", path, err),
+            Error::OsuConversion(path, err) => write!(f, "error converting {}: {}", path, err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Zip(err) => Some(err),
+            Error::Qua(_, err) => Some(err),
+            Error::QuaConversion(_, err) => Some(err),
+            Error::Osu(_, err) => Some(err),
+            Error::OsuConversion(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<ZipError> for Error {
+    #[inline]
+    fn from(err: ZipError) -> Self {
+        match err {
+            ZipError::Io(err) => Error::Io(err),
+            err => Error::Zip(err),
+        }
+    }
+}
+
+/// Largest buffer preallocated for reading a file from an archive.
+const MAX_PREALLOCATION: u64 = 16 * 1024 * 1024;
+
+/// A seekable reader of an archive.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+enum Storage {
+    Archive(ZipArchive<Box<dyn ReadSeek>>),
+    Directory(PathBuf),
+}
+
+/// A parsed difficulty file.
+enum Parsed {
+    Qua(plitki_map_qua::Qua),
+    Osu(plitki_map_osu::Osu),
+}
+
+/// A mapset archive or directory: the difficulty files of one song with their audio and
+/// background files.
+///
+/// The difficulties are listed up front and loaded one at a time with [`MapsetArchive::load()`].
+/// Difficulty files which can't be parsed are left out, and their errors are kept in
+/// [`MapsetArchive::invalid_difficulties()`].
+pub struct MapsetArchive {
+    storage: Storage,
+    info: MapsetInfo,
+    difficulties: Vec<DifficultyInfo>,
+    invalid_difficulties: Vec<Error>,
+}
+
+impl fmt::Debug for MapsetArchive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MapsetArchive")
+            .field("info", &self.info)
+            .field("difficulties", &self.difficulties)
+            .field("invalid_difficulties", &self.invalid_difficulties)
+            .finish()
+    }
+}
+
+impl MapsetArchive {
+    /// Opens a mapset from an archive or an extracted directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Self::from_directory(path)
+        } else {
+            Self::from_archive(fs::File::open(path)?)
+        }
+    }
+
+    /// Opens a mapset from a zip archive like `.qp` or `.osz`.
+    pub fn from_archive<R: ReadSeek + 'static>(reader: R) -> Result<Self, Error> {
+        let archive = ZipArchive::new(Box::new(reader) as Box<dyn ReadSeek>)?;
+
+        let mut paths: Vec<String> = archive
+            .file_names()
+            // Difficulties are always at the root of the archive.
+            .filter(|name| !name.contains('/') && Format::from_file_name(name).is_some())
+            .map(str::to_owned)
+            .collect();
+        paths.sort();
+
+        Self::new(Storage::Archive(archive), paths)
+    }
+
+    /// Opens an extracted mapset from a directory.
+    pub fn from_directory<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            // Names which aren't valid UTF-8 can't be referenced from the difficulties anyway.
+            if let Ok(name) = entry.file_name().into_string() {
+                if Format::from_file_name(&name).is_some() {
+                    paths.push(name);
+                }
+            }
+        }
+        paths.sort();
+
+        Self::new(Storage::Directory(path), paths)
+    }
+
+    fn new(storage: Storage, paths: Vec<String>) -> Result<Self, Error> {
+        let mut mapset = Self {
+            storage,
+            info: MapsetInfo::default(),
+            difficulties: Vec::new(),
+            invalid_difficulties: Vec::new(),
+        };
+
+        for path in paths {
+            let parsed = match mapset.parse(&path) {
+                Ok(x) => x,
+                Err(err) => {
+                    // One broken difficulty shouldn't make the rest of the mapset unplayable.
+                    mapset.invalid_difficulties.push(err);
+                    continue;
+                }
+            };
+
+            let (info, mapset_info) = match parsed {
+                Parsed::Qua(qua) => (
+                    DifficultyInfo {
+                        lane_count: qua.lane_count(),
+                        path,
+                        format: Format::Qua,
+                        difficulty_name: qua.difficulty_name,
+                        mapper: qua.creator,
+                    },
+                    MapsetInfo {
+                        song_artist: qua.artist,
+                        song_title: qua.title,
+                        audio_file: qua.audio_file,
+                        background_file: qua.background_file,
+                    },
+                ),
+                Parsed::Osu(osu) => {
+                    // Only osu!mania difficulties can be played.
+                    if osu.mode != plitki_map_osu::MODE_MANIA {
+                        continue;
+                    }
+
+                    (
+                        DifficultyInfo {
+                            lane_count: osu.lane_count(),
+                            path,
+                            format: Format::Osu,
+                            difficulty_name: osu.version,
+                            mapper: osu.creator,
+                        },
+                        MapsetInfo {
+                            song_artist: osu.artist,
+                            song_title: osu.title,
+                            audio_file: osu.audio_file,
+                            background_file: osu.background_file,
+                        },
+                    )
+                }
+            };
+
+            if mapset.difficulties.is_empty() {
+                mapset.info = mapset_info;
+            }
+            mapset.difficulties.push(info);
+        }
+
+        Ok(mapset)
+    }
+
+    /// Returns the metadata shared by the difficulties.
+    #[inline]
+    pub fn info(&self) -> &MapsetInfo {
+        &self.info
+    }
+
+    /// Returns the difficulties of the mapset, sorted by path.
+    #[inline]
+    pub fn difficulties(&self) -> &[DifficultyInfo] {
+        &self.difficulties
+    }
+
+    /// Returns the errors of the difficulty files which couldn't be parsed, sorted by path.
+    #[inline]
+    pub fn invalid_difficulties(&self) -> &[Error] {
+        &self.invalid_difficulties
+    }
+
+    /// Reads the file with the given path relative to the mapset root.
+    ///
+    /// If there's no file with this exact path, a file with the same path in a different case is
+    /// looked up, as maps made on case-insensitive file systems often get the case wrong.
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let path = path.replace('\\', "/");
+
+        // Don't let the maps reach outside of the mapset.
+        if Path::new(&path)
+            .components()
+            .any(|x| !matches!(x, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(Error::InvalidPath(path));
+        }
+
+        match &mut self.storage {
+            Storage::Archive(archive) => {
+                let name = match archive.index_for_name(&path) {
+                    Some(_) => path,
+                    None => archive
+                        .file_names()
+                        .find(|name| name.eq_ignore_ascii_case(&path))
+                        .ok_or(Error::MissingFile(path))?
+                        .to_owned(),
+                };
+
+                let mut file = archive.by_name(&name)?;
+                // The size comes from the archive and can't be trusted, so it only caps the
+                // preallocation.
+                let capacity = file.size().min(MAX_PREALLOCATION) as usize;
+                let mut contents = Vec::with_capacity(capacity);
+                file.read_to_end(&mut contents)?;
+                Ok(contents)
+            }
+            Storage::Directory(dir) => {
+                let mut full_path = dir.join(&path);
+                if !full_path.is_file() {
+                    full_path =
+                        find_case_insensitive(dir, &path).ok_or(Error::MissingFile(path))?;
+                }
+
+                Ok(fs::read(full_path)?)
+            }
+        }
+    }
+
+    fn parse(&mut self, path: &str) -> Result<Parsed, Error> {
+        let contents = self.read_file(path)?;
+
+        match Format::from_file_name(path) {
+            Some(Format::Qua) => plitki_map_qua::from_reader(&contents[..])
+                .map(Parsed::Qua)
+                .map_err(|err| Error::Qua(path.to_owned(), err)),
+            Some(Format::Osu) => plitki_map_osu::from_reader(&contents[..])
+                .map(Parsed::Osu)
+                .map_err(|err| Error::Osu(path.to_owned(), err)),
+            None => unreachable!(),
+        }
+    }
+
+    /// Loads the difficulty with the given index along with its audio and background files.
+    ///
+    /// The audio and background files are the ones set in the difficulty, which may differ from
+    /// the ones in [`MapsetArchive::info()`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn load(&mut self, index: usize) -> Result<LoadedDifficulty, Error> {
+        let path = self.difficulties[index].path.clone();
+
+        let map = match self.parse(&path)? {
+            Parsed::Qua(qua) => {
+                Map::try_from(qua).map_err(|err| Error::QuaConversion(path.clone(), err))?
+            }
+            Parsed::Osu(osu) => {
+                Map::try_from(osu).map_err(|err| Error::OsuConversion(path.clone(), err))?
+            }
+        };
+
+        let audio = map
+            .audio_file
+            .as_deref()
+            .map(|path| self.read_file(path))
+            .transpose()?;
+        // The game is playable without the background, so don't fail if it's missing.
+        let background = match map
+            .background_file
+            .as_deref()
+            .map(|path| self.read_file(path))
+        {
+            Some(Err(Error::MissingFile(_))) | None => None,
+            Some(result) => Some(result?),
+        };
+
+        Ok(LoadedDifficulty {
+            map,
+            audio,
+            background,
+        })
+    }
+}
+
+/// Finds a file in `dir` whose path matches `path` ignoring the case.
+fn find_case_insensitive(dir: &Path, path: &str) -> Option<PathBuf> {
+    let mut result = dir.to_path_buf();
+
+    for component in path.split('/').filter(|x| !x.is_empty() && *x != ".") {
+        let entry = fs::read_dir(&result)
+            .ok()?
+            .filter_map(Result::ok)
+            .find(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(component)
+            })?;
+        result.push(entry.file_name());
+    }
+
+    Some(result)
+}
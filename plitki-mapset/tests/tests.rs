@@ -0,0 +1,228 @@
+use std::{
+    convert::TryFrom,
+    fs,
+    io::{Cursor, Write},
+};
+
+extern crate plitki_mapset;
+use plitki_mapset::{DifficultyInfo, Error, Format, MapsetArchive, MapsetInfo};
+
+use plitki_core::map::Map;
+use pretty_assertions::assert_eq;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Packs the files of a test data directory into an in-memory zip archive.
+fn archive(dir: &str, prefix: &str) -> Cursor<Vec<u8>> {
+    let mut names: Vec<_> = fs::read_dir(format!("tests/data/{}", dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for name in names {
+        let options = SimpleFileOptions::default().compression_method(if name.ends_with(".osu") {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        });
+        writer
+            .start_file(format!("{}{}", prefix, name), options)
+            .unwrap();
+        writer
+            .write_all(&fs::read(format!("tests/data/{}/{}", dir, name)).unwrap())
+            .unwrap();
+    }
+
+    let mut cursor = writer.finish().unwrap();
+    cursor.set_position(0);
+    cursor
+}
+
+fn quaver_difficulties() -> Vec<DifficultyInfo> {
+    vec![
+        DifficultyInfo {
+            path: "easy.qua".to_owned(),
+            format: Format::Qua,
+            difficulty_name: Some("Easy".to_owned()),
+            mapper: Some("YaLTeR".to_owned()),
+            lane_count: 4,
+        },
+        DifficultyInfo {
+            path: "hard.qua".to_owned(),
+            format: Format::Qua,
+            difficulty_name: Some("Hard".to_owned()),
+            mapper: Some("YaLTeR".to_owned()),
+            lane_count: 7,
+        },
+    ]
+}
+
+fn quaver_info() -> MapsetInfo {
+    MapsetInfo {
+        song_artist: Some("Unknown".to_owned()),
+        song_title: Some("Sample Map".to_owned()),
+        audio_file: Some("audio.mp3".to_owned()),
+        background_file: Some("bg.jpg".to_owned()),
+    }
+}
+
+#[test]
+fn list_qp_difficulties() {
+    let mapset = MapsetArchive::from_archive(archive("quaver", "")).unwrap();
+    assert_eq!(mapset.info(), &quaver_info());
+    assert_eq!(mapset.difficulties(), &quaver_difficulties()[..]);
+}
+
+#[test]
+fn list_directory_difficulties() {
+    let mapset = MapsetArchive::open("tests/data/quaver").unwrap();
+    assert_eq!(mapset.info(), &quaver_info());
+    assert_eq!(mapset.difficulties(), &quaver_difficulties()[..]);
+}
+
+#[test]
+fn load_qp_difficulty() {
+    let mut mapset = MapsetArchive::from_archive(archive("quaver", "")).unwrap();
+
+    let difficulty = mapset.load(0).unwrap();
+    let qua =
+        plitki_map_qua::from_reader(fs::File::open("tests/data/quaver/easy.qua").unwrap()).unwrap();
+    assert_eq!(difficulty.map, Map::try_from(qua).unwrap());
+    assert_eq!(difficulty.audio.as_deref(), Some(&b"audio"[..]));
+    assert_eq!(difficulty.background.as_deref(), Some(&b"background"[..]));
+
+    // The audio file name has the wrong case.
+    let difficulty = mapset.load(1).unwrap();
+    assert_eq!(difficulty.map.lane_count(), 7);
+    assert_eq!(difficulty.audio.as_deref(), Some(&b"audio"[..]));
+    assert_eq!(difficulty.background, None);
+}
+
+#[test]
+fn load_directory_difficulty() {
+    let mut mapset = MapsetArchive::open("tests/data/quaver").unwrap();
+
+    let difficulty = mapset.load(1).unwrap();
+    assert_eq!(difficulty.map.lane_count(), 7);
+    assert_eq!(difficulty.audio.as_deref(), Some(&b"audio"[..]));
+}
+
+#[test]
+fn load_osz_difficulty() {
+    let mut mapset = MapsetArchive::from_archive(archive("osu", "")).unwrap();
+
+    // The osu!standard difficulty is skipped.
+    assert_eq!(mapset.difficulties().len(), 1);
+    let info = &mapset.difficulties()[0];
+    assert_eq!(info.path, "mania.osu");
+    assert_eq!(info.format, Format::Osu);
+    assert_eq!(info.difficulty_name.as_deref(), Some("Easy"));
+    assert_eq!(info.lane_count, 4);
+    assert_eq!(mapset.info().audio_file.as_deref(), Some("song.mp3"));
+    assert_eq!(
+        mapset.info().background_file.as_deref(),
+        Some("background.png")
+    );
+
+    let difficulty = mapset.load(0).unwrap();
+    let osu =
+        plitki_map_osu::from_reader(fs::File::open("tests/data/osu/mania.osu").unwrap()).unwrap();
+    assert_eq!(difficulty.map, Map::try_from(osu).unwrap());
+    assert_eq!(difficulty.audio.as_deref(), Some(&b"song"[..]));
+    assert_eq!(difficulty.background.as_deref(), Some(&b"background"[..]));
+}
+
+#[test]
+fn nested_difficulties_are_ignored() {
+    let mapset = MapsetArchive::from_archive(archive("quaver", "nested/")).unwrap();
+    assert_eq!(mapset.info(), &MapsetInfo::default());
+    assert_eq!(mapset.difficulties(), &[]);
+}
+
+#[test]
+fn read_file() {
+    let mut mapset = MapsetArchive::from_archive(archive("quaver", "")).unwrap();
+
+    assert_eq!(mapset.read_file("BG.jpg").unwrap(), b"background");
+    assert!(matches!(
+        mapset.read_file("missing.png"),
+        Err(Error::MissingFile(path)) if path == "missing.png"
+    ));
+    assert!(matches!(
+        mapset.read_file("..\\secret.txt"),
+        Err(Error::InvalidPath(path)) if path == "../secret.txt"
+    ));
+
+    let mut mapset = MapsetArchive::open("tests/data/quaver").unwrap();
+    assert_eq!(mapset.read_file("./BG.JPG").unwrap(), b"background");
+    assert!(matches!(
+        mapset.read_file("/etc/passwd"),
+        Err(Error::InvalidPath(_))
+    ));
+    assert!(matches!(
+        mapset.read_file("../osu/song.mp3"),
+        Err(Error::InvalidPath(_))
+    ));
+}
+
+#[test]
+fn invalid_difficulty() {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer
+        .start_file("broken.qua", SimpleFileOptions::default())
+        .unwrap();
+    writer.write_all(b"HitObjects: 5").unwrap();
+    writer
+        .start_file("easy.qua", SimpleFileOptions::default())
+        .unwrap();
+    writer
+        .write_all(&fs::read("tests/data/quaver/easy.qua").unwrap())
+        .unwrap();
+    let mut cursor = writer.finish().unwrap();
+    cursor.set_position(0);
+
+    // The broken difficulty is left out.
+    let mut mapset = MapsetArchive::from_archive(cursor).unwrap();
+    assert_eq!(mapset.difficulties(), &quaver_difficulties()[..1]);
+    assert!(matches!(
+        mapset.invalid_difficulties(),
+        [Error::Qua(path, _)] if path == "broken.qua"
+    ));
+
+    // The background and the audio are missing, but only the audio is needed.
+    assert!(matches!(
+        mapset.load(0),
+        Err(Error::MissingFile(path)) if path == "audio.mp3"
+    ));
+}
+
+#[test]
+fn missing_background() {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer
+        .start_file("easy.qua", SimpleFileOptions::default())
+        .unwrap();
+    writer
+        .write_all(&fs::read("tests/data/quaver/easy.qua").unwrap())
+        .unwrap();
+    writer
+        .start_file("audio.mp3", SimpleFileOptions::default())
+        .unwrap();
+    writer.write_all(b"audio").unwrap();
+    let mut cursor = writer.finish().unwrap();
+    cursor.set_position(0);
+
+    let mut mapset = MapsetArchive::from_archive(cursor).unwrap();
+    let difficulty = mapset.load(0).unwrap();
+    assert_eq!(difficulty.audio.as_deref(), Some(&b"audio"[..]));
+    assert_eq!(difficulty.background, None);
+}
+
+#[test]
+fn invalid_archive() {
+    assert!(matches!(
+        MapsetArchive::from_archive(Cursor::new(b"not a zip".to_vec())),
+        Err(Error::Zip(_))
+    ));
+}
@@ -1,17 +1,21 @@
 use std::cmp::max;
 use std::fs::{self, File};
-use std::io::{self, Cursor, Write};
+use std::io::{self, Write};
 use std::path::Path;
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, anyhow, ensure};
 use calloop::{EventLoop, LoopHandle, LoopSignal};
-use plitki_audio::rodio::Source as _;
-use plitki_audio::{AudioEngine, rodio};
+use plitki_audio::{AudioEngine, Backend, DeviceSettings, MAX_RATE, MIN_RATE, Pitch, Track, rodio};
+use plitki_core::health::{FailMode, Health, HealthModel};
+use plitki_core::judgement::HitWindows;
 use plitki_core::map::Map;
 use plitki_core::state::GameState;
 use plitki_core::timing::{
-    GameTimestamp, GameTimestampDifference, MapTimestampDifference, Timestamp,
+    GameTimestamp, GameTimestampDifference, MapTimestampDifference, Rate, Timestamp,
 };
+use plitki_mapset::MapsetArchive;
 use rustix::termios::{self, Winsize};
 
 use crate::frame_clock::FrameClock;
@@ -31,14 +35,39 @@ pub struct App {
     need_full_redraw: bool,
 
     audio: AudioEngine,
+    /// Contents of the audio file, kept around to restart the track.
+    audio_file: Option<Vec<u8>>,
+    /// Whether the music was stopped after failing.
+    audio_stopped: bool,
     frame_clock: FrameClock,
     gameplay: Option<Gameplay>,
+
+    /// Start of the A–B loop, set before the end.
+    loop_start: Option<GameTimestamp>,
+    /// The A–B loop that is currently playing.
+    ab_loop: Option<(GameTimestamp, GameTimestamp)>,
 }
 
 impl App {
     pub fn new(event_loop: &EventLoop<'static, Self>) -> anyhow::Result<Self> {
         let size = termios::tcgetwinsize(rustix::stdio::stdout())?;
 
+        let device =
+            std::env::args().find_map(|arg| Some(arg.strip_prefix("--audio-device=")?.to_owned()));
+        let buffer_size =
+            std::env::args().find_map(|arg| arg.strip_prefix("--audio-buffer-size=")?.parse().ok());
+        let audio = if device.is_some() || buffer_size.is_some() {
+            let settings = DeviceSettings {
+                host: None,
+                device,
+                buffer_size,
+            };
+            AudioEngine::with_backend(Backend::Device(settings))
+                .context("error opening the audio device")?
+        } else {
+            AudioEngine::new()
+        };
+
         Ok(Self {
             _loop_handle: event_loop.handle(),
             stop_signal: event_loop.get_signal(),
@@ -47,9 +76,13 @@ impl App {
             size,
             got_sync: false,
             need_full_redraw: true,
-            audio: AudioEngine::new(),
+            audio,
+            audio_file: None,
+            audio_stopped: false,
             frame_clock: FrameClock::new(),
             gameplay: None,
+            loop_start: None,
+            ab_loop: None,
         })
     }
 
@@ -114,57 +147,132 @@ impl App {
 
                 if self.gameplay.is_none() {
                     // This finishes initialization, we can do our first render.
-                    let (qua, map_dir) = if let Some(path) = std::env::args_os().nth(1) {
-                        let file =
-                            File::open(&path).with_context(|| format!("error opening {path:?}"))?;
-                        let qua = plitki_map_qua::from_reader(file)
-                            .with_context(|| format!("error parsing qua {path:?}"))?;
-                        let parent = Path::new(&path).parent().map(Path::to_path_buf);
-                        (qua, parent)
+                    let has_arg = |name: &str| std::env::args_os().any(|arg| arg == name);
+                    let autoplay = has_arg("--autoplay");
+                    let fail_mode = if has_arg("--sudden-death") {
+                        Some(FailMode::SuddenDeath)
+                    } else if has_arg("--perfect-only") {
+                        Some(FailMode::PerfectOnly)
+                    } else if has_arg("--health") {
+                        Some(FailMode::Normal)
                     } else {
-                        let qua = include_bytes!("../../plitki-map-qua/tests/data/actual_map.qua");
-                        (plitki_map_qua::from_reader(&qua[..])?, None)
+                        None
+                    };
+                    let rate = std::env::args()
+                        .find_map(|arg| arg.strip_prefix("--rate=")?.parse::<f64>().ok())
+                        .filter(|rate| rate.is_finite())
+                        .map_or(Rate::default(), |rate| {
+                            Rate::from_f32(rate.clamp(MIN_RATE, MAX_RATE) as f32)
+                        });
+                    let pitch = if has_arg("--nightcore") {
+                        Pitch::Change
+                    } else {
+                        Pitch::Keep
                     };
-                    let map = Map::from(qua);
-
-                    // Load the audio file.
-                    let track = if let Some(name) = &map.audio_file {
-                        if let Some(mut dir) = map_dir {
-                            dir.push(name);
-                            match fs::read(&dir) {
-                                Ok(contents) => {
-                                    let contents = Cursor::new(contents);
-                                    match rodio::Decoder::new(contents) {
-                                        Ok(x) => Some(x),
-                                        Err(err) => {
-                                            // warn!("error decoding audio file: {err:?}");
-                                            let _ = err;
-                                            None
-                                        }
-                                    }
+                    let path = std::env::args_os()
+                        .skip(1)
+                        .find(|arg| !arg.to_string_lossy().starts_with("--"));
+
+                    let (map, audio) = if let Some(path) = path {
+                        let extension = Path::new(&path)
+                            .extension()
+                            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+                        if Path::new(&path).is_dir()
+                            || matches!(extension.as_deref(), Some("qp" | "osz"))
+                        {
+                            let mut mapset = MapsetArchive::open(&path)
+                                .with_context(|| format!("error opening mapset {path:?}"))?;
+                            ensure!(
+                                !mapset.difficulties().is_empty(),
+                                "{path:?} has no difficulties"
+                            );
+                            let difficulty = mapset
+                                .load(0)
+                                .with_context(|| format!("error loading mapset {path:?}"))?;
+                            (difficulty.map, difficulty.audio)
+                        } else {
+                            let file = File::open(&path)
+                                .with_context(|| format!("error opening {path:?}"))?;
+                            let map = match extension.as_deref() {
+                                Some("osu") => {
+                                    let osu = plitki_map_osu::from_reader(file)
+                                        .with_context(|| format!("error parsing osu {path:?}"))?;
+                                    Map::try_from(osu)
+                                        .with_context(|| format!("error converting osu {path:?}"))?
                                 }
-                                Err(_err) => {
-                                    // warn!("error reading audio file: {err:?}");
+                                Some("sm" | "ssc") => {
+                                    let simfile =
+                                        plitki_map_sm::from_reader(file).with_context(|| {
+                                            format!("error parsing simfile {path:?}")
+                                        })?;
+                                    ensure!(!simfile.charts.is_empty(), "{path:?} has no charts");
+                                    // Unsupported notes like mines are left out.
+                                    simfile.convert(0).map
+                                }
+                                Some(ext @ ("bms" | "bme" | "bml" | "pms")) => {
+                                    // Pick different #RANDOM branches on every run.
+                                    let seed = SystemTime::now()
+                                        .duration_since(UNIX_EPOCH)
+                                        .map_or(0, |x| x.as_nanos() as u64);
+                                    let bms = plitki_map_bms::from_reader(file, seed)
+                                        .with_context(|| format!("error parsing bms {path:?}"))?;
+                                    let layout = if ext == "pms" {
+                                        plitki_map_bms::Layout::PopN9K
+                                    } else {
+                                        bms.detect_layout()
+                                    };
+                                    // Keysounds aren't played yet.
+                                    bms.convert(layout).map
+                                }
+                                _ => {
+                                    let qua = plitki_map_qua::from_reader(file)
+                                        .with_context(|| format!("error parsing qua {path:?}"))?;
+                                    Map::try_from(qua)
+                                        .with_context(|| format!("error converting qua {path:?}"))?
+                                }
+                            };
+
+                            // Load the audio file.
+                            let audio = match (&map.audio_file, Path::new(&path).parent()) {
+                                (Some(name), Some(dir)) => fs::read(dir.join(name)).ok(),
+                                _ => {
+                                    // warn!("map has no audio file set");
                                     None
                                 }
-                            }
-                        } else {
-                            // warn!(".qua file has no parent dir");
-                            None
+                            };
+                            (map, audio)
                         }
                     } else {
-                        // warn!("map has no audio file set");
-                        None
+                        let qua = include_bytes!("../../plitki-map-qua/tests/data/actual_map.qua");
+                        (Map::try_from(plitki_map_qua::from_reader(&qua[..])?)?, None)
                     };
 
-                    let hit_window = GameTimestampDifference::from_millis(164);
-                    let mut game_state = GameState::new(map, hit_window)
+                    // Check that the audio file can be decoded.
+                    self.audio_file = audio.filter(|contents| {
+                        match Track::new(contents.clone()) {
+                            Ok(_) => true,
+                            Err(err) => {
+                                // warn!("error decoding audio file: {err:?}");
+                                let _ = err;
+                                false
+                            }
+                        }
+                    });
+
+                    let mut game_state = GameState::new(map, HitWindows::quaver_standard())
                         .map_err(|_| anyhow!("map has invalid objects"))?;
                     game_state.timestamp_converter.global_offset =
                         GameTimestampDifference::from_millis(-120);
                     game_state.timestamp_converter.local_offset =
                         MapTimestampDifference::from_millis(25);
+                    // Hit windows stay in game time, the audio plays at the same rate as the map.
+                    game_state.timestamp_converter.rate = rate;
+                    game_state.health = fail_mode
+                        .map(|mode| Health::new(HealthModel::quaver().with_fail_mode(mode)));
                     let mut gameplay = Gameplay::new(game_state, self.size);
+                    if autoplay {
+                        gameplay.enable_autoplay();
+                    }
                     let starting_silence = gameplay.starting_silence();
                     gameplay.set_now(GameTimestamp(
                         Timestamp::zero()
@@ -173,12 +281,8 @@ impl App {
                     self.gameplay = Some(gameplay);
 
                     self.audio.set_volume(0.1);
-                    if let Some(track) = track {
-                        self.audio.play_track(track.delay(starting_silence));
-                    } else {
-                        self.audio
-                            .play_track(rodio::source::Zero::<f32>::new(2, 44100));
-                    }
+                    self.audio.set_rate(f64::from(rate.as_f32()), pitch);
+                    self.play_audio(None, None);
                 }
             }
         }
@@ -186,31 +290,129 @@ impl App {
         Ok(())
     }
 
+    /// Starts the audio from `start`, or from the beginning, looping until `loop_end` if set.
+    fn play_audio(&mut self, start: Option<GameTimestamp>, loop_end: Option<GameTimestamp>) {
+        let Some(gameplay) = &self.gameplay else {
+            return;
+        };
+
+        let starting_silence = gameplay.game_to_track(GameTimestamp::zero());
+        let start = start.map_or(Duration::ZERO, |start| gameplay.game_to_track(start));
+        let end = loop_end.map(|end| gameplay.game_to_track(end));
+
+        // The track seeks to the start with the demuxer rather than decoding everything before it.
+        let result = match &self.audio_file {
+            Some(contents) => Track::new(contents.clone()).and_then(|track| {
+                let track = track.with_leading_silence(starting_silence);
+                match end {
+                    Some(end) => self.audio.play_decoded_track_loop(track, start, end),
+                    None => self.audio.play_decoded_track(track, start),
+                }
+            }),
+            None => {
+                let track = rodio::source::Zero::<f32>::new(2, 44100);
+                match end {
+                    Some(end) => self.audio.play_track_loop(track, start, end),
+                    None => self.audio.play_track_from(track, start),
+                }
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            // warn!("error playing audio file: {err:?}");
+            let _ = err;
+        }
+        self.audio_stopped = false;
+    }
+
+    /// Updates the gameplay to the current audio time.
+    fn update_now(&mut self) {
+        let now = self.now();
+        let Some(gameplay) = &mut self.gameplay else {
+            return;
+        };
+
+        // The audio jumps back when the A–B loop wraps around.
+        if let Some((start, _)) = self.ab_loop
+            && gameplay.failed_at.is_none()
+            && now < gameplay.now
+        {
+            gameplay.seek(start);
+        }
+
+        gameplay.set_now(now);
+
+        if gameplay.failed_at.is_some() && !self.audio_stopped {
+            // Stop the music.
+            self.audio_stopped = true;
+            self.audio
+                .play_track(rodio::source::Zero::<f32>::new(2, 44100));
+        }
+    }
+
+    /// Sets the start or the end of the A–B loop at the current time.
+    fn set_loop_point(&mut self, is_end: bool) {
+        let Some(gameplay) = &mut self.gameplay else {
+            return;
+        };
+        let now = gameplay.now;
+
+        if !is_end {
+            self.loop_start = Some(now);
+            return;
+        }
+
+        let Some(start) = self.loop_start else {
+            return;
+        };
+        if now <= start {
+            return;
+        }
+
+        self.ab_loop = Some((start, now));
+        gameplay.seek(start);
+        self.play_audio(Some(start), Some(now));
+    }
+
+    /// Stops the A–B loop and continues playing from the current time.
+    fn clear_loop(&mut self) {
+        self.loop_start = None;
+        if self.ab_loop.take().is_none() {
+            return;
+        }
+
+        if let Some(now) = self.gameplay.as_ref().map(|gameplay| gameplay.now) {
+            self.play_audio(Some(now), None);
+        }
+    }
+
     fn now(&self) -> GameTimestamp {
         let Some(gameplay) = &self.gameplay else {
             return GameTimestamp::zero();
         };
 
-        let audio_time_passed = self.audio.track_time();
-        let audio_time_passed = Timestamp::try_from(audio_time_passed)
-            .unwrap()
-            .into_milli_hundredths();
-        let starting_silence = Timestamp::try_from(gameplay.starting_silence())
-            .unwrap()
-            .into_milli_hundredths();
-        GameTimestamp(Timestamp::from_milli_hundredths(
-            audio_time_passed.saturating_sub(starting_silence),
-        ))
+        gameplay.track_to_game(self.audio.track_time())
     }
 
     fn key(&mut self, key: Key, mods: Modifier) {
         match key {
             Key::Char('q' | '\x1B') => self.signal_stop(),
             Key::Char('c') if mods == Modifier::Ctrl => self.signal_stop(),
+            Key::Char('[') => {
+                self.update_now();
+                self.set_loop_point(false);
+            }
+            Key::Char(']') => {
+                self.update_now();
+                self.set_loop_point(true);
+            }
+            Key::Char('\\') => {
+                self.update_now();
+                self.clear_loop();
+            }
             _ => {
-                let now = self.now();
+                self.update_now();
                 if let Some(gameplay) = &mut self.gameplay {
-                    gameplay.set_now(now);
                     gameplay.key(key, mods);
                 }
             }
@@ -218,9 +420,8 @@ impl App {
     }
 
     fn key_up(&mut self, key: Key) {
-        let now = self.now();
+        self.update_now();
         if let Some(gameplay) = &mut self.gameplay {
-            gameplay.set_now(now);
             gameplay.key_up(key);
         }
     }
@@ -244,14 +445,14 @@ impl App {
             self.draw_binds(&mut stdout)?;
         }
 
-        let now = self.now();
+        self.update_now();
         if let Some(gameplay) = &mut self.gameplay {
             if self.need_full_redraw {
                 gameplay.draw_borders(&mut stdout)?;
             }
 
-            gameplay.set_now(now);
             gameplay.draw_playfield(&mut stdout)?;
+            gameplay.draw_score(&mut stdout)?;
         }
 
         self.draw_fps(&mut stdout)?;
@@ -268,7 +469,7 @@ impl App {
     }
 
     fn draw_binds(&self, stdout: &mut io::StdoutLock) -> io::Result<()> {
-        let y = max(6, self.size.ws_row) - 7;
+        let y = max(9, self.size.ws_row) - 10;
         write!(stdout, "\x1B[{y};0H")?;
 
         write!(stdout, "▁▂▃▄▅▆▇█\x1B[E")?;
@@ -276,9 +477,12 @@ impl App {
         write!(stdout, "▁🭻🭺🭹🭸🭷🭶▔\x1B[E")?;
         write!(stdout, "q quit\x1B[E")?;
         write!(stdout, "w upscroll\x1B[E")?;
+        write!(stdout, "c snap colors\x1B[E")?;
         write!(stdout, "F3/F4      speed ±5\x1B[E")?;
         write!(stdout, "Ctrl+F3/F4 speed ±1\x1B[E")?;
         write!(stdout, "-/+ offset ±5 ms\x1B[E")?;
+        write!(stdout, "[/] loop start/end\x1B[E")?;
+        write!(stdout, "\\   stop loop\x1B[E")?;
 
         Ok(())
     }
@@ -3,18 +3,30 @@ use std::io::{self, Write as _};
 use std::iter::zip;
 use std::time::Duration;
 
+use plitki_core::autoplay::Autoplay;
+use plitki_core::replay::InputKind;
+use plitki_core::score::{Score, ScoringSystem};
 use plitki_core::scroll::{Position, ScreenPositionDifference, ScrollSpeed};
-use plitki_core::state::{GameState, ObjectCache};
-use plitki_core::timing::{GameTimestamp, GameTimestampDifference, MapTimestampDifference};
+use plitki_core::snap::Snap;
+use plitki_core::state::{Event, EventKind, GameState, ObjectCache};
+use plitki_core::timing::{
+    GameTimestamp, GameTimestampDifference, MapTimestampDifference, Timestamp,
+};
 use rustix::termios::Winsize;
 
 use crate::parser::{Key, Modifier};
 
 pub struct Gameplay {
     pub state: GameState,
+    pub score: Score,
+    pub autoplay: Option<Autoplay>,
     pub scroll_speed: ScrollSpeed,
     pub downscroll: bool,
+    /// Whether notes are colored by their snap rather than by their lane.
+    pub snap_colors: bool,
     pub now: GameTimestamp,
+    /// Timestamp when the player failed, the gameplay stops there.
+    pub failed_at: Option<GameTimestamp>,
 
     pub is_lane_pressed: Vec<bool>,
 
@@ -25,6 +37,7 @@ pub struct Gameplay {
 #[derive(Clone, Copy)]
 enum Color {
     Normal,
+    Snap(Snap),
     Missed,
     TimingLine,
     JudgementLine,
@@ -33,19 +46,44 @@ enum Color {
 impl Gameplay {
     pub fn new(state: GameState, size: Winsize) -> Self {
         let lane_count = state.lane_count();
+        let score = Score::new(ScoringSystem::Quaver, &state);
 
         Self {
             state,
+            score,
+            autoplay: None,
             scroll_speed: ScrollSpeed(32),
             downscroll: true,
+            snap_colors: false,
             now: GameTimestamp::zero(),
+            failed_at: None,
             is_lane_pressed: vec![false; lane_count],
             size,
             buffer: Vec::new(),
         }
     }
 
+    pub fn enable_autoplay(&mut self) {
+        self.autoplay = Some(Autoplay::new(&self.state));
+    }
+
+    /// Restarts the gameplay from `timestamp`, skipping all objects before it.
+    pub fn seek(&mut self, timestamp: GameTimestamp) {
+        self.state.seek(timestamp);
+        self.score = Score::new(self.score.system(), &self.state);
+        if self.autoplay.is_some() {
+            self.enable_autoplay();
+        }
+        self.is_lane_pressed.fill(false);
+        self.now = timestamp;
+        self.failed_at = None;
+    }
+
     pub fn set_now(&mut self, now: GameTimestamp) {
+        if self.failed_at.is_some() {
+            return;
+        }
+
         self.now = now;
         self.update(now);
     }
@@ -61,6 +99,7 @@ impl Gameplay {
                 self.scroll_speed.0 = self.scroll_speed.0.saturating_add(c);
             }
             Key::Char('w') => self.downscroll = !self.downscroll,
+            Key::Char('c') => self.snap_colors = !self.snap_colors,
             Key::Char('-') => {
                 let diff = MapTimestampDifference::from_millis(5);
                 self.state.timestamp_converter.local_offset = self
@@ -68,7 +107,7 @@ impl Gameplay {
                     .timestamp_converter
                     .local_offset
                     .saturating_sub(diff);
-                self.state.update(self.now);
+                self.update(self.now);
             }
             Key::Char('=') | Key::Char('+') => {
                 let diff = MapTimestampDifference::from_millis(5);
@@ -77,15 +116,16 @@ impl Gameplay {
                     .timestamp_converter
                     .local_offset
                     .saturating_add(diff);
-                self.state.update(self.now);
+                self.update(self.now);
             }
             Key::Char(key) => {
-                if let Some(lane) = lane_for_key(self.state.lane_count(), key)
+                if self.autoplay.is_none()
+                    && let Some(lane) = lane_for_key(self.state.lane_count(), key)
                     && !self.is_lane_pressed[lane]
                 {
                     self.is_lane_pressed[lane] = true;
                     if let Some(event) = self.state.key_press(lane, self.now) {
-                        self.event(event);
+                        self.event(lane, event);
                     }
                 }
             }
@@ -93,13 +133,14 @@ impl Gameplay {
     }
 
     pub fn key_up(&mut self, key: Key) {
-        if let Key::Char(key) = key
+        if self.autoplay.is_none()
+            && let Key::Char(key) = key
             && let Some(lane) = lane_for_key(self.state.lane_count(), key)
             && self.is_lane_pressed[lane]
         {
             self.is_lane_pressed[lane] = false;
             if let Some(event) = self.state.key_release(lane, self.now) {
-                self.event(event);
+                self.event(lane, event);
             }
         }
     }
@@ -147,11 +188,70 @@ impl Gameplay {
         Duration::try_from((music_start - start_at).0).unwrap()
     }
 
-    fn event(&mut self, _event: plitki_core::state::Event) {}
+    /// Returns the time in the audio track corresponding to `timestamp`.
+    ///
+    /// The track starts with the starting silence and plays at the rate, so the track time is the
+    /// game time since the start of the silence, scaled by the rate.
+    pub fn game_to_track(&self, timestamp: GameTimestamp) -> Duration {
+        let silence = Timestamp::try_from(self.starting_silence())
+            .unwrap()
+            .into_milli_hundredths();
+        let time = GameTimestampDifference::from_milli_hundredths(
+            timestamp.into_milli_hundredths().saturating_add(silence),
+        );
+        let time = self.state.timestamp_converter.game_to_map_difference(time);
+        Duration::from_micros(u64::try_from(time.into_milli_hundredths().max(0)).unwrap() * 10)
+    }
+
+    /// Returns the game timestamp corresponding to the time in the audio track.
+    ///
+    /// This is the inverse of [`Gameplay::game_to_track()`].
+    pub fn track_to_game(&self, track_time: Duration) -> GameTimestamp {
+        let time = Timestamp::try_from(track_time)
+            .unwrap()
+            .into_milli_hundredths();
+        let time = self
+            .state
+            .timestamp_converter
+            .map_to_game_difference(MapTimestampDifference::from_milli_hundredths(time));
+        let silence = Timestamp::try_from(self.starting_silence())
+            .unwrap()
+            .into_milli_hundredths();
+        GameTimestamp::saturating_from_milli_hundredths(
+            time.into_milli_hundredths().saturating_sub(silence),
+        )
+    }
+
+    fn event(&mut self, lane: usize, event: Event) {
+        if event.kind == EventKind::Failed {
+            self.failed_at = Some(self.now);
+        }
+
+        self.score.process_event(&self.state, lane, event);
+    }
 
     fn update(&mut self, timestamp: GameTimestamp) {
-        while let Some(event) = self.state.update(timestamp) {
-            self.event(event);
+        while let Some(input) = self
+            .autoplay
+            .as_mut()
+            .and_then(|autoplay| autoplay.next_input(timestamp))
+        {
+            self.update_lanes(input.timestamp);
+
+            self.is_lane_pressed[input.lane] = input.kind == InputKind::Press;
+            if let Some(event) = input.apply(&mut self.state) {
+                self.event(input.lane, event);
+            }
+        }
+
+        self.update_lanes(timestamp);
+    }
+
+    fn update_lanes(&mut self, timestamp: GameTimestamp) {
+        for lane in 0..self.state.lane_count() {
+            while let Some(event) = self.state.update_lane(lane, timestamp) {
+                self.event(lane, event);
+            }
         }
     }
 
@@ -179,6 +279,15 @@ impl Gameplay {
         let first_pos_subrow = now_pos_subrow - judgement_y * 8;
         let num_rows = i64::from(self.size.ws_row);
 
+        // Objects in scroll groups are positioned relative to their own scroll group.
+        let scroll_group_first_pos_subrows: Vec<i64> =
+            (0..self.state.immutable.scroll_group_caches.len())
+                .map(|group| {
+                    let pos = self.state.scroll_group_position_at_time(Some(group), now);
+                    pos_to_subrow(pos) - judgement_y * 8
+                })
+                .collect();
+
         for line in &self.state.immutable.timing_lines {
             let pos = pos_to_subrow(line.position) - first_pos_subrow;
             let row = pos.div_euclid(8);
@@ -190,31 +299,36 @@ impl Gameplay {
             }
         }
 
-        let iter = zip(
-            zip(
-                &self.state.immutable.map.lanes,
-                &self.state.immutable.lane_caches,
-            ),
-            &self.state.lane_states,
-        );
-        for (i, ((lane, cache), state)) in iter.enumerate() {
+        let iter = zip(&self.state.immutable.lane_caches, &self.state.lane_states);
+        for (i, (cache, state)) in iter.enumerate() {
             let iter = zip(
-                zip(&lane.objects, &cache.object_caches),
+                zip(&cache.snaps, &cache.object_caches),
                 &state.object_states,
             );
-            for ((_obj, cache), state) in iter.rev() {
-                if state.is_hit() {
+            for (index, ((snap, cache), state)) in iter.enumerate().rev() {
+                if state.is_hit() || state.is_skipped() {
                     continue;
                 };
 
-                let start = self.state.object_start_position(*state, *cache, now_pos);
-                let start = pos_to_subrow(start);
-
-                let end = match cache {
-                    ObjectCache::Regular(_) => start + self.note_height(),
-                    ObjectCache::LongNote(cache) => pos_to_subrow(cache.end_position),
+                let (start, end) = match cache {
+                    ObjectCache::Regular(_) => {
+                        let start = self.state.object_start_position(*state, *cache, now);
+                        let start = pos_to_subrow(start);
+                        (start, start + self.note_height())
+                    }
+                    // LNs that turn around mid-way cover more than the span between their ends.
+                    ObjectCache::LongNote(_) => {
+                        let geometry = self.state.long_note_geometry(i, index, now);
+                        (
+                            pos_to_subrow(geometry.lowest_position),
+                            pos_to_subrow(geometry.highest_position),
+                        )
+                    }
                 };
 
+                let first_pos_subrow = cache.scroll_group().map_or(first_pos_subrow, |group| {
+                    scroll_group_first_pos_subrows[group]
+                });
                 let start = start - first_pos_subrow;
                 let end = end - first_pos_subrow;
 
@@ -227,6 +341,8 @@ impl Gameplay {
 
                 let color = if state.is_missed() {
                     Color::Missed
+                } else if self.snap_colors {
+                    Color::Snap(snap.start)
                 } else {
                     Color::Normal
                 };
@@ -273,6 +389,25 @@ impl Gameplay {
         Ok(())
     }
 
+    pub fn draw_score(&self, stdout: &mut io::StdoutLock) -> io::Result<()> {
+        let accuracy = self.score.accuracy();
+        let combo = self.score.combo();
+        write!(
+            stdout,
+            "\x1B[2;0HAcc: {accuracy:>6.2}%\x1B[ECombo: {combo:>5}×"
+        )?;
+
+        if let Some(health) = &self.state.health {
+            let health = f64::from(health.value()) / 100.;
+            write!(stdout, "\x1B[EHP:  {health:>6.2}%")?;
+        }
+        if self.failed_at.is_some() {
+            write!(stdout, "\x1B[E\x1B[31mFAILED\x1B[39m")?;
+        }
+
+        Ok(())
+    }
+
     pub fn draw_playfield(&mut self, stdout: &mut io::StdoutLock) -> io::Result<()> {
         self.render();
 
@@ -374,6 +509,7 @@ fn lane_color(lane_count: usize, lane: usize, color: Color) -> u8 {
     match color {
         Color::TimingLine => return 90,
         Color::JudgementLine => return 39,
+        Color::Snap(snap) => return snap_color(snap),
         _ => (),
     };
 
@@ -385,6 +521,21 @@ fn lane_color(lane_count: usize, lane: usize, color: Color) -> u8 {
     if alt { 96 } else { 39 }
 }
 
+/// Returns the color of notes with `snap`, following the usual VSRG snap colors.
+fn snap_color(snap: Snap) -> u8 {
+    match snap {
+        Snap::Whole => 91,
+        Snap::Half => 94,
+        Snap::Third => 95,
+        Snap::Quarter => 93,
+        Snap::Sixth => 35,
+        Snap::Eighth => 33,
+        Snap::Twelfth => 96,
+        Snap::Sixteenth => 92,
+        Snap::Other => 37,
+    }
+}
+
 fn lane_for_key(lane_count: usize, key: char) -> Option<usize> {
     let lane = match lane_count {
         4 => match key {
@@ -13,6 +13,7 @@ use std::{
 
 use calloop::EventLoop;
 use plitki_core::{
+    judgement::HitWindows,
     map::Map,
     scroll::ScrollSpeed,
     state::{GameState, LongNoteState, ObjectState, RegularObjectState},
@@ -106,7 +107,11 @@ struct State {
 impl State {
     fn new(map: Map) -> Self {
         Self {
-            game_state: GameState::new(map, GameTimestampDifference::from_millis(76)).unwrap(),
+            game_state: GameState::new(
+                map,
+                HitWindows::uniform(GameTimestampDifference::from_millis(76)),
+            )
+            .unwrap(),
             cap_fps: false,
             scroll_speed: ScrollSpeed(32),
             no_scroll_speed_changes: false,
@@ -174,7 +179,7 @@ fn main() {
             None,
         )
     };
-    let map: Map = from_reader(&*qua).unwrap().into();
+    let map: Map = from_reader(&*qua).unwrap().try_into().unwrap();
     let mut audio_file = map.audio_file.as_ref().cloned();
 
     // The latest game state on the main thread. Main thread uses this for updates relying on
@@ -13,7 +13,7 @@ use palette::{ComponentWise, Srgba};
 use plitki_core::{
     object::Object,
     scroll::{Position, ScreenPositionDifference, ScrollSpeedMultiplier},
-    state::{Hit, LongNoteCache, LongNoteState, ObjectCache, ObjectState},
+    state::{Hit, LongNoteState, ObjectCache, ObjectState},
     timing::{GameTimestamp, GameTimestampDifference, MapTimestamp},
 };
 use rust_hawktracer::*;
@@ -54,6 +54,8 @@ pub struct SingleFrameRenderer<'a> {
     game_timestamp: GameTimestamp,
     map_timestamp: MapTimestamp,
     current_position: Position,
+    /// Current positions of the scroll groups.
+    scroll_group_positions: Vec<Position>,
     lane_width: f32,
     border_offset: f32,
     border_width: f32,
@@ -370,6 +372,13 @@ impl<'a> SingleFrameRenderer<'a> {
         } else {
             state.game_state.position_at_time(map_timestamp)
         };
+        let scroll_group_positions = (0..state.game_state.immutable.scroll_group_caches.len())
+            .map(|group| {
+                state
+                    .game_state
+                    .scroll_group_position_at_time(Some(group), map_timestamp)
+            })
+            .collect();
 
         Self {
             renderer,
@@ -377,6 +386,7 @@ impl<'a> SingleFrameRenderer<'a> {
             game_timestamp,
             map_timestamp,
             current_position,
+            scroll_group_positions,
             lane_width,
             border_offset,
             border_width,
@@ -406,6 +416,16 @@ impl<'a> SingleFrameRenderer<'a> {
                 .to_pixels(from_core_position_difference(screen_position_difference))
     }
 
+    /// Converts a position in a scroll group into a position which can be drawn against
+    /// `current_position`.
+    #[inline]
+    fn scroll_group_to_core(&self, position: Position, scroll_group: Option<usize>) -> Position {
+        match scroll_group {
+            Some(group) => position + (self.current_position - self.scroll_group_positions[group]),
+            None => position,
+        }
+    }
+
     #[inline]
     fn screen_to_core(&self, screen_position: f32) -> Position {
         let screen_position_difference = to_core_position_difference(
@@ -510,6 +530,8 @@ impl<'a> SingleFrameRenderer<'a> {
     fn push_objects(&mut self) {
         // Yay, partial borrowing to win vs. the borrow checker...
         let state = self.state;
+        let current_position = self.current_position;
+        let scroll_group_positions = &self.scroll_group_positions;
 
         let first_visible_position = self.screen_to_core(-self.note_height);
         let one_past_last_visible_position = self.screen_to_core(self.height());
@@ -518,7 +540,7 @@ impl<'a> SingleFrameRenderer<'a> {
             .map(|lane| {
                 (
                     lane,
-                    &state.game_state.immutable.map.lanes[lane].objects[..],
+                    &state.game_state.immutable.chart.lanes[lane].objects[..],
                     &state.game_state.lane_states[lane].object_states[..],
                     &state.game_state.immutable.lane_caches[lane].object_caches[..],
                 )
@@ -544,27 +566,35 @@ impl<'a> SingleFrameRenderer<'a> {
 
                 first_visible_index..one_past_last_visible_index
             } else {
-                let first_visible_index = object_caches
-                    .binary_search_by_key(&first_visible_position, |cache| cache.end_position())
-                    .unwrap_or_else(identity);
-                let one_past_last_visible_index = object_caches
-                    .binary_search_by_key(&one_past_last_visible_position, |cache| {
-                        cache.start_position()
-                    })
-                    .unwrap_or_else(identity);
-
-                first_visible_index..one_past_last_visible_index
+                // Object positions aren't sorted when scroll speed changes go negative, so every
+                // object is checked against its full extent below.
+                0..objects.len()
             };
 
-            for ((object, object_state), object_cache) in objects[range.clone()]
+            let no_scroll_speed_changes = self.no_scroll_speed_changes;
+            // Objects in scroll groups are positioned relative to their own scroll group.
+            let to_core = |position, cache: &ObjectCache| match cache.scroll_group() {
+                Some(group) => position + (current_position - scroll_group_positions[group]),
+                None => position,
+            };
+            for (index, ((object, object_state), object_cache)) in objects[range.clone()]
                 .iter()
                 .zip(object_states[range.clone()].iter())
-                .zip(object_caches[range].iter())
+                .zip(object_caches[range.clone()].iter())
+                .enumerate()
+                .map(|(index, x)| (range.start + index, x))
                 .rev()
-                .filter(|((_, s), _)| !s.is_hit())
+                .filter(|(_, ((_, s), _))| !s.is_hit() && !s.is_skipped())
+                .filter(|(_, (_, cache))| {
+                    no_scroll_speed_changes
+                        || (to_core(cache.highest_position(), cache) >= first_visible_position
+                            && to_core(cache.lowest_position(), cache)
+                                < one_past_last_visible_position)
+                })
             {
                 self.renderer.sprites.push(self.object_sprite(
                     lane,
+                    index,
                     object,
                     object_state,
                     object_cache,
@@ -576,6 +606,7 @@ impl<'a> SingleFrameRenderer<'a> {
     fn object_sprite(
         &self,
         lane: usize,
+        index: usize,
         object: &Object,
         object_state: &ObjectState,
         object_cache: &ObjectCache,
@@ -612,35 +643,28 @@ impl<'a> SingleFrameRenderer<'a> {
 
             (start, ln_positions)
         } else {
-            let start = match *object {
-                Object::Regular { .. } => object_cache.start_position(),
-                Object::LongNote { start, end } => match *object_state {
-                    ObjectState::LongNote(LongNoteState::Held { .. }) => self
-                        .state
-                        .game_state
-                        .position_at_time(self.map_timestamp.clamp(start, end)),
-
-                    ObjectState::LongNote(LongNoteState::Missed {
-                        held_until: Some(held_until),
-                        ..
-                    }) => self
-                        .state
-                        .game_state
-                        .position_at_time(held_until.max(start)),
-
-                    _ => object_cache.start_position(),
-                },
-            };
-
-            let ln_positions = match *object_cache {
-                ObjectCache::Regular(_) => None,
-                ObjectCache::LongNote(LongNoteCache {
-                    end_position,
-                    start_position,
-                }) => Some((start_position, end_position)),
-            };
-
-            (start, ln_positions)
+            match *object_cache {
+                ObjectCache::Regular(_) => (
+                    self.scroll_group_to_core(
+                        object_cache.start_position(),
+                        object_cache.scroll_group(),
+                    ),
+                    None,
+                ),
+                // The sprite covers the whole body, which extends past the head or the tail when
+                // the LN turns around.
+                ObjectCache::LongNote(_) => {
+                    let game_state = &self.state.game_state;
+                    let geometry = game_state.long_note_geometry(lane, index, self.map_timestamp);
+                    let to_core =
+                        |position| self.scroll_group_to_core(position, object_cache.scroll_group());
+                    let lowest_position = to_core(geometry.lowest_position);
+                    (
+                        lowest_position,
+                        Some((lowest_position, to_core(geometry.highest_position))),
+                    )
+                }
+            }
         };
 
         let pos = Point2::new(
@@ -733,6 +757,7 @@ impl<'a> SingleFrameRenderer<'a> {
         for Hit {
             timestamp,
             difference,
+            ..
         } in self.state.game_state.last_hits.iter()
         {
             let offset = difference.into_milli_hundredths() as f32 * hit_difference_offset_factor
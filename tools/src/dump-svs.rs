@@ -1,4 +1,4 @@
-use std::{fs::File, path::PathBuf};
+use std::{convert::TryFrom, fs::File, path::PathBuf};
 
 use plitki_core::map::Map;
 use structopt::StructOpt;
@@ -14,7 +14,7 @@ fn main() {
     let opt = Opt::from_args();
     let file = File::open(opt.path).unwrap();
     let qua = plitki_map_qua::from_reader(file).unwrap();
-    let map: Map = qua.into();
+    let map = Map::try_from(qua).unwrap();
 
     println!("initial\t{}", map.initial_scroll_speed_multiplier.as_f32());
     for sv in map.scroll_speed_changes {